@@ -0,0 +1,259 @@
+use std::collections::{HashMap,HashSet};
+use std::collections::hash_map::Entry;
+use std::fmt;
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::select::*;
+use crate::document::Document;
+
+///Identifies a group of annotations: the annotation type and set
+pub(crate) type AnnotationGroup = (AnnotationType, Option<String>);
+
+#[derive(Default)]
+///Annotations gathered from a document (optionally only those by a particular processor), grouped by annotation type and set
+pub(crate) struct AnnotationSample {
+    ///Token annotations: maps the ID of the annotated element to the class
+    pub(crate) tokens: HashMap<AnnotationGroup,HashMap<String,String>>,
+    ///The number of token annotations that were left out because the element already has one of the same type and set
+    pub(crate) duplicates: HashMap<AnnotationGroup,usize>,
+    ///Span annotations: the class and the (sorted) IDs of the spanned elements
+    pub(crate) spans: HashMap<AnnotationGroup,Vec<(String,Vec<String>)>>,
+}
+
+impl AnnotationSample {
+    ///Gathers all token annotations (on elements with an ID) and span annotations (directly in a layer). Only the
+    ///first token annotation of a type and set on an element is taken, any others are counted as duplicates.
+    pub(crate) fn new(document: &Document, processor: Option<ProcKey>) -> Self {
+        let mut sample = Self::default();
+        for item in document.select_data(Selector::elements(), Recursion::Always, false) {
+            if let DataType::Element(key) = item.data {
+                let element = document.get_element(*key).expect("unwrapping element");
+                if processor.is_some() && element.processor_key() != processor {
+                    continue;
+                }
+                let annotationtype = if let Some(annotationtype) = element.elementtype().annotationtype() { annotationtype } else { continue };
+                let group = (annotationtype, element.set().map(|s| s.to_string()));
+                let class = element.class().unwrap_or("").to_string();
+                let parent = element.get_parent();
+                if ElementGroup::Inline.contains(element.elementtype()) {
+                    if let Some(parent_id) = parent.as_ref().and_then(|p| p.id()) {
+                        match sample.tokens.entry(group.clone()).or_default().entry(parent_id.to_string()) {
+                            Entry::Occupied(_) => *sample.duplicates.entry(group).or_insert(0) += 1,
+                            Entry::Vacant(entry) => { entry.insert(class); },
+                        }
+                    }
+                } else if ElementGroup::Span.contains(element.elementtype()) && parent.map(|p| ElementGroup::Layer.contains(p.elementtype())) == Some(true) {
+                    let mut span = document.span_ids(*key);
+                    span.sort();
+                    sample.spans.entry(group).or_default().push((class, span));
+                }
+            }
+        }
+        sample
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+///Agreement on a token annotation type and set. Only elements annotated by both annotators count as items
+///for the observed agreement, kappa and alpha; F1 also accounts for annotations made by one annotator only. If there are no items, the observed agreement, kappa and alpha are 0.
+pub struct TokenAgreement {
+    pub annotationtype: AnnotationType,
+    pub set: Option<String>,
+    ///The number of elements annotated by both annotators
+    pub items: usize,
+    ///The proportion of items on which both annotators agree
+    pub observed: f64,
+    ///Cohen's kappa
+    pub kappa: f64,
+    ///Krippendorff's alpha (nominal)
+    pub alpha: f64,
+    ///F1 score, taking the first annotator as reference
+    pub f1: f64,
+    ///The number of annotations (by either annotator) that were left out because the element was already annotated in this
+    ///annotation type and set
+    pub duplicates: usize,
+}
+
+#[derive(Debug,Clone,PartialEq)]
+///Agreement on a span annotation type and set. Spans match exactly if they have the same class
+///and span the same elements, and partially if they have the same class and overlap.
+pub struct SpanAgreement {
+    pub annotationtype: AnnotationType,
+    pub set: Option<String>,
+    ///The number of spans by the first annotator
+    pub count1: usize,
+    ///The number of spans by the second annotator
+    pub count2: usize,
+    ///The number of exactly matching spans
+    pub exact: usize,
+    ///The number of partially (or exactly) matching spans
+    pub partial: usize,
+    pub exact_f1: f64,
+    pub partial_f1: f64,
+}
+
+#[derive(Debug,Clone,Default,PartialEq)]
+///Inter-annotator agreement, grouped by annotation type and set
+pub struct AgreementReport {
+    pub tokens: Vec<TokenAgreement>,
+    pub spans: Vec<SpanAgreement>,
+}
+
+impl AgreementReport {
+    ///Returns the agreement for the specified token annotation type and set
+    pub fn token(&self, annotationtype: AnnotationType, set: Option<&str>) -> Option<&TokenAgreement> {
+        self.tokens.iter().find(|agreement| agreement.annotationtype == annotationtype && agreement.set.as_deref() == set)
+    }
+
+    ///Returns the agreement for the specified span annotation type and set
+    pub fn span(&self, annotationtype: AnnotationType, set: Option<&str>) -> Option<&SpanAgreement> {
+        self.spans.iter().find(|agreement| agreement.annotationtype == annotationtype && agreement.set.as_deref() == set)
+    }
+}
+
+impl fmt::Display for AgreementReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for agreement in self.tokens.iter() {
+            write!(f, "{} ({}): items={} observed={:.3} kappa={:.3} alpha={:.3} f1={:.3}", agreement.annotationtype, agreement.set.as_deref().unwrap_or("no set"), agreement.items, agreement.observed, agreement.kappa, agreement.alpha, agreement.f1)?;
+            if agreement.duplicates > 0 {
+                write!(f, " duplicates={}", agreement.duplicates)?;
+            }
+            writeln!(f)?;
+        }
+        for agreement in self.spans.iter() {
+            writeln!(f, "{} ({}): spans={}/{} exact={} partial={} exact_f1={:.3} partial_f1={:.3}", agreement.annotationtype, agreement.set.as_deref().unwrap_or("no set"), agreement.count1, agreement.count2, agreement.exact, agreement.partial, agreement.exact_f1, agreement.partial_f1)?;
+        }
+        Ok(())
+    }
+}
+
+impl Document {
+    ///Computes the agreement between the annotations in this document and those in another
+    ///document with the same elements (matched by ID)
+    pub fn agreement(&self, other: &Document) -> AgreementReport {
+        agreement(&AnnotationSample::new(self, None), &AnnotationSample::new(other, None))
+    }
+
+    ///Computes the agreement between the annotations of two processors (by ID) within this document
+    pub fn processor_agreement(&self, processor1: &str, processor2: &str) -> Result<AgreementReport, FoliaError> {
+        let processor1 = self.get_processor_key_by_id(processor1).ok_or_else(|| FoliaError::KeyError(format!("No such processor: {}", processor1)))?;
+        let processor2 = self.get_processor_key_by_id(processor2).ok_or_else(|| FoliaError::KeyError(format!("No such processor: {}", processor2)))?;
+        Ok(agreement(&AnnotationSample::new(self, Some(processor1)), &AnnotationSample::new(self, Some(processor2))))
+    }
+}
+
+///Returns the annotation groups in both samples, in a fixed order
+pub(crate) fn groups<T>(a: &HashMap<AnnotationGroup,T>, b: &HashMap<AnnotationGroup,T>) -> Vec<AnnotationGroup> {
+    let mut groups: Vec<AnnotationGroup> = a.keys().chain(b.keys()).cloned().collect::<HashSet<_>>().into_iter().collect();
+    groups.sort_by(|x, y| (x.0.as_str(), &x.1).cmp(&(y.0.as_str(), &y.1)));
+    groups
+}
+
+///Computes the F1 score given the number of true positives, predictions and reference items
+pub(crate) fn f1_score(truepositives: usize, predicted: usize, reference: usize) -> f64 {
+    let precision = if predicted > 0 { truepositives as f64 / predicted as f64 } else { 0.0 };
+    let recall = if reference > 0 { truepositives as f64 / reference as f64 } else { 0.0 };
+    if precision + recall > 0.0 {
+        2.0 * precision * recall / (precision + recall)
+    } else {
+        0.0
+    }
+}
+
+///Matches spans one-to-one, exact matches first. Returns the number of exact and the number of
+///partial (including exact) matches.
+pub(crate) fn match_spans(reference: &[(String,Vec<String>)], predicted: &[(String,Vec<String>)]) -> (usize, usize) {
+    let mut used: Vec<bool> = vec![false; predicted.len()];
+    let mut matched: Vec<bool> = vec![false; reference.len()];
+    for (j, (class, span)) in reference.iter().enumerate() {
+        if let Some(i) = (0..predicted.len()).find(|i| !used[*i] && predicted[*i].0 == *class && predicted[*i].1 == *span) {
+            used[i] = true;
+            matched[j] = true;
+        }
+    }
+    let exact = matched.iter().filter(|m| **m).count();
+    let mut partial = exact;
+    for (j, (class, span)) in reference.iter().enumerate() {
+        if matched[j] {
+            continue;
+        }
+        if let Some(i) = (0..predicted.len()).find(|i| !used[*i] && predicted[*i].0 == *class && predicted[*i].1.iter().any(|id| span.contains(id))) {
+            used[i] = true;
+            partial += 1;
+        }
+    }
+    (exact, partial)
+}
+
+fn agreement(a: &AnnotationSample, b: &AnnotationSample) -> AgreementReport {
+    let mut report = AgreementReport::default();
+    let empty_tokens: HashMap<String,String> = HashMap::new();
+    for group in groups(&a.tokens, &b.tokens) {
+        let tokens1 = a.tokens.get(&group).unwrap_or(&empty_tokens);
+        let tokens2 = b.tokens.get(&group).unwrap_or(&empty_tokens);
+        let mut items = 0;
+        let mut agreed = 0;
+        let mut counts1: HashMap<&str,usize> = HashMap::new();
+        let mut counts2: HashMap<&str,usize> = HashMap::new();
+        for (id, class1) in tokens1.iter() {
+            if let Some(class2) = tokens2.get(id) {
+                items += 1;
+                if class1 == class2 {
+                    agreed += 1;
+                }
+                *counts1.entry(class1).or_insert(0) += 1;
+                *counts2.entry(class2).or_insert(0) += 1;
+            }
+        }
+        let (observed, kappa, alpha) = if items > 0 {
+            let n = items as f64;
+            let observed = agreed as f64 / n;
+            //Cohen's kappa
+            let expected: f64 = counts1.iter().map(|(class, count)| (*count as f64 / n) * (*counts2.get(class).unwrap_or(&0) as f64 / n)).sum();
+            let kappa = if expected < 1.0 { (observed - expected) / (1.0 - expected) } else if observed == 1.0 { 1.0 } else { 0.0 };
+            //Krippendorff's alpha, for two annotators and nominal data
+            let values = 2.0 * n;
+            let mut pooled: HashMap<&str,usize> = counts1.clone();
+            for (class, count) in counts2.iter() {
+                *pooled.entry(class).or_insert(0) += count;
+            }
+            let expected_disagreement = (values * values - pooled.values().map(|c| (*c as f64) * (*c as f64)).sum::<f64>()) / (values * (values - 1.0));
+            let observed_disagreement = (items - agreed) as f64 / n;
+            let alpha = if expected_disagreement > 0.0 { 1.0 - observed_disagreement / expected_disagreement } else if observed_disagreement == 0.0 { 1.0 } else { 0.0 };
+            (observed, kappa, alpha)
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+        report.tokens.push(TokenAgreement {
+            annotationtype: group.0,
+            set: group.1.clone(),
+            items,
+            observed,
+            kappa,
+            alpha,
+            f1: f1_score(agreed, tokens2.len(), tokens1.len()),
+            duplicates: a.duplicates.get(&group).unwrap_or(&0) + b.duplicates.get(&group).unwrap_or(&0),
+        });
+    }
+
+    let empty_spans: Vec<(String,Vec<String>)> = Vec::new();
+    for group in groups(&a.spans, &b.spans) {
+        let spans1 = a.spans.get(&group).unwrap_or(&empty_spans);
+        let spans2 = b.spans.get(&group).unwrap_or(&empty_spans);
+        let (exact, partial) = match_spans(spans1, spans2);
+        report.spans.push(SpanAgreement {
+            annotationtype: group.0,
+            set: group.1.clone(),
+            count1: spans1.len(),
+            count2: spans2.len(),
+            exact,
+            partial,
+            exact_f1: f1_score(exact, spans2.len(), spans1.len()),
+            partial_f1: f1_score(partial, spans2.len(), spans1.len()),
+        });
+    }
+    report
+}
@@ -245,139 +245,144 @@ impl Attribute {
     ///Parse an XML attribute into a FoLiA Attribute
     pub fn parse<R: BufRead>(reader: &Reader<R>, attrib: &quick_xml::events::attributes::Attribute) -> Result<Attribute,FoliaError> {
         if let Ok(value) = attrib.unescape_and_decode_value(&reader) {
-            match attrib.key {
-                b"xml:id" => {
-                    Ok(Attribute::Id(value))
-                },
-                b"set" => {
-                    Ok(Attribute::Set(value))
-                },
-                b"class" => {
-                    Ok(Attribute::Class(value))
-                },
-                b"processor" => {
-                    Ok(Attribute::Processor(value))
-                },
-                b"annotator" => {
-                    Ok(Attribute::Annotator(value))
-                },
-                b"annotatortype" => {
-                    match value.as_str() {
-                        "auto" => Ok(Attribute::AnnotatorType(ProcessorType::Auto)),
-                        "manual" => Ok(Attribute::AnnotatorType(ProcessorType::Manual)),
-                        "generator" => Ok(Attribute::AnnotatorType(ProcessorType::Generator)),
-                        "datasource" => Ok(Attribute::AnnotatorType(ProcessorType::DataSource)),
-                        other => Err(FoliaError::ParseError(format!("Invalid value for annotatortype: {}", other)))
-                    }
-                },
-                b"subset" => {
-                    Ok(Attribute::Subset(value))
-                },
-                b"format" => {
-                    Ok(Attribute::Format(value))
-                },
-                b"xlink:href" => {
-                    Ok(Attribute::Href(value))
-                },
-                b"xlink:type" => {
-                    Ok(Attribute::XLinkType(value))
-                },
-                b"speaker" => {
-                    Ok(Attribute::Speaker(value))
-                },
-                b"src" => {
-                    Ok(Attribute::Src(value))
-                },
-                b"n" => {
-                    Ok(Attribute::N(value))
-                },
-                b"t" => {
-                    Ok(Attribute::Text(value))
-                },
-                b"datetime" => {
-                    match NaiveDateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S") {
-                        Ok(dt) => Ok(Attribute::DateTime(dt)),
-                        Err(e) => Err(FoliaError::ParseError(format!("Unable to parse datetime {} -> {}",value, e)))
-                    }
-                },
-                b"begintime" => {
-                    Ok(Attribute::BeginTime(value))
-                },
-                b"endtime" => {
-                    Ok(Attribute::EndTime(value))
-                },
-                b"textclass" => {
-                    Ok(Attribute::Textclass(value))
-                },
-                b"metadata" => {
-                    Ok(Attribute::Metadata(value))
-                },
-                b"id" => {
-                    Ok(Attribute::Idref(value))
-                },
-                b"type" => {
-                    Ok(Attribute::Type(value))
-                },
-                b"auth" => {
-                    Ok(Attribute::Auth(value))
-                },
-                b"original" => {
-                    Ok(Attribute::Original(value))
-                },
-                b"pagenr" => {
-                    Ok(Attribute::PageNr(value))
-                },
-                b"offset" => {
-                    if let Ok(value) = u16::from_str(&value) {
-                        Ok(Attribute::Offset(value))
-                    } else {
-                        Err(FoliaError::ParseError(format!("Invalid offset value: '{}'", value)))
-                    }
-                },
-                b"linenr" => {
-                    if let Ok(value) = u16::from_str(&value) {
-                        Ok(Attribute::LineNr(value))
-                    } else {
-                        Err(FoliaError::ParseError(format!("Invalid line number value: '{}'", value)))
-                    }
-                },
-                b"newpage" => {
-                    match value.as_str() {
-                        "yes" | "true" => Ok(Attribute::NewPage(true)),
-                        "no" | "false" => Ok(Attribute::NewPage(false)),
-                        _ => Err(FoliaError::ParseError(format!("Invalid newpage value: '{}'", value)))
-                    }
-                },
-                b"ref" => {
-                    Ok(Attribute::Ref(value))
-                },
-                b"confidence" => {
-                    if let Ok(value) = f64::from_str(&value) {
-                        Ok(Attribute::Confidence(value))
-                    } else {
-                        Err(FoliaError::ParseError(format!("Invalid confidence value: '{}'", value)))
-                    }
-                },
-                b"space" => {
-                    match value.as_str() {
-                        "yes" | "true" => Ok(Attribute::Space(true)),
-                        "no" | "false" => Ok(Attribute::Space(false)),
-                        _ => Err(FoliaError::ParseError(format!("Invalid space value: '{}'", value)))
-                    }
-                },
-                b"typegroup" => { //used for explicit mode, does not need to be parsed
+            Self::from_key_value(attrib.key, value)
+        } else {
+            Err(FoliaError::ParseError("Unable to parse attribute value (invalid utf-8?)".to_string()))
+        }
+    }
+
+    ///Instantiate a FoLiA Attribute from an XML attribute name and its (unescaped) value
+    pub fn from_key_value(key: &[u8], value: String) -> Result<Attribute,FoliaError> {
+        match key {
+            b"xml:id" => {
+                Ok(Attribute::Id(value))
+            },
+            b"set" => {
+                Ok(Attribute::Set(value))
+            },
+            b"class" => {
+                Ok(Attribute::Class(value))
+            },
+            b"processor" => {
+                Ok(Attribute::Processor(value))
+            },
+            b"annotator" => {
+                Ok(Attribute::Annotator(value))
+            },
+            b"annotatortype" => {
+                match value.as_str() {
+                    "auto" => Ok(Attribute::AnnotatorType(ProcessorType::Auto)),
+                    "manual" => Ok(Attribute::AnnotatorType(ProcessorType::Manual)),
+                    "generator" => Ok(Attribute::AnnotatorType(ProcessorType::Generator)),
+                    "datasource" => Ok(Attribute::AnnotatorType(ProcessorType::DataSource)),
+                    other => Err(FoliaError::ParseError(format!("Invalid value for annotatortype: {}", other)))
+                }
+            },
+            b"subset" => {
+                Ok(Attribute::Subset(value))
+            },
+            b"format" => {
+                Ok(Attribute::Format(value))
+            },
+            b"xlink:href" => {
+                Ok(Attribute::Href(value))
+            },
+            b"xlink:type" => {
+                Ok(Attribute::XLinkType(value))
+            },
+            b"speaker" => {
+                Ok(Attribute::Speaker(value))
+            },
+            b"src" => {
+                Ok(Attribute::Src(value))
+            },
+            b"n" => {
+                Ok(Attribute::N(value))
+            },
+            b"t" => {
+                Ok(Attribute::Text(value))
+            },
+            b"datetime" => {
+                match NaiveDateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S") {
+                    Ok(dt) => Ok(Attribute::DateTime(dt)),
+                    Err(e) => Err(FoliaError::ParseError(format!("Unable to parse datetime {} -> {}",value, e)))
+                }
+            },
+            b"begintime" => {
+                Ok(Attribute::BeginTime(value))
+            },
+            b"endtime" => {
+                Ok(Attribute::EndTime(value))
+            },
+            b"textclass" => {
+                Ok(Attribute::Textclass(value))
+            },
+            b"metadata" => {
+                Ok(Attribute::Metadata(value))
+            },
+            b"id" => {
+                Ok(Attribute::Idref(value))
+            },
+            b"type" => {
+                Ok(Attribute::Type(value))
+            },
+            b"auth" => {
+                Ok(Attribute::Auth(value))
+            },
+            b"original" => {
+                Ok(Attribute::Original(value))
+            },
+            b"pagenr" => {
+                Ok(Attribute::PageNr(value))
+            },
+            b"offset" => {
+                if let Ok(value) = u16::from_str(&value) {
+                    Ok(Attribute::Offset(value))
+                } else {
+                    Err(FoliaError::ParseError(format!("Invalid offset value: '{}'", value)))
+                }
+            },
+            b"linenr" => {
+                if let Ok(value) = u16::from_str(&value) {
+                    Ok(Attribute::LineNr(value))
+                } else {
+                    Err(FoliaError::ParseError(format!("Invalid line number value: '{}'", value)))
+                }
+            },
+            b"newpage" => {
+                match value.as_str() {
+                    "yes" | "true" => Ok(Attribute::NewPage(true)),
+                    "no" | "false" => Ok(Attribute::NewPage(false)),
+                    _ => Err(FoliaError::ParseError(format!("Invalid newpage value: '{}'", value)))
+                }
+            },
+            b"ref" => {
+                Ok(Attribute::Ref(value))
+            },
+            b"confidence" => {
+                if let Ok(value) = f64::from_str(&value) {
+                    Ok(Attribute::Confidence(value))
+                } else {
+                    Err(FoliaError::ParseError(format!("Invalid confidence value: '{}'", value)))
+                }
+            },
+            b"space" => {
+                match value.as_str() {
+                    "yes" | "true" => Ok(Attribute::Space(true)),
+                    "no" | "false" => Ok(Attribute::Space(false)),
+                    _ => Err(FoliaError::ParseError(format!("Invalid space value: '{}'", value)))
+                }
+            },
+            b"typegroup" => { //used for explicit mode, does not need to be parsed
+                Ok(Attribute::Ignore)
+            },
+            attrib_key => {
+                if attrib_key.contains(&58) { //58 is a colon, we assume alien namespaces and ignore it
                     Ok(Attribute::Ignore)
-                },
-                attrib_key => {
-                    if attrib_key.contains(&58) { //58 is a colon, we assume alien namespaces and ignore it
-                        Ok(Attribute::Ignore)
-                    } else {
-                        Err(FoliaError::ParseError(format!("Unknown attribute: '{}'", std::str::from_utf8(attrib.key).expect("unable to parse attribute name"))))
-                    }
+                } else {
+                    Err(FoliaError::ParseError(format!("Unknown attribute: '{}'", std::str::from_utf8(attrib_key).expect("unable to parse attribute name"))))
                 }
             }
-        } else {
-            Err(FoliaError::ParseError("Unable to parse attribute value (invalid utf-8?)".to_string()))
         }
     }
 }
@@ -0,0 +1,74 @@
+extern crate clap;
+
+use std::fs;
+use std::str;
+use std::path::Path;
+use clap::App;
+use folia::*;
+
+fn main() {
+    let argmatches = App::new("conllu2folia")
+        .version("0.0.1")
+        .author("Maarten van Gompel (proycon) <proycon@anaproy.nl>")
+        .about("Convert CoNLL-U files to FoLiA documents, written to standard output")
+        .arg(clap::Arg::with_name("id")
+            .help("The ID of the resulting document, defaults to the base name of the input file")
+            .long("id")
+            .takes_value(true)
+        )
+        .arg(clap::Arg::with_name("uposset")
+            .help("The set for universal part-of-speech tags (UPOS)")
+            .long("uposset")
+            .takes_value(true)
+            .default_value(UD_POS_SET)
+        )
+        .arg(clap::Arg::with_name("xposset")
+            .help("The set for language-specific part-of-speech tags (XPOS), not converted if not specified")
+            .long("xposset")
+            .takes_value(true)
+        )
+        .arg(clap::Arg::with_name("lemmaset")
+            .help("The set for lemmas, lemmas are declared without a set if not specified")
+            .long("lemmaset")
+            .takes_value(true)
+        )
+        .arg(clap::Arg::with_name("dependencyset")
+            .help("The set for dependency relations")
+            .long("dependencyset")
+            .takes_value(true)
+            .default_value(UD_DEPENDENCY_SET)
+        )
+        .arg(clap::Arg::with_name("file")
+            .help("CoNLL-U file to convert")
+            .multiple(true)
+            .required(true)
+        ).get_matches();
+
+    let parameters = ConlluParameters::default()
+        .with_uposset(argmatches.value_of("uposset").map(|s| s.to_string()))
+        .with_xposset(argmatches.value_of("xposset").map(|s| s.to_string()))
+        .with_lemmaset(argmatches.value_of("lemmaset").map(|s| s.to_string()))
+        .with_dependencyset(argmatches.value_of("dependencyset").map(|s| s.to_string()));
+
+    for filename in argmatches.values_of("file").expect("Expected one or more files") {
+        let id = match argmatches.value_of("id") {
+            Some(id) => id.to_string(),
+            None => Path::new(filename).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "untitled".to_string()),
+        };
+        match fs::read_to_string(filename) {
+            Ok(data) => {
+                match Document::from_conllu(&id, &data, &parameters, DocumentProperties::default()) {
+                    Ok((doc, report)) => {
+                        eprint!("{}", report);
+                        match doc.xml(0,4) {
+                            Ok(xml) => println!("{}",str::from_utf8(&xml).expect("valid utf-8")),
+                            Err(err) => eprintln!("{}",err)
+                        }
+                    },
+                    Err(err) => eprintln!("{}",err)
+                }
+            },
+            Err(err) => eprintln!("{}",err)
+        }
+    }
+}
@@ -0,0 +1,56 @@
+extern crate clap;
+
+use clap::App;
+use folia::*;
+
+fn main() {
+    let argmatches = App::new("folia2conllu")
+        .version("0.0.1")
+        .author("Maarten van Gompel (proycon) <proycon@anaproy.nl>")
+        .about("Convert FoLiA documents to CoNLL-U")
+        .arg(clap::Arg::with_name("uposset")
+            .help("The set for universal part-of-speech tags (UPOS)")
+            .long("uposset")
+            .takes_value(true)
+            .default_value(UD_POS_SET)
+        )
+        .arg(clap::Arg::with_name("xposset")
+            .help("The set for language-specific part-of-speech tags (XPOS), not converted if not specified")
+            .long("xposset")
+            .takes_value(true)
+        )
+        .arg(clap::Arg::with_name("lemmaset")
+            .help("The set to take lemmas from, defaults to any set")
+            .long("lemmaset")
+            .takes_value(true)
+        )
+        .arg(clap::Arg::with_name("dependencyset")
+            .help("The set for dependency relations")
+            .long("dependencyset")
+            .takes_value(true)
+            .default_value(UD_DEPENDENCY_SET)
+        )
+        .arg(clap::Arg::with_name("file")
+            .help("FoLiA document to convert")
+            .multiple(true)
+            .required(true)
+        ).get_matches();
+
+    let parameters = ConlluParameters::default()
+        .with_uposset(argmatches.value_of("uposset").map(|s| s.to_string()))
+        .with_xposset(argmatches.value_of("xposset").map(|s| s.to_string()))
+        .with_lemmaset(argmatches.value_of("lemmaset").map(|s| s.to_string()))
+        .with_dependencyset(argmatches.value_of("dependencyset").map(|s| s.to_string()));
+
+    for filename in argmatches.values_of("file").expect("Expected one or more files") {
+        match Document::from_file(filename, DocumentProperties::default()) {
+            Ok(doc) => {
+                match doc.to_conllu(0, &parameters) {
+                    Ok(conllu) => print!("{}", conllu),
+                    Err(err) => eprintln!("{}",err)
+                }
+            },
+            Err(err) => eprintln!("{}",err)
+        }
+    }
+}
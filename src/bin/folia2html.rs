@@ -0,0 +1,46 @@
+extern crate clap;
+
+use clap::App;
+use folia::*;
+
+fn main() {
+    let argmatches = App::new("folia2html")
+        .version("0.0.1")
+        .author("Maarten van Gompel (proycon) <proycon@anaproy.nl>")
+        .about("Convert FoLiA documents to HTML for visualisation in a browser")
+        .arg(clap::Arg::with_name("textclass")
+            .help("The text class to render")
+            .short("c")
+            .long("textclass")
+            .takes_value(true)
+            .default_value("current")
+        )
+        .arg(clap::Arg::with_name("textset")
+            .help("The text set to render")
+            .long("textset")
+            .takes_value(true)
+            .default_value(DEFAULT_TEXT_SET)
+        )
+        .arg(clap::Arg::with_name("file")
+            .help("FoLiA document to convert")
+            .multiple(true)
+            .required(true)
+        ).get_matches();
+
+    let textparameters = TextParameters::default()
+        .set(argmatches.value_of("textset").expect("textset"))
+        .textclass(argmatches.value_of("textclass").expect("textclass"));
+    let parameters = ExportParameters::default().with_textparameters(textparameters);
+
+    for filename in argmatches.values_of("file").expect("Expected one or more files") {
+        match Document::from_file(filename, DocumentProperties::default()) {
+            Ok(doc) => {
+                match doc.to_html(0, &parameters) {
+                    Ok(html) => print!("{}", html),
+                    Err(err) => eprintln!("{}",err)
+                }
+            },
+            Err(err) => eprintln!("{}",err)
+        }
+    }
+}
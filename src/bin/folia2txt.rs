@@ -0,0 +1,80 @@
+extern crate clap;
+
+use clap::App;
+use folia::*;
+
+fn main() {
+    let argmatches = App::new("folia2txt")
+        .version("0.0.1")
+        .author("Maarten van Gompel (proycon) <proycon@anaproy.nl>")
+        .about("Convert FoLiA documents to plain text, tokenised text or a vertical word/pos/lemma format")
+        .arg(clap::Arg::with_name("tokenised")
+            .help("Output tokenised text, one sentence per line")
+            .short("t")
+            .long("tokenised")
+            .conflicts_with("vertical")
+        )
+        .arg(clap::Arg::with_name("vertical")
+            .help("Output a vertical format with one token per line: word, part-of-speech and lemma")
+            .short("v")
+            .long("vertical")
+        )
+        .arg(clap::Arg::with_name("textclass")
+            .help("The text class to extract")
+            .short("c")
+            .long("textclass")
+            .takes_value(true)
+            .default_value("current")
+        )
+        .arg(clap::Arg::with_name("textset")
+            .help("The text set to extract from")
+            .long("textset")
+            .takes_value(true)
+            .default_value(DEFAULT_TEXT_SET)
+        )
+        .arg(clap::Arg::with_name("posset")
+            .help("The set to take part-of-speech tags from (vertical output only), defaults to any set")
+            .long("posset")
+            .takes_value(true)
+        )
+        .arg(clap::Arg::with_name("lemmaset")
+            .help("The set to take lemmas from (vertical output only), defaults to any set")
+            .long("lemmaset")
+            .takes_value(true)
+        )
+        .arg(clap::Arg::with_name("file")
+            .help("FoLiA document to convert")
+            .multiple(true)
+            .required(true)
+        ).get_matches();
+
+    let textparameters = TextParameters::default()
+        .set(argmatches.value_of("textset").expect("textset"))
+        .textclass(argmatches.value_of("textclass").expect("textclass"));
+    let mut parameters = ExportParameters::default().with_textparameters(textparameters);
+    if let Some(posset) = argmatches.value_of("posset") {
+        parameters = parameters.with_posset(posset);
+    }
+    if let Some(lemmaset) = argmatches.value_of("lemmaset") {
+        parameters = parameters.with_lemmaset(lemmaset);
+    }
+
+    for filename in argmatches.values_of("file").expect("Expected one or more files") {
+        match Document::from_file(filename, DocumentProperties::default()) {
+            Ok(doc) => {
+                let result = if argmatches.is_present("vertical") {
+                    doc.to_vertical(0, &parameters)
+                } else if argmatches.is_present("tokenised") {
+                    doc.to_tokenised_text(0, &parameters)
+                } else {
+                    doc.to_text(0, &parameters)
+                };
+                match result {
+                    Ok(text) => print!("{}", text),
+                    Err(err) => eprintln!("{}",err)
+                }
+            },
+            Err(err) => eprintln!("{}",err)
+        }
+    }
+}
@@ -0,0 +1,44 @@
+extern crate clap;
+
+use std::process::exit;
+use clap::App;
+use folia::*;
+
+fn main() {
+    let argmatches = App::new("foliadiff")
+        .version("0.0.1")
+        .author("Maarten van Gompel (proycon) <proycon@anaproy.nl>")
+        .about("Compare two versions of a FoLiA document: reports added, removed and changed elements and annotations. Exits with status 1 if the documents differ.")
+        .arg(clap::Arg::with_name("old")
+            .help("The old version of the FoLiA document")
+            .required(true)
+        )
+        .arg(clap::Arg::with_name("new")
+            .help("The new version of the FoLiA document")
+            .required(true)
+        ).get_matches();
+
+    let load = |filename: &str| -> Document {
+        match Document::from_file(filename, DocumentProperties::default()) {
+            Ok(doc) => doc,
+            Err(err) => {
+                eprintln!("{}: {}", filename, err);
+                exit(2);
+            }
+        }
+    };
+    let old = load(argmatches.value_of("old").expect("old"));
+    let new = load(argmatches.value_of("new").expect("new"));
+    match old.diff(&new) {
+        Ok(diff) => {
+            print!("{}", diff);
+            if !diff.is_empty() {
+                exit(1);
+            }
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(2);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+extern crate clap;
+
+use std::fs;
+use std::str;
+use std::process::exit;
+use clap::App;
+use folia::*;
+
+fn main() {
+    let argmatches = App::new("foliaupgrade")
+        .version("0.0.1")
+        .author("Maarten van Gompel (proycon) <proycon@anaproy.nl>")
+        .about("Upgrade FoLiA v1 documents to FoLiA v2, written to standard output")
+        .arg(clap::Arg::with_name("inplace")
+            .help("Overwrite the input files rather than writing to standard output")
+            .long("inplace")
+            .short("i")
+        )
+        .arg(clap::Arg::with_name("file")
+            .help("FoLiA document to upgrade")
+            .multiple(true)
+            .required(true)
+        ).get_matches();
+
+    let inplace = argmatches.is_present("inplace");
+    let mut failed = false;
+    for filename in argmatches.values_of("file").expect("Expected one or more files") {
+        match Document::from_file(filename, DocumentProperties::default()) {
+            Ok(doc) => {
+                match doc.xml(0,4) {
+                    Ok(xml) if inplace => {
+                        if let Err(err) = fs::write(filename, &xml) {
+                            eprintln!("{}: {}", filename, err);
+                            failed = true;
+                        }
+                    },
+                    Ok(xml) => println!("{}",str::from_utf8(&xml).expect("valid utf-8")),
+                    Err(err) => {
+                        eprintln!("{}: {}", filename, err);
+                        failed = true;
+                    }
+                }
+            },
+            Err(err) => {
+                eprintln!("{}: {}", filename, err);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        exit(1);
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::{HashMap,HashSet};
+use std::fmt::Write;
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::attrib::*;
+use crate::query::*;
+use crate::select::*;
+use crate::text::*;
+use crate::document::*;
+use crate::conversion::*;
+
+///The FoLiA set definition for Universal Dependencies part-of-speech tags
+pub const UD_POS_SET: &str = "https://raw.githubusercontent.com/proycon/folia/master/setdefinitions/universal-pos.foliaset.ttl";
+///The FoLiA set definition for Universal Dependencies dependency relations
+pub const UD_DEPENDENCY_SET: &str = "https://raw.githubusercontent.com/proycon/folia/master/setdefinitions/universal-dependencies.foliaset.ttl";
+
+///The value CoNLL-U uses for empty fields
+const EMPTY: &str = "_";
+
+#[derive(Clone)]
+///Parameters for CoNLL-U conversion (``Document::to_conllu()`` and ``Document::from_conllu()``).
+///For export, a set of ``None`` matches any set; for import, it declares the annotation type without a set.
+pub struct ConlluParameters {
+    ///Determines which text is extracted for the FORM column (text set, text class)
+    pub textparameters: TextParameters,
+    ///The set for the UPOS column, features of this part-of-speech annotation constitute the FEATS column
+    pub uposset: Option<String>,
+    ///The set for the XPOS column, language-specific part-of-speech tags are only converted if this is set
+    pub xposset: Option<String>,
+    ///The set for the LEMMA column
+    pub lemmaset: Option<String>,
+    ///The set for the HEAD and DEPREL columns
+    pub dependencyset: Option<String>,
+}
+
+impl Default for ConlluParameters {
+    fn default() -> Self {
+        Self {
+            textparameters: TextParameters::default(),
+            uposset: Some(UD_POS_SET.to_string()),
+            xposset: None,
+            lemmaset: None,
+            dependencyset: Some(UD_DEPENDENCY_SET.to_string()),
+        }
+    }
+}
+
+impl ConlluParameters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_textparameters(mut self, textparameters: TextParameters) -> Self {
+        self.textparameters = textparameters;
+        self
+    }
+
+    pub fn with_uposset(mut self, set: Option<String>) -> Self {
+        self.uposset = set;
+        self
+    }
+
+    pub fn with_xposset(mut self, set: Option<String>) -> Self {
+        self.xposset = set;
+        self
+    }
+
+    pub fn with_lemmaset(mut self, set: Option<String>) -> Self {
+        self.lemmaset = set;
+        self
+    }
+
+    pub fn with_dependencyset(mut self, set: Option<String>) -> Self {
+        self.dependencyset = set;
+        self
+    }
+}
+
+///Turns an optional set into a comparison for querying
+pub(crate) fn set_cmp(set: &Option<String>) -> Cmp<String> {
+    match set {
+        Some(set) => Cmp::Is(set.clone()),
+        None => Cmp::Any,
+    }
+}
+
+///A single token line of a CoNLL-U sentence
+struct ConlluToken<'a> {
+    id: usize,
+    form: &'a str,
+    lemma: &'a str,
+    upos: &'a str,
+    xpos: &'a str,
+    feats: &'a str,
+    head: &'a str,
+    deprel: &'a str,
+    misc: &'a str,
+}
+
+impl<'a> ConlluToken<'a> {
+    ///Parses a token line, returns ``None`` for multi-word tokens and empty nodes as these are not
+    ///converted, they are recorded in the report instead.
+    fn parse(line: &'a str, linenr: usize, report: &mut ConversionReport) -> Result<Option<Self>,FoliaError> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 10 {
+            return Err(FoliaError::ParseError(format!("CoNLL-U line {} has {} fields, expected 10", linenr, fields.len())));
+        }
+        if fields[0].contains('-') {
+            report.add("multi-word token");
+            return Ok(None);
+        } else if fields[0].contains('.') {
+            report.add("empty node");
+            return Ok(None);
+        }
+        let id = fields[0].parse::<usize>().map_err(|_| FoliaError::ParseError(format!("CoNLL-U line {} has an invalid ID: {}", linenr, fields[0])))?;
+        Ok(Some(Self {
+            id,
+            form: fields[1],
+            lemma: fields[2],
+            upos: fields[3],
+            xpos: fields[4],
+            feats: fields[5],
+            head: fields[6],
+            deprel: fields[7],
+            misc: fields[9],
+        }))
+    }
+
+    fn space_after(&self) -> bool {
+        !self.misc.split('|').any(|item| item == "SpaceAfter=No")
+    }
+}
+
+impl Document {
+    ///Exports the document (or the subtree under ``root_key``) to CoNLL-U. Words map to token lines, part-of-speech, lemma and
+    ///dependency annotations (in the sets specified by the parameters) to their respective
+    ///columns, features of the UPOS annotation to FEATS and ``space="no"`` to ``SpaceAfter=No`` in MISC. HEAD and DEPREL are
+    ///left empty for words without a dependency (other than the root) and for dependencies on a head outside of the sentence.
+    pub fn to_conllu(&self, root_key: ElementKey, parameters: &ConlluParameters) -> Result<String,FoliaError> {
+        let mut output = String::new();
+        let mut paragraph_key: Option<ElementKey> = None;
+        let mut sentencenr = 0;
+        for sentence_key in self.sentence_keys(root_key) {
+            let words = self.word_keys(sentence_key);
+            if words.is_empty() {
+                continue;
+            }
+            sentencenr += 1;
+            let sentence = self.get_element(sentence_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", sentence_key)))?;
+
+            let paragraph = sentence.get_ancestor(ElementType::Paragraph, Cmp::Any);
+            if let Some(paragraph) = paragraph {
+                if paragraph.key() != paragraph_key {
+                    paragraph_key = paragraph.key();
+                    match paragraph.id() {
+                        Some(id) => writeln!(output, "# newpar id = {}", id),
+                        None => writeln!(output, "# newpar"),
+                    }?;
+                }
+            }
+            match sentence.id() {
+                Some(id) => writeln!(output, "# sent_id = {}", id),
+                None => writeln!(output, "# sent_id = {}", sentencenr),
+            }?;
+            if let Ok(text) = sentence.text(&parameters.textparameters) {
+                writeln!(output, "# text = {}", text.split_whitespace().collect::<Vec<&str>>().join(" "))?;
+            }
+
+            //maps word keys to their CoNLL-U index
+            let indices: HashMap<ElementKey,usize> = words.iter().enumerate().map(|(i, key)| (*key, i+1)).collect();
+            let dependencies: Vec<Vec<(Option<ElementKey>,String)>> = words.iter().map(|word_key| self.dependencies_by_word(*word_key, parameters)).collect();
+            //the root is the word with a dependency without head or, failing that, the only word without a dependency that
+            //is the head of others
+            let root: Option<ElementKey> = if dependencies.iter().flatten().any(|(head, _)| head.is_none()) {
+                None
+            } else {
+                let heads: HashSet<ElementKey> = dependencies.iter().flatten().filter_map(|(head, _)| *head).collect();
+                let candidates: Vec<ElementKey> = words.iter().zip(dependencies.iter())
+                                                       .filter(|(word_key, deps)| deps.is_empty() && heads.contains(word_key))
+                                                       .map(|(word_key, _)| *word_key).collect();
+                if candidates.len() == 1 { Some(candidates[0]) } else { None }
+            };
+
+            for (i, word_key) in words.iter().enumerate() {
+                let word = self.get_element(*word_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", word_key)))?;
+                let form = match word.text(&parameters.textparameters) {
+                    Ok(text) => text,
+                    Err(FoliaError::NoTextError(_)) => EMPTY.to_string(),
+                    Err(err) => return Err(err),
+                };
+                let lemma = word.get_annotation(AnnotationType::LEMMA, set_cmp(&parameters.lemmaset), Recursion::No);
+                let upos = word.get_annotation(AnnotationType::POS, set_cmp(&parameters.uposset), Recursion::No);
+                let xpos = if parameters.xposset.is_some() {
+                    word.get_annotation(AnnotationType::POS, set_cmp(&parameters.xposset), Recursion::No)
+                } else {
+                    None
+                };
+                let mut feats: Vec<String> = Vec::new();
+                if let Some(upos) = upos.as_ref() {
+                    for feature in upos.get_features(Cmp::Any) {
+                        if let (Some(subset), Some(class)) = (feature.subset(), feature.class()) {
+                            feats.push(format!("{}={}", subset, class));
+                        }
+                    }
+                }
+                feats.sort_by_key(|feat| feat.to_lowercase());
+
+                let (head, deprel) = match dependencies[i].first() {
+                    Some((Some(head_key), deprel)) => match indices.get(head_key) {
+                        Some(index) => (index.to_string(), deprel.clone()),
+                        //the head is outside of the sentence
+                        None => (EMPTY.to_string(), EMPTY.to_string()),
+                    },
+                    Some((None, deprel)) => ("0".to_string(), deprel.clone()),
+                    None if root == Some(*word_key) => ("0".to_string(), "root".to_string()),
+                    None => (EMPTY.to_string(), EMPTY.to_string()),
+                };
+                let misc = match word.attrib(AttribType::SPACE) {
+                    Some(Attribute::Space(false)) => "SpaceAfter=No",
+                    _ => EMPTY,
+                };
+                writeln!(output, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                         i+1,
+                         form,
+                         lemma.as_ref().and_then(|e| e.class()).unwrap_or(EMPTY),
+                         upos.as_ref().and_then(|e| e.class()).unwrap_or(EMPTY),
+                         xpos.as_ref().and_then(|e| e.class()).unwrap_or(EMPTY),
+                         if feats.is_empty() { EMPTY.to_string() } else { feats.join("|") },
+                         head,
+                         deprel,
+                         EMPTY,
+                         misc
+                )?;
+            }
+            output.push('\n');
+        }
+        Ok(output)
+    }
+
+    ///Returns the head and relation class for all dependencies in which the specified word is the dependent, the head is ``None``
+    ///for the root relation
+    fn dependencies_by_word(&self, word_key: ElementKey, parameters: &ConlluParameters) -> Vec<(Option<ElementKey>,String)> {
+        let mut result = Vec::new();
+        if let Some(word) = self.get_element(word_key) {
+            for dependency in word.get_annotations(AnnotationType::DEPENDENCY, set_cmp(&parameters.dependencyset), Recursion::No) {
+                let is_dependent = dependency.get_elements(ElementType::DependencyDependent, Cmp::Any, Recursion::No)
+                                             .any(|dep| dep.get_elements(ElementType::WordReference, Cmp::Any, Recursion::No)
+                                                           .any(|wref| wref.resolve().and_then(|w| w.key()) == Some(word_key)));
+                if is_dependent {
+                    match dependency.get_element(ElementType::Headspan, Cmp::Any, Recursion::No) {
+                        Some(hd) => {
+                            let head_key = hd.get_element(ElementType::WordReference, Cmp::Any, Recursion::No).and_then(|wref| wref.resolve().and_then(|head| head.key()));
+                            if let Some(head_key) = head_key {
+                                result.push((Some(head_key), dependency.class().unwrap_or(EMPTY).to_string()));
+                            }
+                        },
+                        None => result.push((None, dependency.class().unwrap_or(EMPTY).to_string())),
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    ///Builds a new document from CoNLL-U data. Sentences (and paragraphs if ``# newpar`` is used)
+    ///and words are created, along with part-of-speech annotations with features (FEATS), lemmas and dependency relations in the sets
+    ///specified by the parameters, which are declared explicitly. The IDs from ``# newpar id`` and ``# sent_id`` are used if they are
+    ///valid and unique, IDs are generated otherwise. The root relation becomes a dependency without head. Multi-word tokens and
+    ///empty nodes are not converted, they are reported in the returned ``ConversionReport``.
+    pub fn from_conllu(id: &str, data: &str, parameters: &ConlluParameters, properties: DocumentProperties) -> Result<(Self,ConversionReport), FoliaError> {
+        let mut report = ConversionReport::new();
+        let mut doc = Self::new(id, properties)?;
+        doc.declare(AnnotationType::TOKEN, &None, &None, &None)?;
+        doc.declare(AnnotationType::SENTENCE, &None, &None, &None)?;
+        doc.declare(AnnotationType::POS, &parameters.uposset, &None, &None)?;
+        doc.declare(AnnotationType::LEMMA, &parameters.lemmaset, &None, &None)?;
+        doc.declare(AnnotationType::DEPENDENCY, &parameters.dependencyset, &None, &None)?;
+        if parameters.xposset.is_some() {
+            doc.declare(AnnotationType::POS, &parameters.xposset, &None, &None)?;
+        }
+
+        let mut parent_key: ElementKey = 0;
+        let mut sentencenr = 0;
+        let mut paragraphnr = 0;
+        let mut text: Option<String> = None;
+        let mut sent_id: Option<String> = None;
+        let mut tokens: Vec<ConlluToken> = Vec::new();
+        for (linenr, line) in data.lines().chain(std::iter::once("")).enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() {
+                if !tokens.is_empty() {
+                    sentencenr += 1;
+                    let sentence_id = doc.conllu_id(sent_id.as_deref(), || format!("{}.s.{}", id, sentencenr));
+                    doc.add_conllu_sentence(parent_key, sentencenr, &sentence_id, text.take(), &tokens, parameters)?;
+                    tokens.clear();
+                }
+                text = None;
+                sent_id = None;
+            } else if let Some(comment) = line.strip_prefix('#') {
+                let comment = comment.trim();
+                if let Some(value) = comment.strip_prefix("newpar") {
+                    doc.declare(AnnotationType::PARAGRAPH, &None, &None, &None)?;
+                    paragraphnr += 1;
+                    let paragraph_id = doc.conllu_id(value.trim().strip_prefix("id =").map(|value| value.trim()), || format!("{}.p.{}", id, paragraphnr));
+                    parent_key = doc.annotate(0, ElementData::new(ElementType::Paragraph).with_attrib(Attribute::Id(paragraph_id)))?;
+                } else if let Some(value) = comment.strip_prefix("sent_id =") {
+                    sent_id = Some(value.trim().to_string());
+                } else if let Some(value) = comment.strip_prefix("text =") {
+                    text = Some(value.trim().to_string());
+                }
+            } else if let Some(token) = ConlluToken::parse(line, linenr + 1, &mut report)? {
+                tokens.push(token);
+            }
+        }
+        Ok((doc, report))
+    }
+
+    ///Returns the ID from a CoNLL-U comment if it is a valid XML ID that is not in use yet, or a generated one otherwise
+    fn conllu_id<F>(&self, id: Option<&str>, generate: F) -> String
+    where F: FnOnce() -> String {
+        match id {
+            Some(id) if is_xml_id(id) && self.get_element_key_by_id(id).is_none() => id.to_string(),
+            _ => generate(),
+        }
+    }
+
+    ///Adds a sentence from CoNLL-U tokens
+    fn add_conllu_sentence(&mut self, parent_key: ElementKey, sentencenr: usize, sentence_id: &str, text: Option<String>, tokens: &[ConlluToken], parameters: &ConlluParameters) -> Result<ElementKey,FoliaError> {
+        let mut sentence = ElementData::new(ElementType::Sentence).with_attrib(Attribute::Id(sentence_id.to_string()));
+        if let Some(text) = text {
+            sentence = sentence.with_text(text);
+        }
+        let sentence_key = self.annotate(parent_key, sentence)?;
+
+        let mut word_ids: HashMap<usize,String> = HashMap::new();
+        for token in tokens.iter() {
+            let word_id = format!("{}.w.{}", sentence_id, token.id);
+            let mut word = ElementData::new(ElementType::Word)
+                                .with_attrib(Attribute::Id(word_id.clone()))
+                                .with_text(token.form.to_string());
+            if !token.space_after() {
+                word = word.with_attrib(Attribute::Space(false));
+            }
+            if token.lemma != EMPTY {
+                let mut lemma = ElementData::new(ElementType::LemmaAnnotation).with_attrib(Attribute::Class(token.lemma.to_string()));
+                if let Some(set) = &parameters.lemmaset {
+                    lemma = lemma.with_attrib(Attribute::Set(set.clone()));
+                }
+                word = word.add_element(lemma);
+            }
+            if token.xpos != EMPTY {
+                if let Some(set) = &parameters.xposset {
+                    word = word.add_element(ElementData::new(ElementType::PosAnnotation)
+                                            .with_attrib(Attribute::Set(set.clone()))
+                                            .with_attrib(Attribute::Class(token.xpos.to_string())));
+                }
+            }
+            let word_key = self.annotate(sentence_key, word)?;
+
+            if token.upos != EMPTY {
+                let mut pos = ElementData::new(ElementType::PosAnnotation).with_attrib(Attribute::Class(token.upos.to_string()));
+                if let Some(set) = &parameters.uposset {
+                    pos = pos.with_attrib(Attribute::Set(set.clone()));
+                }
+                let pos_key = self.annotate(word_key, pos)?;
+                if token.feats != EMPTY {
+                    //features are added separately as they need the part-of-speech annotation as their context
+                    for feat in token.feats.split('|') {
+                        if let Some(index) = feat.find('=') {
+                            let (subset, class) = feat.split_at(index);
+                            self.add_element_to(pos_key, ElementData::new(ElementType::Feature)
+                                                    .with_attrib(Attribute::Subset(subset.to_string()))
+                                                    .with_attrib(Attribute::Class(class[1..].to_string())))?;
+                        }
+                    }
+                }
+            }
+            word_ids.insert(token.id, word_id);
+        }
+
+        for token in tokens.iter() {
+            if token.head == EMPTY {
+                continue;
+            }
+            let mut dependency = ElementData::new(ElementType::Dependency).with_attrib(Attribute::Class(token.deprel.to_string()));
+            //the root relation has no head
+            if token.head != "0" {
+                let head = token.head.parse::<usize>().ok().and_then(|head| word_ids.get(&head))
+                                .ok_or_else(|| FoliaError::ParseError(format!("CoNLL-U token {} in sentence {} has an invalid head: {}", token.id, sentencenr, token.head)))?;
+                dependency = dependency.add_element(ElementData::new(ElementType::Headspan).with_span(&[head.as_str()]));
+            }
+            dependency = dependency.add_element(ElementData::new(ElementType::DependencyDependent).with_span(&[word_ids[&token.id].as_str()]));
+            if let Some(set) = &parameters.dependencyset {
+                dependency = dependency.with_attrib(Attribute::Set(set.clone()));
+            }
+            self.annotate(sentence_key, dependency)?;
+        }
+        Ok(sentence_key)
+    }
+}
+
+///Checks whether the string is usable as an XML ID (an NCName, limited to ASCII)
+fn is_xml_id(id: &str) -> bool {
+    let mut chars = id.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.'),
+        _ => false,
+    }
+}
@@ -0,0 +1,40 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug,Clone,Default,PartialEq)]
+///Reports content that could not be mapped when converting from or to another format (TEI, NAF),
+///rather than silently dropping it. Unmapped content is identified by a label (usually the element
+///or layer name) and counted.
+pub struct ConversionReport {
+    pub unmapped: BTreeMap<String,usize>,
+}
+
+impl ConversionReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Records an occurrence of unmapped content
+    pub fn add(&mut self, label: &str) {
+        *self.unmapped.entry(label.to_string()).or_insert(0) += 1;
+    }
+
+    ///Returns how often the specified content was not mapped
+    pub fn count(&self, label: &str) -> usize {
+        self.unmapped.get(label).copied().unwrap_or(0)
+    }
+
+    ///Returns true if all content was mapped
+    pub fn is_empty(&self) -> bool {
+        self.unmapped.is_empty()
+    }
+}
+
+impl fmt::Display for ConversionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (label, count) in self.unmapped.iter() {
+            writeln!(f, "unmapped: {} ({}x)", label, count)?;
+        }
+        Ok(())
+    }
+}
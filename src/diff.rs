@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap,HashSet};
+use std::fmt;
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::attrib::*;
+use crate::metadata::*;
+use crate::select::*;
+use crate::subdocument::*;
+use crate::document::Document;
+
+#[derive(Debug,Clone,PartialEq)]
+///A single difference between two documents. Elements are identified by their ID, annotations
+///without an ID by the ID of the nearest element with an ID (``id``).
+pub enum Change {
+    ///An element that only exists in the new document
+    Added { id: String, elementtype: ElementType },
+    ///An element that only exists in the old document
+    Removed { id: String, elementtype: ElementType },
+    ///An element with the same ID is of a different type
+    TypeChanged { id: String, old: ElementType, new: ElementType },
+    ///An element was moved to another parent (identified by the ID of the nearest ancestor with an ID)
+    Moved { id: String, old: Option<String>, new: Option<String> },
+    ///An attribute (other than the class) changed
+    AttributeChanged { id: String, attribute: String, old: Option<String>, new: Option<String> },
+    ///The class of an element or annotation changed
+    ClassChanged { id: String, elementtype: ElementType, set: Option<String>, old: Option<String>, new: Option<String> },
+    ///The text content (of the specified class) changed
+    TextChanged { id: String, textclass: Option<String>, old: String, new: String },
+    ///The elements spanned by a span annotation changed
+    SpanChanged { id: String, elementtype: ElementType, old: Vec<String>, new: Vec<String> },
+    ///An annotation without ID was added
+    AnnotationAdded { id: String, elementtype: ElementType, set: Option<String>, class: Option<String> },
+    ///An annotation without ID was removed
+    AnnotationRemoved { id: String, elementtype: ElementType, set: Option<String>, class: Option<String> },
+    DeclarationAdded { annotationtype: AnnotationType, set: Option<String> },
+    DeclarationRemoved { annotationtype: AnnotationType, set: Option<String> },
+    ProcessorAdded { id: String },
+    ProcessorRemoved { id: String },
+    ///A processor with the same ID has a different name, type or version
+    ProcessorChanged { id: String },
+    MetadataChanged { key: String, old: Option<String>, new: Option<String> },
+}
+
+fn show(value: &Option<String>) -> &str {
+    value.as_deref().unwrap_or("(none)")
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Added { id, elementtype } => write!(f, "+ {} {}", elementtype, id),
+            Change::Removed { id, elementtype } => write!(f, "- {} {}", elementtype, id),
+            Change::TypeChanged { id, old, new } => write!(f, "~ {}: type {} -> {}", id, old, new),
+            Change::Moved { id, old, new } => write!(f, "~ {}: moved from {} to {}", id, show(old), show(new)),
+            Change::AttributeChanged { id, attribute, old, new } => write!(f, "~ {}: @{} {} -> {}", id, attribute, show(old), show(new)),
+            Change::ClassChanged { id, elementtype, set, old, new } => write!(f, "~ {}: {} ({}) class {} -> {}", id, elementtype, show(set), show(old), show(new)),
+            Change::TextChanged { id, textclass: Some(textclass), old, new } => write!(f, "~ {}: text ({}) \"{}\" -> \"{}\"", id, textclass, old, new),
+            Change::TextChanged { id, textclass: None, old, new } => write!(f, "~ {}: text \"{}\" -> \"{}\"", id, old, new),
+            Change::SpanChanged { id, elementtype, old, new } => write!(f, "~ {}: {} span {} -> {}", id, elementtype, old.join(" "), new.join(" ")),
+            Change::AnnotationAdded { id, elementtype, set, class } => write!(f, "+ {}: {} ({}) class {}", id, elementtype, show(set), show(class)),
+            Change::AnnotationRemoved { id, elementtype, set, class } => write!(f, "- {}: {} ({}) class {}", id, elementtype, show(set), show(class)),
+            Change::DeclarationAdded { annotationtype, set } => write!(f, "+ declaration {} ({})", annotationtype, show(set)),
+            Change::DeclarationRemoved { annotationtype, set } => write!(f, "- declaration {} ({})", annotationtype, show(set)),
+            Change::ProcessorAdded { id } => write!(f, "+ processor {}", id),
+            Change::ProcessorRemoved { id } => write!(f, "- processor {}", id),
+            Change::ProcessorChanged { id } => write!(f, "~ processor {}", id),
+            Change::MetadataChanged { key, old, new } => write!(f, "~ metadata {}: {} -> {}", key, show(old), show(new)),
+        }
+    }
+}
+
+#[derive(Debug,Clone,Default,PartialEq)]
+///The differences between two documents, as a list of changes
+pub struct DocumentDiff {
+    pub changes: Vec<Change>,
+}
+
+impl DocumentDiff {
+    ///Returns true if the documents do not differ
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for DocumentDiff {
+    ///Human-readable report, one change per line
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in self.changes.iter() {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+impl Document {
+    ///Compares this (old) document to another (new) version of it. Elements are compared by ID,
+    ///annotations without ID (e.g. token annotations and text content) by type and set within the element
+    ///they belong to. Declarations, provenance and metadata are compared as well.
+    pub fn diff(&self, new: &Document) -> Result<DocumentDiff, FoliaError> {
+        let mut changes: Vec<Change> = Vec::new();
+
+        for (key, value) in self.metadata.data.iter().collect::<BTreeMap<_,_>>() {
+            let newvalue = new.metadata.data.get(key);
+            if newvalue != Some(value) {
+                changes.push(Change::MetadataChanged { key: key.clone(), old: Some(value.clone()), new: newvalue.cloned() });
+            }
+        }
+        for (key, value) in new.metadata.data.iter().collect::<BTreeMap<_,_>>() {
+            if !self.metadata.data.contains_key(key) {
+                changes.push(Change::MetadataChanged { key: key.clone(), old: None, new: Some(value.clone()) });
+            }
+        }
+
+        for declaration in self.declarations().flatten() {
+            if new.get_declaration_by_id(&Declaration::index_id(declaration.annotationtype, &declaration.set.as_deref())).is_none() {
+                changes.push(Change::DeclarationRemoved { annotationtype: declaration.annotationtype, set: declaration.set.clone() });
+            }
+        }
+        for declaration in new.declarations().flatten() {
+            if self.get_declaration_by_id(&Declaration::index_id(declaration.annotationtype, &declaration.set.as_deref())).is_none() {
+                changes.push(Change::DeclarationAdded { annotationtype: declaration.annotationtype, set: declaration.set.clone() });
+            }
+        }
+
+        for processor in self.processors() {
+            if let Some(newprocessor) = new.get_processor_by_id(&processor.id) {
+                if processor.name != newprocessor.name || processor.processortype != newprocessor.processortype || processor.version != newprocessor.version {
+                    changes.push(Change::ProcessorChanged { id: processor.id.clone() });
+                }
+            } else {
+                changes.push(Change::ProcessorRemoved { id: processor.id.clone() });
+            }
+        }
+        for processor in new.processors() {
+            if self.get_processor_by_id(&processor.id).is_none() {
+                changes.push(Change::ProcessorAdded { id: processor.id.clone() });
+            }
+        }
+
+        let mut seen: HashSet<String> = HashSet::new();
+        for key in self.identified_keys() {
+            let id = self.get_elementdata(key).and_then(|e| e.id()).expect("element has ID").to_string();
+            let elementtype = self.get_elementdata(key).expect("element").elementtype;
+            if let Some(newkey) = new.get_element_key_by_id(&id) {
+                seen.insert(id.clone());
+                let newelementtype = new.get_elementdata(newkey).expect("element").elementtype;
+                if elementtype != newelementtype {
+                    changes.push(Change::TypeChanged { id, old: elementtype, new: newelementtype });
+                    continue;
+                }
+                let oldparent = self.get_elementdata(key).and_then(|e| e.parent_key()).and_then(|k| self.nearest_id(k));
+                let newparent = new.get_elementdata(newkey).and_then(|e| e.parent_key()).and_then(|k| new.nearest_id(k));
+                if oldparent != newparent {
+                    changes.push(Change::Moved { id: id.clone(), old: oldparent, new: newparent });
+                }
+                self.diff_element(new, key, newkey, &id, &mut changes);
+                self.diff_children(new, key, newkey, &mut changes);
+            } else {
+                changes.push(Change::Removed { id, elementtype });
+            }
+        }
+        for key in new.identified_keys() {
+            let element = new.get_elementdata(key).expect("element");
+            let id = element.id().expect("element has ID");
+            if !seen.contains(id) {
+                changes.push(Change::Added { id: id.to_string(), elementtype: element.elementtype });
+            }
+        }
+        Ok(DocumentDiff { changes })
+    }
+
+    ///Returns the keys of all elements with an ID, in document order
+    fn identified_keys(&self) -> Vec<ElementKey> {
+        let mut keys: Vec<ElementKey> = Vec::new();
+        for item in self.select_data_by_key(0, Selector::elements(), Recursion::Always, true, false) {
+            if let DataType::Element(key) = item.data {
+                if self.get_elementdata(*key).and_then(|e| e.id()).is_some() {
+                    keys.push(*key);
+                }
+            }
+        }
+        keys
+    }
+
+    ///Compares the attributes, class and span of two elements
+    fn diff_element(&self, new: &Document, key: ElementKey, newkey: ElementKey, id: &str, changes: &mut Vec<Change>) {
+        let oldattribs = diff_attributes(self, key);
+        let newattribs = diff_attributes(new, newkey);
+        for (attribute, value) in oldattribs.iter() {
+            if newattribs.get(attribute) != Some(value) {
+                changes.push(Change::AttributeChanged { id: id.to_string(), attribute: attribute.to_string(), old: Some(value.clone()), new: newattribs.get(attribute).cloned() });
+            }
+        }
+        for (attribute, value) in newattribs.iter() {
+            if !oldattribs.contains_key(attribute) {
+                changes.push(Change::AttributeChanged { id: id.to_string(), attribute: attribute.to_string(), old: None, new: Some(value.clone()) });
+            }
+        }
+        let (elementtype, set, _, _) = element_signature(self, key);
+        let oldclass = self.get_element(key).and_then(|e| e.class().map(|s| s.to_string()));
+        let newclass = new.get_element(newkey).and_then(|e| e.class().map(|s| s.to_string()));
+        if oldclass != newclass {
+            changes.push(Change::ClassChanged { id: id.to_string(), elementtype, set, old: oldclass, new: newclass });
+        }
+        if ElementGroup::Span.contains(elementtype) {
+            let oldspan = self.span_ids(key);
+            let newspan = new.span_ids(newkey);
+            if oldspan != newspan {
+                changes.push(Change::SpanChanged { id: id.to_string(), elementtype, old: oldspan, new: newspan });
+            }
+        }
+    }
+
+    ///Compares the children without ID of two corresponding elements. They are paired by type,
+    ///set, subset and span (and class, for text content).
+    fn diff_children(&self, new: &Document, key: ElementKey, newkey: ElementKey, changes: &mut Vec<Change>) {
+        let oldchildren = self.unidentified_children(key);
+        let mut newchildren: Vec<Option<ElementKey>> = new.unidentified_children(newkey).into_iter().map(Some).collect();
+        for oldchild in oldchildren {
+            let signature = diff_signature(self, oldchild);
+            let counterpart = newchildren.iter_mut().find(|newchild| newchild.map(|newchild| diff_signature(new, newchild)) == Some(signature.clone()));
+            let id = self.nearest_id(oldchild).unwrap_or_default();
+            let (elementtype, set, _, _) = element_signature(self, oldchild);
+            let oldclass = self.get_element(oldchild).and_then(|e| e.class().map(|s| s.to_string()));
+            if let Some(counterpart) = counterpart {
+                let newchild = counterpart.take().expect("unwrapping counterpart");
+                let props = self.props(elementtype);
+                if props.textcontainer || props.phoncontainer {
+                    let oldtext = self.raw_text(oldchild);
+                    let newtext = new.raw_text(newchild);
+                    if oldtext != newtext {
+                        changes.push(Change::TextChanged { id, textclass: oldclass, old: oldtext, new: newtext });
+                    }
+                } else {
+                    let newclass = new.get_element(newchild).and_then(|e| e.class().map(|s| s.to_string()));
+                    if oldclass != newclass {
+                        changes.push(Change::ClassChanged { id, elementtype, set, old: oldclass, new: newclass });
+                    }
+                    self.diff_children(new, oldchild, newchild, changes);
+                }
+            } else {
+                changes.push(Change::AnnotationRemoved { id, elementtype, set, class: oldclass });
+            }
+        }
+        for newchild in newchildren.into_iter().flatten() {
+            let (elementtype, set, _, _) = element_signature(new, newchild);
+            let class = new.get_element(newchild).and_then(|e| e.class().map(|s| s.to_string()));
+            changes.push(Change::AnnotationAdded { id: new.nearest_id(newchild).unwrap_or_default(), elementtype, set, class });
+        }
+    }
+
+    ///Returns the child elements that have no ID (word references are covered by their span and excluded)
+    fn unidentified_children(&self, key: ElementKey) -> Vec<ElementKey> {
+        let mut children: Vec<ElementKey> = Vec::new();
+        if let Some(element) = self.get_elementdata(key) {
+            for item in element.data.iter() {
+                if let DataType::Element(child_key) = item {
+                    if let Some(child) = self.get_elementdata(*child_key) {
+                        if child.id().is_none() && child.elementtype != ElementType::WordReference {
+                            children.push(*child_key);
+                        }
+                    }
+                }
+            }
+        }
+        children
+    }
+}
+
+///Returns the signature by which children without ID are paired
+fn diff_signature(document: &Document, key: ElementKey) -> (ElementSignature, Vec<String>, Option<String>) {
+    let signature = element_signature(document, key);
+    let props = document.props(signature.0);
+    let class = if props.textcontainer || props.phoncontainer {
+        document.get_element(key).and_then(|e| e.class().map(|s| s.to_string()))
+    } else {
+        None
+    };
+    (signature, document.span_ids(key), class)
+}
+
+///Returns the attributes of an element (except ID and class) by name
+fn diff_attributes(document: &Document, key: ElementKey) -> BTreeMap<&'static str,String> {
+    let mut attributes: BTreeMap<&'static str,String> = BTreeMap::new();
+    if let Some(element) = document.get_element(key) {
+        for attrib in element.attribs().iter() {
+            if *attrib != Attribute::Ignore && !attrib.decodable() && attrib.attribtype() != AttribType::ID {
+                attributes.insert(attrib.attribtype().into(), format!("{}", attrib));
+            }
+        }
+        if element.elementtype() != ElementType::Feature {
+            if let Some(set) = element.set() {
+                attributes.insert("set", set.to_string());
+            }
+        }
+        if let Some(subset) = element.subset() {
+            attributes.insert("subset", subset.to_string());
+        }
+        if let Some(processor) = element.processor() {
+            attributes.insert("processor", processor.to_string());
+        }
+    }
+    attributes
+}
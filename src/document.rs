@@ -14,6 +14,8 @@ use quick_xml::events::{Event,BytesStart,BytesEnd,BytesText};
 
 use crate::common::*;
 use crate::types::*;
+use crate::transaction::*;
+use crate::journal::*;
 use crate::error::*;
 use crate::element::*;
 use crate::attrib::*;
@@ -47,6 +49,11 @@ pub struct Document {
     pub active_processor: Option<ProcKey>,
 
     pub autodeclare: bool,
+
+    ///The active transaction and the undo/redo stacks
+    pub(crate) history: History,
+    ///The journal of edits, if enabled
+    pub(crate) journal: Option<Journal>,
 }
 
 
@@ -57,6 +64,8 @@ pub struct DocumentProperties {
     pub autodeclare: bool,
     pub declare: Vec<(AnnotationType,Option<String>)>,
     pub processor: Option<Processor>,
+    ///Load external metadata referenced by a local ``src`` file when loading a document from file
+    pub load_external_metadata: bool,
 }
 
 impl Default for DocumentProperties {
@@ -66,6 +75,7 @@ impl Default for DocumentProperties {
             autodeclare: true,
             declare: vec![(AnnotationType::TEXT, Some(DEFAULT_TEXT_SET.to_string()) )],
             processor: None,
+            load_external_metadata: false,
         }
     }
 }
@@ -87,6 +97,10 @@ impl DocumentProperties {
         self.processor = Some(processor);
         self
     }
+    pub fn with_load_external_metadata(mut self, value: bool) -> DocumentProperties {
+        self.load_external_metadata = value;
+        self
+    }
 }
 
 impl Document {
@@ -102,6 +116,8 @@ impl Document {
             metadata: Metadata::default(),
             submetadata: HashMap::default(),
             autodeclare: properties.autodeclare,
+            history: History::default(),
+            journal: None,
             active_processor: None,
         };
         let mut body = match properties.bodytype {
@@ -135,9 +151,13 @@ impl Document {
     pub fn from_file(filename: &str, properties: DocumentProperties) -> Result<Self, FoliaError> {
         let mut reader = Reader::from_file(Path::new(filename))?;
         reader.trim_text(false);
+        let load_external_metadata = properties.load_external_metadata;
         let mut doc = Self::parse(&mut reader, properties)?;
         //associate the filename with the document
         doc.filename = Some(filename.to_string());
+        if load_external_metadata {
+            doc.load_external_metadata()?;
+        }
         Ok(doc)
     }
 
@@ -172,8 +192,12 @@ impl Document {
     ///Add an element to the document (but the element will be an orphan unless it is the very
     ///first one, you may want to use ``add_element_to`` or ``annotate`` instead)
     pub fn add_element(&mut self, element: ElementData) -> Result<ElementKey, FoliaError> {
-        let (element,_) = self.add_children(element)?;
-        <Self as Store<ElementData,ElementKey>>::add(self, element, None)
+        let (element,added_subelements) = self.add_children(element)?;
+        let key = <Self as Store<ElementData,ElementKey>>::add(self, element, None)?;
+        if let Some(added_subelements) = added_subelements {
+            self.adopt_subelements(key, &added_subelements);
+        }
+        Ok(key)
     }
 
     ///Add a declaration. It is strongly recommended to use ``declare()`` instead
@@ -199,15 +223,17 @@ impl Document {
         let (element,added_subelements) = self.add_children(element)?;
         match <Self as Store<ElementData,ElementKey>>::add(self, element, Some(parent_key)) {
             Ok(child_key) => {
+                if let Some(added_subelements) = added_subelements.as_ref() {
+                    self.adopt_subelements(child_key, added_subelements);
+                }
+                self.attach_element(parent_key, child_key)?;
+                //postprocessing is deferred until the whole subtree is attached, as it may need to
+                //inspect ancestors (e.g. wref needs to find its span)
                 if let Some(added_subelements) = added_subelements {
                     for subchild_key in added_subelements.iter() {
-                        if let Some(subchilddata) = self.get_mut_elementdata(*subchild_key) {
-                            subchilddata.set_parent_key(Some(child_key));
-                        }
                         self.post_add(*subchild_key, None)?;
                     }
                 }
-                self.attach_element(parent_key, child_key)?;
                 self.post_add(child_key, None)?;
                 Ok(child_key)
             },
@@ -218,6 +244,9 @@ impl Document {
     }
 
     ///Before we can add an element, we need to create and add its hitherto 'unborn' children.
+    ///Returns the element (referring to the added children by key) and the keys of all
+    ///descendants that were added. Only the direct children are still without a parent, it is up
+    ///to the caller to set it and to do the postprocessing (``post_add()``).
     pub(crate) fn add_children(&mut self, mut element: ElementData) -> Result<(ElementData, Option<Vec<ElementKey>>),FoliaError> {
         let mut has_unborn_children = false;
         for child in element.data.iter() {
@@ -231,27 +260,14 @@ impl Document {
             for child in element.data {
                 if let DataType::AddElement(child_elementdata) = child {
                     //first we do a recursion step to add the grandchildren, if any
-                    let child_key = match self.add_children(child_elementdata) {
-                        Ok((child_elementdata_new, Some(added_grandchildren))) => {
-                            let child_key = <Self as Store<ElementData,ElementKey>>::add(self, child_elementdata_new, None)?;
-                            for grandchild_key in added_grandchildren {
-                                if let Some(subchilddata) = self.get_mut_elementdata(grandchild_key) {
-                                    subchilddata.set_parent_key(Some(child_key));
-                                }
-                                self.post_add(grandchild_key, None)?;
-                            }
-                            child_key
-                        },
-                        Ok((child_elementdata_new, None)) => {
-                            let child_key = <Self as Store<ElementData,ElementKey>>::add(self, child_elementdata_new, None)?;
-                            child_key
-                        }
-                        Err(e) => {
-                            return Err(e);
-                        }
-                    };
+                    let (child_elementdata_new, added_grandchildren) = self.add_children(child_elementdata)?;
+                    let child_key = <Self as Store<ElementData,ElementKey>>::add(self, child_elementdata_new, None)?;
                     new_data.push(DataType::Element(child_key));
                     added_elements.push(child_key);
+                    if let Some(added_grandchildren) = added_grandchildren {
+                        self.adopt_subelements(child_key, &added_grandchildren);
+                        added_elements.extend(added_grandchildren);
+                    }
                 } else {
                     new_data.push(child);
                 }
@@ -263,6 +279,17 @@ impl Document {
         }
     }
 
+    ///Sets the parent of all parentless elements amongst the specified subelements
+    fn adopt_subelements(&mut self, parent_key: ElementKey, subelements: &[ElementKey]) {
+        for subchild_key in subelements.iter() {
+            if let Some(subchilddata) = self.get_mut_elementdata(*subchild_key) {
+                if subchilddata.parent_key().is_none() {
+                    subchilddata.set_parent_key(Some(parent_key));
+                }
+            }
+        }
+    }
+
     ///Adds the child element to the parent element, automatically takes care
     ///of removing the old parent (if any).
     pub fn attach_element(&mut self, parent_key: ElementKey, child_key: ElementKey) -> Result<(),FoliaError> {
@@ -398,6 +425,12 @@ impl Document {
     ///Add an element to the provenance chain
     ///Returns the key
     pub fn add_processor(&mut self, processor: Processor) -> Result<ProcKey, FoliaError> {
+        if self.journaling() {
+            let recorded = to_json_value(&processor)?;
+            let child_key = self.unjournaled(|doc| doc.add_processor(processor))?;
+            self.record(Edit::AddProcessor { parent: None, processor: recorded }, vec!());
+            return Ok(child_key);
+        }
         let pending_subprocessors: Vec<Processor> = processor.pending_processors.replace(vec!());
         let child_key = self.add(processor, None);
         if let Ok(child_key) = child_key {
@@ -412,6 +445,13 @@ impl Document {
     ///Add a processor as a subprocessor
     ///Returns the key
     pub fn add_subprocessor(&mut self, parent_key: ProcKey, processor: Processor) -> Result<ProcKey, FoliaError> {
+        if self.journaling() {
+            let parent = self.get_processor(parent_key).map(|parent| parent.id.clone());
+            let recorded = to_json_value(&processor)?;
+            let child_key = self.unjournaled(|doc| doc.add_subprocessor(parent_key, processor))?;
+            self.record(Edit::AddProcessor { parent, processor: recorded }, vec!());
+            return Ok(child_key);
+        }
         let pending_subprocessors: Vec<Processor> = processor.pending_processors.replace(vec!());
         let child_key = self.add(processor, None);
         if let Ok(child_key) = child_key {
@@ -485,6 +525,7 @@ impl Document {
                 Ok(k) => { declaration_key = Some(k) },
                 Err(e) => return Err(e)
             }
+            self.record(Edit::Declare { annotationtype: annotationtype.as_str().to_string(), set: set.clone(), alias: alias.clone(), format: format.clone() }, vec!());
         }
         if let Some(prockey) = self.active_processor {
             if let Some(declaration) = self.get_mut_declaration(declaration_key.expect("get deckey")) {
@@ -500,6 +541,18 @@ impl Document {
 
     ///This is a high-level function that adds an annotation to an element, and does all necessary validation. It will simply call `add_element_to` for token annotation elements that fit within the scope and validate. For span annotation, it will create and find or create the proper annotation layer and insert the element there.
     pub fn annotate(&mut self, parent_key: ElementKey, element: ElementData) -> Result<ElementKey, FoliaError> {
+        if !self.journaling() {
+            return self.add_annotation(parent_key, element);
+        }
+        let parent = self.locate(parent_key)?;
+        let recorded = to_json_value(&element)?;
+        let key = self.unjournaled(|doc| doc.add_annotation(parent_key, element))?;
+        let ids = self.element_ids(key)?;
+        self.record(Edit::Annotate { parent, element: recorded }, ids);
+        Ok(key)
+    }
+
+    fn add_annotation(&mut self, parent_key: ElementKey, element: ElementData) -> Result<ElementKey, FoliaError> {
         let parent = self.get_element(parent_key).ok_or(
             FoliaError::InternalError(format!("Specified element key not found: {:?}", parent_key))
         )?;
@@ -562,6 +615,7 @@ impl Document {
                             };
                             self.check_element_addable(layer_key.unwrap() , &element)?;
                             //we only did one iteration, taking the closest common ancestor
+                            let element = self.with_generated_ids(layer_key.unwrap(), element);
                             return self.add_element_to(layer_key.unwrap(), element);
                         }
                     }
@@ -583,15 +637,18 @@ impl Document {
                     }
                 };
                 self.check_element_addable(layer_key.unwrap(), &element)?;
+                let element = self.with_generated_ids(layer_key.unwrap(), element);
                 self.add_element_to(layer_key.unwrap(), element)
             } else {
                 //normal behaviour
                 self.check_element_addable(parent_key, &element)?;
+                let element = self.with_generated_ids(parent_key, element);
                 self.add_element_to(parent_key, element)
             }
         } else {
             //normal behaviour
             self.check_element_addable(parent_key, &element)?;
+            let element = self.with_generated_ids(parent_key, element);
             self.add_element_to(parent_key, element)
         }
     }
@@ -779,6 +836,12 @@ impl Store<ElementData,ElementKey> for Document {
         self.elementstore.items.iter()
     }
 
+    ///Retrieves an item from the store for editing, recording its prior state if a transaction is active
+    fn get_mut(&mut self, key: ElementKey) -> Option<&mut ElementData> {
+        self.record_element(key);
+        self.elementstore.items.get_mut(key as usize).and_then(|item| item.as_deref_mut())
+    }
+
     ///Actively encode element for storage, this encodes attributes that need to be encoded (such as set,class,processor), and adds them to their respective stores.
     ///It does not handle relations between elements (data/children and parent)
     ///nor does it add the element itself to the store
@@ -825,7 +888,12 @@ impl Store<ElementData,ElementKey> for Document {
                         let parent = self.get_elementdata(parent_key).ok_or( FoliaError::InternalError("Context for feature does not exist!".to_string()))?;
 
                         let annotationtype = parent.elementtype.annotationtype().expect(format!("Unwrapping annotation type of parent {}", element.elementtype).as_str() );
-                        let deckey = self.declare(annotationtype, &element.set().unwrap().map(|s| s.to_string()),  &None, &None)?;
+                        //features inherit the declaration of their parent unless a set is
+                        //explicitly provided
+                        let deckey = match (element.set().unwrap(), parent.declaration_key()) {
+                            (None, Ok(Some(parent_deckey))) => parent_deckey,
+                            (set, _) => self.declare(annotationtype, &set.map(|s| s.to_string()),  &None, &None)?
+                        };
                         declaration_key  = Some(deckey);
 
                         if let Some(declaration) = self.get_mut_declaration(deckey) {
@@ -909,6 +977,12 @@ impl Store<Declaration,DecKey> for Document {
     fn iter(&self) -> std::slice::Iter<Option<Box<Declaration>>> {
         self.declarationstore.items.iter()
     }
+
+    ///Retrieves an item from the store for editing, recording its prior state if a transaction is active
+    fn get_mut(&mut self, key: DecKey) -> Option<&mut Declaration> {
+        self.record_declaration(key);
+        self.declarationstore.items.get_mut(key as usize).and_then(|item| item.as_deref_mut())
+    }
 }
 
 impl Store<Processor,ProcKey> for Document {
@@ -929,4 +1003,10 @@ impl Store<Processor,ProcKey> for Document {
     fn iter(&self) -> std::slice::Iter<Option<Box<Processor>>> {
         self.provenancestore.items.iter()
     }
+
+    ///Retrieves an item from the store for editing, recording its prior state if a transaction is active
+    fn get_mut(&mut self, key: ProcKey) -> Option<&mut Processor> {
+        self.record_processor(key);
+        self.provenancestore.items.get_mut(key as usize).and_then(|item| item.as_deref_mut())
+    }
 }
@@ -0,0 +1,321 @@
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::attrib::*;
+use crate::store::*;
+use crate::metadata::*;
+use crate::select::*;
+use crate::query::*;
+use crate::journal::*;
+use crate::document::Document;
+
+impl Document {
+    ///Sets the class of an element, or removes it if ``None`` is passed. The class is encoded against the
+    ///declaration of the element.
+    pub fn set_class(&mut self, key: ElementKey, class: Option<&str>) -> Result<(), FoliaError> {
+        let before = self.before_edit(key, |doc| Ok(doc.get_element(key).and_then(|e| e.class().map(|class| class.to_string()))))?;
+        self.replace_attrib(key, AttribType::CLASS, class.map(|class| Attribute::Class(class.to_string())))?;
+        if let Some((element, before)) = before {
+            self.record(Edit::SetClass { element, before, after: class.map(|class| class.to_string()) }, vec!());
+        }
+        Ok(())
+    }
+
+    ///Sets the set of an element, or removes it if ``None`` is passed, moving the element to the
+    ///declaration for that set. The declaration is added if needed and ``autodeclare`` is enabled.
+    pub fn set_set(&mut self, key: ElementKey, set: Option<&str>) -> Result<(), FoliaError> {
+        let before = self.before_edit(key, |doc| Ok(doc.get_element(key).and_then(|e| e.set().map(|set| set.to_string()))))?;
+        self.replace_attrib(key, AttribType::SET, set.map(|set| Attribute::Set(set.to_string())))?;
+        if let Some((element, before)) = before {
+            self.record(Edit::SetSet { element, before, after: set.map(|set| set.to_string()) }, vec!());
+        }
+        Ok(())
+    }
+
+    ///Sets the processor (by ID) of an element, the processor must exist
+    pub fn set_processor(&mut self, key: ElementKey, processor_id: &str) -> Result<(), FoliaError> {
+        if self.get_processor_key_by_id(processor_id).is_none() {
+            return Err(FoliaError::KeyError(format!("No such processor: {}", processor_id)));
+        }
+        let before = self.before_edit(key, |doc| Ok(doc.get_element(key).and_then(|e| e.processor().map(|processor| processor.to_string()))))?;
+        self.replace_attrib(key, AttribType::PROCESSOR, Some(Attribute::Processor(processor_id.to_string())))?;
+        if let Some((element, before)) = before {
+            self.record(Edit::SetProcessor { element, before, after: processor_id.to_string() }, vec!());
+        }
+        Ok(())
+    }
+
+    ///Sets the confidence (a value between 0 and 1) of an element, or removes it if ``None`` is passed
+    pub fn set_confidence(&mut self, key: ElementKey, confidence: Option<f64>) -> Result<(), FoliaError> {
+        if let Some(confidence) = confidence {
+            if !(0.0..=1.0).contains(&confidence) {
+                return Err(FoliaError::ValidationError(format!("Confidence must be between 0 and 1, got {}", confidence)));
+            }
+        }
+        let before = self.before_edit(key, |doc| Ok(doc.get_elementdata(key).and_then(|e| e.confidence())))?;
+        let element = self.get_mut_elementdata(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+        match confidence {
+            Some(confidence) => element.set_attrib(Attribute::Confidence(confidence)),
+            None => { element.del_attrib(AttribType::CONFIDENCE); },
+        }
+        if let Some((element, before)) = before {
+            self.record(Edit::SetConfidence { element, before, after: confidence }, vec!());
+        }
+        Ok(())
+    }
+
+    ///Sets the text content of an element for the specified text class (``current`` if ``None``), replacing
+    ///any existing text content of that class, including any markup in it. Returns the key of the text content element.
+    pub fn set_text(&mut self, key: ElementKey, text: &str, textclass: Option<&str>) -> Result<ElementKey, FoliaError> {
+        let before = self.before_edit(key, |doc| {
+            match doc.find_textcontent(key, textclass.unwrap_or("current"))? {
+                Some(textcontent_key) => Ok(doc.element_json(textcontent_key)?.get("text").and_then(|text| text.as_str()).map(|text| text.to_string())),
+                None => Ok(None)
+            }
+        })?;
+        let textcontent_key = self.unjournaled(|doc| doc.replace_text(key, text, textclass))?;
+        if let Some((element, before)) = before {
+            self.record(Edit::SetText { element, textclass: textclass.map(|textclass| textclass.to_string()), before, after: text.to_string() }, vec!());
+        }
+        Ok(textcontent_key)
+    }
+
+    fn replace_text(&mut self, key: ElementKey, text: &str, textclass: Option<&str>) -> Result<ElementKey, FoliaError> {
+        let textclass = textclass.unwrap_or("current");
+        if let Some(textcontent_key) = self.find_textcontent(key, textclass)? {
+            let markup: Vec<ElementKey> = self.get_elementdata(textcontent_key).expect("unwrapping text content").data.iter().filter_map(|item| {
+                if let DataType::Element(child_key) = item { Some(*child_key) } else { None }
+            }).collect();
+            for child_key in markup {
+                self.remove_element(child_key)?;
+            }
+            let textcontent = self.get_mut_elementdata(textcontent_key).expect("unwrapping text content");
+            textcontent.data = vec!(DataType::Text(text.to_string()));
+            Ok(textcontent_key)
+        } else {
+            self.check_declared(AnnotationType::TEXT, None)?;
+            let mut textcontent = ElementData::new(ElementType::TextContent).with(DataType::Text(text.to_string()));
+            if textclass != "current" {
+                textcontent.set_attrib(Attribute::Class(textclass.to_string()));
+            }
+            self.annotate(key, textcontent)
+        }
+    }
+
+    ///Returns the key of the text content element of the specified class directly under the element, if any
+    fn find_textcontent(&self, key: ElementKey, textclass: &str) -> Result<Option<ElementKey>, FoliaError> {
+        let element = self.get_element(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+        for item in element.elementdata().data.iter() {
+            if let DataType::Element(child_key) = item {
+                if let Some(child) = self.get_element(*child_key) {
+                    if child.elementtype() == ElementType::TextContent && child.class().unwrap_or("current") == textclass {
+                        return Ok(Some(*child_key));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    ///Replaces an annotation by a new one, at the same position. The old annotation, and everything
+    ///under it, is removed. Returns the key of the new annotation.
+    pub fn replace_annotation(&mut self, key: ElementKey, element: ElementData) -> Result<ElementKey, FoliaError> {
+        if !self.journaling() {
+            return self.replace_element(key, element);
+        }
+        let element_locator = self.locate(key)?;
+        let before = self.element_json(key)?;
+        let after = to_json_value(&element)?;
+        let new_key = self.unjournaled(|doc| doc.replace_element(key, element))?;
+        let ids = self.element_ids(new_key)?;
+        self.record(Edit::ReplaceAnnotation { element: element_locator, before, after }, ids);
+        Ok(new_key)
+    }
+
+    fn replace_element(&mut self, key: ElementKey, element: ElementData) -> Result<ElementKey, FoliaError> {
+        let parent_key = self.get_elementdata(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?
+                             .parent_key().ok_or_else(|| FoliaError::ValidationError("Unable to replace an element without parent".to_string()))?;
+        if let Some(annotationtype) = element.elementtype.annotationtype() {
+            self.check_declared(annotationtype, element.set()?)?;
+        }
+        let new_key = self.annotate(parent_key, element)?;
+        //move the new annotation to the position of the old one, if it ended up under the same parent
+        if let Some(parent) = self.get_mut_elementdata(parent_key) {
+            if let (Some(index), Some(new_index)) = (parent.index(&DataType::Element(key)), parent.index(&DataType::Element(new_key))) {
+                let item = parent.remove(new_index).expect("unwrapping new annotation");
+                parent.data.insert(index, item);
+            }
+        }
+        self.remove_element(key)?;
+        Ok(new_key)
+    }
+
+    ///Removes an element, and everything under it, from the document entirely
+    pub fn remove_element(&mut self, key: ElementKey) -> Result<(), FoliaError> {
+        if key == 0 {
+            return Err(FoliaError::ValidationError("Unable to remove the body".to_string()));
+        }
+        if self.journaling() {
+            let element = self.locate(key)?;
+            let before = self.element_json(key)?;
+            self.unjournaled(|doc| doc.remove_element(key))?;
+            self.record(Edit::RemoveElement { element, before }, vec!());
+            return Ok(());
+        }
+        let keys: Vec<ElementKey> = self.select_data_by_key(key, Selector::elements(), Recursion::Always, true, false).filter_map(|item| {
+            if let DataType::Element(subkey) = item.data { Some(*subkey) } else { None }
+        }).collect();
+        let (dangling, backpointers) = self.span_references(&keys);
+        self.detach_element(key)?;
+        for subkey in keys.iter() {
+            if let Some(id) = self.get_elementdata(*subkey).and_then(|e| e.id()).map(|id| id.to_string()) {
+                <Self as Store<ElementData,ElementKey>>::index_mut(self).remove(&id);
+            }
+            self.record_element(*subkey);
+            if let Some(item) = self.elementstore.items.get_mut(*subkey as usize) {
+                *item = None;
+            }
+        }
+        //remove the back-pointers to spans that no longer cover the target
+        for (target_key, span_key) in backpointers {
+            if !self.span_covers(span_key, target_key) {
+                if let Some(target) = self.get_mut_elementdata(target_key) {
+                    target.data.retain(|item| *item != DataType::SpanReference(span_key));
+                }
+            }
+        }
+        //remove the word references to removed elements, and any span annotations (and layers) left empty
+        for wref_key in dangling {
+            if let Some(mut candidate) = self.get_elementdata(wref_key).and_then(|wref| wref.parent_key()) {
+                self.remove_element(wref_key)?;
+                while let Some(element) = self.get_elementdata(candidate) {
+                    let empty = if ElementGroup::Layer.contains(element.elementtype) {
+                        !element.data.iter().any(|item| matches!(item, DataType::Element(_)))
+                    } else if ElementGroup::Span.contains(element.elementtype) || ElementGroup::SpanRole.contains(element.elementtype) {
+                        !self.has_wrefs(candidate)
+                    } else {
+                        false
+                    };
+                    if !empty {
+                        break;
+                    }
+                    let parent_key = element.parent_key();
+                    self.remove_element(candidate)?;
+                    match parent_key {
+                        Some(parent_key) => candidate = parent_key,
+                        None => break,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    ///Finds the span references affected by the removal of the specified elements. Returns the word references (outside
+    ///of the removed elements) that refer to removed elements, and the (target, span) pairs of the span back-pointers
+    ///held by elements that are referred to by removed word references.
+    fn span_references(&self, keys: &[ElementKey]) -> (Vec<ElementKey>, Vec<(ElementKey,ElementKey)>) {
+        let mut dangling: Vec<ElementKey> = Vec::new();
+        let mut backpointers: Vec<(ElementKey,ElementKey)> = Vec::new();
+        for subkey in keys.iter() {
+            let element = if let Some(element) = self.get_elementdata(*subkey) { element } else { continue };
+            if let Some(id) = element.id() {
+                for item in element.data.iter() {
+                    if let DataType::SpanReference(span_key) = item {
+                        if !keys.contains(span_key) {
+                            for wref_key in self.select_keys_by_key(*span_key, Selector::elements().element(Cmp::Is(ElementType::WordReference))) {
+                                if self.get_elementdata(wref_key).and_then(|wref| wref.idref()) == Some(id) && !dangling.contains(&wref_key) {
+                                    dangling.push(wref_key);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            if element.elementtype == ElementType::WordReference {
+                let target_key = element.idref().and_then(|idref| self.get_element_key_by_id(idref));
+                let span_key = self.ancestors_by_key(*subkey, Selector::elements().elementgroup(Cmp::Is(ElementGroup::Span))).next().and_then(|span| span.element.key());
+                if let (Some(target_key), Some(span_key)) = (target_key, span_key) {
+                    if !keys.contains(&target_key) {
+                        backpointers.push((target_key, span_key));
+                    }
+                }
+            }
+        }
+        (dangling, backpointers)
+    }
+
+    ///Returns the keys of all elements under the specified element matching the selector
+    fn select_keys_by_key(&self, key: ElementKey, selector: Selector) -> Vec<ElementKey> {
+        self.select_data_by_key(key, selector, Recursion::Always, false, false).filter_map(|item| {
+            if let DataType::Element(subkey) = item.data { Some(*subkey) } else { None }
+        }).collect()
+    }
+
+    ///Checks whether the span still exists and refers to the target element through a word reference
+    fn span_covers(&self, span_key: ElementKey, target_key: ElementKey) -> bool {
+        match (self.get_elementdata(span_key), self.get_elementdata(target_key).and_then(|target| target.id())) {
+            (Some(_), Some(id)) => self.select_keys_by_key(span_key, Selector::elements().element(Cmp::Is(ElementType::WordReference))).into_iter()
+                                       .any(|wref_key| self.get_elementdata(wref_key).and_then(|wref| wref.idref()) == Some(id)),
+            _ => false,
+        }
+    }
+
+    ///Checks whether there are any word references under the element
+    fn has_wrefs(&self, key: ElementKey) -> bool {
+        !self.select_keys_by_key(key, Selector::elements().element(Cmp::Is(ElementType::WordReference))).is_empty()
+    }
+
+    ///Returns a copy of the attributes of an element, with set, subset, class and processor in decoded form
+    pub(crate) fn decoded_attributes(&self, key: ElementKey) -> Result<Vec<Attribute>, FoliaError> {
+        let element = self.get_element(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+        let mut attribs: Vec<Attribute> = element.attribs().iter().filter(|attrib| **attrib != Attribute::Ignore && !attrib.decodable()).cloned().collect();
+        if element.elementtype() != ElementType::Feature {
+            if let Some(set) = element.set() {
+                attribs.push(Attribute::Set(set.to_string()));
+            }
+        }
+        if let Some(subset) = element.subset() {
+            attribs.push(Attribute::Subset(subset.to_string()));
+        }
+        if let Some(class) = element.class() {
+            attribs.push(Attribute::Class(class.to_string()));
+        }
+        if let Some(processor) = element.processor() {
+            attribs.push(Attribute::Processor(processor.to_string()));
+        }
+        Ok(attribs)
+    }
+
+    ///Replaces (or removes if ``None``) the attribute of the specified type and re-encodes the element
+    fn replace_attrib(&mut self, key: ElementKey, attribtype: AttribType, attrib: Option<Attribute>) -> Result<(), FoliaError> {
+        let mut attribs = self.decoded_attributes(key)?;
+        attribs.retain(|a| a.attribtype() != attribtype);
+        attribs.extend(attrib);
+        self.reencode(key, attribs)
+    }
+
+    ///Encodes the (decoded) attributes against the stores and assigns them to the element
+    fn reencode(&mut self, key: ElementKey, attribs: Vec<Attribute>) -> Result<(), FoliaError> {
+        let element = self.get_elementdata(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+        let parent_key = element.parent_key();
+        let decoded = ElementData::new(element.elementtype).with_attribs(attribs);
+        if let Some(annotationtype) = decoded.elementtype.annotationtype() {
+            self.check_declared(annotationtype, decoded.set()?)?;
+        }
+        let encoded = self.encode(decoded, parent_key)?;
+        self.get_mut_elementdata(key).expect("unwrapping element").set_attribs(encoded.attribs);
+        Ok(())
+    }
+
+    ///Checks whether a declaration exists for the annotation type and set, which is required if ``autodeclare`` is disabled
+    pub(crate) fn check_declared(&self, annotationtype: AnnotationType, set: Option<&str>) -> Result<(), FoliaError> {
+        if self.autodeclare || self.get_declaration_key_by_id(&Declaration::index_id(annotationtype, &set)).is_some()
+           || (set.is_none() && self.declarationstore.get_default_key(annotationtype).is_some()) {
+            Ok(())
+        } else {
+            Err(FoliaError::EncodeError(format!("No declaration for {} with set {} and autodeclare is disabled", annotationtype, set.unwrap_or("(none)"))))
+        }
+    }
+}
@@ -444,7 +444,7 @@ pub trait ReadElement {
                 0 => Writer::new(Cursor::new(Vec::new())),
                 indent =>  Writer::new_with_indent(Cursor::new(Vec::new()), b' ', indent)
             };
-            doc.xml_elements(&mut writer, self.key().unwrap())?;
+            doc.xml_elements(&mut writer, self.key().unwrap(), false)?;
             let result = writer.into_inner().into_inner();
             let result = from_utf8(&result).expect("encoding utf-8");
             Ok(result.to_string())
@@ -28,6 +28,8 @@ pub enum FoliaError {
     QueryError(String),
     TypeError(String),
     NoTextError(String),
+    ///Snapshot errors occur when a binary snapshot is invalid or outdated
+    SnapshotError(String),
     IndexError,
 }
 
@@ -43,6 +45,7 @@ impl From<FoliaError> for String {
             FoliaError::InternalError(err) |
             FoliaError::EncodeError(err) |
             FoliaError::NoTextError(err) |
+            FoliaError::SnapshotError(err) |
             FoliaError::QueryError(err) |
             FoliaError::TypeError(err) |
             FoliaError::KeyError(err) => {
@@ -78,6 +81,18 @@ impl From<quick_xml::Error> for FoliaError {
     }
 }
 
+///Errors from writing to a string only occur during serialisation
+impl From<fmt::Error> for FoliaError {
+    fn from(err: fmt::Error) -> FoliaError {
+        FoliaError::SerialisationError(format!("{}",err))
+    }
+}
+
+///Turns an XML writer error into a serialisation error (a plain conversion yields an ``XmlError``, which is meant for parsing)
+pub(crate) fn to_serialisation_error(err: quick_xml::Error) -> FoliaError {
+    FoliaError::SerialisationError(format!("{}",err))
+}
+
 impl FoliaError {
     fn as_str(&self) -> &str {
         match *self {
@@ -93,6 +108,7 @@ impl FoliaError {
             FoliaError::QueryError(ref _err) => "Query Error",
             FoliaError::TypeError(ref _err) => "Type Error",
             FoliaError::NoTextError(ref _err) => "No Text Error",
+            FoliaError::SnapshotError(ref _err) => "Snapshot Error",
             FoliaError::IndexError => "invalid index",
         }
     }
@@ -114,6 +130,7 @@ impl Error for FoliaError {
             FoliaError::QueryError(ref _err) => None,
             FoliaError::TypeError(ref _err) => None,
             FoliaError::NoTextError(ref _err) => None,
+            FoliaError::SnapshotError(ref _err) => None,
             FoliaError::IndexError => None,
         }
     }
@@ -131,6 +148,7 @@ impl fmt::Display for FoliaError {
             FoliaError::InternalError(ref err) |
             FoliaError::EncodeError(ref err) |
             FoliaError::NoTextError(ref err) |
+            FoliaError::SnapshotError(ref err) |
             FoliaError::QueryError(ref err) |
             FoliaError::TypeError(ref err) |
             FoliaError::KeyError(ref err) => {
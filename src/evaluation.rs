@@ -0,0 +1,180 @@
+use std::collections::{BTreeMap,HashMap};
+use std::fmt;
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::select::*;
+use crate::query::*;
+use crate::agreement::*;
+use crate::document::Document;
+
+#[derive(Debug,Clone,Default,PartialEq,Serialize)]
+///Precision, recall and F1 score, along with the counts they are computed from
+pub struct Scores {
+    pub truepositives: usize,
+    pub falsepositives: usize,
+    pub falsenegatives: usize,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+}
+
+impl Scores {
+    fn new(truepositives: usize, falsepositives: usize, falsenegatives: usize) -> Self {
+        let predicted = truepositives + falsepositives;
+        let reference = truepositives + falsenegatives;
+        Self {
+            truepositives,
+            falsepositives,
+            falsenegatives,
+            precision: if predicted > 0 { truepositives as f64 / predicted as f64 } else { 0.0 },
+            recall: if reference > 0 { truepositives as f64 / reference as f64 } else { 0.0 },
+            f1: f1_score(truepositives, predicted, reference),
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Serialize)]
+///Evaluation of a single annotation type and set
+pub struct Evaluation {
+    pub annotationtype: AnnotationType,
+    pub set: Option<String>,
+    ///Is this a span annotation? Spans are only correct if they have the right class and span exactly the right elements.
+    pub span: bool,
+    ///Overall (micro-averaged) scores
+    pub scores: Scores,
+    ///Scores per class
+    pub classes: BTreeMap<String,Scores>,
+    ///Confusion matrix for token annotations, maps the gold class to the predicted classes and their
+    ///counts. Only covers elements annotated in both documents. Empty for span annotations.
+    pub confusion: BTreeMap<String,BTreeMap<String,usize>>,
+}
+
+#[derive(Debug,Clone,Default,PartialEq,Serialize)]
+///Evaluation of a document against a gold standard document, per annotation type and set
+pub struct EvaluationReport {
+    pub evaluations: Vec<Evaluation>,
+}
+
+impl EvaluationReport {
+    ///Returns the evaluation for the specified annotation type and set
+    pub fn get(&self, annotationtype: AnnotationType, set: Option<&str>) -> Option<&Evaluation> {
+        self.evaluations.iter().find(|evaluation| evaluation.annotationtype == annotationtype && evaluation.set.as_deref() == set)
+    }
+
+    ///Serialises the report to JSON
+    pub fn json(&self, pretty: bool) -> Result<String, FoliaError> {
+        if pretty {
+            serde_json::to_string_pretty(self)
+        } else {
+            serde_json::to_string(self)
+        }.map_err(|e| FoliaError::SerialisationError(format!("{}", e)))
+    }
+}
+
+impl fmt::Display for EvaluationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for evaluation in self.evaluations.iter() {
+            let set = evaluation.set.as_deref().unwrap_or("no set");
+            writeln!(f, "{}\t{}\t(all)\t{:.3}\t{:.3}\t{:.3}", evaluation.annotationtype, set, evaluation.scores.precision, evaluation.scores.recall, evaluation.scores.f1)?;
+            for (class, scores) in evaluation.classes.iter() {
+                writeln!(f, "{}\t{}\t{}\t{:.3}\t{:.3}\t{:.3}", evaluation.annotationtype, set, class, scores.precision, scores.recall, scores.f1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+///Keeps counts per class
+#[derive(Default)]
+struct Counts {
+    truepositives: usize,
+    falsepositives: usize,
+    falsenegatives: usize,
+}
+
+fn scores(counts: &HashMap<String,Counts>) -> (Scores, BTreeMap<String,Scores>) {
+    let classes: BTreeMap<String,Scores> = counts.iter().map(|(class, c)| (class.clone(), Scores::new(c.truepositives, c.falsepositives, c.falsenegatives))).collect();
+    let overall = Scores::new(counts.values().map(|c| c.truepositives).sum(), counts.values().map(|c| c.falsepositives).sum(), counts.values().map(|c| c.falsenegatives).sum());
+    (overall, classes)
+}
+
+impl Document {
+    ///Evaluates the annotations in this document against a gold standard document with the same
+    ///tokenisation (word IDs). Token annotations are compared per element, span annotations by
+    ///the elements they span. Returns precision, recall and F1 per annotation type, set and class.
+    pub fn evaluate(&self, gold: &Document) -> Result<EvaluationReport, FoliaError> {
+        if self.word_ids() != gold.word_ids() {
+            return Err(FoliaError::ValidationError("Unable to evaluate, the tokenisation differs from the gold standard".to_string()));
+        }
+        let system = AnnotationSample::new(self, None);
+        let gold = AnnotationSample::new(gold, None);
+        let mut report = EvaluationReport::default();
+
+        let empty_tokens: HashMap<String,String> = HashMap::new();
+        for group in groups(&gold.tokens, &system.tokens) {
+            let goldtokens = gold.tokens.get(&group).unwrap_or(&empty_tokens);
+            let systemtokens = system.tokens.get(&group).unwrap_or(&empty_tokens);
+            let mut counts: HashMap<String,Counts> = HashMap::new();
+            let mut confusion: BTreeMap<String,BTreeMap<String,usize>> = BTreeMap::new();
+            for (id, goldclass) in goldtokens.iter() {
+                match systemtokens.get(id) {
+                    Some(systemclass) => {
+                        *confusion.entry(goldclass.clone()).or_default().entry(systemclass.clone()).or_insert(0) += 1;
+                        if systemclass == goldclass {
+                            counts.entry(goldclass.clone()).or_default().truepositives += 1;
+                        } else {
+                            counts.entry(goldclass.clone()).or_default().falsenegatives += 1;
+                            counts.entry(systemclass.clone()).or_default().falsepositives += 1;
+                        }
+                    },
+                    None => counts.entry(goldclass.clone()).or_default().falsenegatives += 1,
+                }
+            }
+            for (id, systemclass) in systemtokens.iter() {
+                if !goldtokens.contains_key(id) {
+                    counts.entry(systemclass.clone()).or_default().falsepositives += 1;
+                }
+            }
+            let (scores, classes) = scores(&counts);
+            report.evaluations.push(Evaluation { annotationtype: group.0, set: group.1, span: false, scores, classes, confusion });
+        }
+
+        let empty_spans: Vec<(String,Vec<String>)> = Vec::new();
+        for group in groups(&gold.spans, &system.spans) {
+            let goldspans = gold.spans.get(&group).unwrap_or(&empty_spans);
+            let systemspans = system.spans.get(&group).unwrap_or(&empty_spans);
+            let mut counts: HashMap<String,Counts> = HashMap::new();
+            let mut used: Vec<bool> = vec![false; systemspans.len()];
+            for goldspan in goldspans.iter() {
+                if let Some(i) = (0..systemspans.len()).find(|i| !used[*i] && systemspans[*i] == *goldspan) {
+                    used[i] = true;
+                    counts.entry(goldspan.0.clone()).or_default().truepositives += 1;
+                } else {
+                    counts.entry(goldspan.0.clone()).or_default().falsenegatives += 1;
+                }
+            }
+            for (i, (class, _)) in systemspans.iter().enumerate() {
+                if !used[i] {
+                    counts.entry(class.clone()).or_default().falsepositives += 1;
+                }
+            }
+            let (scores, classes) = scores(&counts);
+            report.evaluations.push(Evaluation { annotationtype: group.0, set: group.1, span: true, scores, classes, confusion: BTreeMap::new() });
+        }
+        Ok(report)
+    }
+
+    ///Returns the IDs of all words, in document order
+    fn word_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = Vec::new();
+        for item in self.select_data(Selector::elements().element(Cmp::Is(ElementType::Word)), Recursion::Always, false) {
+            if let DataType::Element(key) = item.data {
+                ids.push(self.get_elementdata(*key).and_then(|e| e.id()).unwrap_or("").to_string());
+            }
+        }
+        ids
+    }
+}
@@ -0,0 +1,218 @@
+//! Foreign metadata: metadata in another XML format (e.g. CMDI, IMDI or Dublin Core), embedded in ``foreign-data``
+//! blocks or referenced as an external file through ``src``. Foreign metadata is kept as raw XML so it survives
+//! processing unchanged, typed accessors are provided for the common Dublin Core fields.
+
+use std::fs;
+use std::path::{Path,PathBuf};
+
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+use crate::error::*;
+use crate::metadata::*;
+use crate::document::Document;
+
+pub const NSCMDI: &[u8] = b"http://www.clarin.eu/cmd/";
+pub const NSIMDI: &[u8] = b"http://www.mpi.nl/IMDI/Schema/IMDI";
+pub const NSDC: &[u8] = b"http://purl.org/dc/elements/1.1/";
+pub const NSDCTERMS: &[u8] = b"http://purl.org/dc/terms/";
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+///The format of foreign metadata, as determined by the namespace of its root element
+pub enum ForeignFormat {
+    CMDI,
+    IMDI,
+    DublinCore,
+    Other,
+}
+
+#[derive(Debug,Clone,PartialEq,Default)]
+///A block of foreign metadata, holding the raw XML of the contents of a ``foreign-data`` element
+pub struct ForeignData {
+    pub xml: String,
+}
+
+impl ForeignData {
+    pub fn new(xml: &str) -> Self {
+        Self { xml: xml.to_string() }
+    }
+
+    ///Returns the namespace of the first (root) element
+    pub fn namespace(&self) -> Option<String> {
+        let mut reader = Reader::from_str(&self.xml);
+        let mut buf = Vec::new();
+        let mut nsbuf = Vec::new();
+        loop {
+            match reader.read_namespaced_event(&mut buf, &mut nsbuf) {
+                Ok((ns, Event::Start(_))) | Ok((ns, Event::Empty(_))) => return ns.map(|ns| String::from_utf8_lossy(ns).to_string()),
+                Ok((_, Event::Eof)) | Err(_) => return None,
+                _ => {}
+            }
+            buf.clear();
+        }
+    }
+
+    ///Determines the format of the foreign metadata
+    pub fn format(&self) -> ForeignFormat {
+        match self.namespace() {
+            Some(ns) if ns.as_bytes().starts_with(NSCMDI) => ForeignFormat::CMDI,
+            Some(ns) if ns.as_bytes() == NSIMDI => ForeignFormat::IMDI,
+            Some(ns) if ns.as_bytes() == NSDC || ns.as_bytes() == NSDCTERMS || ns.starts_with("http://www.openarchives.org/OAI/2.0/oai_dc") => ForeignFormat::DublinCore,
+            _ => ForeignFormat::Other,
+        }
+    }
+
+    ///Collects all Dublin Core elements anywhere in the foreign metadata, as (field, value) pairs in document order
+    fn dublin_core_fields(&self) -> Result<Vec<(String,String)>, FoliaError> {
+        let mut fields: Vec<(String,String)> = Vec::new();
+        let mut reader = Reader::from_str(&self.xml);
+        reader.trim_text(true);
+        let mut buf = Vec::new();
+        let mut nsbuf = Vec::new();
+        let mut field: Option<String> = None;
+        let mut value = String::new();
+        loop {
+            match reader.read_namespaced_event(&mut buf, &mut nsbuf)? {
+                (Some(ns), Event::Start(ref e)) if ns == NSDC || ns == NSDCTERMS => {
+                    field = Some(String::from_utf8_lossy(e.local_name()).to_string());
+                    value.clear();
+                },
+                (_, Event::Text(ref e)) | (_, Event::CData(ref e)) if field.is_some() => {
+                    value.push_str(&e.unescape_and_decode(&reader)?);
+                },
+                (Some(ns), Event::End(ref e)) if ns == NSDC || ns == NSDCTERMS => {
+                    if let Some(field) = field.take() {
+                        if field.as_bytes() == e.local_name() {
+                            fields.push((field, value.clone()));
+                        }
+                    }
+                },
+                (_, Event::Eof) => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        Ok(fields)
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Default)]
+///The fifteen elements of the Dublin Core Metadata Element Set, all of which may occur multiple times
+pub struct DublinCore {
+    pub title: Vec<String>,
+    pub creator: Vec<String>,
+    pub subject: Vec<String>,
+    pub description: Vec<String>,
+    pub publisher: Vec<String>,
+    pub contributor: Vec<String>,
+    pub date: Vec<String>,
+    pub dctype: Vec<String>,
+    pub format: Vec<String>,
+    pub identifier: Vec<String>,
+    pub source: Vec<String>,
+    pub language: Vec<String>,
+    pub relation: Vec<String>,
+    pub coverage: Vec<String>,
+    pub rights: Vec<String>,
+}
+
+impl DublinCore {
+    ///Adds a value for a Dublin Core field, returns false if the field is not one of the fifteen core elements
+    pub fn add(&mut self, field: &str, value: &str) -> bool {
+        let values = match field {
+            "title" => &mut self.title,
+            "creator" => &mut self.creator,
+            "subject" => &mut self.subject,
+            "description" => &mut self.description,
+            "publisher" => &mut self.publisher,
+            "contributor" => &mut self.contributor,
+            "date" => &mut self.date,
+            "type" => &mut self.dctype,
+            "format" => &mut self.format,
+            "identifier" => &mut self.identifier,
+            "source" => &mut self.source,
+            "language" => &mut self.language,
+            "relation" => &mut self.relation,
+            "coverage" => &mut self.coverage,
+            "rights" => &mut self.rights,
+            _ => return false,
+        };
+        values.push(value.to_string());
+        true
+    }
+
+    ///Returns the first title
+    pub fn title(&self) -> Option<&str> { self.title.first().map(String::as_str) }
+    ///Returns the first creator
+    pub fn creator(&self) -> Option<&str> { self.creator.first().map(String::as_str) }
+    ///Returns the first date
+    pub fn date(&self) -> Option<&str> { self.date.first().map(String::as_str) }
+    ///Returns the first language
+    pub fn language(&self) -> Option<&str> { self.language.first().map(String::as_str) }
+    ///Returns the first identifier
+    pub fn identifier(&self) -> Option<&str> { self.identifier.first().map(String::as_str) }
+}
+
+impl Metadata {
+    ///Adds a block of foreign metadata
+    pub fn with_foreign(mut self, foreign: ForeignData) -> Self {
+        self.foreign.push(foreign);
+        self
+    }
+
+    ///Returns the first block of foreign metadata (embedded or loaded from an external source) of the specified format
+    pub fn foreign_by_format(&self, format: ForeignFormat) -> Option<&ForeignData> {
+        self.foreign.iter().chain(self.external.iter()).find(|foreign| foreign.format() == format)
+    }
+
+    ///Collects the Dublin Core fields from all foreign metadata (embedded or loaded from an external source), this also
+    ///finds Dublin Core elements embedded in other formats such as CMDI. Returns ``None`` if there are none.
+    pub fn dublin_core(&self) -> Result<Option<DublinCore>, FoliaError> {
+        let mut dublincore = DublinCore::default();
+        let mut found = false;
+        for foreign in self.foreign.iter().chain(self.external.iter()) {
+            for (field, value) in foreign.dublin_core_fields()? {
+                found |= dublincore.add(&field, &value);
+            }
+        }
+        Ok(if found { Some(dublincore) } else { None })
+    }
+
+    ///Loads the external metadata referenced by ``src`` if it is a local file, relative paths are resolved against the
+    ///specified directory. Remote sources are left alone. Returns whether anything was loaded. The loaded metadata is
+    ///only used for lookups, it is never serialised as the document still refers to it through ``src``.
+    pub fn load_external(&mut self, basedir: Option<&Path>) -> Result<bool, FoliaError> {
+        let path: PathBuf = match &self.src {
+            Some(src) if src.starts_with("file://") => PathBuf::from(&src["file://".len()..]),
+            Some(src) if src.contains("://") => return Ok(false),
+            Some(src) => PathBuf::from(src),
+            None => return Ok(false),
+        };
+        let path = match basedir {
+            Some(basedir) if path.is_relative() => basedir.join(path),
+            _ => path,
+        };
+        let xml = fs::read_to_string(&path)?;
+        //strip the XML declaration, the metadata is treated as a fragment just like embedded foreign data
+        let xml = match (xml.trim_start().starts_with("<?xml"), xml.find("?>")) {
+            (true, Some(end)) => xml[end + 2..].trim().to_string(),
+            _ => xml.trim().to_string(),
+        };
+        self.external = Some(ForeignData { xml });
+        Ok(true)
+    }
+}
+
+impl Document {
+    ///Loads the external metadata of the document and of all submetadata, for those that refer to a local file through
+    ///``src``. Relative paths are resolved against the directory of the document. This is done automatically when the
+    ///document is loaded with ``DocumentProperties.load_external_metadata`` set.
+    pub fn load_external_metadata(&mut self) -> Result<(), FoliaError> {
+        let basedir: Option<PathBuf> = self.filename.as_ref().and_then(|filename| Path::new(filename).parent().map(|dir| dir.to_path_buf()));
+        self.metadata.load_external(basedir.as_deref())?;
+        for submetadata in self.submetadata.values_mut() {
+            submetadata.load_external(basedir.as_deref())?;
+        }
+        Ok(())
+    }
+}
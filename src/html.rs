@@ -0,0 +1,330 @@
+use std::fmt::Write;
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::attrib::*;
+use crate::query::*;
+use crate::select::*;
+use crate::text::*;
+use crate::metadata::*;
+use crate::plaintext::*;
+use crate::document::*;
+
+///Maps FoLiA structure elements to HTML tags, all other structure elements become a ``span``
+const HTML_TAGS: &[(ElementType,&str)] = &[(ElementType::Division, "div"), (ElementType::Head, "h2"), (ElementType::Paragraph, "p"),
+                                           (ElementType::List, "ul"), (ElementType::ListItem, "li"), (ElementType::Figure, "figure"),
+                                           (ElementType::Caption, "figcaption"), (ElementType::Quote, "q"), (ElementType::Table, "table"),
+                                           (ElementType::TableHead, "thead"), (ElementType::Row, "tr"), (ElementType::Cell, "td"),
+                                           (ElementType::Linebreak, "br"), (ElementType::Event, "div"), (ElementType::Utterance, "div"),
+                                           (ElementType::Entry, "div"), (ElementType::Example, "div")];
+
+///Returns the HTML tag for a structure element
+fn html_tag(elementtype: ElementType) -> &'static str {
+    HTML_TAGS.iter().find(|(t, _)| *t == elementtype).map(|(_, tag)| *tag).unwrap_or("span")
+}
+
+///Tests whether an element is rendered inline, inline elements are separated by a space (unless ``space="no"``)
+fn html_inline(elementtype: ElementType) -> bool {
+    match elementtype {
+        ElementType::Word | ElementType::Correction => true,
+        ElementType::Linebreak => false,
+        elementtype => ElementGroup::Structure.contains(elementtype) && (html_tag(elementtype) == "span" || html_tag(elementtype) == "q"),
+    }
+}
+
+///The stylesheet embedded in the HTML output
+const HTML_STYLE: &str = "body { font-family: sans-serif; margin: 2em; }
+header.folia-metadata { border-bottom: 1px solid #ccc; margin-bottom: 2em; font-size: 90%; }
+header.folia-metadata th { text-align: left; padding-right: 1em; }
+.folia-w:hover { background: #eee; }
+.folia-w[title] { border-bottom: 1px dotted #888; }
+.folia-span { background: #fff3b0; border-radius: 3px; padding: 1px 0; }
+.folia-span .folia-span { background: #c8e6ff; }
+.folia-correction del { color: #b00; }
+.folia-correction ins { color: #070; text-decoration: none; }
+.folia-note { font-size: 80%; color: #555; }
+";
+
+///Escapes text for inclusion in HTML (also in attribute values)
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl Document {
+    ///Renders the document (or the subtree under ``root_key``) as a standalone HTML page for browsing. Structure elements
+    ///are rendered as HTML elements, inline annotations (with their features) as tooltips, span annotations as
+    ///highlighted ranges over the words they span and corrections as a diff of the original and the new version.
+    ///Metadata, declarations and provenance are shown in a header.
+    pub fn to_html(&self, root_key: ElementKey, parameters: &ExportParameters) -> Result<String,FoliaError> {
+        let mut output = String::new();
+        let title = self.metadata.data.get("title").map(|s| s.as_str()).unwrap_or_else(|| self.id());
+        write!(output, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\"/>\n<meta name=\"generator\" content=\"{}\"/>\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n",
+               GENERATOR, html_escape(title), HTML_STYLE)?;
+        self.html_metadata(&mut output, title)?;
+        output.push_str("<main class=\"folia-text\">\n");
+        self.html_element(&mut output, root_key, parameters)?;
+        output.push_str("</main>\n</body>\n</html>\n");
+        Ok(output)
+    }
+
+    ///Renders the header with metadata, declarations and provenance
+    fn html_metadata(&self, output: &mut String, title: &str) -> Result<(),FoliaError> {
+        writeln!(output, "<header class=\"folia-metadata\">\n<h1>{}</h1>", html_escape(title))?;
+        if !self.metadata.data.is_empty() {
+            output.push_str("<table class=\"folia-meta\">\n");
+            let mut keys: Vec<&String> = self.metadata.data.keys().collect();
+            keys.sort();
+            for key in keys {
+                writeln!(output, "<tr><th>{}</th><td>{}</td></tr>", html_escape(key), html_escape(&self.metadata.data[key]))?;
+            }
+            output.push_str("</table>\n");
+        }
+        output.push_str("<h2>Annotations</h2>\n<ul class=\"folia-declarations\">\n");
+        for declaration in self.declarations().flatten() {
+            match &declaration.set {
+                Some(set) => writeln!(output, "<li>{} <span class=\"folia-set\">({})</span></li>", declaration.annotationtype, html_escape(set)),
+                None => writeln!(output, "<li>{}</li>", declaration.annotationtype),
+            }?;
+        }
+        output.push_str("</ul>\n");
+        if !self.provenancestore.chain.is_empty() {
+            output.push_str("<h2>Provenance</h2>\n<ul class=\"folia-provenance\">\n");
+            for processor_key in self.provenancestore.chain.iter() {
+                self.html_processor(output, *processor_key)?;
+            }
+            output.push_str("</ul>\n");
+        }
+        output.push_str("</header>\n");
+        Ok(())
+    }
+
+    ///Renders a processor and its subprocessors in the provenance chain
+    fn html_processor(&self, output: &mut String, processor_key: ProcKey) -> Result<(),FoliaError> {
+        if let Some(processor) = self.get_processor(processor_key) {
+            write!(output, "<li id=\"{}\"><strong>{}</strong>", html_escape(&processor.id), html_escape(&processor.name))?;
+            if !processor.version.is_empty() {
+                write!(output, " {}", html_escape(&processor.version))?;
+            }
+            write!(output, " ({})", processor.processortype)?;
+            if let Some(dt) = processor.begindatetime {
+                write!(output, " {}", dt.format("%Y-%m-%d %H:%M:%S"))?;
+            }
+            if !processor.processors.is_empty() {
+                output.push_str("\n<ul>\n");
+                for subprocessor_key in processor.processors.iter() {
+                    self.html_processor(output, *subprocessor_key)?;
+                }
+                output.push_str("</ul>\n");
+            }
+            output.push_str("</li>\n");
+        }
+        Ok(())
+    }
+
+    ///Renders an element and everything under it, elements that are not rendered themselves (annotations,
+    ///layers) are expressed through tooltips and highlighting on the elements they apply to
+    fn html_element(&self, output: &mut String, key: ElementKey, parameters: &ExportParameters) -> Result<(),FoliaError> {
+        let element = self.get_element(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+        match element.elementtype() {
+            ElementType::Text | ElementType::Speech => self.html_children(output, key, parameters),
+            ElementType::Word => self.html_word(output, &element, parameters),
+            ElementType::Correction => self.html_correction(output, &element, parameters),
+            elementtype if ElementGroup::Structure.contains(elementtype) => {
+                let tag = html_tag(elementtype);
+                write!(output, "<{} class=\"folia-{}\"", tag, elementtype)?;
+                self.html_attributes(output, &element)?;
+                if elementtype == ElementType::Linebreak {
+                    output.push_str("/>");
+                    return Ok(());
+                }
+                output.push('>');
+                if !self.html_has_structure(key) {
+                    output.push_str(&html_escape(&self.html_text(key, parameters)?));
+                }
+                self.html_children(output, key, parameters)?;
+                write!(output, "</{}>", tag)?;
+                if tag != "span" && tag != "q" {
+                    output.push('\n');
+                }
+                Ok(())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    ///Renders the children of an element. Consecutive inline elements such as words are separated by a space (unless ``space="no"``),
+    ///words are wrapped in highlighted ranges for the span annotations they are part of.
+    fn html_children(&self, output: &mut String, key: ElementKey, parameters: &ExportParameters) -> Result<(),FoliaError> {
+        let mut open_spans: Vec<ElementKey> = Vec::new();
+        let mut space = false;
+        for item in self.select_data_by_key(key, Selector::elements().with_comments(), Recursion::No, false, false) {
+            match item.data {
+                DataType::Element(child_key) => {
+                    let child = self.get_element(*child_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", child_key)))?;
+                    let spans = if child.elementtype() == ElementType::Word { self.html_spans(*child_key) } else { Vec::new() };
+                    //close all ranges (and those opened after them) that do not continue
+                    if let Some(index) = open_spans.iter().position(|span_key| !spans.contains(span_key)) {
+                        for _ in index..open_spans.len() {
+                            output.push_str("</span>");
+                        }
+                        open_spans.truncate(index);
+                    }
+                    let inline = html_inline(child.elementtype());
+                    if space && inline {
+                        output.push(' ');
+                    }
+                    for span_key in spans.into_iter().filter(|span_key| !open_spans.contains(span_key)).collect::<Vec<_>>() {
+                        if let Some(span) = self.get_element(span_key) {
+                            write!(output, "<span class=\"folia-span folia-{}\"", span.elementtype())?;
+                            self.html_attributes(output, &span)?;
+                            output.push('>');
+                            open_spans.push(span_key);
+                        }
+                    }
+                    self.html_element(output, *child_key, parameters)?;
+                    space = inline && child.attrib(AttribType::SPACE) != Some(&Attribute::Space(false));
+                },
+                DataType::Comment(comment) => {
+                    write!(output, "<!-- {} -->", comment.replace("--", "- -"))?;
+                },
+                _ => {},
+            }
+        }
+        for _ in open_spans.iter() {
+            output.push_str("</span>");
+        }
+        Ok(())
+    }
+
+    ///Returns the keys of the span annotations a word is part of, in document order. For span roles
+    ///(such as the head of a dependency) the span annotation itself is returned.
+    fn html_spans(&self, word_key: ElementKey) -> Vec<ElementKey> {
+        let mut spans: Vec<ElementKey> = Vec::new();
+        let selector = Selector::elements().elementgroup(Cmp::Is(ElementGroup::Span));
+        for span in self.select_by_key(word_key, selector, Recursion::No, false, true) {
+            let mut span_key = span.key().expect("span key");
+            while let Some(parent_key) = self.get_elementdata(span_key).and_then(|span| span.parent_key()) {
+                match self.get_elementdata(parent_key) {
+                    Some(parent) if ElementGroup::Span.contains(parent.elementtype) => span_key = parent_key,
+                    _ => break,
+                }
+            }
+            if !spans.contains(&span_key) {
+                spans.push(span_key);
+            }
+        }
+        spans.sort_unstable();
+        spans
+    }
+
+    ///Renders a word, with its inline annotations as tooltip
+    fn html_word(&self, output: &mut String, word: &Element, parameters: &ExportParameters) -> Result<(),FoliaError> {
+        output.push_str("<span class=\"folia-w\"");
+        self.html_attributes(output, word)?;
+        output.push('>');
+        let key = word.key().expect("word key");
+        let correction = word.get_element(ElementType::Correction, Cmp::Any, Recursion::No);
+        match correction {
+            Some(correction) => self.html_correction(output, &correction, parameters)?,
+            None => output.push_str(&html_escape(&self.html_text(key, parameters)?)),
+        }
+        output.push_str("</span>");
+        Ok(())
+    }
+
+    ///Renders a correction as a diff: the original version is shown as deleted and the new version as
+    ///inserted, suggestions are added to the tooltip
+    fn html_correction(&self, output: &mut String, correction: &Element, parameters: &ExportParameters) -> Result<(),FoliaError> {
+        output.push_str("<span class=\"folia-correction\"");
+        let mut tooltip = self.html_tooltip(correction);
+        for suggestion in correction.get_elements(ElementType::Suggestion, Cmp::Any, Recursion::No) {
+            if let Ok(text) = self.html_text(suggestion.key().expect("key"), parameters) {
+                tooltip.push(format!("suggestion: {}", text));
+            }
+        }
+        if let Some(id) = correction.id() {
+            write!(output, " id=\"{}\"", html_escape(id))?;
+        }
+        if !tooltip.is_empty() {
+            write!(output, " title=\"{}\"", html_escape(&tooltip.join("\n")))?;
+        }
+        output.push('>');
+        for (elementtype, tag) in [(ElementType::Original, "del"), (ElementType::New, "ins"), (ElementType::Current, "span")].iter() {
+            if let Some(part) = correction.get_element(*elementtype, Cmp::Any, Recursion::No) {
+                let part_key = part.key().expect("key");
+                write!(output, "<{} class=\"folia-{}\">", tag, elementtype)?;
+                if self.html_has_structure(part_key) {
+                    self.html_children(output, part_key, parameters)?;
+                } else {
+                    output.push_str(&html_escape(&self.html_text(part_key, parameters)?));
+                }
+                write!(output, "</{}>", tag)?;
+            }
+        }
+        output.push_str("</span>");
+        Ok(())
+    }
+
+    ///Writes the ``id`` and ``title`` (tooltip) attributes for an element
+    fn html_attributes(&self, output: &mut String, element: &Element) -> Result<(),FoliaError> {
+        if let Some(id) = element.id() {
+            write!(output, " id=\"{}\"", html_escape(id))?;
+        }
+        let tooltip = self.html_tooltip(element);
+        if !tooltip.is_empty() {
+            write!(output, " title=\"{}\"", html_escape(&tooltip.join("\n")))?;
+        }
+        Ok(())
+    }
+
+    ///Returns the lines of the tooltip for an element: its own class (for annotations) and its inline
+    ///annotations with their features
+    fn html_tooltip(&self, element: &Element) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        if !ElementGroup::Structure.contains(element.elementtype()) {
+            if let Some(class) = element.class() {
+                lines.push(format!("{}: {}", self.props(element.elementtype()).label.to_lowercase(), class));
+            }
+        }
+        for annotation in self.select_by_key(element.key().expect("key"), Selector::elements().elementgroup(Cmp::Is(ElementGroup::Inline)), Recursion::No, false, false) {
+            let label = self.props(annotation.elementtype()).label.to_lowercase();
+            match annotation.class() {
+                Some(class) => lines.push(format!("{}: {}", label, class)),
+                None => lines.push(label),
+            }
+            for feature in annotation.get_elements(ElementType::Feature, Cmp::Any, Recursion::No) {
+                if let (Some(subset), Some(class)) = (feature.subset(), feature.class()) {
+                    lines.push(format!("  {}: {}", subset, class));
+                }
+            }
+        }
+        lines
+    }
+
+    ///Tests whether an element has structural children that carry its text
+    fn html_has_structure(&self, key: ElementKey) -> bool {
+        self.select_by_key(key, Selector::elements().elementgroup(Cmp::Is(ElementGroup::Structure)), Recursion::No, false, false)
+            .any(|child| child.elementtype() != ElementType::Linebreak && child.elementtype() != ElementType::Whitespace)
+    }
+
+    ///Returns the text of an element, or an empty string if it has none
+    fn html_text(&self, key: ElementKey, parameters: &ExportParameters) -> Result<String,FoliaError> {
+        match self.text(key, &parameters.textparameters) {
+            Ok(text) => Ok(text),
+            Err(FoliaError::NoTextError(_)) => Ok(String::new()),
+            Err(err) => Err(err),
+        }
+    }
+}
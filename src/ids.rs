@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::attrib::*;
+use crate::store::*;
+use crate::select::*;
+use crate::document::Document;
+
+impl Document {
+    ///Returns the ID of the element, or of its nearest ancestor with an ID. The body and anything
+    ///without an identified ancestor fall back to the ID of the document.
+    fn id_base(&self, key: ElementKey) -> String {
+        let mut key = Some(key);
+        while let Some(k) = key {
+            if let Some(element) = self.get_elementdata(k) {
+                if element.parent_key().is_none() {
+                    break;
+                }
+                if let Some(id) = element.id() {
+                    return id.to_string();
+                }
+                key = element.parent_key();
+            } else {
+                break;
+            }
+        }
+        self.id.clone()
+    }
+
+    ///Generates a new ID for an element of the specified type that is to be added under the specified parent, following the FoLiA convention
+    ///(e.g. ``doc.p.1.s.2.w.3``): the ID of the parent (or its nearest ancestor with an ID, or the document), the
+    ///tag of the element and a number. The number is one higher than the number of siblings of the same type
+    ///and is increased further until the ID is unique.
+    pub fn generate_id(&self, parent_key: ElementKey, elementtype: ElementType) -> String {
+        let base = self.id_base(parent_key);
+        let siblings = self.get_elementdata(parent_key).map(|parent| parent.data.iter().filter(|item| {
+            if let DataType::Element(key) = item {
+                self.get_elementdata(*key).map(|e| e.elementtype) == Some(elementtype)
+            } else {
+                false
+            }
+        }).count()).unwrap_or(0);
+        let xmltag = self.props(elementtype).xmltag;
+        let mut n = siblings + 1;
+        loop {
+            let id = format!("{}.{}.{}", base, xmltag, n);
+            if self.get_element_key_by_id(&id).is_none() {
+                return id;
+            }
+            n += 1;
+        }
+    }
+
+    ///Assigns generated IDs to an element that is about to be added under the specified parent, and to any
+    ///elements to be added under it, if they have no ID and the specification states that IDs
+    ///should be generated for them.
+    pub(crate) fn with_generated_ids(&self, parent_key: ElementKey, mut element: ElementData) -> ElementData {
+        let mut id = element.id().map(|id| id.to_string());
+        if id.is_none() && self.props(element.elementtype).auto_generate_id {
+            let newid = self.generate_id(parent_key, element.elementtype);
+            element.set_attrib(Attribute::Id(newid.clone()));
+            id = Some(newid);
+        }
+        let base = id.unwrap_or_else(|| self.id_base(parent_key));
+        self.generate_child_ids(&base, &mut element);
+        element
+    }
+
+    fn generate_child_ids(&self, base: &str, element: &mut ElementData) {
+        let mut counters: HashMap<ElementType,usize> = HashMap::new();
+        for item in element.data.iter_mut() {
+            if let DataType::AddElement(child) = item {
+                let childbase = if let Some(id) = child.id() {
+                    id.to_string()
+                } else if self.props(child.elementtype).auto_generate_id {
+                    let n = counters.entry(child.elementtype).or_insert(0);
+                    let xmltag = self.props(child.elementtype).xmltag;
+                    let mut id;
+                    loop {
+                        *n += 1;
+                        id = format!("{}.{}.{}", base, xmltag, n);
+                        if self.get_element_key_by_id(&id).is_none() {
+                            break;
+                        }
+                    }
+                    child.set_attrib(Attribute::Id(id.clone()));
+                    id
+                } else {
+                    base.to_string()
+                };
+                self.generate_child_ids(&childbase, child);
+            }
+        }
+    }
+
+    ///Rewrites all IDs under the body consistently, following the FoLiA convention (e.g. ``doc.p.1.s.2.w.3``). Elements that
+    ///have an ID, or for which the specification states IDs should be generated, are (re)numbered
+    ///in document order. References to IDs (e.g. in ``wref``) and the index are updated as well.
+    ///The ID of the body itself is retained. Returns a map of old to new IDs.
+    pub fn renumber_ids(&mut self) -> Result<HashMap<String,String>, FoliaError> {
+        let mut newids: HashMap<ElementKey,String> = HashMap::new();
+        let mut counters: HashMap<(String,ElementType),usize> = HashMap::new();
+        for item in self.select_data_by_key(0, Selector::elements(), Recursion::Always, false, false) {
+            if let DataType::Element(key) = item.data {
+                let element = self.get_elementdata(*key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+                if element.id().is_none() && !self.props(element.elementtype).auto_generate_id {
+                    continue;
+                }
+                //the new ID of the nearest ancestor with an ID
+                let mut base: Option<String> = None;
+                let mut ancestor_key = element.parent_key();
+                while let Some(k) = ancestor_key {
+                    if let Some(id) = newids.get(&k) {
+                        base = Some(id.clone());
+                        break;
+                    }
+                    ancestor_key = self.get_elementdata(k).and_then(|ancestor| ancestor.parent_key());
+                }
+                //children of the body are based on the ID of the document
+                let base = base.unwrap_or_else(|| self.id.clone());
+                let n = counters.entry((base.clone(), element.elementtype)).or_insert(0);
+                *n += 1;
+                newids.insert(*key, format!("{}.{}.{}", base, self.props(element.elementtype).xmltag, n));
+            }
+        }
+
+        let mut idmap: HashMap<String,String> = HashMap::new();
+        for (key, newid) in newids.iter() {
+            if let Some(oldid) = self.get_elementdata(*key).and_then(|e| e.id()) {
+                idmap.insert(oldid.to_string(), newid.clone());
+            }
+        }
+        for (key, newid) in newids {
+            if let Some(element) = self.get_mut_elementdata(key) {
+                element.set_attrib(Attribute::Id(newid));
+            }
+        }
+
+        //update references and rebuild the index
+        let mut index: HashMap<String,ElementKey> = HashMap::new();
+        for key in 0..self.elementstore.items.len() {
+            let element = if let Some(element) = self.get_mut_elementdata(key as ElementKey) { element } else { continue };
+            for attrib in element.attribs.iter_mut() {
+                if let Attribute::Idref(idref) = attrib {
+                    if let Some(newid) = idmap.get(idref.as_str()) {
+                        *idref = newid.clone();
+                    }
+                }
+            }
+            if let (Some(id), Some(key)) = (element.id(), element.key()) {
+                index.insert(id.to_string(), key);
+            }
+        }
+        *<Document as Store<ElementData,ElementKey>>::index_mut(self) = index;
+        Ok(idmap)
+    }
+}
@@ -0,0 +1,330 @@
+//! An optional journal of the edits made to a document, for instance to synchronise them to other
+//! copies of the same document. Only edits made through the high-level editing methods are
+//! recorded: ``annotate()``, ``declare()``, ``add_processor()``, ``add_subprocessor()``, the setters
+//! such as ``set_class()``, ``replace_annotation()`` and ``remove_element()``, as well as transactions,
+//! undo and redo.
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::metadata::*;
+use crate::document::Document;
+
+#[derive(Debug,Clone,PartialEq,Serialize,Deserialize)]
+///Locates an element independently of its key: the ID of the element or its nearest ancestor with an ID (``None`` for the body),
+///and the path from there, as indices amongst the child elements.
+pub struct ElementLocator {
+    pub id: Option<String>,
+    #[serde(default,skip_serializing_if="Vec::is_empty")]
+    pub path: Vec<usize>,
+}
+
+#[derive(Debug,Clone,PartialEq,Serialize,Deserialize)]
+#[serde(tag="operation")]
+///An edit recorded in the journal. Elements are represented in FoLiA-JSON.
+pub enum Edit {
+    Annotate { parent: ElementLocator, element: serde_json::Value },
+    Declare {
+        annotationtype: String,
+        set: Option<String>,
+        #[serde(default,skip_serializing_if="Option::is_none")]
+        alias: Option<String>,
+        #[serde(default,skip_serializing_if="Option::is_none")]
+        format: Option<String>,
+    },
+    AddProcessor { parent: Option<String>, processor: serde_json::Value },
+    SetClass { element: ElementLocator, before: Option<String>, after: Option<String> },
+    SetSet { element: ElementLocator, before: Option<String>, after: Option<String> },
+    SetProcessor { element: ElementLocator, before: Option<String>, after: String },
+    SetConfidence { element: ElementLocator, before: Option<f64>, after: Option<f64> },
+    SetText { element: ElementLocator, textclass: Option<String>, before: Option<String>, after: String },
+    ReplaceAnnotation { element: ElementLocator, before: serde_json::Value, after: serde_json::Value },
+    RemoveElement { element: ElementLocator, before: serde_json::Value },
+    BeginTransaction,
+    Commit,
+    Undo,
+    Redo,
+}
+
+#[derive(Debug,Clone,PartialEq,Serialize,Deserialize)]
+///An entry in the journal
+pub struct JournalEntry {
+    #[serde(flatten)]
+    pub edit: Edit,
+    ///The IDs of the affected elements: the edited element (or its nearest ancestor with an ID) and any identified elements that were added or removed
+    #[serde(default,skip_serializing_if="Vec::is_empty")]
+    pub ids: Vec<String>,
+    ///The ID of the processor that was active when the edit was made
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub active_processor: Option<String>,
+}
+
+#[derive(Debug,Clone,Default,PartialEq,Serialize,Deserialize)]
+///A journal of edits, in the order they were made
+pub struct Journal {
+    pub entries: Vec<JournalEntry>,
+    ///The nesting depth of recorded edits, edits made as part of another edit are not recorded separately
+    #[serde(skip)]
+    depth: usize,
+}
+
+impl Journal {
+    ///Serialises the journal to JSON
+    pub fn json(&self, pretty: bool) -> Result<String, FoliaError> {
+        if pretty {
+            serde_json::to_string_pretty(self)
+        } else {
+            serde_json::to_string(self)
+        }.map_err(|e| FoliaError::SerialisationError(format!("{}", e)))
+    }
+
+    ///Loads a journal from JSON
+    pub fn from_json(data: &str) -> Result<Self, FoliaError> {
+        serde_json::from_str(data).map_err(|e| FoliaError::ParseError(format!("Invalid journal: {}", e)))
+    }
+}
+
+impl Document {
+    ///Starts recording edits in a journal, this has no effect if the journal is already enabled
+    pub fn enable_journal(&mut self) {
+        if self.journal.is_none() {
+            self.journal = Some(Journal::default());
+        }
+    }
+
+    ///Stops recording edits, returns the journal
+    pub fn disable_journal(&mut self) -> Option<Journal> {
+        self.journal.take()
+    }
+
+    ///Returns the journal, if enabled
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    ///Returns the entries recorded so far and empties the journal, which remains enabled
+    pub fn drain_journal(&mut self) -> Vec<JournalEntry> {
+        self.journal.as_mut().map(|journal| std::mem::take(&mut journal.entries)).unwrap_or_default()
+    }
+
+    ///Applies the edits of a journal to this document, which should be a copy of the document the
+    ///journal was recorded on, in the same state as when recording started. Each edit is made with
+    ///the processor that was active when it was recorded, the active processor is restored afterwards.
+    pub fn replay(&mut self, entries: &[JournalEntry]) -> Result<(), FoliaError> {
+        let active_processor = self.active_processor;
+        let result = entries.iter().try_for_each(|entry| self.replay_entry(entry));
+        self.active_processor = active_processor;
+        result
+    }
+
+    ///Applies a single edit of a journal
+    fn replay_entry(&mut self, entry: &JournalEntry) -> Result<(), FoliaError> {
+        self.active_processor = match entry.active_processor.as_ref() {
+            Some(processor) => Some(self.get_processor_key_by_id(processor).ok_or_else(|| FoliaError::KeyError(format!("No such processor: {}", processor)))?),
+            None => None,
+        };
+        match &entry.edit {
+            Edit::Annotate { parent, element } => {
+                let parent_key = self.resolve_locator(parent)?;
+                self.annotate(parent_key, element_from_json(element)?)?;
+            },
+            Edit::Declare { annotationtype, set, alias, format } => {
+                let annotationtype = AnnotationType::from_str(annotationtype).ok_or_else(|| FoliaError::ParseError(format!("Invalid annotation type: {}", annotationtype)))?;
+                self.declare(annotationtype, set, alias, format)?;
+            },
+            Edit::AddProcessor { parent, processor } => {
+                let processor: Processor = serde_json::from_value(processor.clone()).map_err(|e| FoliaError::ParseError(format!("Invalid processor: {}", e)))?;
+                match parent {
+                    Some(parent) => {
+                        let parent_key = self.get_processor_key_by_id(parent).ok_or_else(|| FoliaError::KeyError(format!("No such processor: {}", parent)))?;
+                        self.add_subprocessor(parent_key, processor)?;
+                    },
+                    None => { self.add_processor(processor)?; },
+                }
+            },
+            Edit::SetClass { element, after, .. } => self.set_class(self.resolve_locator(element)?, after.as_deref())?,
+            Edit::SetSet { element, after, .. } => self.set_set(self.resolve_locator(element)?, after.as_deref())?,
+            Edit::SetProcessor { element, after, .. } => self.set_processor(self.resolve_locator(element)?, after)?,
+            Edit::SetConfidence { element, after, .. } => self.set_confidence(self.resolve_locator(element)?, *after)?,
+            Edit::SetText { element, textclass, after, .. } => { self.set_text(self.resolve_locator(element)?, after, textclass.as_deref())?; },
+            Edit::ReplaceAnnotation { element, after, .. } => { self.replace_annotation(self.resolve_locator(element)?, element_from_json(after)?)?; },
+            Edit::RemoveElement { element, .. } => self.remove_element(self.resolve_locator(element)?)?,
+            Edit::BeginTransaction => self.begin_transaction()?,
+            Edit::Commit => self.commit()?,
+            Edit::Undo => { self.undo()?; },
+            Edit::Redo => { self.redo()?; },
+        }
+        Ok(())
+    }
+
+    ///Returns a locator for the element with the specified key
+    pub fn locate(&self, key: ElementKey) -> Result<ElementLocator, FoliaError> {
+        let mut path: Vec<usize> = Vec::new();
+        let mut key = key;
+        loop {
+            let element = self.get_elementdata(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+            if let Some(id) = element.id() {
+                path.reverse();
+                return Ok(ElementLocator { id: Some(id.to_string()), path });
+            }
+            match element.parent_key() {
+                Some(parent_key) => {
+                    let parent = self.get_elementdata(parent_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", parent_key)))?;
+                    let index = child_keys(parent).position(|child_key| child_key == key).ok_or_else(|| FoliaError::InternalError("Element not found in its parent".to_string()))?;
+                    path.push(index);
+                    key = parent_key;
+                },
+                None => {
+                    path.reverse();
+                    return Ok(ElementLocator { id: None, path });
+                }
+            }
+        }
+    }
+
+    ///Returns the key of the element the locator refers to
+    pub fn resolve_locator(&self, locator: &ElementLocator) -> Result<ElementKey, FoliaError> {
+        let mut key = match locator.id.as_ref() {
+            Some(id) => self.get_element_key_by_id(id).ok_or_else(|| FoliaError::KeyError(format!("No such element: {}", id)))?,
+            None => 0,
+        };
+        for index in locator.path.iter() {
+            let element = self.get_elementdata(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+            key = child_keys(element).nth(*index).ok_or_else(|| FoliaError::KeyError(format!("Unable to resolve element locator {:?}", locator)))?;
+        }
+        Ok(key)
+    }
+
+    ///Returns true if edits are to be recorded, i.e. the journal is enabled and no recorded edit is in progress
+    pub(crate) fn journaling(&self) -> bool {
+        self.journal.as_ref().map(|journal| journal.depth == 0) == Some(true)
+    }
+
+    ///Performs an edit without recording it, or anything it does, in the journal
+    pub(crate) fn unjournaled<T, F>(&mut self, edit: F) -> Result<T, FoliaError>
+    where F: FnOnce(&mut Document) -> Result<T, FoliaError> {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.depth += 1;
+        }
+        let result = edit(self);
+        if let Some(journal) = self.journal.as_mut() {
+            journal.depth -= 1;
+        }
+        result
+    }
+
+    ///Returns a locator for the element and the value obtained from it with ``f``, prior to an
+    ///edit. Returns ``None`` if the edit is not to be recorded.
+    pub(crate) fn before_edit<B, F>(&self, key: ElementKey, f: F) -> Result<Option<(ElementLocator,B)>, FoliaError>
+    where F: FnOnce(&Document) -> Result<B, FoliaError> {
+        if self.journaling() {
+            Ok(Some((self.locate(key)?, f(self)?)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    ///Adds an entry to the journal (if enabled). The IDs of the affected elements are derived from the edit, ``ids`` adds
+    ///any others (such as generated IDs).
+    pub(crate) fn record(&mut self, edit: Edit, ids: Vec<String>) {
+        if self.journaling() {
+            let active_processor = self.active_processor.and_then(|key| self.get_processor(key)).map(|processor| processor.id.clone());
+            let mut affected = affected_ids(&edit);
+            for id in ids {
+                if !affected.contains(&id) {
+                    affected.push(id);
+                }
+            }
+            if let Some(journal) = self.journal.as_mut() {
+                journal.entries.push(JournalEntry { edit, ids: affected, active_processor });
+            }
+        }
+    }
+
+    ///Returns the IDs of the element and any elements under it
+    pub(crate) fn element_ids(&self, key: ElementKey) -> Result<Vec<String>, FoliaError> {
+        let mut ids = Vec::new();
+        json_ids(&self.element_json(key)?, &mut ids);
+        Ok(ids)
+    }
+
+    ///Returns the number of entries in the journal
+    pub(crate) fn journal_len(&self) -> Option<usize> {
+        self.journal.as_ref().map(|journal| journal.entries.len())
+    }
+
+    ///Removes the entries after the specified number
+    pub(crate) fn truncate_journal(&mut self, len: usize) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.entries.truncate(len);
+        }
+    }
+
+    ///Returns the FoLiA-JSON representation of an element, with sets, classes and processors decoded
+    pub(crate) fn element_json(&self, key: ElementKey) -> Result<serde_json::Value, FoliaError> {
+        let element = self.get_element(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+        serde_json::to_value(element).map_err(|e| FoliaError::SerialisationError(format!("{}", e)))
+    }
+}
+
+///Returns the FoLiA-JSON representation of an element that is not (yet) part of a document
+pub(crate) fn to_json_value<T: serde::Serialize>(data: &T) -> Result<serde_json::Value, FoliaError> {
+    serde_json::to_value(data).map_err(|e| FoliaError::SerialisationError(format!("{}", e)))
+}
+
+fn child_keys(element: &ElementData) -> impl Iterator<Item=ElementKey> + '_ {
+    element.data.iter().filter_map(|item| {
+        if let DataType::Element(key) = item { Some(*key) } else { None }
+    })
+}
+
+fn element_from_json(value: &serde_json::Value) -> Result<ElementData, FoliaError> {
+    serde_json::from_value(value.clone()).map_err(|e| FoliaError::ParseError(format!("Invalid FoLiA-JSON: {}", e)))
+}
+
+///Collects the IDs of the elements affected by an edit
+fn affected_ids(edit: &Edit) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::new();
+    match edit {
+        Edit::Annotate { parent, element } => {
+            ids.extend(parent.id.iter().cloned());
+            json_ids(element, &mut ids);
+        },
+        Edit::SetClass { element, .. } | Edit::SetSet { element, .. } | Edit::SetProcessor { element, .. } |
+        Edit::SetConfidence { element, .. } | Edit::SetText { element, .. } => ids.extend(element.id.iter().cloned()),
+        Edit::ReplaceAnnotation { element, before, after } => {
+            ids.extend(element.id.iter().cloned());
+            json_ids(before, &mut ids);
+            json_ids(after, &mut ids);
+        },
+        Edit::RemoveElement { element, before } => {
+            ids.extend(element.id.iter().cloned());
+            json_ids(before, &mut ids);
+        },
+        _ => {},
+    }
+    let mut unique: Vec<String> = Vec::new();
+    for id in ids {
+        if !unique.contains(&id) {
+            unique.push(id);
+        }
+    }
+    unique
+}
+
+///Collects the IDs in the FoLiA-JSON representation of an element
+fn json_ids(value: &serde_json::Value, ids: &mut Vec<String>) {
+    if let serde_json::Value::Object(map) = value {
+        if map.get("type").and_then(|t| t.as_str()) != Some(ElementType::WordReference.as_str()) {
+            if let Some(serde_json::Value::String(id)) = map.get("id") {
+                ids.push(id.clone());
+            }
+        }
+        if let Some(serde_json::Value::Array(children)) = map.get("children") {
+            for child in children {
+                json_ids(child, ids);
+            }
+        }
+    }
+}
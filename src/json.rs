@@ -0,0 +1,643 @@
+use std::collections::{BTreeMap,HashMap};
+use std::cell::RefCell;
+use std::str::FromStr;
+
+use serde::ser::{Serialize,Serializer,SerializeMap,SerializeSeq};
+use serde::de::{Deserialize,Deserializer};
+use chrono::NaiveDateTime;
+
+use crate::common::*;
+use crate::types::*;
+use crate::transaction::*;
+use crate::journal::*;
+use crate::error::*;
+use crate::element::*;
+use crate::attrib::*;
+use crate::metadata::*;
+use crate::foreign::*;
+use crate::store::*;
+use crate::elementstore::*;
+use crate::document::*;
+
+///The type FoLiA-JSON uses for XML comments, which have no FoLiA element of their own
+pub const JSON_XMLCOMMENT: &str = "xmlcomment";
+
+const DATETIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+///An attribute value as it appears in FoLiA-JSON
+#[derive(Serialize,Deserialize)]
+#[serde(untagged)]
+enum JsonValue {
+    Bool(bool),
+    Integer(u64),
+    Number(f64),
+    Text(String),
+}
+
+impl JsonValue {
+    ///Returns the value as it would be written in XML
+    fn into_xml_value(self) -> String {
+        match self {
+            JsonValue::Bool(true) => "yes".to_string(),
+            JsonValue::Bool(false) => "no".to_string(),
+            JsonValue::Integer(n) => n.to_string(),
+            JsonValue::Number(f) => f.to_string(),
+            JsonValue::Text(s) => s,
+        }
+    }
+}
+
+impl Attribute {
+    ///Returns the name of the attribute in FoLiA-JSON, this is the XML attribute name except
+    ///for ``xml:id`` (``id``), the ``id`` reference (``idref``) and ``type`` (``reftype``, as
+    ///``type`` holds the element type)
+    pub fn json_key(&self) -> &'static str {
+        match self.attribtype() {
+            AttribType::ID => "id",
+            AttribType::IDREF => "idref",
+            AttribType::TYPE => "reftype",
+            attribtype => attribtype.into()
+        }
+    }
+
+    ///Instantiate a FoLiA Attribute from a FoLiA-JSON key and value
+    fn from_json(key: &str, value: JsonValue) -> Result<Attribute,FoliaError> {
+        let key: &str = match key {
+            "id" => "xml:id",
+            "idref" => "id",
+            "reftype" => "type",
+            key => key
+        };
+        Attribute::from_key_value(key.as_bytes(), value.into_xml_value())
+    }
+
+    fn json_value(&self) -> Result<JsonValue,FoliaError> {
+        match self {
+            Attribute::Confidence(f) => Ok(JsonValue::Number(*f)),
+            Attribute::Offset(n) | Attribute::LineNr(n) => Ok(JsonValue::Integer(*n as u64)),
+            Attribute::Space(b) | Attribute::NewPage(b) => Ok(JsonValue::Bool(*b)),
+            attrib => Ok(JsonValue::Text(attrib.to_string()?)),
+        }
+    }
+}
+
+impl Serialize for Attribute {
+    ///Serialises an attribute to a map with a single key/value pair, encoded attributes can not
+    ///be serialised and have to be decoded first.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(self.json_key(), &self.json_value().map_err(serde::ser::Error::custom)?)?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Attribute {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let map: BTreeMap<String,JsonValue> = BTreeMap::deserialize(deserializer)?;
+        if map.len() != 1 {
+            return Err(serde::de::Error::custom("Expected exactly one key/value pair for an attribute"));
+        }
+        let (key, value) = map.into_iter().next().expect("unwrapping attribute");
+        Attribute::from_json(&key, value).map_err(serde::de::Error::custom)
+    }
+}
+
+///Serialises data items to a FoLiA-JSON ``children`` list, elements are resolved through the
+///document (if any)
+struct JsonChildren<'a> {
+    document: Option<&'a Document>,
+    data: &'a [DataType],
+}
+
+impl<'a> Serialize for JsonChildren<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(None)?;
+        for item in self.data.iter() {
+            match item {
+                DataType::Text(text) => seq.serialize_element(text)?,
+                DataType::Comment(comment) => {
+                    let mut map: BTreeMap<&str,&str> = BTreeMap::new();
+                    map.insert("type", JSON_XMLCOMMENT);
+                    map.insert("text", comment.as_str());
+                    seq.serialize_element(&map)?;
+                },
+                DataType::AddElement(elementdata) => seq.serialize_element(elementdata)?,
+                DataType::Element(key) => {
+                    let element = self.document.and_then(|doc| doc.get_element(*key))
+                                      .ok_or_else(|| serde::ser::Error::custom(format!("Unable to resolve element {}", key)))?;
+                    seq.serialize_element(&element)?;
+                },
+                DataType::SpanReference(_) => {
+                    //not expressed explicitly
+                }
+            }
+        }
+        seq.end()
+    }
+}
+
+///Returns all text under the given data items, ignoring delimiters. This is used for the
+///``text`` property on text content elements.
+fn json_text(document: &Document, data: &[DataType]) -> String {
+    let mut text = String::new();
+    for item in data.iter() {
+        match item {
+            DataType::Text(s) => text += s,
+            DataType::Element(key) => {
+                if let Some(element) = document.get_elementdata(*key) {
+                    text += json_text(document, &element.data).as_str();
+                }
+            },
+            _ => {}
+        }
+    }
+    text
+}
+
+impl Serialize for ElementData {
+    ///Serialises an element that is not (yet) part of a document to FoLiA-JSON. Elements
+    ///that are part of a document are encoded and have to be serialised through ``Element`` instead.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("type", self.elementtype.as_str())?;
+        for attrib in self.attribs.iter() {
+            if attrib.decodable() {
+                return Err(serde::ser::Error::custom("Element is encoded, serialise it via Element instead of ElementData"));
+            } else if *attrib != Attribute::Ignore {
+                map.serialize_entry(attrib.json_key(), &attrib.json_value().map_err(serde::ser::Error::custom)?)?;
+            }
+        }
+        if !self.data.is_empty() {
+            map.serialize_entry("children", &JsonChildren { document: None, data: &self.data })?;
+        }
+        map.end()
+    }
+}
+
+impl<'a> Serialize for Element<'a> {
+    ///Serialises an element (and everything under it) to FoLiA-JSON, decoding sets, classes and processors.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("type", self.elementtype().as_str())?;
+        for attrib in self.attribs().iter() {
+            if *attrib != Attribute::Ignore && !attrib.decodable() {
+                map.serialize_entry(attrib.json_key(), &attrib.json_value().map_err(serde::ser::Error::custom)?)?;
+            }
+        }
+        if self.elementtype() != ElementType::Feature {
+            if let Some(set) = self.set() {
+                map.serialize_entry("set", set)?;
+            }
+        }
+        if let Some(subset) = self.subset() {
+            map.serialize_entry("subset", subset)?;
+        }
+        if let Some(class) = self.class() {
+            map.serialize_entry("class", class)?;
+        }
+        if let Some(processor) = self.processor() {
+            map.serialize_entry("processor", processor)?;
+        }
+        let document = self.document();
+        if let Some(document) = document {
+            if self.elementtype() == ElementType::TextContent || self.elementtype() == ElementType::PhonContent {
+                map.serialize_entry("text", &json_text(document, &self.data.data))?;
+            }
+        }
+        if self.data.data.iter().any(|item| !matches!(item, DataType::SpanReference(_))) {
+            map.serialize_entry("children", &JsonChildren { document, data: &self.data.data })?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for ElementData {
+    ///Deserialises an element from FoLiA-JSON, children are held as ``DataType::AddElement``
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let map: BTreeMap<String,serde_json::Value> = BTreeMap::deserialize(deserializer)?;
+        ElementData::from_json_map(map).map_err(serde::de::Error::custom)
+    }
+}
+
+impl ElementData {
+    fn from_json_map(mut map: BTreeMap<String,serde_json::Value>) -> Result<Self,FoliaError> {
+        let elementtype = match map.remove("type") {
+            Some(serde_json::Value::String(tag)) => tag,
+            _ => return Err(FoliaError::ParseError("FoLiA-JSON element has no type".to_string()))
+        };
+        if elementtype == JSON_XMLCOMMENT {
+            return Err(FoliaError::ParseError("Comments can only occur as children of an element".to_string()));
+        }
+        let mut element = ElementData::new(ElementType::from_str(elementtype.as_str())?);
+        //the text of text content elements is derived from its children
+        map.remove("text");
+        if let Some(children) = map.remove("children") {
+            if let serde_json::Value::Array(children) = children {
+                for child in children {
+                    element.push(DataType::from_json_value(child)?);
+                }
+            } else {
+                return Err(FoliaError::ParseError("FoLiA-JSON children must be a list".to_string()));
+            }
+        }
+        for (key, value) in map {
+            let value: JsonValue = serde_json::from_value(value).map_err(|e| FoliaError::ParseError(format!("Invalid value for attribute {}: {}", key, e)))?;
+            element.set_attrib(Attribute::from_json(key.as_str(), value)?);
+        }
+        Ok(element)
+    }
+}
+
+impl DataType {
+    ///Converts a child in FoLiA-JSON to a data item: strings become text, objects become elements to be added (or comments)
+    fn from_json_value(value: serde_json::Value) -> Result<Self,FoliaError> {
+        match value {
+            serde_json::Value::String(text) => Ok(DataType::Text(text)),
+            serde_json::Value::Object(object) => {
+                let map: BTreeMap<String,serde_json::Value> = object.into_iter().collect();
+                if map.get("type").and_then(|t| t.as_str()) == Some(JSON_XMLCOMMENT) {
+                    let comment = map.get("text").and_then(|t| t.as_str()).unwrap_or("");
+                    Ok(DataType::Comment(comment.to_string()))
+                } else {
+                    Ok(DataType::AddElement(ElementData::from_json_map(map)?))
+                }
+            },
+            _ => Err(FoliaError::ParseError("FoLiA-JSON children must be strings or objects".to_string()))
+        }
+    }
+}
+
+#[derive(Serialize,Deserialize,Default)]
+///The FoLiA-JSON representation of a declaration
+struct JsonDeclaration {
+    annotationtype: String,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    set: Option<String>,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    alias: Option<String>,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    format: Option<String>,
+    ///Processor IDs
+    #[serde(default,skip_serializing_if="Vec::is_empty")]
+    processors: Vec<String>,
+}
+
+impl JsonDeclaration {
+    fn new(declaration: &Declaration, document: Option<&Document>) -> Self {
+        Self {
+            annotationtype: declaration.annotationtype.as_str().to_string(),
+            set: declaration.set.clone(),
+            alias: declaration.alias.clone(),
+            format: declaration.format.clone(),
+            processors: match document {
+                Some(document) => declaration.processors.iter().filter_map(|key| document.get_processor(*key).map(|p| p.id.clone())).collect(),
+                None => Vec::new(),
+            }
+        }
+    }
+
+    fn annotationtype(&self) -> Result<AnnotationType,FoliaError> {
+        AnnotationType::from_str(self.annotationtype.as_str()).ok_or_else(|| FoliaError::ParseError(format!("Invalid annotation type: {}", self.annotationtype)))
+    }
+}
+
+impl Serialize for Declaration {
+    ///Serialises a declaration to FoLiA-JSON, processors can only be serialised as part of a document
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        JsonDeclaration::new(self, None).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Declaration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let declaration = JsonDeclaration::deserialize(deserializer)?;
+        let annotationtype = declaration.annotationtype().map_err(serde::de::Error::custom)?;
+        Ok(Declaration::new(annotationtype, declaration.set, declaration.alias, declaration.format))
+    }
+}
+
+#[derive(Serialize,Deserialize,Default)]
+///The FoLiA-JSON representation of a processor
+struct JsonProcessor {
+    id: String,
+    name: String,
+    #[serde(rename="type")]
+    processortype: String,
+    #[serde(default,skip_serializing_if="String::is_empty")]
+    version: String,
+    #[serde(default,skip_serializing_if="String::is_empty")]
+    folia_version: String,
+    #[serde(default,skip_serializing_if="String::is_empty")]
+    document_version: String,
+    #[serde(default,skip_serializing_if="String::is_empty")]
+    command: String,
+    #[serde(default,skip_serializing_if="String::is_empty")]
+    host: String,
+    #[serde(default,skip_serializing_if="String::is_empty")]
+    user: String,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    begindatetime: Option<String>,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    enddatetime: Option<String>,
+    #[serde(default,skip_serializing_if="String::is_empty")]
+    src: String,
+    #[serde(default,skip_serializing_if="String::is_empty")]
+    format: String,
+    #[serde(default,skip_serializing_if="String::is_empty")]
+    resourcelink: String,
+    #[serde(default,skip_serializing_if="BTreeMap::is_empty")]
+    metadata: BTreeMap<String,String>,
+    ///Subprocessors
+    #[serde(default,skip_serializing_if="Vec::is_empty")]
+    processors: Vec<JsonProcessor>,
+}
+
+impl JsonProcessor {
+    ///Converts a processor, subprocessors are resolved through the document if provided, or
+    ///taken from the pending subprocessors otherwise
+    fn new(processor: &Processor, document: Option<&Document>) -> Self {
+        Self {
+            id: processor.id.clone(),
+            name: processor.name.clone(),
+            processortype: processor.processortype.as_str().to_string(),
+            version: processor.version.clone(),
+            folia_version: processor.folia_version.clone(),
+            document_version: processor.document_version.clone(),
+            command: processor.command.clone(),
+            host: processor.host.clone(),
+            user: processor.user.clone(),
+            begindatetime: processor.begindatetime.map(|dt| dt.format(DATETIME_FORMAT).to_string()),
+            enddatetime: processor.enddatetime.map(|dt| dt.format(DATETIME_FORMAT).to_string()),
+            src: processor.src.clone(),
+            format: processor.format.clone(),
+            resourcelink: processor.resourcelink.clone(),
+            metadata: processor.metadata.data.iter().map(|(k,v)| (k.clone(), v.clone())).collect(),
+            processors: match document {
+                Some(document) => processor.processors.iter().filter_map(|key| document.get_processor(*key).map(|p| JsonProcessor::new(p, Some(document)))).collect(),
+                None => processor.pending_processors.borrow().iter().map(|p| JsonProcessor::new(p, None)).collect(),
+            }
+        }
+    }
+
+    fn into_processor(self) -> Result<Processor,FoliaError> {
+        let processortype = match self.processortype.as_str() {
+            "manual" => ProcessorType::Manual,
+            "auto" => ProcessorType::Auto,
+            "generator" => ProcessorType::Generator,
+            "datasource" => ProcessorType::DataSource,
+            other => return Err(FoliaError::ParseError(format!("Invalid processor type: {}", other)))
+        };
+        let parse_datetime = |value: Option<String>| -> Result<Option<NaiveDateTime>,FoliaError> {
+            match value {
+                Some(value) => NaiveDateTime::parse_from_str(&value, DATETIME_FORMAT).map(Some)
+                                    .map_err(|e| FoliaError::ParseError(format!("Unable to parse datetime {} -> {}", value, e))),
+                None => Ok(None)
+            }
+        };
+        let mut subprocessors = Vec::with_capacity(self.processors.len());
+        for subprocessor in self.processors {
+            subprocessors.push(subprocessor.into_processor()?);
+        }
+        Ok(Processor {
+            id: self.id,
+            name: self.name,
+            processortype,
+            version: self.version,
+            folia_version: self.folia_version,
+            document_version: self.document_version,
+            command: self.command,
+            host: self.host,
+            user: self.user,
+            begindatetime: parse_datetime(self.begindatetime)?,
+            enddatetime: parse_datetime(self.enddatetime)?,
+            src: self.src,
+            format: self.format,
+            resourcelink: self.resourcelink,
+            metadata: Metadata { data: self.metadata.into_iter().collect(), ..Metadata::default() },
+            pending_processors: RefCell::new(subprocessors),
+            ..Processor::default()
+        })
+    }
+}
+
+impl Serialize for Processor {
+    ///Serialises a processor to FoLiA-JSON, including any pending subprocessors. Use the
+    ///document serialisation to include subprocessors of processors that are already added to a document.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        JsonProcessor::new(self, None).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Processor {
+    ///Deserialises a processor from FoLiA-JSON, subprocessors are stored as pending subprocessors
+    ///and are added when the processor is added to a document.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        JsonProcessor::deserialize(deserializer)?.into_processor().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Serialize,Deserialize,Default)]
+///The FoLiA-JSON representation of submetadata
+struct JsonMetadata {
+    #[serde(rename="type",default,skip_serializing_if="Option::is_none")]
+    metadatatype: Option<String>,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    src: Option<String>,
+    #[serde(default)]
+    data: BTreeMap<String,String>,
+    #[serde(rename="foreigndata",default,skip_serializing_if="Vec::is_empty")]
+    foreign: Vec<String>,
+}
+
+impl JsonMetadata {
+    fn new(metadata: &Metadata) -> Self {
+        Self {
+            metadatatype: metadata.metadatatype.clone(),
+            src: metadata.src.clone(),
+            data: metadata.data.iter().map(|(k,v)| (k.clone(), v.clone())).collect(),
+            foreign: metadata.foreign.iter().map(|foreign| foreign.xml.clone()).collect(),
+        }
+    }
+
+    fn into_metadata(self) -> Metadata {
+        Metadata {
+            data: self.data.into_iter().collect(),
+            src: self.src,
+            metadatatype: self.metadatatype,
+            foreign: self.foreign.into_iter().map(|xml| ForeignData { xml }).collect(),
+            external: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+///The FoLiA-JSON representation of a document, for deserialisation
+struct JsonDocument {
+    id: String,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    metadatatype: Option<String>,
+    #[serde(default)]
+    metadatasrc: Option<String>,
+    #[serde(default)]
+    metadata: BTreeMap<String,String>,
+    #[serde(default)]
+    metadataforeign: Vec<String>,
+    #[serde(default)]
+    submetadata: BTreeMap<String,JsonMetadata>,
+    #[serde(default)]
+    declarations: Vec<JsonDeclaration>,
+    #[serde(default)]
+    provenance: Vec<JsonProcessor>,
+    #[serde(default)]
+    children: Vec<ElementData>,
+}
+
+impl Serialize for Document {
+    ///Serialises the document to FoLiA-JSON, this follows the JSON output of foliapy
+    ///(``id``, ``version``, ``generator``, ``declarations``, ``metadata`` and ``children``)
+    ///and adds ``provenance``, ``metadatatype``, ``metadatasrc``, ``metadataforeign`` and ``submetadata``.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("id", self.id())?;
+        map.serialize_entry("version", self.version.as_str())?;
+        map.serialize_entry("generator", GENERATOR)?;
+        if let Some(metadatatype) = &self.metadata.metadatatype {
+            map.serialize_entry("metadatatype", metadatatype)?;
+        }
+        if let Some(src) = &self.metadata.src {
+            map.serialize_entry("metadatasrc", src)?;
+        }
+        let metadata = JsonMetadata::new(&self.metadata);
+        map.serialize_entry("metadata", &metadata.data)?;
+        if !metadata.foreign.is_empty() {
+            map.serialize_entry("metadataforeign", &metadata.foreign)?;
+        }
+        if !self.submetadata.is_empty() {
+            let submetadata: BTreeMap<&String,JsonMetadata> = self.submetadata.iter().map(|(id, metadata)| (id, JsonMetadata::new(metadata))).collect();
+            map.serialize_entry("submetadata", &submetadata)?;
+        }
+        let declarations: Vec<JsonDeclaration> = self.declarations().filter_map(|declaration| declaration.as_ref().map(|d| JsonDeclaration::new(d, Some(self)))).collect();
+        map.serialize_entry("declarations", &declarations)?;
+        let provenance: Vec<JsonProcessor> = self.provenancestore.chain.iter().filter_map(|key| self.get_processor(*key).map(|p| JsonProcessor::new(p, Some(self)))).collect();
+        map.serialize_entry("provenance", &provenance)?;
+        let body = self.get_element(0).ok_or_else(|| serde::ser::Error::custom("Document has no body"))?;
+        map.serialize_entry("children", &[body])?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Document {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Document::from_jsondocument(JsonDocument::deserialize(deserializer)?, DocumentProperties::default()).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Document {
+    ///Load a FoLiA document from its FoLiA-JSON representation
+    pub fn from_json(data: &str, properties: DocumentProperties) -> Result<Self, FoliaError> {
+        let jsondoc: JsonDocument = serde_json::from_str(data).map_err(|e| FoliaError::ParseError(format!("Invalid FoLiA-JSON: {}", e)))?;
+        Self::from_jsondocument(jsondoc, properties)
+    }
+
+    ///Serialises the document to FoLiA-JSON
+    pub fn json(&self, pretty: bool) -> Result<String, FoliaError> {
+        if pretty {
+            serde_json::to_string_pretty(self)
+        } else {
+            serde_json::to_string(self)
+        }.map_err(|e| FoliaError::SerialisationError(format!("{}", e)))
+    }
+
+    ///Serialises the element with the specified key, and everything under it, to FoLiA-JSON
+    pub fn json_element(&self, key: ElementKey, pretty: bool) -> Result<String, FoliaError> {
+        let element = self.get_element(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+        if pretty {
+            serde_json::to_string_pretty(&element)
+        } else {
+            serde_json::to_string(&element)
+        }.map_err(|e| FoliaError::SerialisationError(format!("{}", e)))
+    }
+
+    ///Adds an element (and everything under it) from its FoLiA-JSON representation as a child of the specified parent.
+    ///Returns the key of the added element.
+    pub fn add_json_to(&mut self, parent_key: ElementKey, data: &str) -> Result<ElementKey, FoliaError> {
+        let element: ElementData = serde_json::from_str(data).map_err(|e| FoliaError::ParseError(format!("Invalid FoLiA-JSON: {}", e)))?;
+        self.add_tree_to(parent_key, element)
+    }
+
+    fn from_jsondocument(jsondoc: JsonDocument, properties: DocumentProperties) -> Result<Self, FoliaError> {
+        let mut doc = Self {
+                            id: jsondoc.id,
+                            filename: None,
+                            version: jsondoc.version.unwrap_or_else(|| FOLIAVERSION.to_string()),
+                            elementstore: ElementStore::default(),
+                            provenancestore: ProvenanceStore::default(),
+                            declarationstore: DeclarationStore::default(),
+                            metadata: JsonMetadata { data: jsondoc.metadata, src: jsondoc.metadatasrc, metadatatype: jsondoc.metadatatype, foreign: jsondoc.metadataforeign }.into_metadata(),
+                            submetadata: HashMap::default(),
+                            autodeclare: properties.autodeclare,
+                            history: History::default(),
+                            journal: None,
+                            active_processor: None,
+        };
+        for (id, submetadata) in jsondoc.submetadata {
+            doc.submetadata.insert(id, submetadata.into_metadata());
+        }
+        for processor in jsondoc.provenance {
+            doc.add_processor(processor.into_processor()?)?;
+        }
+        for jsondeclaration in jsondoc.declarations {
+            let mut declaration = Declaration::new(jsondeclaration.annotationtype()?, jsondeclaration.set.clone(), jsondeclaration.alias.clone(), jsondeclaration.format.clone());
+            for processor_id in jsondeclaration.processors.iter() {
+                let proc_key = doc.get_processor_key_by_id(processor_id).ok_or_else(|| FoliaError::ParseError(format!("Declaration refers to undefined processor {}", processor_id)))?;
+                declaration.processors.push(proc_key);
+            }
+            doc.add_declaration(declaration)?;
+        }
+
+        let mut children = jsondoc.children.into_iter();
+        let mut body = children.next().ok_or_else(|| FoliaError::ParseError("FoLiA-JSON document has no body".to_string()))?;
+        if children.next().is_some() {
+            return Err(FoliaError::ParseError("FoLiA-JSON document has multiple bodies".to_string()));
+        }
+        if body.elementtype != ElementType::Text && body.elementtype != ElementType::Speech {
+            return Err(FoliaError::ParseError(format!("Expected text or speech as body, got {}", body.elementtype)));
+        }
+        let data = std::mem::take(&mut body.data);
+        doc.add(body, None)?;
+        doc.apply_properties(properties)?;
+        for item in data {
+            doc.add_tree_item(0, item)?;
+        }
+        Ok(doc)
+    }
+
+    ///Adds an element and the elements to be added under it, one by one, so that each element is
+    ///attached to its parent before its children are added (features need their parent as context).
+    pub(crate) fn add_tree_to(&mut self, parent_key: ElementKey, mut element: ElementData) -> Result<ElementKey, FoliaError> {
+        let data = std::mem::take(&mut element.data);
+        let key = self.add_element_to(parent_key, element)?;
+        for item in data {
+            self.add_tree_item(key, item)?;
+        }
+        Ok(key)
+    }
+
+    pub(crate) fn add_tree_item(&mut self, parent_key: ElementKey, item: DataType) -> Result<(), FoliaError> {
+        match item {
+            DataType::AddElement(element) => {
+                self.add_tree_to(parent_key, element)?;
+            },
+            DataType::Element(_) | DataType::SpanReference(_) => {
+                return Err(FoliaError::InternalError("Unable to add a reference to an existing element".to_string()));
+            },
+            item => {
+                let parent = self.get_mut_elementdata(parent_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", parent_key)))?;
+                parent.push(item);
+            }
+        }
+        Ok(())
+    }
+}
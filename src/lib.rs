@@ -8,6 +8,7 @@ extern crate strum_macros;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
 
 extern crate rand;
 extern crate hex;
@@ -28,6 +29,29 @@ pub mod document;
 pub mod parser;
 pub mod serialiser;
 pub mod specification;
+pub mod plaintext;
+pub mod conllu;
+pub mod json;
+pub mod standoff;
+pub mod conversion;
+pub mod tei;
+pub mod naf;
+pub mod html;
+pub mod subdocument;
+pub mod diff;
+pub mod agreement;
+pub mod evaluation;
+pub mod ids;
+pub mod edit;
+pub mod transaction;
+pub mod journal;
+pub mod provenance;
+pub mod upgrade;
+pub mod version;
+pub mod submetadata;
+pub mod foreign;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
 
 
 pub use common::*;
@@ -43,6 +67,29 @@ pub use select::*;
 pub use specification::*;
 pub use text::*;
 pub use metadata::*;
+pub use plaintext::*;
+pub use conllu::*;
+pub use json::*;
+pub use standoff::*;
+pub use conversion::*;
+pub use tei::*;
+pub use naf::*;
+pub use html::*;
+pub use subdocument::*;
+pub use diff::*;
+pub use agreement::*;
+pub use evaluation::*;
+pub use ids::*;
+pub use edit::*;
+pub use transaction::*;
+pub use journal::*;
+pub use provenance::*;
+pub use upgrade::*;
+pub use version::*;
+pub use submetadata::*;
+pub use foreign::*;
+#[cfg(feature = "snapshot")]
+pub use snapshot::*;
 
 
 
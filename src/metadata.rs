@@ -12,6 +12,7 @@ use crate::error::*;
 use crate::types::*;
 use crate::store::*;
 use crate::document::*;
+use crate::foreign::*;
 
 
 ///Represent a declaration for a particular annotation type, a set (optional), and associated with
@@ -538,11 +539,44 @@ impl Storable<ProcKey> for Processor {
 #[derive(Default,Clone)]
 ///A key/value store (``data``) containing arbitrary metadata (FoLiA native metadata)
 ///Instead of using the key/value store, it may also refer to an external metadata source
-///(``src``).
+///(``src``), or embed metadata in another format (``foreign``).
 pub struct Metadata {
     pub data: HashMap<String,String>,
     pub src: Option<String>,
     pub metadatatype: Option<String>,
+    ///Foreign metadata (e.g. CMDI, IMDI or Dublin Core) as raw XML, one item per ``foreign-data`` block
+    pub foreign: Vec<ForeignData>,
+    ///The external metadata referenced by ``src``, if it was loaded. This is never serialised.
+    pub external: Option<ForeignData>,
 }
 
 
+impl Metadata {
+    ///Creates a new, empty, metadata block. Use the ``with_*`` methods to populate it.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Sets a (native) metadata field
+    pub fn with_meta(mut self, meta_id: &str, value: &str) -> Self {
+        self.data.insert(meta_id.to_string(), value.to_string());
+        self
+    }
+
+    ///Sets the external metadata source
+    pub fn with_src(mut self, src: &str) -> Self {
+        self.src = Some(src.to_string());
+        self
+    }
+
+    ///Sets the metadata type (e.g. ``native``, ``cmdi``, ``imdi``)
+    pub fn with_type(mut self, metadatatype: &str) -> Self {
+        self.metadatatype = Some(metadatatype.to_string());
+        self
+    }
+
+    ///Returns the value of a (native) metadata field
+    pub fn get(&self, meta_id: &str) -> Option<&str> {
+        self.data.get(meta_id).map(|value| value.as_str())
+    }
+}
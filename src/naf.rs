@@ -0,0 +1,611 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::str;
+
+use quick_xml::{Reader,Writer};
+use quick_xml::events::{Event,BytesStart,BytesEnd,BytesText};
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::attrib::*;
+use crate::query::*;
+use crate::select::*;
+use crate::text::*;
+use crate::conllu::set_cmp;
+use crate::conversion::*;
+use crate::document::*;
+
+///The NAF version that is produced
+pub const NAFVERSION: &str = "v3.1";
+
+///The NAF layers that are converted, all others are reported
+const NAF_LAYERS: &[&str] = &["nafHeader", "raw", "text", "terms", "entities", "chunks", "deps"];
+
+const NL: &[u8] = b"\n";
+
+#[derive(Clone,Default)]
+///Parameters for NAF conversion (``Document::to_naf()`` and ``Document::from_naf()``).
+///For export, a set of ``None`` matches any set; for import, it declares the annotation type without a set.
+pub struct NafParameters {
+    ///Determines which text is extracted for the word forms (text set, text class)
+    pub textparameters: TextParameters,
+    ///The set for the part-of-speech tags of terms
+    pub posset: Option<String>,
+    ///The set for the lemmas of terms
+    pub lemmaset: Option<String>,
+    ///The set for named entities
+    pub entityset: Option<String>,
+    ///The set for chunks
+    pub chunkset: Option<String>,
+    ///The set for dependency relations
+    pub dependencyset: Option<String>,
+}
+
+impl NafParameters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_textparameters(mut self, textparameters: TextParameters) -> Self {
+        self.textparameters = textparameters;
+        self
+    }
+
+    pub fn with_posset(mut self, set: Option<String>) -> Self {
+        self.posset = set;
+        self
+    }
+
+    pub fn with_lemmaset(mut self, set: Option<String>) -> Self {
+        self.lemmaset = set;
+        self
+    }
+
+    pub fn with_entityset(mut self, set: Option<String>) -> Self {
+        self.entityset = set;
+        self
+    }
+
+    pub fn with_chunkset(mut self, set: Option<String>) -> Self {
+        self.chunkset = set;
+        self
+    }
+
+    pub fn with_dependencyset(mut self, set: Option<String>) -> Self {
+        self.dependencyset = set;
+        self
+    }
+}
+
+///A word form in the NAF text layer
+struct NafWordform {
+    id: String,
+    text: String,
+    sentence: String,
+    paragraph: Option<String>,
+    offset: Option<usize>,
+    length: Option<usize>,
+}
+
+///A term, entity or chunk in NAF: an annotation over one or more targets
+#[derive(Default)]
+struct NafSpan {
+    id: String,
+    class: Option<String>,
+    lemma: Option<String>,
+    targets: Vec<String>,
+}
+
+///A dependency relation in NAF, between terms
+struct NafDependency {
+    from: String,
+    to: String,
+    class: Option<String>,
+}
+
+impl Document {
+    ///Exports the document (or the subtree under ``root_key``) to NAF. Words map to word forms in the text layer
+    ///(with offsets into the raw layer) and to terms carrying the part-of-speech and lemma annotations. Named entities,
+    ///chunks and dependency relations map to the entities, chunks and deps layers. Sentences and paragraphs
+    ///map to ``@sent`` and ``@para``. All other content can not be mapped and is reported in the returned ``ConversionReport``.
+    pub fn to_naf(&self, root_key: ElementKey, parameters: &NafParameters) -> Result<(String,ConversionReport),FoliaError> {
+        let mut report = ConversionReport::new();
+        self.naf_report(root_key, parameters, &mut report);
+        for key in self.metadata.data.keys() {
+            if key != "title" && key != "language" {
+                report.add(&format!("metadata/{}", key));
+            }
+        }
+
+        //the raw text and the word forms, one term per word
+        let textparameters = parameters.textparameters.clone().retaintokenisation(true);
+        let mut raw = String::new();
+        let mut length = 0; //in unicode points
+        let mut wordforms: Vec<NafWordform> = Vec::new();
+        let mut terms: HashMap<ElementKey,usize> = HashMap::new(); //word keys to term numbers
+        let mut paragraphs: HashMap<ElementKey,usize> = HashMap::new();
+        let mut sentencenr = 0;
+        for sentence_key in self.sentence_keys(root_key) {
+            let sentence = self.get_element(sentence_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", sentence_key)))?;
+            let paragraph = match sentence.get_ancestor(ElementType::Paragraph, Cmp::Any).and_then(|p| p.key()) {
+                Some(paragraph_key) => {
+                    let paragraphnr = paragraphs.len() + 1;
+                    Some(*paragraphs.entry(paragraph_key).or_insert(paragraphnr))
+                },
+                None => None,
+            };
+            let mut space = false;
+            let mut first = true;
+            for word_key in self.word_keys(sentence_key) {
+                let word = self.get_element(word_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", word_key)))?;
+                let text = match word.text(&textparameters) {
+                    Ok(text) => text,
+                    Err(FoliaError::NoTextError(_)) => continue,
+                    Err(err) => return Err(err),
+                };
+                if first {
+                    sentencenr += 1;
+                    if !wordforms.is_empty() {
+                        raw.push('\n');
+                        length += 1;
+                    }
+                } else if space {
+                    raw.push(' ');
+                    length += 1;
+                }
+                first = false;
+                let textlength = text.chars().count();
+                terms.insert(word_key, wordforms.len() + 1);
+                wordforms.push(NafWordform {
+                    id: format!("w{}", wordforms.len() + 1),
+                    text: text.clone(),
+                    sentence: sentencenr.to_string(),
+                    paragraph: paragraph.map(|p| p.to_string()),
+                    offset: Some(length),
+                    length: Some(textlength),
+                });
+                raw += &text;
+                length += textlength;
+                space = word.attrib(AttribType::SPACE) != Some(&Attribute::Space(false));
+            }
+        }
+
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        let language = self.metadata.data.get("language").map(|s| s.as_str()).unwrap_or("und");
+        let mut naf_start = BytesStart::borrowed_name(b"NAF");
+        naf_start.push_attribute(("xml:lang", language));
+        naf_start.push_attribute(("version", NAFVERSION));
+        writer.write_event(Event::Start(naf_start)).map_err(to_serialisation_error)?;
+        writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+
+        writer.write_event(Event::Start(BytesStart::borrowed_name(b"nafHeader"))).map_err(to_serialisation_error)?;
+        let mut filedesc = BytesStart::borrowed_name(b"fileDesc");
+        filedesc.push_attribute(("title", self.metadata.data.get("title").map(|s| s.as_str()).unwrap_or_else(|| self.id())));
+        writer.write_event(Event::Empty(filedesc)).map_err(to_serialisation_error)?;
+        let mut public = BytesStart::borrowed_name(b"public");
+        public.push_attribute(("publicId", self.id()));
+        writer.write_event(Event::Empty(public)).map_err(to_serialisation_error)?;
+        writer.write_event(Event::End(BytesEnd::borrowed(b"nafHeader"))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+
+        writer.write_event(Event::Start(BytesStart::borrowed_name(b"raw"))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::Text(BytesText::from_plain_str(raw.as_str()))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::End(BytesEnd::borrowed(b"raw"))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+
+        naf_layer_start(&mut writer, "text")?;
+        for wordform in wordforms.iter() {
+            let mut wf = BytesStart::borrowed_name(b"wf");
+            wf.push_attribute(("id", wordform.id.as_str()));
+            wf.push_attribute(("sent", wordform.sentence.as_str()));
+            if let Some(paragraph) = &wordform.paragraph {
+                wf.push_attribute(("para", paragraph.as_str()));
+            }
+            wf.push_attribute(("offset", wordform.offset.unwrap_or(0).to_string().as_str()));
+            wf.push_attribute(("length", wordform.length.unwrap_or(0).to_string().as_str()));
+            writer.write_event(Event::Start(wf)).map_err(to_serialisation_error)?;
+            writer.write_event(Event::Text(BytesText::from_plain_str(wordform.text.as_str()))).map_err(to_serialisation_error)?;
+            writer.write_event(Event::End(BytesEnd::borrowed(b"wf"))).map_err(to_serialisation_error)?;
+            writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+        }
+        naf_layer_end(&mut writer, "text")?;
+
+        let mut word_keys: Vec<(&ElementKey,&usize)> = terms.iter().collect();
+        word_keys.sort_by_key(|(_, termnr)| **termnr);
+        naf_layer_start(&mut writer, "terms")?;
+        for (word_key, termnr) in word_keys {
+            let word = self.get_element(*word_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", word_key)))?;
+            let mut term = BytesStart::borrowed_name(b"term");
+            let id = format!("t{}", termnr);
+            term.push_attribute(("id", id.as_str()));
+            let lemma = word.get_annotation(AnnotationType::LEMMA, set_cmp(&parameters.lemmaset), Recursion::No);
+            if let Some(lemma) = lemma.as_ref().and_then(|lemma| lemma.class()) {
+                term.push_attribute(("lemma", lemma));
+            }
+            let pos = word.get_annotation(AnnotationType::POS, set_cmp(&parameters.posset), Recursion::No);
+            if let Some(pos) = pos.as_ref().and_then(|pos| pos.class()) {
+                term.push_attribute(("pos", pos));
+            }
+            writer.write_event(Event::Start(term)).map_err(to_serialisation_error)?;
+            naf_targets(&mut writer, &[format!("w{}", termnr)])?;
+            writer.write_event(Event::End(BytesEnd::borrowed(b"term"))).map_err(to_serialisation_error)?;
+            writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+        }
+        naf_layer_end(&mut writer, "terms")?;
+
+        let root = self.get_element(root_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", root_key)))?;
+        let span_terms = |span: &Element| -> Vec<String> {
+            let mut termnrs: Vec<usize> = span.get_elements(ElementType::WordReference, Cmp::Any, Recursion::No)
+                                              .filter_map(|wref| wref.resolve().and_then(|word| word.key()).and_then(|key| terms.get(&key).copied()))
+                                              .collect();
+            termnrs.sort_unstable();
+            termnrs.into_iter().map(|termnr| format!("t{}", termnr)).collect()
+        };
+
+        let entities: Vec<(Option<String>,Vec<String>)> = root.get_elements(ElementType::Entity, set_cmp(&parameters.entityset), Recursion::Always)
+                                                            .map(|entity| (entity.class().map(|s| s.to_string()), span_terms(&entity)))
+                                                            .filter(|(_, targets)| !targets.is_empty())
+                                                            .collect();
+        if !entities.is_empty() {
+            naf_layer_start(&mut writer, "entities")?;
+            for (i, (class, targets)) in entities.iter().enumerate() {
+                let mut entity = BytesStart::borrowed_name(b"entity");
+                let id = format!("e{}", i + 1);
+                entity.push_attribute(("id", id.as_str()));
+                if let Some(class) = class {
+                    entity.push_attribute(("type", class.as_str()));
+                }
+                writer.write_event(Event::Start(entity)).map_err(to_serialisation_error)?;
+                writer.write_event(Event::Start(BytesStart::borrowed_name(b"references"))).map_err(to_serialisation_error)?;
+                naf_targets(&mut writer, targets)?;
+                writer.write_event(Event::End(BytesEnd::borrowed(b"references"))).map_err(to_serialisation_error)?;
+                writer.write_event(Event::End(BytesEnd::borrowed(b"entity"))).map_err(to_serialisation_error)?;
+                writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+            }
+            naf_layer_end(&mut writer, "entities")?;
+        }
+
+        let chunks: Vec<(Option<String>,Vec<String>)> = root.get_elements(ElementType::Chunk, set_cmp(&parameters.chunkset), Recursion::Always)
+                                                          .map(|chunk| (chunk.class().map(|s| s.to_string()), span_terms(&chunk)))
+                                                          .filter(|(_, targets)| !targets.is_empty())
+                                                          .collect();
+        if !chunks.is_empty() {
+            naf_layer_start(&mut writer, "chunks")?;
+            for (i, (class, targets)) in chunks.iter().enumerate() {
+                let mut chunk = BytesStart::borrowed_name(b"chunk");
+                let id = format!("c{}", i + 1);
+                chunk.push_attribute(("id", id.as_str()));
+                chunk.push_attribute(("head", targets[0].as_str()));
+                if let Some(class) = class {
+                    chunk.push_attribute(("phrase", class.as_str()));
+                }
+                writer.write_event(Event::Start(chunk)).map_err(to_serialisation_error)?;
+                naf_targets(&mut writer, targets)?;
+                writer.write_event(Event::End(BytesEnd::borrowed(b"chunk"))).map_err(to_serialisation_error)?;
+                writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+            }
+            naf_layer_end(&mut writer, "chunks")?;
+        }
+
+        let mut dependencies: Vec<NafDependency> = Vec::new();
+        for dependency in root.get_elements(ElementType::Dependency, set_cmp(&parameters.dependencyset), Recursion::Always) {
+            let head = dependency.get_element(ElementType::Headspan, Cmp::Any, Recursion::No).map(|hd| span_terms(&hd)).unwrap_or_default();
+            let dependent = dependency.get_element(ElementType::DependencyDependent, Cmp::Any, Recursion::No).map(|dep| span_terms(&dep)).unwrap_or_default();
+            if let (Some(from), Some(to)) = (head.into_iter().next(), dependent.into_iter().next()) {
+                dependencies.push(NafDependency { from, to, class: dependency.class().map(|s| s.to_string()) });
+            }
+        }
+        if !dependencies.is_empty() {
+            naf_layer_start(&mut writer, "deps")?;
+            for dependency in dependencies.iter() {
+                let mut dep = BytesStart::borrowed_name(b"dep");
+                dep.push_attribute(("from", dependency.from.as_str()));
+                dep.push_attribute(("to", dependency.to.as_str()));
+                if let Some(class) = &dependency.class {
+                    dep.push_attribute(("rfunc", class.as_str()));
+                }
+                writer.write_event(Event::Empty(dep)).map_err(to_serialisation_error)?;
+                writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+            }
+            naf_layer_end(&mut writer, "deps")?;
+        }
+
+        writer.write_event(Event::End(BytesEnd::borrowed(b"NAF"))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+        let result = String::from_utf8(writer.into_inner().into_inner()).map_err(|err| FoliaError::SerialisationError(format!("{}", err)))?;
+        Ok((result, report))
+    }
+
+    ///Reports all elements under the specified element that have no counterpart in NAF
+    fn naf_report(&self, key: ElementKey, parameters: &NafParameters, report: &mut ConversionReport) {
+        let element = match self.get_element(key) {
+            Some(element) => element,
+            None => return,
+        };
+        let in_set = |set: &Option<String>| set.is_none() || element.set() == set.as_deref();
+        let parenttype = element.get_parent().map(|parent| parent.elementtype());
+        let mapped = match element.elementtype() {
+            ElementType::Text | ElementType::Paragraph | ElementType::Sentence | ElementType::Word => true,
+            ElementType::TextContent => return,
+            ElementType::PosAnnotation => parenttype == Some(ElementType::Word) && in_set(&parameters.posset),
+            ElementType::LemmaAnnotation => parenttype == Some(ElementType::Word) && in_set(&parameters.lemmaset),
+            ElementType::EntitiesLayer | ElementType::ChunkingLayer | ElementType::DependenciesLayer => true,
+            ElementType::Entity => in_set(&parameters.entityset),
+            ElementType::Chunk => in_set(&parameters.chunkset),
+            ElementType::Dependency => in_set(&parameters.dependencyset),
+            ElementType::WordReference | ElementType::Headspan | ElementType::DependencyDependent => parenttype.map(|parenttype| ElementGroup::Span.contains(parenttype) || parenttype == ElementType::Dependency).unwrap_or(false),
+            _ => false,
+        };
+        if !mapped {
+            report.add(element.elementtype().as_str());
+        }
+        if let Some(elementdata) = self.get_elementdata(key) {
+            for item in elementdata.data.iter() {
+                if let DataType::Element(child_key) = item {
+                    self.naf_report(*child_key, parameters, report);
+                }
+            }
+        }
+    }
+
+    ///Builds a new document from NAF. Word forms become words in sentences (and paragraphs if ``@para`` is
+    ///used), spacing is derived from the offsets. The part-of-speech tags and lemmas of single-word terms, named
+    ///entities, chunks and dependency relations are converted in the sets specified by the parameters. All
+    ///other layers and elements, as well as entities, chunks and dependencies whose targets can not be resolved,
+    ///are reported in the returned ``ConversionReport``.
+    pub fn from_naf(id: &str, data: &str, parameters: &NafParameters, properties: DocumentProperties) -> Result<(Self,ConversionReport), FoliaError> {
+        let mut report = ConversionReport::new();
+        let mut doc = Self::new(id, properties)?;
+        let mut wordforms: Vec<NafWordform> = Vec::new();
+        let mut terms: Vec<NafSpan> = Vec::new();
+        let mut entities: Vec<NafSpan> = Vec::new();
+        let mut chunks: Vec<NafSpan> = Vec::new();
+        let mut dependencies: Vec<NafDependency> = Vec::new();
+
+        let mut reader = Reader::from_str(data);
+        reader.trim_text(false);
+        let mut buf = Vec::new();
+        let mut path: Vec<String> = Vec::new();
+        let mut text = String::new();
+        loop {
+            let event = reader.read_event(&mut buf)?;
+            match event {
+                Event::Start(ref e) | Event::Empty(ref e) => {
+                    let tag = reader.decode(e.local_name())?.to_string();
+                    let mut attribs: HashMap<String,String> = HashMap::new();
+                    for attrib in e.attributes() {
+                        let attrib = attrib?;
+                        let value = attrib.unescape_and_decode_value(&reader)?;
+                        attribs.insert(reader.decode(attrib.key)?.to_string(), value);
+                    }
+                    let layer = path.get(1).map(|s| s.as_str());
+                    match (path.len(), layer, tag.as_str()) {
+                        (0, _, "NAF") => {
+                            if let Some(language) = attribs.remove("xml:lang") {
+                                doc.metadata.data.insert("language".to_string(), language);
+                            }
+                        },
+                        (1, _, tag) if !NAF_LAYERS.contains(&tag) => {
+                            report.add(tag);
+                        },
+                        (1, _, _) => {},
+                        (_, Some("nafHeader"), "fileDesc") => {
+                            if let Some(title) = attribs.remove("title") {
+                                doc.metadata.data.insert("title".to_string(), title);
+                            }
+                        },
+                        (_, Some("nafHeader"), _) => {},
+                        (2, Some("text"), "wf") => {
+                            let attrib = |name: &str| attribs.get(name).cloned();
+                            wordforms.push(NafWordform {
+                                id: attrib("id").ok_or_else(|| FoliaError::ParseError("NAF word form has no ID".to_string()))?,
+                                text: String::new(),
+                                sentence: attrib("sent").unwrap_or_default(),
+                                paragraph: attrib("para"),
+                                offset: attrib("offset").and_then(|s| s.parse().ok()),
+                                length: attrib("length").and_then(|s| s.parse().ok()),
+                            });
+                            text.clear();
+                        },
+                        (2, Some("terms"), "term") => {
+                            terms.push(NafSpan { id: attribs.remove("id").unwrap_or_default(), class: attribs.remove("pos"), lemma: attribs.remove("lemma"), targets: Vec::new() });
+                        },
+                        (2, Some("entities"), "entity") => {
+                            entities.push(NafSpan { class: attribs.remove("type"), ..NafSpan::default() });
+                        },
+                        (2, Some("chunks"), "chunk") => {
+                            chunks.push(NafSpan { class: attribs.remove("phrase"), ..NafSpan::default() });
+                        },
+                        (2, Some("deps"), "dep") => {
+                            if let (Some(from), Some(to)) = (attribs.remove("from"), attribs.remove("to")) {
+                                dependencies.push(NafDependency { from, to, class: attribs.remove("rfunc") });
+                            }
+                        },
+                        (_, Some("terms"), "span") | (_, Some("entities"), "references") | (_, Some("entities"), "span") | (_, Some("chunks"), "span") => {},
+                        (_, Some(layer), "target") if path.last().map(|s| s.as_str()) == Some("span") => {
+                            let span = match layer {
+                                "terms" => terms.last_mut(),
+                                "entities" => entities.last_mut(),
+                                "chunks" => chunks.last_mut(),
+                                _ => None,
+                            };
+                            if let (Some(span), Some(target)) = (span, attribs.remove("id")) {
+                                span.targets.push(target);
+                            }
+                        },
+                        (_, Some(layer), tag) => {
+                            report.add(&format!("{}/{}", layer, tag));
+                        },
+                        _ => {},
+                    }
+                    if let Event::Start(_) = event {
+                        path.push(tag);
+                    }
+                },
+                Event::End(_) => {
+                    if path.len() == 3 && path[1] == "text" {
+                        if let Some(wordform) = wordforms.last_mut() {
+                            wordform.text = text.trim().to_string();
+                        }
+                    }
+                    path.pop();
+                },
+                Event::Text(ref e) if path.len() == 3 && path[1] == "text" => {
+                    text += &e.unescape_and_decode(&reader)?;
+                },
+                Event::CData(ref e) if path.len() == 3 && path[1] == "text" => {
+                    text += reader.decode(e)?;
+                },
+                Event::Eof => break,
+                _ => {},
+            }
+            buf.clear();
+        }
+
+        //build the structure
+        doc.declare(AnnotationType::TOKEN, &None, &None, &None)?;
+        doc.declare(AnnotationType::SENTENCE, &None, &None, &None)?;
+        let mut word_ids: HashMap<&str,String> = HashMap::new(); //NAF word form IDs to FoLiA word IDs
+        let mut paragraph: Option<(&str,ElementKey)> = None;
+        let mut sentence: Option<(&str,ElementKey,String)> = None;
+        let mut paragraphnr = 0;
+        let mut sentencenr = 0;
+        let mut wordnr = 0;
+        for (i, wordform) in wordforms.iter().enumerate() {
+            let mut parent_key: ElementKey = 0;
+            if let Some(paragraph_id) = wordform.paragraph.as_deref() {
+                if paragraph.map(|(p, _)| p) != Some(paragraph_id) {
+                    doc.declare(AnnotationType::PARAGRAPH, &None, &None, &None)?;
+                    paragraphnr += 1;
+                    let paragraph_key = doc.annotate(0, ElementData::new(ElementType::Paragraph).with_attrib(Attribute::Id(format!("{}.p.{}", id, paragraphnr))))?;
+                    paragraph = Some((paragraph_id, paragraph_key));
+                    sentence = None;
+                }
+                parent_key = paragraph.expect("paragraph").1;
+            }
+            if sentence.as_ref().map(|(s, _, _)| *s) != Some(wordform.sentence.as_str()) {
+                sentencenr += 1;
+                wordnr = 0;
+                let sentence_id = format!("{}.s.{}", id, sentencenr);
+                let sentence_key = doc.annotate(parent_key, ElementData::new(ElementType::Sentence).with_attrib(Attribute::Id(sentence_id.clone())))?;
+                sentence = Some((wordform.sentence.as_str(), sentence_key, sentence_id));
+            }
+            let (_, sentence_key, sentence_id) = sentence.as_ref().expect("sentence");
+            wordnr += 1;
+            let word_id = format!("{}.w.{}", sentence_id, wordnr);
+            let mut word = ElementData::new(ElementType::Word)
+                                .with_attrib(Attribute::Id(word_id.clone()))
+                                .with_text(wordform.text.clone());
+            if let Some(next) = wordforms.get(i + 1) {
+                if let (Some(offset), Some(length), Some(nextoffset)) = (wordform.offset, wordform.length, next.offset) {
+                    if next.sentence == wordform.sentence && offset + length == nextoffset {
+                        word = word.with_attrib(Attribute::Space(false));
+                    }
+                }
+            }
+            doc.annotate(*sentence_key, word)?;
+            word_ids.insert(wordform.id.as_str(), word_id);
+        }
+
+        //terms map to the words they span
+        let mut term_words: HashMap<&str,Vec<&str>> = HashMap::new();
+        for term in terms.iter() {
+            let words: Vec<&str> = term.targets.iter().filter_map(|target| word_ids.get(target.as_str()).map(|s| s.as_str())).collect();
+            if words.len() == 1 {
+                let word_key = doc.get_element_key_by_id(words[0]).expect("word");
+                if let Some(pos) = &term.class {
+                    doc.declare(AnnotationType::POS, &parameters.posset, &None, &None)?;
+                    let mut data = ElementData::new(ElementType::PosAnnotation).with_attrib(Attribute::Class(pos.clone()));
+                    if let Some(set) = &parameters.posset {
+                        data = data.with_attrib(Attribute::Set(set.clone()));
+                    }
+                    doc.annotate(word_key, data)?;
+                }
+                if let Some(lemma) = &term.lemma {
+                    doc.declare(AnnotationType::LEMMA, &parameters.lemmaset, &None, &None)?;
+                    let mut data = ElementData::new(ElementType::LemmaAnnotation).with_attrib(Attribute::Class(lemma.clone()));
+                    if let Some(set) = &parameters.lemmaset {
+                        data = data.with_attrib(Attribute::Set(set.clone()));
+                    }
+                    doc.annotate(word_key, data)?;
+                }
+            } else if term.class.is_some() || term.lemma.is_some() {
+                report.add("terms/term (multi-word)");
+            }
+            term_words.insert(term.id.as_str(), words);
+        }
+        let span_words = |targets: &[String]| -> Vec<&str> {
+            targets.iter().filter_map(|target| term_words.get(target.as_str())).flatten().copied().collect()
+        };
+
+        for (elementtype, annotationtype, spans, set, label) in [(ElementType::Entity, AnnotationType::ENTITY, &entities, &parameters.entityset, "entities/entity"),
+                                                                 (ElementType::Chunk, AnnotationType::CHUNKING, &chunks, &parameters.chunkset, "chunks/chunk")].iter() {
+            for span in spans.iter() {
+                let words = span_words(&span.targets);
+                if words.is_empty() {
+                    report.add(&format!("{} (unresolved targets)", label));
+                    continue;
+                }
+                doc.declare(*annotationtype, set, &None, &None)?;
+                let mut data = ElementData::new(*elementtype).with_span(&words);
+                if let Some(set) = set {
+                    data = data.with_attrib(Attribute::Set(set.clone()));
+                }
+                if let Some(class) = &span.class {
+                    data = data.with_attrib(Attribute::Class(class.clone()));
+                }
+                doc.annotate_span(data)?;
+            }
+        }
+
+        for dependency in dependencies.iter() {
+            let head = span_words(std::slice::from_ref(&dependency.from));
+            let dependent = span_words(std::slice::from_ref(&dependency.to));
+            if head.is_empty() || dependent.is_empty() {
+                report.add("deps/dep (unresolved targets)");
+                continue;
+            }
+            doc.declare(AnnotationType::DEPENDENCY, &parameters.dependencyset, &None, &None)?;
+            let mut data = ElementData::new(ElementType::Dependency)
+                                .add_element(ElementData::new(ElementType::Headspan).with_span(&head))
+                                .add_element(ElementData::new(ElementType::DependencyDependent).with_span(&dependent));
+            if let Some(set) = &parameters.dependencyset {
+                data = data.with_attrib(Attribute::Set(set.clone()));
+            }
+            if let Some(class) = &dependency.class {
+                data = data.with_attrib(Attribute::Class(class.clone()));
+            }
+            let sentence_key = doc.get_element_by_id(dependent[0])
+                                  .and_then(|word| word.get_ancestor(ElementType::Sentence, Cmp::Any).and_then(|sentence| sentence.key()))
+                                  .expect("sentence");
+            doc.annotate(sentence_key, data)?;
+        }
+        Ok((doc, report))
+    }
+}
+
+fn naf_layer_start(writer: &mut Writer<Cursor<Vec<u8>>>, layer: &str) -> Result<(),FoliaError> {
+    writer.write_event(Event::Start(BytesStart::borrowed_name(layer.as_bytes()))).map_err(to_serialisation_error)?;
+    writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+    Ok(())
+}
+
+fn naf_layer_end(writer: &mut Writer<Cursor<Vec<u8>>>, layer: &str) -> Result<(),FoliaError> {
+    writer.write_event(Event::End(BytesEnd::borrowed(layer.as_bytes()))).map_err(to_serialisation_error)?;
+    writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+    Ok(())
+}
+
+///Writes a span of targets
+fn naf_targets(writer: &mut Writer<Cursor<Vec<u8>>>, targets: &[String]) -> Result<(),FoliaError> {
+    writer.write_event(Event::Start(BytesStart::borrowed_name(b"span"))).map_err(to_serialisation_error)?;
+    for target in targets.iter() {
+        let mut start = BytesStart::borrowed_name(b"target");
+        start.push_attribute(("id", target.as_str()));
+        writer.write_event(Event::Empty(start)).map_err(to_serialisation_error)?;
+    }
+    writer.write_event(Event::End(BytesEnd::borrowed(b"span"))).map_err(to_serialisation_error)?;
+    Ok(())
+}
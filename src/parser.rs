@@ -8,18 +8,22 @@ use std::string::ToString;
 use std::fmt::Display;
 use std::collections::HashMap;
 
-use quick_xml::Reader;
-use quick_xml::events::Event;
+use quick_xml::{Reader,Writer};
+use quick_xml::events::{Event,BytesStart};
 use chrono::NaiveDateTime;
 
 use crate::common::*;
 use crate::types::*;
+use crate::transaction::*;
+use crate::journal::*;
+use crate::upgrade::*;
 use crate::error::*;
 use crate::element::*;
 use crate::attrib::*;
 use crate::store::*;
 use crate::elementstore::*;
 use crate::metadata::*;
+use crate::foreign::*;
 use crate::select::*;
 use crate::document::*;
 use crate::specification::*;
@@ -42,6 +46,8 @@ impl Document {
                             metadata: Metadata::default(),
                             submetadata: HashMap::default(),
                             autodeclare: properties.autodeclare,
+                            history: History::default(),
+                            journal: None,
                             active_processor: None,
         };
 
@@ -86,14 +92,58 @@ impl Document {
         let mut parsedeclarations = false;
         let mut parseprovenance = false;
         let mut submetadata: Option<String> = None;
-        let mut submetadata_type: Option<String> = None;
         let mut text: Option<String> = None;
         let mut meta_id: Option<String> = None;
         let mut declaration_key: Option<DecKey> = None;
         let mut annotators: Vec<(DecKey,String)> = Vec::new(); //mapping of declaration keys to processor ids; temporary structure
+        let mut legacy_annotators: Vec<(DecKey,String,Option<ProcessorType>)> = Vec::new(); //old-style (FoLiA v1) default annotators of declarations
         let mut processor_stack: Vec<ProcKey> = vec![];
+        let mut foreign: Option<ForeignCapture> = None; //the foreign-data block being parsed
         loop {
             let e = reader.read_namespaced_event(&mut buf, &mut nsbuf)?;
+            if let Some(capture) = foreign.as_mut() {
+                //foreign metadata is copied as is, up to the end of the foreign-data element
+                let (_, e) = e;
+                match e {
+                    Event::End(_) if capture.depth == 0 => {
+                        let xml = foreign.take().expect("unwrapping foreign data").finish()?;
+                        let metadata = match &submetadata {
+                            Some(submetadata_id) => doc.submetadata.get_mut(submetadata_id).expect("unwrapping submetadata"),
+                            None => &mut doc.metadata,
+                        };
+                        metadata.foreign.push(ForeignData { xml });
+                    },
+                    Event::Start(e) => {
+                        capture.add_bindings(reader, &e, &nsbuf);
+                        capture.events.push(Event::Start(e.into_owned()));
+                        capture.depth += 1;
+                    },
+                    Event::Empty(e) => {
+                        capture.add_bindings(reader, &e, &nsbuf);
+                        capture.events.push(Event::Empty(e.into_owned()));
+                        if capture.depth == 0 {
+                            capture.flush()?;
+                        }
+                    },
+                    Event::End(e) => {
+                        capture.events.push(Event::End(e.into_owned()));
+                        capture.depth -= 1;
+                        if capture.depth == 0 {
+                            capture.flush()?;
+                        }
+                    },
+                    Event::Eof => {
+                        return Err(FoliaError::ParseError("Premature end of document".to_string()));
+                    },
+                    e if capture.depth == 0 => {
+                        capture.writer.write_event(e)?;
+                    },
+                    e => {
+                        capture.events.push(e.into_owned());
+                    }
+                }
+                continue;
+            }
             match e {
                 (ref ns, Event::Empty(ref e)) => {
                     match (*ns, e.local_name())  {
@@ -107,6 +157,10 @@ impl Document {
                                 doc.add_subprocessor(*parent_key, processor).map_err(FoliaError::add_parseerror("Error adding subprocessor"))?;
                             }
                         },
+                        (Some(ns), b"submetadata") if ns == NSFOLIA => {
+                            let (id, metadata) = Metadata::parse_submetadata(reader, e)?;
+                            doc.submetadata.insert(id, metadata);
+                        },
                         (Some(ns), b"annotator") if ns == NSFOLIA && parsedeclarations => {
                             let mut processor_id: Option<String> = None;
                             for attrib in e.attributes() {
@@ -131,6 +185,15 @@ impl Document {
                                 }
                             }
                         },
+                        (Some(ns), tag) if ns == NSFOLIA && parsedeclarations => {
+                            //a declaration without annotators
+                            let (declaration, annotator, annotatortype) = Declaration::parse(&reader, e, tag).map_err(FoliaError::add_parseerror("Error parsing declaration"))?;
+                            let result = doc.add_declaration(declaration).map_err(FoliaError::add_parseerror("Error adding declaration"))?;
+                            if let Some(annotator) = annotator {
+                                legacy_annotators.push((result, annotator, annotatortype));
+                            }
+                            declaration_key = None;
+                        },
                         _ => {
                         }
                     }
@@ -162,25 +225,12 @@ impl Document {
                             parseprovenance = true;
                         },
                         (Some(ns), b"submetadata") if ns == NSFOLIA => {
-                            for attrib in e.attributes() {
-                                let attrib = attrib.expect("unwrapping submetadata attribute");
-                                if let Ok(value) = attrib.unescape_and_decode_value(&reader) {
-                                    match attrib.key {
-                                        b"xml:id" => {
-                                            submetadata = Some(value.clone());
-                                        },
-                                        b"type" => {
-                                            submetadata_type = Some(value.clone());
-                                        },
-                                        otherwise => {
-                                            eprintln!("WARNING: Unhandled attribute submetadata/@{:?}",str::from_utf8(otherwise).unwrap());
-                                        }
-                                    }
-                                }
-                            }
-                            if submetadata.is_none() {
-                                return Err(FoliaError::ParseError("Submetadata has no ID".to_string()));
-                            }
+                            let (id, metadata) = Metadata::parse_submetadata(reader, e)?;
+                            doc.submetadata.insert(id.clone(), metadata);
+                            submetadata = Some(id);
+                        },
+                        (Some(ns), b"foreign-data") if ns == NSFOLIA => {
+                            foreign = Some(ForeignCapture::new());
                         },
                         (Some(ns), b"meta") if ns == NSFOLIA => {
                             for attrib in e.attributes() {
@@ -210,8 +260,11 @@ impl Document {
                             }
                         },
                         (Some(ns), tag) if ns == NSFOLIA && parsedeclarations => {
-                            let declaration = Declaration::parse(&reader, e, tag).map_err(FoliaError::add_parseerror("Error parsing declaration"))?;
+                            let (declaration, annotator, annotatortype) = Declaration::parse(&reader, e, tag).map_err(FoliaError::add_parseerror("Error parsing declaration"))?;
                             let result = doc.add_declaration(declaration).map_err(FoliaError::add_parseerror("Error adding declaration"))?;
+                            if let Some(annotator) = annotator {
+                                legacy_annotators.push((result, annotator, annotatortype));
+                            }
                             declaration_key = Some(result);
                         },
                         (Some(ns), tag) if ns == NSFOLIA => {
@@ -238,6 +291,16 @@ impl Document {
                             submetadata = None;
                         },
                         (Some(ns), b"metadata") if ns == NSFOLIA => {
+                            //old-style default annotators become processors
+                            for (dec_key, annotator, annotatortype) in legacy_annotators.iter() {
+                                let processor_id = doc.legacy_processor(annotator, *annotatortype).map_err(FoliaError::add_parseerror("Error adding processor for annotator"))?;
+                                let processor_key = doc.get_processor_key_by_id(&processor_id).expect("processor key");
+                                if let Some(declaration) = doc.get_mut_declaration(*dec_key) {
+                                    if !declaration.processors.contains(&processor_key) {
+                                        declaration.processors.push(processor_key);
+                                    }
+                                }
+                            }
                             break;
                         },
                         (Some(ns), b"annotations") if ns == NSFOLIA => {
@@ -262,11 +325,7 @@ impl Document {
                         (Some(ns), b"meta") if ns == NSFOLIA => {
                             if let (Some(text), Some(meta_id)) = (&text, &meta_id) {
                                 if let Some(submetadata_id) = &submetadata {
-                                    let submetadata_opt: Option<&mut Metadata> = doc.submetadata.get_mut(submetadata_id);
-                                    if let Some(submetadata) = submetadata_opt {
-                                        if submetadata.metadatatype.is_none() && submetadata_type.is_some() {
-                                            submetadata.metadatatype = submetadata_type.clone();
-                                        }
+                                    if let Some(submetadata) = doc.submetadata.get_mut(submetadata_id) {
                                         submetadata.data.insert(meta_id.clone(), text.clone());
                                     }
                                 } else {
@@ -331,6 +390,9 @@ impl Document {
             doc.add(body,None).map_err(FoliaError::add_parseerror("Error adding body"))?;
             doc.apply_properties(properties).map_err(FoliaError::add_parseerror("Error applying properties"))?;
             doc.parse_elements(reader, &mut buf, &mut nsbuf)?;
+            if is_legacy_version(&doc.version) {
+                doc.finish_upgrade().map_err(FoliaError::add_parseerror("Error upgrading document"))?;
+            }
             Ok(doc)
         } else {
             Err(FoliaError::ParseError("No body found".to_string()))
@@ -340,6 +402,7 @@ impl Document {
     ///Parses all elementsm from XML, this in turn invokes all parsers for the subelements
     pub(crate) fn parse_elements<R: BufRead>(&mut self, reader: &mut Reader<R>, mut buf: &mut Vec<u8>, mut nsbuf: &mut Vec<u8>) -> Result<(), FoliaError> {
         if !self.elementstore.items.is_empty() {
+            let legacy = is_legacy_version(&self.version);
             let mut stack: Vec<ElementKey> = vec![0]; //0 is the root/body element, we always start with it
             loop {
                 let e = reader.read_namespaced_event(&mut buf, &mut nsbuf)?;
@@ -351,6 +414,7 @@ impl Document {
                         let (elem, children) = ElementData::parse(reader, e)
                                                 .map_err(FoliaError::add_parseerror_string(
                                                          format!("Error parsing <{}/>", tagname))  )?;
+                        let elem = if legacy { self.upgrade_legacy_attribs(elem)? } else { elem };
                         let key = self.add(elem,stack.last().map(|key| *key))
                                                 .map_err(FoliaError::add_parseerror_string(
                                                          format!("Error adding <{}/>", tagname))  )?;
@@ -373,6 +437,7 @@ impl Document {
                         let (elem, children) = ElementData::parse(reader, e)
                                                 .map_err(FoliaError::add_parseerror_string(
                                                          format!("Error parsing <{}>", tagname))  )?;
+                        let elem = if legacy { self.upgrade_legacy_attribs(elem)? } else { elem };
                         let key = self.add(elem,stack.last().map(|key| *key))
                                                 .map_err(FoliaError::add_parseerror_string(
                                                          format!("Error adding <{}>", tagname))  )?;
@@ -395,7 +460,7 @@ impl Document {
 
                             //verify we actually close the right thing (otherwise we have malformed XML)
                             let elementname = str::from_utf8(e.local_name()).expect("Decoding XML tag from utf-8");
-                            let elementtype = ElementType::from_str(elementname)?;
+                            let elementtype = parse_elementtype(elementname)?;
                             if elem.elementtype != elementtype {
                                 return Err(FoliaError::ParseError(format!("Malformed XML? Invalid element closed: {}, expected: {}", elementname, elem.elementtype.to_string() )));
                             }
@@ -457,7 +522,7 @@ fn get_declaration_type(tag: &str) -> Result<AnnotationType, FoliaError> {
         if suffix != "-annotation" {
             Err(FoliaError::ParseError(format!("Expected declaration element, got: {}", tag)))
         } else {
-            if let Some(annotationtype) = AnnotationType::from_str(declaration_type_string) {
+            if let Some(annotationtype) = parse_annotationtype(declaration_type_string) {
                 Ok(annotationtype)
             } else {
                 Err(FoliaError::ParseError(format!("Unknown declaration: {}", tag )))
@@ -520,6 +585,39 @@ impl Processor {
     }
 }
 
+impl Metadata {
+
+    ///Parse a submetadata element from XML, returns its ID along with the metadata. Any ``meta`` child
+    ///elements are added by the main parser in Document::parse()
+    pub(crate) fn parse_submetadata<R: BufRead>(reader: &Reader<R>, event: &quick_xml::events::BytesStart) -> Result<(String, Metadata), FoliaError> {
+        let mut id: Option<String> = None;
+        let mut metadata = Metadata::default();
+        for attrib in event.attributes() {
+            let attrib = attrib.expect("unwrapping submetadata attribute");
+            if let Ok(value) = attrib.unescape_and_decode_value(reader) {
+                match attrib.key {
+                    b"xml:id" => {
+                        id = Some(value);
+                    },
+                    b"type" => {
+                        metadata.metadatatype = Some(value);
+                    },
+                    b"src" => {
+                        metadata.src = Some(value);
+                    },
+                    otherwise => {
+                        eprintln!("WARNING: Unhandled attribute submetadata/@{:?}",str::from_utf8(otherwise).unwrap());
+                    }
+                }
+            }
+        }
+        match id {
+            Some(id) => Ok((id, metadata)),
+            None => Err(FoliaError::ParseError("Submetadata has no ID".to_string()))
+        }
+    }
+}
+
 impl ElementData {
     fn parse_attributes<R: BufRead>(reader: &Reader<R>, attribiter: quick_xml::events::attributes::Attributes, elementtype: ElementType) -> Result<(Vec<Attribute>,Vec<ElementData>), FoliaError> {
         let mut attributes: Vec<Attribute> = Vec::new();
@@ -564,7 +662,7 @@ impl ElementData {
     ///appended by the main parser in Document::parse_body()
     pub(crate) fn parse<R: BufRead>(reader: &Reader<R>, event: &quick_xml::events::BytesStart) -> Result<(ElementData,Vec<ElementData>), FoliaError> {
         let elementname = str::from_utf8(event.local_name()).expect("utf-8 decoding");
-        let elementtype = ElementType::from_str(elementname)?;
+        let elementtype = parse_elementtype(elementname)?;
         let (attributes, children) = ElementData::parse_attributes(reader, event.attributes(), elementtype)
                                      .map_err(FoliaError::add_parseerror_string(format!("Error parsing attributes for {}", elementname)))?;
         Ok((ElementData::new(elementtype).with_attribs(attributes), children))
@@ -572,11 +670,14 @@ impl ElementData {
 }
 
 impl Declaration {
-    pub(crate) fn parse<R: BufRead>(reader: &Reader<R>, event: &quick_xml::events::BytesStart, tag: &[u8]) -> Result<Declaration, FoliaError> {
+    ///Parses a declaration, also returns the old-style (FoLiA v1) default annotator and annotator type, if any
+    pub(crate) fn parse<R: BufRead>(reader: &Reader<R>, event: &quick_xml::events::BytesStart, tag: &[u8]) -> Result<(Declaration, Option<String>, Option<ProcessorType>), FoliaError> {
         let declaration_type = get_declaration_type(str::from_utf8(tag).expect("utf-8 decoding"))?;
         let mut set: Option<String> = None;
         let mut alias: Option<String> = None;
         let mut format: Option<String> = None;
+        let mut annotator: Option<String> = None;
+        let mut annotatortype: Option<ProcessorType> = None;
         for attrib in event.attributes() {
             let attrib = attrib.expect("unwrapping declaration attribute");
             if let Ok(value) = attrib.unescape_and_decode_value(&reader) {
@@ -591,10 +692,12 @@ impl Declaration {
                         format = Some(value.clone());
                     },
                     b"annotator" => {
-                        //TODO: handle old-style default
+                        annotator = Some(value.clone());
                     },
                     b"annotatortype" => {
-                        //TODO: handle old-style default
+                        if let Attribute::AnnotatorType(processortype) = Attribute::parse(reader, &attrib)? {
+                            annotatortype = Some(processortype);
+                        }
                     },
                     otherwise => {
                         eprintln!("WARNING: Unhandled attribute on declaration: @{:?}",str::from_utf8(otherwise).unwrap());
@@ -602,6 +705,84 @@ impl Declaration {
                 }
             }
         }
-        Ok(Declaration::new(declaration_type, set, alias, format))
+        Ok((Declaration::new(declaration_type, set, alias, format), annotator, annotatortype))
+    }
+}
+
+///A foreign-data block being copied as raw XML. Each top-level element is buffered until it is complete, so all
+///namespace bindings used in it can be declared on it, the foreign data may rely on declarations outside of it
+///(e.g. on the FoLiA root element).
+struct ForeignCapture {
+    writer: Writer<Vec<u8>>,
+    ///The events of the top-level element being copied
+    events: Vec<Event<'static>>,
+    ///The namespace bindings (prefix, namespace) used in the top-level element being copied, an empty prefix is the default namespace
+    bindings: Vec<(Vec<u8>,Vec<u8>)>,
+    ///The element depth in the foreign data
+    depth: usize,
+}
+
+impl ForeignCapture {
+    fn new() -> Self {
+        Self { writer: Writer::new(Vec::new()), events: Vec::new(), bindings: Vec::new(), depth: 0 }
+    }
+
+    ///Records the namespace bindings used by the element and its attributes
+    fn add_bindings<R: BufRead>(&mut self, reader: &Reader<R>, e: &BytesStart, nsbuf: &[u8]) {
+        if let (Some(ns), _) = reader.event_namespace(e.name(), nsbuf) {
+            self.add_binding(prefix(e.name()), ns);
+        }
+        for attrib in e.attributes().filter_map(|attrib| attrib.ok()) {
+            let prefix = prefix(attrib.key);
+            if prefix.is_empty() || prefix == b"xmlns" || prefix == b"xml" {
+                continue;
+            }
+            if let (Some(ns), _) = reader.attribute_namespace(attrib.key, nsbuf) {
+                self.add_binding(prefix, ns);
+            }
+        }
+    }
+
+    fn add_binding(&mut self, prefix: &[u8], ns: &[u8]) {
+        if !self.bindings.iter().any(|(bound_prefix, _)| bound_prefix.as_slice() == prefix) {
+            self.bindings.push((prefix.to_vec(), ns.to_vec()));
+        }
+    }
+
+    ///Writes the buffered top-level element, declaring the bindings it uses on its start tag
+    fn flush(&mut self) -> Result<(), FoliaError> {
+        let bindings = std::mem::take(&mut self.bindings);
+        for (i, event) in std::mem::take(&mut self.events).into_iter().enumerate() {
+            match event {
+                Event::Start(e) if i == 0 => self.writer.write_event(Event::Start(with_namespace_declarations(e, &bindings)))?,
+                Event::Empty(e) if i == 0 => self.writer.write_event(Event::Empty(with_namespace_declarations(e, &bindings)))?,
+                event => self.writer.write_event(event)?,
+            }
+        }
+        Ok(())
+    }
+
+    ///Returns the raw XML
+    fn finish(self) -> Result<String, FoliaError> {
+        String::from_utf8(self.writer.into_inner()).map_err(|e| FoliaError::ParseError(format!("Invalid UTF-8 in foreign data: {}", e)))
+    }
+}
+
+///Returns the prefix of a qualified name, or an empty slice if there is none
+fn prefix(name: &[u8]) -> &[u8] {
+    match name.iter().position(|c| *c == b':') {
+        Some(pos) => &name[..pos],
+        None => &[],
+    }
+}
+
+///Adds namespace declarations for the bindings to the start tag, unless it declares these prefixes itself
+fn with_namespace_declarations(mut e: BytesStart<'static>, bindings: &[(Vec<u8>,Vec<u8>)]) -> BytesStart<'static> {
+    for (prefix, ns) in bindings.iter() {
+        let key: Vec<u8> = if prefix.is_empty() { b"xmlns".to_vec() } else { [b"xmlns:", prefix.as_slice()].concat() };
+        if !e.attributes().filter_map(|attrib| attrib.ok()).any(|attrib| attrib.key == key.as_slice()) {
+            e.push_attribute((key.as_slice(), ns.as_slice()));
+        }
     }
+    e
 }
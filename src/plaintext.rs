@@ -0,0 +1,193 @@
+use std::fmt::Write;
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::query::*;
+use crate::select::*;
+use crate::text::*;
+use crate::document::*;
+
+///The value written for a column that has no annotation in the vertical format
+pub const VERTICAL_EMPTY: &str = "_";
+
+#[derive(Clone)]
+///Parameters for the plain text exporters (``to_text()``, ``to_tokenised_text()`` and ``to_vertical()``)
+pub struct ExportParameters {
+    ///Determines which text is extracted (text set, text class)
+    pub textparameters: TextParameters,
+    ///The set to draw Part-of-Speech tags from (``None`` matches any set)
+    pub posset: Option<String>,
+    ///The set to draw lemmas from (``None`` matches any set)
+    pub lemmaset: Option<String>,
+    ///Column separator for the vertical format
+    pub columndelimiter: String,
+}
+
+impl Default for ExportParameters {
+    fn default() -> Self {
+        Self {
+            textparameters: TextParameters::default(),
+            posset: None,
+            lemmaset: None,
+            columndelimiter: "\t".to_string(),
+        }
+    }
+}
+
+impl ExportParameters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_textparameters(mut self, textparameters: TextParameters) -> Self {
+        self.textparameters = textparameters;
+        self
+    }
+
+    pub fn with_posset(mut self, set: &str) -> Self {
+        self.posset = Some(set.to_string());
+        self
+    }
+
+    pub fn with_lemmaset(mut self, set: &str) -> Self {
+        self.lemmaset = Some(set.to_string());
+        self
+    }
+
+    pub fn with_columndelimiter(mut self, delimiter: &str) -> Self {
+        self.columndelimiter = delimiter.to_string();
+        self
+    }
+}
+
+impl Document {
+    ///Exports the document (or the subtree under ``root_key``) to plain text. Paragraph,
+    ///sentence and other structural delimiters are taken from the specification, as in ``text()``.
+    pub fn to_text(&self, root_key: ElementKey, parameters: &ExportParameters) -> Result<String,FoliaError> {
+        match self.text(root_key, &parameters.textparameters) {
+            Ok(text) => Ok(text.trim_end().to_string() + "\n"),
+            Err(FoliaError::NoTextError(_)) => Ok(String::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    ///Exports the document (or the subtree under ``root_key``) to tokenised text, with one sentence per line
+    ///and tokens delimited by a single space.
+    pub fn to_tokenised_text(&self, root_key: ElementKey, parameters: &ExportParameters) -> Result<String,FoliaError> {
+        let textparameters = parameters.textparameters.clone().retaintokenisation(true);
+        let mut output = String::new();
+        for unit_key in self.sentence_keys(root_key) {
+            let words = self.word_keys(unit_key);
+            let line = if words.is_empty() {
+                match self.text(unit_key, &textparameters) {
+                    Ok(text) => text.split_whitespace().collect::<Vec<&str>>().join(" "),
+                    Err(FoliaError::NoTextError(_)) => continue,
+                    Err(err) => return Err(err),
+                }
+            } else {
+                let mut tokens: Vec<String> = Vec::with_capacity(words.len());
+                for word_key in words {
+                    match self.text(word_key, &textparameters) {
+                        Ok(text) => tokens.push(text),
+                        Err(FoliaError::NoTextError(_)) => {},
+                        Err(err) => return Err(err),
+                    }
+                }
+                tokens.join(" ")
+            };
+            if !line.is_empty() {
+                output += &line;
+                output.push('\n');
+            }
+        }
+        Ok(output)
+    }
+
+    ///Exports the document (or the subtree under ``root_key``) to a vertical format with one
+    ///token per line, holding the columns word, part-of-speech and lemma. Sentences are separated by an
+    ///empty line. Missing annotations are written as ``VERTICAL_EMPTY``.
+    pub fn to_vertical(&self, root_key: ElementKey, parameters: &ExportParameters) -> Result<String,FoliaError> {
+        let posset = match &parameters.posset {
+            Some(set) => Cmp::Is(set.clone()),
+            None => Cmp::Any,
+        };
+        let lemmaset = match &parameters.lemmaset {
+            Some(set) => Cmp::Is(set.clone()),
+            None => Cmp::Any,
+        };
+        let delimiter = parameters.columndelimiter.as_str();
+        let mut output = String::new();
+        for sentence_key in self.sentence_keys(root_key) {
+            let words = self.word_keys(sentence_key);
+            if words.is_empty() {
+                continue;
+            }
+            for word_key in words {
+                let word = self.get_element(word_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", word_key)))?;
+                let text = match word.text(&parameters.textparameters) {
+                    Ok(text) => text,
+                    Err(FoliaError::NoTextError(_)) => VERTICAL_EMPTY.to_string(),
+                    Err(err) => return Err(err),
+                };
+                let pos = word.get_annotation(AnnotationType::POS, posset.clone(), Recursion::No).and_then(|pos| pos.class().map(|s| s.to_string()));
+                let lemma = word.get_annotation(AnnotationType::LEMMA, lemmaset.clone(), Recursion::No).and_then(|lemma| lemma.class().map(|s| s.to_string()));
+                writeln!(output, "{}{}{}{}{}", text, delimiter,
+                         pos.as_deref().unwrap_or(VERTICAL_EMPTY), delimiter,
+                         lemma.as_deref().unwrap_or(VERTICAL_EMPTY)
+                ).map_err(|e| FoliaError::SerialisationError(format!("{}", e)))?;
+            }
+            output.push('\n');
+        }
+        Ok(output)
+    }
+
+    ///Returns the keys of all sentences under the specified element (inclusive), in document order. If
+    ///there are no sentences, paragraphs are returned instead, and if there are no paragraphs
+    ///either, the element itself is returned.
+    pub(crate) fn sentence_keys(&self, root_key: ElementKey) -> Vec<ElementKey> {
+        for elementtype in [ElementType::Sentence, ElementType::Paragraph].iter() {
+            let mut keys: Vec<ElementKey> = Vec::new();
+            if self.get_elementdata(root_key).map(|e| e.elementtype) == Some(*elementtype) {
+                keys.push(root_key);
+            } else {
+                self.collect_token_keys(root_key, *elementtype, &mut keys);
+            }
+            if !keys.is_empty() {
+                return keys;
+            }
+        }
+        vec![root_key]
+    }
+
+    ///Returns the keys of all words (tokens) under the specified element, in document order
+    pub(crate) fn word_keys(&self, key: ElementKey) -> Vec<ElementKey> {
+        let mut keys: Vec<ElementKey> = Vec::new();
+        self.collect_token_keys(key, ElementType::Word, &mut keys);
+        keys
+    }
+
+    ///Collects elements of the given type for the token-based exporters: descends into structure but not into
+    ///annotation layers (which only hold references), alternatives or non-current corrections.
+    fn collect_token_keys(&self, key: ElementKey, elementtype: ElementType, keys: &mut Vec<ElementKey>) {
+        if let Some(element) = self.get_elementdata(key) {
+            for item in element.data.iter() {
+                if let DataType::Element(child_key) = item {
+                    if let Some(child) = self.get_elementdata(*child_key) {
+                        if child.elementtype == elementtype {
+                            keys.push(*child_key);
+                        } else if !ElementGroup::Layer.contains(child.elementtype) &&
+                                  !ElementGroup::Span.contains(child.elementtype) &&
+                                  child.elementtype != ElementType::Alternative &&
+                                  child.elementtype != ElementType::AlternativeLayers &&
+                                  child.elementtype != ElementType::Original &&
+                                  child.elementtype != ElementType::Suggestion {
+                            self.collect_token_keys(*child_key, elementtype, keys);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
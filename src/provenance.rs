@@ -0,0 +1,234 @@
+use chrono::{NaiveDateTime,Timelike,Utc};
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::store::*;
+use crate::metadata::*;
+use crate::select::*;
+use crate::document::Document;
+
+#[derive(Debug,Clone,PartialEq)]
+///A processor in the provenance chain along with its subprocessors
+pub struct ProcessorTree {
+    pub key: ProcKey,
+    pub children: Vec<ProcessorTree>,
+}
+
+#[derive(Debug,Clone,PartialEq)]
+///What a processor contributed to a document
+pub struct ProcessorContribution {
+    pub key: ProcKey,
+    ///The declarations the processor is associated with
+    pub declarations: Vec<DecKey>,
+    ///The number of elements that were produced by the processor (not counting subprocessors)
+    pub annotations: usize,
+}
+
+impl Document {
+    ///Returns all processors (including subprocessors) in the provenance chain, depth-first
+    pub fn processors(&self) -> Vec<&Processor> {
+        let mut processors: Vec<&Processor> = Vec::new();
+        let mut stack: Vec<ProcKey> = self.provenancestore.chain.iter().rev().copied().collect();
+        while let Some(proc_key) = stack.pop() {
+            if let Some(processor) = self.get_processor(proc_key) {
+                processors.push(processor);
+                stack.extend(processor.processors.iter().rev());
+            }
+        }
+        processors
+    }
+
+    ///Returns the provenance chain as a tree of processors and their subprocessors
+    pub fn processor_tree(&self) -> Vec<ProcessorTree> {
+        self.provenancestore.chain.iter().filter_map(|key| self.processor_subtree(*key)).collect()
+    }
+
+    fn processor_subtree(&self, key: ProcKey) -> Option<ProcessorTree> {
+        let processor = self.get_processor(key)?;
+        Some(ProcessorTree {
+            key,
+            children: processor.processors.iter().filter_map(|child_key| self.processor_subtree(*child_key)).collect(),
+        })
+    }
+
+    ///Returns the keys of all processors with the specified name
+    pub fn find_processors_by_name(&self, name: &str) -> Vec<ProcKey> {
+        self.find_processors(|processor| processor.name == name)
+    }
+
+    ///Returns the keys of all processors with the specified version
+    pub fn find_processors_by_version(&self, version: &str) -> Vec<ProcKey> {
+        self.find_processors(|processor| processor.version == version)
+    }
+
+    ///Returns the keys of all processors of the specified type
+    pub fn find_processors_by_type(&self, processortype: ProcessorType) -> Vec<ProcKey> {
+        self.find_processors(|processor| processor.processortype == processortype)
+    }
+
+    ///Returns the keys of all processors in the provenance chain for which the predicate holds
+    pub fn find_processors<F>(&self, predicate: F) -> Vec<ProcKey>
+    where F: Fn(&Processor) -> bool {
+        self.processors().into_iter().filter(|processor| predicate(processor)).filter_map(|processor| processor.key()).collect()
+    }
+
+    ///Reports, for all processors in the provenance chain, which declarations they are associated
+    ///with and how many elements they produced
+    pub fn processor_contributions(&self) -> Vec<ProcessorContribution> {
+        let mut contributions: Vec<ProcessorContribution> = self.processors().into_iter().filter_map(|processor| processor.key()).map(|key| {
+            ProcessorContribution {
+                key,
+                declarations: self.declarationstore.items.iter().flatten().filter(|declaration| declaration.processors.contains(&key)).filter_map(|declaration| declaration.key()).collect(),
+                annotations: 0,
+            }
+        }).collect();
+        for key in self.processor_element_keys(None) {
+            let proc_key = self.get_element(key).and_then(|element| element.processor_key());
+            if let Some(contribution) = contributions.iter_mut().find(|contribution| Some(contribution.key) == proc_key) {
+                contribution.annotations += 1;
+            }
+        }
+        contributions
+    }
+
+    ///Removes all elements produced by the processor (and by its subprocessors if ``subprocessors`` is set), along
+    ///with everything under them. Span annotation layers that are left empty are removed as well. The
+    ///processor itself remains in the provenance chain. Returns the number of elements that were removed.
+    pub fn remove_annotations_by_processor(&mut self, key: ProcKey, subprocessors: bool) -> Result<usize, FoliaError> {
+        let processor = self.get_processor(key).ok_or_else(|| FoliaError::KeyError(format!("No such processor key: {}", key)))?;
+        let mut proc_keys: Vec<ProcKey> = vec!(key);
+        if subprocessors {
+            let mut stack: Vec<ProcKey> = processor.processors.clone();
+            while let Some(proc_key) = stack.pop() {
+                proc_keys.push(proc_key);
+                if let Some(subprocessor) = self.get_processor(proc_key) {
+                    stack.extend(subprocessor.processors.iter().copied());
+                }
+            }
+        }
+        let mut count = 0;
+        for element_key in self.processor_element_keys(Some(&proc_keys)) {
+            //skip elements that were already removed along with an ancestor
+            let parent_key = if let Some(element) = self.get_elementdata(element_key) { element.parent_key() } else { continue };
+            self.remove_element(element_key)?;
+            count += 1;
+            if let Some(parent_key) = parent_key {
+                if let Some(parent) = self.get_elementdata(parent_key) {
+                    if ElementGroup::Layer.contains(parent.elementtype) && !parent.data.iter().any(|item| matches!(item, DataType::Element(_))) {
+                        self.remove_element(parent_key)?;
+                    }
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    ///Returns the keys of all elements under the body with a processor (limited to the specified processors, if any), in document order
+    fn processor_element_keys(&self, proc_keys: Option<&[ProcKey]>) -> Vec<ElementKey> {
+        let mut keys: Vec<ElementKey> = Vec::new();
+        for item in self.select_data(Selector::elements(), Recursion::Always, false) {
+            if let DataType::Element(key) = item.data {
+                if let Some(proc_key) = self.get_element(*key).and_then(|element| element.processor_key()) {
+                    if proc_keys.map(|proc_keys| proc_keys.contains(&proc_key)).unwrap_or(true) {
+                        keys.push(*key);
+                    }
+                }
+            }
+        }
+        keys
+    }
+}
+
+///Keeps a processor active for as long as it is in scope. The guard dereferences to the document, so all edits
+///made through it are attributed to the processor. When the guard is committed or dropped, the end time (and
+///document version, if set) of the processor are recorded and the previously active processor is restored.
+pub struct ProcessorGuard<'a> {
+    document: &'a mut Document,
+    key: ProcKey,
+    previous: Option<ProcKey>,
+    document_version: Option<String>,
+}
+
+impl Document {
+    ///Adds the processor to the provenance chain and activates it for as long as the returned guard is in scope. The begin time is
+    ///set if the processor has none yet.
+    pub fn begin_processor(&mut self, processor: Processor) -> Result<ProcessorGuard<'_>, FoliaError> {
+        let key = self.add_processor(processor)?;
+        Ok(ProcessorGuard::new(self, key))
+    }
+}
+
+impl<'a> ProcessorGuard<'a> {
+    fn new(document: &'a mut Document, key: ProcKey) -> Self {
+        if let Some(processor) = timestamped_processor(document, key) {
+            if processor.begindatetime.is_none() {
+                processor.begindatetime = Some(now());
+            }
+        }
+        let previous = document.active_processor;
+        document.activate_processor(key);
+        Self { document, key, previous, document_version: None }
+    }
+
+    ///Returns the key of the processor
+    pub fn key(&self) -> ProcKey {
+        self.key
+    }
+
+    ///Adds the processor as a subprocessor (e.g. for a library component) and activates it for as long as the returned guard is in
+    ///scope, after which this processor is active again
+    pub fn begin_subprocessor(&mut self, processor: Processor) -> Result<ProcessorGuard<'_>, FoliaError> {
+        let key = self.document.add_subprocessor(self.key, processor)?;
+        Ok(ProcessorGuard::new(self.document, key))
+    }
+
+    ///Sets the version of the document that the processor produced, it is recorded when the guard is committed or dropped
+    pub fn set_document_version(&mut self, version: &str) {
+        self.document_version = Some(version.to_string());
+    }
+
+    ///Records the end time (and document version) of the processor and restores the previously active processor.
+    ///This is equivalent to dropping the guard.
+    pub fn commit(self) {}
+}
+
+impl<'a> std::ops::Deref for ProcessorGuard<'a> {
+    type Target = Document;
+
+    fn deref(&self) -> &Document {
+        self.document
+    }
+}
+
+impl<'a> std::ops::DerefMut for ProcessorGuard<'a> {
+    fn deref_mut(&mut self) -> &mut Document {
+        self.document
+    }
+}
+
+impl<'a> Drop for ProcessorGuard<'a> {
+    fn drop(&mut self) {
+        let document_version = self.document_version.take();
+        if let Some(processor) = timestamped_processor(self.document, self.key) {
+            processor.enddatetime = Some(now());
+            if let Some(document_version) = document_version {
+                processor.document_version = document_version;
+            }
+        }
+        self.document.active_processor = self.previous;
+    }
+}
+
+///Returns the processor so the guard can record its times. These are written to the store directly (as restoring a
+///checkpoint does) rather than through ``get_mut_processor()``, which would count as an edit outside of a transaction
+///and thereby discard the undo history.
+fn timestamped_processor(document: &mut Document, key: ProcKey) -> Option<&mut Processor> {
+    document.provenancestore.items.get_mut(key as usize).and_then(|item| item.as_deref_mut())
+}
+
+///Returns the current time, in seconds precision
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc().with_nanosecond(0).expect("valid time")
+}
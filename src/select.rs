@@ -46,10 +46,10 @@ impl Selector {
         selector.elementgroup = query.elementgroup.clone();
         selector.contexttype = query.contexttype.clone();
         selector.datatypes = vec![DataTypeSelector::Elements];
-        //if we have subsets, we use contexttype instead of elementtype (because elementtype will
+        //if we have subsets or select features, we use contexttype instead of elementtype (because elementtype will
         //always be ElementType::feature)
-        let elementtype_source: &Cmp<ElementType> = match &query.subset {
-            Cmp::Some | Cmp::Is(_) =>  &query.contexttype,
+        let elementtype_source: &Cmp<ElementType> = match (&query.subset, &query.elementtype) {
+            (Cmp::Some, _) | (Cmp::Is(_), _) | (_, Cmp::Is(ElementType::Feature)) =>  &query.contexttype,
             _ => &query.elementtype,
         };
         selector.set = match &query.set {
@@ -702,6 +702,31 @@ impl<'a> SelectAncestors<'a> for Element<'a> {
 }
 
 impl Document {
+    ///Returns the keys of all elements matching the selector, in document order. As the keys do not
+    ///borrow the document, this is the basis for editing multiple elements.
+    pub fn select_keys(&self, selector: Selector, recursion: Recursion) -> Vec<ElementKey> {
+        self.select_data(selector, recursion, false).filter_map(|item| {
+            if let DataType::Element(key) = item.data { Some(*key) } else { None }
+        }).collect()
+    }
+
+    ///Applies a function to all elements matching the selector, in document order. The function
+    ///receives the document and the key of the element, so it can use any of the editing
+    ///methods. Elements are selected before any changes are made; elements removed in the
+    ///meantime are skipped. Stops at the first error. Returns the number of elements the
+    ///function was applied to.
+    pub fn for_each_mut<F>(&mut self, selector: Selector, recursion: Recursion, mut f: F) -> Result<usize, FoliaError>
+    where F: FnMut(&mut Document, ElementKey) -> Result<(), FoliaError> {
+        let mut count = 0;
+        for key in self.select_keys(selector, recursion) {
+            if self.get_elementdata(key).is_some() {
+                f(self, key)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
     pub fn ancestors_by_key<'a>(&'a self, key: ElementKey, selector: Selector) -> AncestorIterator<'a> {
         AncestorIterator::new(self, selector, key, Recursion::Always)
     }
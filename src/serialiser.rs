@@ -19,17 +19,37 @@ use crate::store::*;
 use crate::elementstore::*;
 use crate::metadata::*;
 use crate::select::*;
+use crate::version::*;
 use crate::document::Document;
 
 const NL: &[u8] = b"\n";
 
-fn to_serialisation_error(err: quick_xml::Error) -> FoliaError {
-    FoliaError::SerialisationError(format!("{}",err))
-}
+///Fixed order of attributes in canonical serialisation, attributes not listed here follow in alphabetical order
+const CANONICAL_ATTRIBUTE_ORDER: &[&str] = &["xml:id", "set", "subset", "class", "processor", "annotator", "annotatortype", "confidence", "datetime", "n", "id", "t", "offset", "ref", "textclass", "space", "xlink:type", "xlink:href"];
 
 impl Document {
     ///Serialises a document to XML (vector of bytes, utf-8)
+    ///The document is written as the minimal FoLiA version it requires, see ``required_version()``.
     pub fn xml(&self, root_key: ElementKey, indent: usize) -> Result<Vec<u8>, FoliaError> {
+        self.xml_serialise(root_key, indent, false, self.required_version())
+    }
+
+    ///Serialises a document to XML for the specified (older) FoLiA version, fails if the document
+    ///uses annotation types that are not available in that version
+    pub fn xml_for_version(&self, root_key: ElementKey, indent: usize, version: FoliaVersion) -> Result<Vec<u8>, FoliaError> {
+        self.check_version(version)?;
+        self.xml_serialise(root_key, indent, false, version)
+    }
+
+    ///Serialises a document to canonical XML (vector of bytes, utf-8). The output is deterministic:
+    ///metadata is sorted by key, declarations by annotation type and set, attributes follow a
+    ///fixed order and whitespace in text is normalised. Serialising the same document twice
+    ///always yields identical bytes, which keeps diffs and checksums stable.
+    pub fn xml_canonical(&self, root_key: ElementKey) -> Result<Vec<u8>, FoliaError> {
+        self.xml_serialise(root_key, 0, true, self.required_version())
+    }
+
+    fn xml_serialise(&self, root_key: ElementKey, indent: usize, canonical: bool, version: FoliaVersion) -> Result<Vec<u8>, FoliaError> {
         let mut writer = match indent {
             0 => Writer::new(Cursor::new(Vec::new())),
             indent =>  Writer::new_with_indent(Cursor::new(Vec::new()), b' ', indent)
@@ -39,14 +59,15 @@ impl Document {
         doc_start.push_attribute(("xmlns", str::from_utf8(NSFOLIA).unwrap() ));
         doc_start.push_attribute(("xmlns:xlink", str::from_utf8(NSXLINK).unwrap() ));
         doc_start.push_attribute(("xml:id",self.id.as_str()));
-        doc_start.push_attribute(("version",FOLIAVERSION ));
+        let version = version.to_string();
+        doc_start.push_attribute(("version",version.as_str() ));
         doc_start.push_attribute(("generator", GENERATOR ));
         writer.write_event(Event::Start(doc_start)).map_err(to_serialisation_error)?;
         writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
 
-        self.xml_metadata(&mut writer)?;
+        self.xml_metadata(&mut writer, canonical)?;
 
-        self.xml_elements(&mut writer, root_key)?;
+        self.xml_elements(&mut writer, root_key, canonical)?;
 
         writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
         writer.write_event(Event::End(BytesEnd::borrowed(b"FoLiA"))).map_err(to_serialisation_error)?;
@@ -54,7 +75,7 @@ impl Document {
         Ok(result)
     }
 
-    fn xml_metadata(&self, writer: &mut Writer<Cursor<Vec<u8>>>) -> Result<(), FoliaError> {
+    fn xml_metadata(&self, writer: &mut Writer<Cursor<Vec<u8>>>, canonical: bool) -> Result<(), FoliaError> {
         let mut metadata_start = BytesStart::borrowed_name(b"metadata");
         if let Some(metadatatype) = &self.metadata.metadatatype {
             metadata_start.push_attribute(("type", metadatatype.as_str() ));
@@ -64,9 +85,9 @@ impl Document {
         }
         writer.write_event(Event::Start(metadata_start)).map_err(to_serialisation_error)?;
         writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
-        self.xml_declarations(writer)?;
+        self.xml_declarations(writer, canonical)?;
         self.xml_provenance(writer)?;
-        for (meta_id, value) in self.metadata.data.iter() {
+        for (meta_id, value) in sorted_if(self.metadata.data.iter().collect(), canonical) {
             let mut meta_start = BytesStart::borrowed_name(b"meta");
             meta_start.push_attribute(("id", meta_id.as_str() ));
             writer.write_event(Event::Start(meta_start)).map_err(to_serialisation_error)?;
@@ -74,17 +95,20 @@ impl Document {
             writer.write_event(Event::End(BytesEnd::borrowed(b"meta"))).map_err(to_serialisation_error)?;
             writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
         }
+        xml_foreign_data(writer, &self.metadata)?;
         //there is a bit too much duplication going on here, to be solved later
-        for (submetadata_id, submetadata) in self.submetadata.iter() {
+        for (submetadata_id, submetadata) in sorted_if(self.submetadata.iter().collect(), canonical) {
             let mut submetadata_start = BytesStart::borrowed_name(b"submetadata");
             submetadata_start.push_attribute(("xml:id", submetadata_id.as_str() ));
             if let Some(metadatatype) = &submetadata.metadatatype {
                 submetadata_start.push_attribute(("type", metadatatype.as_str() ));
             }
-            if let Some(src) = &self.metadata.src {
+            if let Some(src) = &submetadata.src {
                 submetadata_start.push_attribute(("src", src.as_str() ));
             }
-            for (meta_id, value) in submetadata.data.iter() {
+            writer.write_event(Event::Start(submetadata_start)).map_err(to_serialisation_error)?;
+            writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+            for (meta_id, value) in sorted_if(submetadata.data.iter().collect(), canonical) {
                 let mut meta_start = BytesStart::borrowed_name(b"meta");
                 meta_start.push_attribute(("id", meta_id.as_str() ));
                 writer.write_event(Event::Start(meta_start)).map_err(to_serialisation_error)?;
@@ -92,46 +116,51 @@ impl Document {
                 writer.write_event(Event::End(BytesEnd::borrowed(b"meta"))).map_err(to_serialisation_error)?;
                 writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
             }
+            xml_foreign_data(writer, submetadata)?;
+            writer.write_event(Event::End(BytesEnd::borrowed(b"submetadata"))).map_err(to_serialisation_error)?;
+            writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
         }
         writer.write_event(Event::End(BytesEnd::borrowed(b"metadata"))).map_err(to_serialisation_error)?;
         writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
         Ok(())
     }
 
-    fn xml_declarations(&self, writer: &mut Writer<Cursor<Vec<u8>>>) -> Result<(), FoliaError> {
+    fn xml_declarations(&self, writer: &mut Writer<Cursor<Vec<u8>>>, canonical: bool) -> Result<(), FoliaError> {
         writer.write_event(Event::Start( BytesStart::borrowed_name(b"annotations"))).map_err(to_serialisation_error)?;
         writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
-        for declaration in self.declarations() {
-            if let Some(declaration) = declaration {
-                let tagname = format!("{}-annotation", declaration.annotationtype.as_str());
-                let mut dec_start = BytesStart::owned_name(tagname.as_bytes());
-                if let Some(set) = &declaration.set {
-                    dec_start.push_attribute(("set", set.as_str() ));
-                }
-                if let Some(alias) = &declaration.alias {
-                    dec_start.push_attribute(("alias", alias.as_str() ));
-                }
-                if let Some(format) = &declaration.format {
-                    dec_start.push_attribute(("format", format.as_str() ));
-                }
-                let dec_end = BytesEnd::owned(tagname.as_bytes().to_vec());
-                if declaration.processors.is_empty() {
-                    writer.write_event(Event::Empty(dec_start)).map_err(to_serialisation_error)?;
-                } else {
-                    writer.write_event(Event::Start(dec_start)).map_err(to_serialisation_error)?;
-                    for proc_key in declaration.processors.iter() {
-                        if let Some(processor) = self.get_processor(*proc_key) {
-                            let mut ann_start = BytesStart::borrowed_name(b"annotator");
-                            ann_start.push_attribute(("processor", processor.id.as_str() ));
-                            writer.write_event(Event::Empty(ann_start)).map_err(to_serialisation_error)?;
-                        } else {
-                            return Err(FoliaError::InternalError(format!("Unable to resolve referenced processor during serialisation")));
-                        }
+        let mut declarations: Vec<&Declaration> = self.declarations().filter_map(|declaration| declaration.as_deref()).collect();
+        if canonical {
+            declarations.sort_by(|a, b| (a.annotationtype.as_str(), &a.set).cmp(&(b.annotationtype.as_str(), &b.set)));
+        }
+        for declaration in declarations {
+            let tagname = format!("{}-annotation", declaration.annotationtype.as_str());
+            let mut dec_start = BytesStart::owned_name(tagname.as_bytes());
+            if let Some(set) = &declaration.set {
+                dec_start.push_attribute(("set", set.as_str() ));
+            }
+            if let Some(alias) = &declaration.alias {
+                dec_start.push_attribute(("alias", alias.as_str() ));
+            }
+            if let Some(format) = &declaration.format {
+                dec_start.push_attribute(("format", format.as_str() ));
+            }
+            let dec_end = BytesEnd::owned(tagname.as_bytes().to_vec());
+            if declaration.processors.is_empty() {
+                writer.write_event(Event::Empty(dec_start)).map_err(to_serialisation_error)?;
+            } else {
+                writer.write_event(Event::Start(dec_start)).map_err(to_serialisation_error)?;
+                for proc_key in declaration.processors.iter() {
+                    if let Some(processor) = self.get_processor(*proc_key) {
+                        let mut ann_start = BytesStart::borrowed_name(b"annotator");
+                        ann_start.push_attribute(("processor", processor.id.as_str() ));
+                        writer.write_event(Event::Empty(ann_start)).map_err(to_serialisation_error)?;
+                    } else {
+                        return Err(FoliaError::InternalError(format!("Unable to resolve referenced processor during serialisation")));
                     }
-                    writer.write_event(Event::End(dec_end)).map_err(to_serialisation_error)?;
                 }
-                writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+                writer.write_event(Event::End(dec_end)).map_err(to_serialisation_error)?;
             }
+            writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
         }
         writer.write_event(Event::End(BytesEnd::borrowed(b"annotations"))).map_err(to_serialisation_error)?;
         writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
@@ -203,7 +232,7 @@ impl Document {
     }
 
     ///Serialize elements to XML
-    pub(crate) fn xml_elements(&self, writer: &mut Writer<Cursor<Vec<u8>>>, root_key: ElementKey) -> Result<(), FoliaError> {
+    pub(crate) fn xml_elements(&self, writer: &mut Writer<Cursor<Vec<u8>>>, root_key: ElementKey, canonical: bool) -> Result<(), FoliaError> {
         //caches declarations that are defaults
         let dec_is_default: Vec<bool> = self.declarationstore.default_mask();
 
@@ -230,10 +259,10 @@ impl Document {
                         let tagstring = element.elementtype().to_string();
                         //eprintln!("[DEBUG] (processing tag {} ({}))", tagstring.as_str(), item.depth);
                         let tag = tagstring.as_bytes();
-                        let mut start = BytesStart::owned(tag.to_vec(), tag.len());
+                        let mut attributes: Vec<(&str,String)> = Vec::new();
                         for attrib in element.attribs().iter() {
                             if *attrib != Attribute::Ignore && !attrib.decodable()  {
-                                start.push_attribute((attrib.attribtype().into(), format!("{}",attrib).as_str() ));
+                                attributes.push((attrib.attribtype().into(), format!("{}",attrib)));
                             }
                         }
                         if let Some(declaration_key) = element.declaration_key() {
@@ -241,14 +270,14 @@ impl Document {
                             if !dec_is_default.get(declaration_key as usize).expect("checking default")  && element.elementtype() != ElementType::Feature {
                                 //decode encoded attributes
                                 if let Some(set) = element.set() {
-                                    start.push_attribute(("set", set) );
+                                    attributes.push(("set", set.to_string()));
                                 }
                             }
                             if let Some(subset) = element.subset() {
-                                start.push_attribute(("subset", subset) );
+                                attributes.push(("subset", subset.to_string()));
                             }
                             if let Some(class) = element.class() {
-                                start.push_attribute(("class", class) );
+                                attributes.push(("class", class.to_string()));
                             }
                             if let Some(processor) = element.processor() {
                                 //check if this processor is the default one, if so we don't need
@@ -264,10 +293,17 @@ impl Document {
                                 };
 
                                 if !is_default {
-                                    start.push_attribute(("processor", processor) );
+                                    attributes.push(("processor", processor.to_string()));
                                 }
                             }
                         }
+                        if canonical {
+                            attributes.sort_by_key(|(name, _)| (CANONICAL_ATTRIBUTE_ORDER.iter().position(|x| x == name).unwrap_or(CANONICAL_ATTRIBUTE_ORDER.len()), *name));
+                        }
+                        let mut start = BytesStart::owned(tag.to_vec(), tag.len());
+                        for (name, value) in attributes.iter() {
+                            start.push_attribute((*name, value.as_str()));
+                        }
                         if element.data.is_empty() {
                             writer.write_event(Event::Empty(start)).map_err(to_serialisation_error)?;
                         } else {
@@ -284,8 +320,13 @@ impl Document {
                 },
                 DataType::Text(text) => {
                     //eprintln!("[DEBUG] (processing text)");
-                    let text = BytesText::from_plain_str(text.as_str());
-                    writer.write_event(Event::Text(text)).map_err(to_serialisation_error)?;
+                    if canonical {
+                        let text = normalise_whitespace(text);
+                        writer.write_event(Event::Text(BytesText::from_plain_str(text.as_str()))).map_err(to_serialisation_error)?;
+                    } else {
+                        let text = BytesText::from_plain_str(text.as_str());
+                        writer.write_event(Event::Text(text)).map_err(to_serialisation_error)?;
+                    }
                 },
                 DataType::Comment(comment) => {
                     //eprintln!("[DEBUG] (processing comment)");
@@ -308,3 +349,40 @@ impl Document {
         Ok(())
     }
 }
+
+///Writes the foreign metadata blocks, the raw XML is written as is
+fn xml_foreign_data(writer: &mut Writer<Cursor<Vec<u8>>>, metadata: &Metadata) -> Result<(), FoliaError> {
+    for foreign in metadata.foreign.iter() {
+        writer.write_event(Event::Start(BytesStart::borrowed_name(b"foreign-data"))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::Text(BytesText::from_escaped(foreign.xml.as_bytes()))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::End(BytesEnd::borrowed(b"foreign-data"))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+    }
+    Ok(())
+}
+
+///Sorts key/value pairs by key, if requested (used for canonical serialisation)
+fn sorted_if<'a, T>(mut items: Vec<(&'a String, &'a T)>, sort: bool) -> Vec<(&'a String, &'a T)> {
+    if sort {
+        items.sort_by(|a, b| a.0.cmp(b.0));
+    }
+    items
+}
+
+///Collapses all consecutive whitespace (including newlines and tabs) into a single space
+fn normalise_whitespace(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut whitespace = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !whitespace {
+                result.push(' ');
+            }
+            whitespace = true;
+        } else {
+            result.push(c);
+            whitespace = false;
+        }
+    }
+    result
+}
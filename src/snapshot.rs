@@ -0,0 +1,690 @@
+//! Compact binary snapshots of a document's encoded stores. A snapshot can be loaded without
+//! invoking the XML parser. It records a fingerprint of the XML it was made from and is
+//! rejected when that XML changes. This module is only available with the ``snapshot`` feature.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+use strum::IntoEnumIterator;
+
+use crate::common::*;
+use crate::types::*;
+use crate::transaction::*;
+use crate::journal::*;
+use crate::error::*;
+use crate::element::*;
+use crate::attrib::*;
+use crate::store::*;
+use crate::elementstore::*;
+use crate::metadata::*;
+use crate::foreign::*;
+use crate::document::*;
+
+///Magic bytes at the start of every snapshot
+pub const SNAPSHOT_MAGIC: &[u8] = b"FOLIASNP";
+///The version of the snapshot format, snapshots with another version are rejected
+pub const SNAPSHOT_VERSION: u16 = 2;
+///The extension appended to the XML filename to obtain the snapshot filename
+pub const SNAPSHOT_EXTENSION: &str = "snapshot";
+
+const DATETIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+///Computes the fingerprint of the XML source a snapshot is made from (64-bit FNV-1a)
+pub fn snapshot_fingerprint(source: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in source.iter() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+///Writes the binary representation of the document model
+struct SnapshotWriter {
+    buffer: Vec<u8>,
+}
+
+impl SnapshotWriter {
+    fn u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    ///Writes an unsigned integer as a variable-length quantity (LEB128)
+    fn uint(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.buffer.push(byte);
+                break;
+            } else {
+                self.buffer.push(byte | 0x80);
+            }
+        }
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.uint(value.len() as u64);
+        self.buffer.extend_from_slice(value.as_bytes());
+    }
+
+    fn option_str(&mut self, value: &Option<String>) {
+        match value {
+            Some(value) => { self.bool(true); self.str(value); },
+            None => self.bool(false),
+        }
+    }
+
+    fn option_uint(&mut self, value: Option<u64>) {
+        match value {
+            Some(value) => { self.bool(true); self.uint(value); },
+            None => self.bool(false),
+        }
+    }
+
+    fn datetime(&mut self, value: &Option<NaiveDateTime>) {
+        match value {
+            Some(value) => {
+                self.bool(true);
+                self.str(&value.format(DATETIME_FORMAT).to_string());
+            },
+            None => self.bool(false),
+        }
+    }
+
+    fn strings(&mut self, items: &[Option<String>]) {
+        self.uint(items.len() as u64);
+        for item in items.iter() {
+            self.option_str(item);
+        }
+    }
+
+    fn metadata(&mut self, metadata: &Metadata) {
+        self.option_str(&metadata.src);
+        self.option_str(&metadata.metadatatype);
+        let mut data: Vec<(&String,&String)> = metadata.data.iter().collect();
+        data.sort();
+        self.uint(data.len() as u64);
+        for (key, value) in data {
+            self.str(key);
+            self.str(value);
+        }
+        self.uint(metadata.foreign.len() as u64);
+        for foreign in metadata.foreign.iter() {
+            self.str(&foreign.xml);
+        }
+    }
+
+    fn attribute(&mut self, attrib: &Attribute) -> Result<(),FoliaError> {
+        match attrib {
+            Attribute::Ignore => self.u8(0),
+            Attribute::Id(s) => { self.u8(1); self.str(s) },
+            Attribute::Set(s) => { self.u8(2); self.str(s) },
+            Attribute::DeclarationRef(key) => { self.u8(3); self.uint(*key as u64) },
+            Attribute::Class(s) => { self.u8(4); self.str(s) },
+            Attribute::ClassRef(key) => { self.u8(5); self.uint(*key as u64) },
+            Attribute::Annotator(s) => { self.u8(6); self.str(s) },
+            Attribute::AnnotatorType(processortype) => { self.u8(7); self.u8(processortype_code(*processortype)) },
+            Attribute::Confidence(f) => { self.u8(8); self.buffer.extend_from_slice(&f.to_le_bytes()) },
+            Attribute::N(s) => { self.u8(9); self.str(s) },
+            Attribute::DateTime(dt) => { self.u8(10); self.datetime(&Some(*dt)) },
+            Attribute::BeginTime(s) => { self.u8(11); self.str(s) },
+            Attribute::EndTime(s) => { self.u8(12); self.str(s) },
+            Attribute::Src(s) => { self.u8(13); self.str(s) },
+            Attribute::Speaker(s) => { self.u8(14); self.str(s) },
+            Attribute::Textclass(s) => { self.u8(15); self.str(s) },
+            Attribute::Metadata(s) => { self.u8(16); self.str(s) },
+            Attribute::Idref(s) => { self.u8(17); self.str(s) },
+            Attribute::Space(b) => { self.u8(18); self.bool(*b) },
+            Attribute::Text(s) => { self.u8(19); self.str(s) },
+            Attribute::Type(s) => { self.u8(20); self.str(s) },
+            Attribute::Auth(s) => { self.u8(21); self.str(s) },
+            Attribute::Offset(n) => { self.u8(22); self.uint(*n as u64) },
+            Attribute::Ref(s) => { self.u8(23); self.str(s) },
+            Attribute::Original(s) => { self.u8(24); self.str(s) },
+            Attribute::LineNr(n) => { self.u8(25); self.uint(*n as u64) },
+            Attribute::PageNr(s) => { self.u8(26); self.str(s) },
+            Attribute::NewPage(b) => { self.u8(27); self.bool(*b) },
+            Attribute::XLinkType(s) => { self.u8(28); self.str(s) },
+            Attribute::Processor(s) => { self.u8(29); self.str(s) },
+            Attribute::ProcessorRef(key) => { self.u8(30); self.uint(*key as u64) },
+            Attribute::Href(s) => { self.u8(31); self.str(s) },
+            Attribute::Format(s) => { self.u8(32); self.str(s) },
+            Attribute::Subset(s) => { self.u8(33); self.str(s) },
+            Attribute::SubsetRef(key) => { self.u8(34); self.uint(*key as u64) },
+        }
+        Ok(())
+    }
+
+    fn element(&mut self, element: &ElementData) -> Result<(),FoliaError> {
+        self.uint(element.elementtype as u64);
+        self.uint(element.attribs.len() as u64);
+        for attrib in element.attribs.iter() {
+            self.attribute(attrib)?;
+        }
+        self.option_uint(element.parent.map(|key| key as u64));
+        self.uint(element.data.len() as u64);
+        for item in element.data.iter() {
+            match item {
+                DataType::Text(s) => { self.u8(0); self.str(s) },
+                DataType::Element(key) => { self.u8(1); self.uint(*key as u64) },
+                DataType::Comment(s) => { self.u8(2); self.str(s) },
+                DataType::SpanReference(key) => { self.u8(3); self.uint(*key as u64) },
+                DataType::AddElement(_) => {
+                    return Err(FoliaError::SerialisationError("Unable to make a snapshot of an element that is still under construction".to_string()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn declaration(&mut self, declaration: &Declaration) {
+        self.uint(declaration.annotationtype as u64);
+        self.option_str(&declaration.set);
+        self.option_str(&declaration.alias);
+        self.option_str(&declaration.format);
+        self.uint(declaration.processors.len() as u64);
+        for processor in declaration.processors.iter() {
+            self.uint(*processor as u64);
+        }
+        for classes in [&declaration.classes, &declaration.subclasses].iter() {
+            match classes {
+                Some(classes) => { self.bool(true); self.strings(classes.items()) },
+                None => self.bool(false),
+            }
+        }
+        match &declaration.subsets {
+            Some(subsets) => { self.bool(true); self.strings(subsets.items()) },
+            None => self.bool(false),
+        }
+    }
+
+    fn processor(&mut self, processor: &Processor) {
+        self.str(&processor.id);
+        self.str(&processor.name);
+        self.u8(processortype_code(processor.processortype));
+        for value in [&processor.version, &processor.folia_version, &processor.document_version, &processor.command,
+                      &processor.host, &processor.user, &processor.src, &processor.format, &processor.resourcelink].iter() {
+            self.str(value);
+        }
+        self.datetime(&processor.begindatetime);
+        self.datetime(&processor.enddatetime);
+        self.uint(processor.processors.len() as u64);
+        for subprocessor in processor.processors.iter() {
+            self.uint(*subprocessor as u64);
+        }
+        self.option_uint(processor.parent.map(|key| key as u64));
+        self.metadata(&processor.metadata);
+    }
+}
+
+///Reads the binary representation of the document model
+struct SnapshotReader<'a> {
+    data: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> SnapshotReader<'a> {
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8],FoliaError> {
+        if length > self.data.len() - self.cursor {
+            return Err(FoliaError::SnapshotError("Premature end of snapshot".to_string()));
+        }
+        let bytes = &self.data[self.cursor..self.cursor+length];
+        self.cursor += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8,FoliaError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn uint(&mut self) -> Result<u64,FoliaError> {
+        let mut value: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift >= 64 {
+                return Err(FoliaError::SnapshotError("Invalid integer in snapshot".to_string()));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    ///Reads the number of items of a collection. Each item takes at least one byte, so a length beyond
+    ///the remaining data means the snapshot is corrupt (rather than a reason to allocate it).
+    fn length(&mut self) -> Result<usize,FoliaError> {
+        let length = self.uint()?;
+        if length > (self.data.len() - self.cursor) as u64 {
+            return Err(FoliaError::SnapshotError("Invalid length in snapshot".to_string()));
+        }
+        Ok(length as usize)
+    }
+
+    ///Reads an unsigned integer and converts it to the desired key type
+    fn key<K: TryFrom<u64>>(&mut self) -> Result<K,FoliaError> {
+        K::try_from(self.uint()?).map_err(|_| FoliaError::SnapshotError("Key out of bounds in snapshot".to_string()))
+    }
+
+    fn bool(&mut self) -> Result<bool,FoliaError> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn string(&mut self) -> Result<String,FoliaError> {
+        let length = self.length()?;
+        let bytes = self.bytes(length)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| FoliaError::SnapshotError("Invalid UTF-8 in snapshot".to_string()))
+    }
+
+    fn option_string(&mut self) -> Result<Option<String>,FoliaError> {
+        if self.bool()? {
+            Ok(Some(self.string()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn option_key<K: TryFrom<u64>>(&mut self) -> Result<Option<K>,FoliaError> {
+        if self.bool()? {
+            Ok(Some(self.key()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn datetime(&mut self) -> Result<Option<NaiveDateTime>,FoliaError> {
+        if self.bool()? {
+            NaiveDateTime::parse_from_str(&self.string()?, DATETIME_FORMAT)
+                .map(Some)
+                .map_err(|err| FoliaError::SnapshotError(format!("Invalid date/time in snapshot: {}", err)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    ///Reads a string store, restoring the exact keys
+    fn strings<K: TryFrom<usize> + TryFrom<u64> + Copy + std::fmt::Debug, S: StringStore<K> + Default>(&mut self) -> Result<S,FoliaError>
+      where usize: TryFrom<K>, <usize as TryFrom<K>>::Error: std::fmt::Debug {
+        let mut store = S::default();
+        let length = self.length()?;
+        for i in 0..length {
+            let item = self.option_string()?;
+            if let Some(item) = item.as_ref() {
+                let key = <K as TryFrom<usize>>::try_from(i).map_err(|_| FoliaError::SnapshotError("Key out of bounds in snapshot".to_string()))?;
+                store.index_mut().insert(item.clone(), key);
+            }
+            store.items_mut().push(item);
+        }
+        Ok(store)
+    }
+
+    fn metadata(&mut self) -> Result<Metadata,FoliaError> {
+        let src = self.option_string()?;
+        let metadatatype = self.option_string()?;
+        let length = self.length()?;
+        let mut data = HashMap::with_capacity(length);
+        for _ in 0..length {
+            let key = self.string()?;
+            data.insert(key, self.string()?);
+        }
+        let length = self.length()?;
+        let mut foreign = Vec::with_capacity(length);
+        for _ in 0..length {
+            foreign.push(ForeignData { xml: self.string()? });
+        }
+        Ok(Metadata { data, src, metadatatype, foreign, external: None })
+    }
+
+    fn attribute(&mut self) -> Result<Attribute,FoliaError> {
+        Ok(match self.u8()? {
+            0 => Attribute::Ignore,
+            1 => Attribute::Id(self.string()?),
+            2 => Attribute::Set(self.string()?),
+            3 => Attribute::DeclarationRef(self.key()?),
+            4 => Attribute::Class(self.string()?),
+            5 => Attribute::ClassRef(self.key()?),
+            6 => Attribute::Annotator(self.string()?),
+            7 => Attribute::AnnotatorType(processortype_from_code(self.u8()?)?),
+            8 => {
+                let mut value: [u8; 8] = [0; 8];
+                value.copy_from_slice(self.bytes(8)?);
+                Attribute::Confidence(f64::from_le_bytes(value))
+            },
+            9 => Attribute::N(self.string()?),
+            10 => Attribute::DateTime(self.datetime()?.ok_or_else(|| FoliaError::SnapshotError("Missing date/time in snapshot".to_string()))?),
+            11 => Attribute::BeginTime(self.string()?),
+            12 => Attribute::EndTime(self.string()?),
+            13 => Attribute::Src(self.string()?),
+            14 => Attribute::Speaker(self.string()?),
+            15 => Attribute::Textclass(self.string()?),
+            16 => Attribute::Metadata(self.string()?),
+            17 => Attribute::Idref(self.string()?),
+            18 => Attribute::Space(self.bool()?),
+            19 => Attribute::Text(self.string()?),
+            20 => Attribute::Type(self.string()?),
+            21 => Attribute::Auth(self.string()?),
+            22 => Attribute::Offset(self.key()?),
+            23 => Attribute::Ref(self.string()?),
+            24 => Attribute::Original(self.string()?),
+            25 => Attribute::LineNr(self.key()?),
+            26 => Attribute::PageNr(self.string()?),
+            27 => Attribute::NewPage(self.bool()?),
+            28 => Attribute::XLinkType(self.string()?),
+            29 => Attribute::Processor(self.string()?),
+            30 => Attribute::ProcessorRef(self.key()?),
+            31 => Attribute::Href(self.string()?),
+            32 => Attribute::Format(self.string()?),
+            33 => Attribute::Subset(self.string()?),
+            34 => Attribute::SubsetRef(self.key()?),
+            code => return Err(FoliaError::SnapshotError(format!("Invalid attribute code in snapshot: {}", code)))
+        })
+    }
+
+    fn element(&mut self, key: ElementKey, elementtypes: &[ElementType]) -> Result<ElementData,FoliaError> {
+        let elementtype = *elementtypes.get(self.uint()? as usize).ok_or_else(|| FoliaError::SnapshotError("Invalid element type in snapshot".to_string()))?;
+        let length = self.length()?;
+        let mut attribs = Vec::with_capacity(length);
+        for _ in 0..length {
+            attribs.push(self.attribute()?);
+        }
+        let parent = self.option_key()?;
+        let length = self.length()?;
+        let mut data = Vec::with_capacity(length);
+        for _ in 0..length {
+            data.push(match self.u8()? {
+                0 => DataType::Text(self.string()?),
+                1 => DataType::Element(self.key()?),
+                2 => DataType::Comment(self.string()?),
+                3 => DataType::SpanReference(self.key()?),
+                code => return Err(FoliaError::SnapshotError(format!("Invalid data code in snapshot: {}", code)))
+            });
+        }
+        Ok(ElementData { elementtype, attribs, data, key: Some(key), parent })
+    }
+
+    fn declaration(&mut self, key: DecKey, annotationtypes: &[AnnotationType]) -> Result<Declaration,FoliaError> {
+        let annotationtype = *annotationtypes.get(self.uint()? as usize).ok_or_else(|| FoliaError::SnapshotError("Invalid annotation type in snapshot".to_string()))?;
+        let mut declaration = Declaration::new(annotationtype, self.option_string()?, self.option_string()?, self.option_string()?);
+        declaration.key = Some(key);
+        let length = self.length()?;
+        for _ in 0..length {
+            declaration.processors.push(self.key()?);
+        }
+        if self.bool()? {
+            declaration.classes = Some(self.strings()?);
+        }
+        if self.bool()? {
+            declaration.subclasses = Some(self.strings()?);
+        }
+        if self.bool()? {
+            declaration.subsets = Some(self.strings()?);
+        }
+        Ok(declaration)
+    }
+
+    fn processor(&mut self, key: ProcKey) -> Result<Processor,FoliaError> {
+        let id = self.string()?;
+        let name = self.string()?;
+        let processortype = processortype_from_code(self.u8()?)?;
+        let mut values: Vec<String> = Vec::with_capacity(9);
+        for _ in 0..9 {
+            values.push(self.string()?);
+        }
+        let mut values = values.into_iter();
+        let mut next = || values.next().expect("processor value");
+        let begindatetime = self.datetime()?;
+        let enddatetime = self.datetime()?;
+        let length = self.length()?;
+        let mut processors = Vec::with_capacity(length);
+        for _ in 0..length {
+            processors.push(self.key()?);
+        }
+        Ok(Processor {
+            id,
+            name,
+            processortype,
+            version: next(),
+            folia_version: next(),
+            document_version: next(),
+            command: next(),
+            host: next(),
+            user: next(),
+            src: next(),
+            format: next(),
+            resourcelink: next(),
+            begindatetime,
+            enddatetime,
+            processors,
+            parent: self.option_key()?,
+            metadata: self.metadata()?,
+            key: Some(key),
+            pending_processors: RefCell::new(vec!()),
+        })
+    }
+}
+
+fn processortype_code(processortype: ProcessorType) -> u8 {
+    match processortype {
+        ProcessorType::Auto => 0,
+        ProcessorType::Manual => 1,
+        ProcessorType::Generator => 2,
+        ProcessorType::DataSource => 3,
+    }
+}
+
+fn processortype_from_code(code: u8) -> Result<ProcessorType,FoliaError> {
+    match code {
+        0 => Ok(ProcessorType::Auto),
+        1 => Ok(ProcessorType::Manual),
+        2 => Ok(ProcessorType::Generator),
+        3 => Ok(ProcessorType::DataSource),
+        _ => Err(FoliaError::SnapshotError(format!("Invalid processor type in snapshot: {}", code)))
+    }
+}
+
+impl Document {
+    ///Serialises the document to a binary snapshot. ``source`` is the XML the document was
+    ///loaded from, its fingerprint is stored in the snapshot so it can be invalidated when
+    ///the XML changes.
+    pub fn to_snapshot(&self, source: &[u8]) -> Result<Vec<u8>,FoliaError> {
+        let mut writer = SnapshotWriter { buffer: Vec::new() };
+
+        //header
+        writer.buffer.extend_from_slice(SNAPSHOT_MAGIC);
+        writer.buffer.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        writer.str(env!("CARGO_PKG_VERSION"));
+        writer.uint(source.len() as u64);
+        writer.buffer.extend_from_slice(&snapshot_fingerprint(source).to_le_bytes());
+
+        //document
+        writer.str(&self.id);
+        writer.str(&self.version);
+        writer.bool(self.autodeclare);
+        writer.option_uint(self.active_processor.map(|key| key as u64));
+        writer.metadata(&self.metadata);
+        let mut submetadata: Vec<(&String,&Metadata)> = self.submetadata.iter().collect();
+        submetadata.sort_by(|a,b| a.0.cmp(b.0));
+        writer.uint(submetadata.len() as u64);
+        for (id, metadata) in submetadata {
+            writer.str(id);
+            writer.metadata(metadata);
+        }
+
+        //provenance store
+        writer.uint(self.provenancestore.items.len() as u64);
+        for processor in self.provenancestore.items.iter() {
+            match processor {
+                Some(processor) => { writer.bool(true); writer.processor(processor) },
+                None => writer.bool(false),
+            }
+        }
+        writer.uint(self.provenancestore.chain.len() as u64);
+        for key in self.provenancestore.chain.iter() {
+            writer.uint(*key as u64);
+        }
+
+        //declaration store
+        writer.uint(self.declarationstore.items.len() as u64);
+        for declaration in self.declarationstore.items.iter() {
+            match declaration {
+                Some(declaration) => { writer.bool(true); writer.declaration(declaration) },
+                None => writer.bool(false),
+            }
+        }
+
+        //element store
+        writer.uint(self.elementstore.items.len() as u64);
+        for element in self.elementstore.items.iter() {
+            match element {
+                Some(element) => { writer.bool(true); writer.element(element)? },
+                None => writer.bool(false),
+            }
+        }
+
+        Ok(writer.buffer)
+    }
+
+    ///Loads a document from a binary snapshot. ``source`` is the XML the snapshot is expected
+    ///to correspond to, a ``SnapshotError`` is returned if it changed since the snapshot was made,
+    ///or if the snapshot was made by another version of the library.
+    pub fn from_snapshot(data: &[u8], source: &[u8]) -> Result<Self,FoliaError> {
+        let mut reader = SnapshotReader { data, cursor: 0 };
+
+        //header
+        if reader.bytes(SNAPSHOT_MAGIC.len())? != SNAPSHOT_MAGIC {
+            return Err(FoliaError::SnapshotError("Not a FoLiA snapshot".to_string()));
+        }
+        let mut version: [u8; 2] = [0; 2];
+        version.copy_from_slice(reader.bytes(2)?);
+        if u16::from_le_bytes(version) != SNAPSHOT_VERSION || reader.string()? != env!("CARGO_PKG_VERSION") {
+            return Err(FoliaError::SnapshotError("Snapshot was made by another version of the library".to_string()));
+        }
+        let length = reader.uint()?;
+        let mut fingerprint: [u8; 8] = [0; 8];
+        fingerprint.copy_from_slice(reader.bytes(8)?);
+        if length != source.len() as u64 || u64::from_le_bytes(fingerprint) != snapshot_fingerprint(source) {
+            return Err(FoliaError::SnapshotError("Snapshot is outdated, the XML source has changed".to_string()));
+        }
+
+        //document
+        let id = reader.string()?;
+        let version = reader.string()?;
+        let autodeclare = reader.bool()?;
+        let active_processor = reader.option_key()?;
+        let metadata = reader.metadata()?;
+        let length = reader.length()?;
+        let mut submetadata = HashMap::with_capacity(length);
+        for _ in 0..length {
+            let id = reader.string()?;
+            submetadata.insert(id, reader.metadata()?);
+        }
+
+        //provenance store
+        let mut provenancestore = ProvenanceStore::default();
+        let length = reader.length()?;
+        for i in 0..length {
+            if reader.bool()? {
+                let processor = reader.processor(ProcKey::try_from(i).map_err(|_| FoliaError::SnapshotError("Too many processors in snapshot".to_string()))?)?;
+                provenancestore.index.insert(processor.id.clone(), processor.key.expect("processor key"));
+                provenancestore.items.push(Some(Box::new(processor)));
+            } else {
+                provenancestore.items.push(None);
+            }
+        }
+        let length = reader.length()?;
+        for _ in 0..length {
+            provenancestore.chain.push(reader.key()?);
+        }
+
+        //declaration store
+        let annotationtypes: Vec<AnnotationType> = AnnotationType::iter().collect();
+        let mut declarationstore = DeclarationStore::default();
+        let length = reader.length()?;
+        for i in 0..length {
+            if reader.bool()? {
+                let declaration = reader.declaration(DecKey::try_from(i).map_err(|_| FoliaError::SnapshotError("Too many declarations in snapshot".to_string()))?, &annotationtypes)?;
+                if let Some(index_id) = declaration.maybe_id() {
+                    declarationstore.index.insert(index_id.to_string(), declaration.key.expect("declaration key"));
+                }
+                declarationstore.items.push(Some(Box::new(declaration)));
+            } else {
+                declarationstore.items.push(None);
+            }
+        }
+
+        //element store
+        let elementtypes: Vec<ElementType> = ElementType::iter().collect();
+        let mut elementstore = ElementStore::default();
+        let length = reader.length()?;
+        elementstore.items.reserve(length);
+        for i in 0..length {
+            if reader.bool()? {
+                let element = reader.element(ElementKey::try_from(i).map_err(|_| FoliaError::SnapshotError("Too many elements in snapshot".to_string()))?, &elementtypes)?;
+                if let Some(id) = element.maybe_id() {
+                    elementstore.index.insert(id.to_string(), element.key.expect("element key"));
+                }
+                elementstore.items.push(Some(Box::new(element)));
+            } else {
+                elementstore.items.push(None);
+            }
+        }
+
+        if reader.cursor != data.len() {
+            return Err(FoliaError::SnapshotError("Trailing data in snapshot".to_string()));
+        }
+
+        Ok(Self {
+            id,
+            version,
+            filename: None,
+            elementstore,
+            provenancestore,
+            declarationstore,
+            metadata,
+            submetadata,
+            active_processor,
+            autodeclare,
+            history: History::default(),
+            journal: None,
+        })
+    }
+
+    ///Returns the name of the snapshot file that accompanies the given XML file
+    pub fn snapshot_filename(filename: &str) -> String {
+        format!("{}.{}", filename, SNAPSHOT_EXTENSION)
+    }
+
+    ///Loads a FoLiA document from file, using the accompanying snapshot file if it is valid,
+    ///and invoking the XML parser otherwise. In the latter case, a new snapshot is written.
+    ///The properties are applied after loading, so a processor in the properties is never part
+    ///of the snapshot.
+    pub fn from_file_with_snapshot(filename: &str, properties: DocumentProperties) -> Result<Self, FoliaError> {
+        let source = fs::read(Path::new(filename))?;
+        let snapshotfilename = Self::snapshot_filename(filename);
+        let snapshot = fs::read(Path::new(&snapshotfilename)).ok().and_then(|snapshot| Self::from_snapshot(&snapshot, &source).ok());
+        let mut doc = if let Some(doc) = snapshot {
+            doc
+        } else {
+            let data = std::str::from_utf8(&source).map_err(|err| FoliaError::ParseError(format!("Invalid UTF-8: {}", err)))?;
+            let mut parseproperties = properties.clone();
+            parseproperties.processor = None;
+            let doc = Self::from_str(data, parseproperties)?;
+            fs::write(Path::new(&snapshotfilename), doc.to_snapshot(&source)?)?;
+            doc
+        };
+        doc.apply_properties(properties)?;
+        doc.filename = Some(filename.to_string());
+        Ok(doc)
+    }
+}
@@ -0,0 +1,757 @@
+use std::collections::{BTreeMap,HashMap,HashSet};
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::attrib::*;
+use crate::query::*;
+use crate::select::*;
+use crate::text::*;
+use crate::conllu::set_cmp;
+use crate::document::*;
+
+///The WebAnno TSV format version that is written
+pub const WEBANNO_TSV_FORMAT: &str = "WebAnno TSV 3.3";
+///The WebAnno (DKPro) layer for dependency relations
+pub const WEBANNO_DEPENDENCY_LAYER: &str = "de.tudarmstadt.ukp.dkpro.core.api.syntax.type.dependency.Dependency";
+///The WebAnno (DKPro) layer dependency relations are attached to
+pub const WEBANNO_DEPENDENCY_BASE: &str = "de.tudarmstadt.ukp.dkpro.core.api.segmentation.type.POS";
+
+///The value WebAnno TSV uses for empty cells
+const EMPTY: &str = "_";
+///The value WebAnno TSV uses for annotations without a label
+const NOLABEL: &str = "*";
+
+#[derive(Clone)]
+///Parameters for stand-off conversion to and from BRAT (``to_brat()``, ``add_brat()``, ``from_brat()``)
+///and WebAnno TSV (``to_webanno_tsv()``, ``from_webanno_tsv()``).
+///For export, a set of ``None`` matches any set; for import, it declares the annotation type without a set.
+pub struct StandoffParameters {
+    ///Determines which text is extracted for the tokens (text set, text class)
+    pub textparameters: TextParameters,
+    ///The span annotation types that are converted
+    pub spantypes: Vec<ElementType>,
+    ///The set for the span annotations
+    pub set: Option<String>,
+    ///The set for dependency relations
+    pub dependencyset: Option<String>,
+}
+
+impl Default for StandoffParameters {
+    fn default() -> Self {
+        Self {
+            textparameters: TextParameters::default(),
+            spantypes: vec![ElementType::Entity, ElementType::Chunk, ElementType::SemanticRole],
+            set: None,
+            dependencyset: None,
+        }
+    }
+}
+
+impl StandoffParameters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_textparameters(mut self, textparameters: TextParameters) -> Self {
+        self.textparameters = textparameters;
+        self
+    }
+
+    pub fn with_spantypes(mut self, spantypes: Vec<ElementType>) -> Self {
+        self.spantypes = spantypes;
+        self
+    }
+
+    pub fn with_set(mut self, set: Option<String>) -> Self {
+        self.set = set;
+        self
+    }
+
+    pub fn with_dependencyset(mut self, set: Option<String>) -> Self {
+        self.dependencyset = set;
+        self
+    }
+
+    ///Returns the label for a span annotation. This is the class, prefixed by the element type
+    ///(``entity-per``) if multiple span types are converted.
+    pub fn label(&self, elementtype: ElementType, class: Option<&str>) -> String {
+        match (self.spantypes.len() > 1, class) {
+            (true, Some(class)) => format!("{}-{}", elementtype, class),
+            (true, None) => elementtype.to_string(),
+            (false, Some(class)) => class.to_string(),
+            (false, None) => elementtype.to_string(),
+        }
+    }
+
+    ///Returns the element type and class for a label, the reverse of ``label()``. Returns
+    ///``None`` if the label does not map to any of the span types.
+    pub fn parse_label(&self, label: &str) -> Option<(ElementType, Option<String>)> {
+        let default = *self.spantypes.first()?;
+        if let Some(elementtype) = self.spantypes.iter().find(|elementtype| elementtype.as_str() == label) {
+            return Some((*elementtype, None));
+        }
+        if self.spantypes.len() > 1 {
+            if let Some(index) = label.find('-') {
+                if let Some(elementtype) = self.spantypes.iter().find(|elementtype| elementtype.as_str() == &label[..index]) {
+                    return Some((*elementtype, Some(label[index+1..].to_string())));
+                }
+            }
+        }
+        Some((default, Some(label.to_string())))
+    }
+}
+
+///A token (word) in the text that stand-off annotations refer to, with character offsets
+struct StandoffToken {
+    key: ElementKey,
+    id: Option<String>,
+    text: String,
+    sentence: usize,
+    begin: usize,
+    end: usize,
+}
+
+///The text stand-off annotations refer to, along with its tokens
+struct StandoffText {
+    text: String,
+    tokens: Vec<StandoffToken>,
+    index: HashMap<ElementKey,usize>,
+}
+
+impl StandoffText {
+    ///Returns the byte offset of every character in the text, followed by the length of the text,
+    ///so character offsets can be mapped to slices of the text
+    fn byte_offsets(&self) -> Vec<usize> {
+        self.text.char_indices().map(|(offset, _)| offset).chain(std::iter::once(self.text.len())).collect()
+    }
+
+    ///Returns the indices of all tokens overlapping with the given character offsets
+    fn tokens_in(&self, begin: usize, end: usize) -> Vec<usize> {
+        self.tokens.iter().enumerate().filter(|(_, token)| token.begin < end && token.end > begin).map(|(i, _)| i).collect()
+    }
+
+    ///Returns the IDs for the tokens with the given indices
+    fn token_ids(&self, indices: &[usize]) -> Result<Vec<&str>,FoliaError> {
+        indices.iter().map(|i| self.tokens[*i].id.as_deref().ok_or_else(|| FoliaError::IncompleteError("Stand-off annotations can only be added to words with an ID".to_string()))).collect()
+    }
+
+    ///Returns the character offsets of the contiguous fragments made up by the given tokens
+    fn fragments(&self, indices: &[usize]) -> Vec<(usize,usize)> {
+        let mut fragments: Vec<(usize,usize)> = Vec::new();
+        let mut previous: Option<usize> = None;
+        for i in indices.iter() {
+            let token = &self.tokens[*i];
+            match (previous, fragments.last_mut()) {
+                (Some(previous), Some(fragment)) if previous + 1 == *i && self.tokens[previous].sentence == token.sentence => fragment.1 = token.end,
+                _ => fragments.push((token.begin, token.end)),
+            }
+            previous = Some(*i);
+        }
+        fragments
+    }
+}
+
+///Returns the keys of the words a span element refers to (through ``wref``), sorted
+fn span_word_keys(element: &Element) -> Vec<ElementKey> {
+    let mut keys: Vec<ElementKey> = element.get_elements(ElementType::WordReference, Cmp::Any, Recursion::No)
+                                           .filter_map(|wref| wref.resolve().and_then(|word| word.key()))
+                                           .collect();
+    keys.sort_unstable();
+    keys
+}
+
+///A span annotation with token indices, as exported to stand-off formats
+struct StandoffSpan {
+    elementtype: ElementType,
+    class: Option<String>,
+    tokens: Vec<usize>,
+}
+
+///A dependency relation with token indices, as exported to stand-off formats
+struct StandoffRelation {
+    class: Option<String>,
+    head: Vec<usize>,
+    dependent: Vec<usize>,
+}
+
+impl Document {
+    ///Computes the text that stand-off annotations refer to, for the subtree under ``root_key``. Tokens are
+    ///delimited by a space (unless ``space="no"``) and sentences by a newline.
+    fn standoff_text(&self, root_key: ElementKey, parameters: &StandoffParameters) -> Result<StandoffText,FoliaError> {
+        let textparameters = parameters.textparameters.clone().retaintokenisation(true);
+        let mut result = StandoffText { text: String::new(), tokens: Vec::new(), index: HashMap::new() };
+        let mut length = 0; //in unicode points
+        let mut sentencenr = 0;
+        for sentence_key in self.sentence_keys(root_key) {
+            let mut space = false;
+            let mut first = true;
+            for word_key in self.word_keys(sentence_key) {
+                let word = self.get_element(word_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", word_key)))?;
+                let text = match word.text(&textparameters) {
+                    Ok(text) => text,
+                    Err(FoliaError::NoTextError(_)) => continue,
+                    Err(err) => return Err(err),
+                };
+                if first && !result.tokens.is_empty() {
+                    result.text.push('\n');
+                    length += 1;
+                } else if space {
+                    result.text.push(' ');
+                    length += 1;
+                }
+                first = false;
+                let textlength = text.chars().count();
+                result.index.insert(word_key, result.tokens.len());
+                result.tokens.push(StandoffToken {
+                    key: word_key,
+                    id: word.id().map(|s| s.to_string()),
+                    text: text.clone(),
+                    sentence: sentencenr,
+                    begin: length,
+                    end: length + textlength,
+                });
+                result.text += &text;
+                length += textlength;
+                space = word.attrib(AttribType::SPACE) != Some(&Attribute::Space(false));
+            }
+            if !first {
+                sentencenr += 1;
+            }
+        }
+        if !result.tokens.is_empty() {
+            result.text.push('\n');
+        }
+        Ok(result)
+    }
+
+    ///Collects the span annotations and dependency relations under ``root_key`` that are to be exported to
+    ///stand-off formats, in the order of their first token
+    fn standoff_annotations(&self, root_key: ElementKey, standofftext: &StandoffText, parameters: &StandoffParameters) -> Result<(Vec<StandoffSpan>,Vec<StandoffRelation>),FoliaError> {
+        let root = self.get_element(root_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", root_key)))?;
+        let token_indices = |keys: Vec<ElementKey>| -> Vec<usize> {
+            let mut indices: Vec<usize> = keys.iter().filter_map(|key| standofftext.index.get(key).copied()).collect();
+            indices.sort_unstable();
+            indices
+        };
+        let mut spans: Vec<StandoffSpan> = Vec::new();
+        for spantype in parameters.spantypes.iter() {
+            for span in root.get_elements(*spantype, set_cmp(&parameters.set), Recursion::Always) {
+                let tokens = token_indices(span_word_keys(&span));
+                if !tokens.is_empty() {
+                    spans.push(StandoffSpan { elementtype: *spantype, class: span.class().map(|s| s.to_string()), tokens });
+                }
+            }
+        }
+        spans.sort_by(|a, b| a.tokens.cmp(&b.tokens));
+        let mut relations: Vec<StandoffRelation> = Vec::new();
+        for dependency in root.get_elements(ElementType::Dependency, set_cmp(&parameters.dependencyset), Recursion::Always) {
+            let head = dependency.get_element(ElementType::Headspan, Cmp::Any, Recursion::No).map(|hd| token_indices(span_word_keys(&hd))).unwrap_or_default();
+            let dependent = dependency.get_element(ElementType::DependencyDependent, Cmp::Any, Recursion::No).map(|dep| token_indices(span_word_keys(&dep))).unwrap_or_default();
+            if !head.is_empty() && !dependent.is_empty() {
+                relations.push(StandoffRelation { class: dependency.class().map(|s| s.to_string()), head, dependent });
+            }
+        }
+        relations.sort_by(|a, b| (&a.dependent, &a.head).cmp(&(&b.dependent, &b.head)));
+        Ok((spans, relations))
+    }
+
+    ///Adds a span annotation over the given tokens, through ``annotate_span()``
+    fn add_standoff_span(&mut self, standofftext: &StandoffText, elementtype: ElementType, class: Option<String>, tokens: &[usize], parameters: &StandoffParameters) -> Result<ElementKey,FoliaError> {
+        if let Some(annotationtype) = elementtype.annotationtype() {
+            self.declare(annotationtype, &parameters.set, &None, &None)?;
+        }
+        let mut span = ElementData::new(elementtype).with_span(&standofftext.token_ids(tokens)?);
+        if let Some(set) = &parameters.set {
+            span = span.with_attrib(Attribute::Set(set.clone()));
+        }
+        if let Some(class) = class {
+            span = span.with_attrib(Attribute::Class(class));
+        }
+        self.annotate_span(span)
+    }
+
+    ///Adds a dependency relation between the given tokens
+    fn add_standoff_relation(&mut self, standofftext: &StandoffText, class: Option<String>, head: &[usize], dependent: &[usize], parameters: &StandoffParameters) -> Result<ElementKey,FoliaError> {
+        self.declare(AnnotationType::DEPENDENCY, &parameters.dependencyset, &None, &None)?;
+        let mut dependency = ElementData::new(ElementType::Dependency)
+                                .add_element(ElementData::new(ElementType::Headspan).with_span(&standofftext.token_ids(head)?))
+                                .add_element(ElementData::new(ElementType::DependencyDependent).with_span(&standofftext.token_ids(dependent)?));
+        if let Some(set) = &parameters.dependencyset {
+            dependency = dependency.with_attrib(Attribute::Set(set.clone()));
+        }
+        if let Some(class) = class {
+            dependency = dependency.with_attrib(Attribute::Class(class));
+        }
+        let sentence_key = self.get_element(standofftext.tokens[dependent[0]].key)
+                               .and_then(|word| word.get_ancestor(ElementType::Sentence, Cmp::Any).and_then(|sentence| sentence.key()))
+                               .ok_or_else(|| FoliaError::IncompleteError("Dependency relations can only be added to words in a sentence".to_string()))?;
+        self.annotate(sentence_key, dependency)
+    }
+
+    ///Builds a new document with the given sentences of tokens. Each token consists of its text, its
+    ///character offsets and whether it is followed by a space. Returns the document and the stand-off text.
+    fn from_standoff_tokens(id: &str, sentences: &[Vec<(String,usize,usize,bool)>], properties: DocumentProperties) -> Result<(Self,StandoffText),FoliaError> {
+        let mut doc = Self::new(id, properties)?;
+        doc.declare(AnnotationType::TOKEN, &None, &None, &None)?;
+        doc.declare(AnnotationType::SENTENCE, &None, &None, &None)?;
+        let mut standofftext = StandoffText { text: String::new(), tokens: Vec::new(), index: HashMap::new() };
+        for (sentencenr, tokens) in sentences.iter().enumerate() {
+            let sentence_id = format!("{}.s.{}", id, sentencenr + 1);
+            let sentence_key = doc.annotate(0, ElementData::new(ElementType::Sentence).with_attrib(Attribute::Id(sentence_id.clone())))?;
+            for (wordnr, (text, begin, end, space)) in tokens.iter().enumerate() {
+                let word_id = format!("{}.w.{}", sentence_id, wordnr + 1);
+                let mut word = ElementData::new(ElementType::Word)
+                                    .with_attrib(Attribute::Id(word_id.clone()))
+                                    .with_text(text.clone());
+                if !space {
+                    word = word.with_attrib(Attribute::Space(false));
+                }
+                let word_key = doc.annotate(sentence_key, word)?;
+                standofftext.index.insert(word_key, standofftext.tokens.len());
+                standofftext.tokens.push(StandoffToken { key: word_key, id: Some(word_id), text: text.clone(), sentence: sentencenr, begin: *begin, end: *end });
+            }
+        }
+        Ok((doc, standofftext))
+    }
+
+    ///Exports the span annotations and dependency relations under ``root_key`` to BRAT stand-off format. Returns
+    ///the text (``.txt``) and the annotations (``.ann``), the offsets of the latter refer to the former.
+    ///Span annotations map to text-bound annotations labelled as in ``StandoffParameters::label()``, dependency relations map to relations between
+    ///the head (``hd``) and dependent (``dep``).
+    pub fn to_brat(&self, root_key: ElementKey, parameters: &StandoffParameters) -> Result<(String,String),FoliaError> {
+        let standofftext = self.standoff_text(root_key, parameters)?;
+        let (spans, relations) = self.standoff_annotations(root_key, &standofftext, parameters)?;
+        let offsets = standofftext.byte_offsets();
+        let mut ann = String::new();
+        let mut textbound: HashMap<(Vec<usize>,String),usize> = HashMap::new();
+        let mut add_textbound = |ann: &mut String, tokens: &[usize], label: String| -> Result<usize,FoliaError> {
+            if let Some(id) = textbound.get(&(tokens.to_vec(), label.clone())) {
+                return Ok(*id);
+            }
+            let id = textbound.len() + 1;
+            let fragments = standofftext.fragments(tokens);
+            let charoffsets: Vec<String> = fragments.iter().map(|(begin, end)| format!("{} {}", begin, end)).collect();
+            let text: Vec<&str> = fragments.iter().map(|(begin, end)| &standofftext.text[offsets[*begin]..offsets[*end]]).collect();
+            writeln!(ann, "T{}\t{} {}\t{}", id, label, charoffsets.join(";"), text.join(" "))?;
+            textbound.insert((tokens.to_vec(), label), id);
+            Ok(id)
+        };
+        for span in spans.iter() {
+            add_textbound(&mut ann, &span.tokens, parameters.label(span.elementtype, span.class.as_deref()))?;
+        }
+        for (i, relation) in relations.iter().enumerate() {
+            let head = add_textbound(&mut ann, &relation.head, ElementType::Headspan.to_string())?;
+            let dependent = add_textbound(&mut ann, &relation.dependent, ElementType::DependencyDependent.to_string())?;
+            writeln!(ann, "R{}\t{} Arg1:T{} Arg2:T{}", i + 1,
+                     relation.class.as_deref().unwrap_or_else(|| ElementType::Dependency.as_str()), head, dependent)?;
+        }
+        Ok((standofftext.text, ann))
+    }
+
+    ///Adds the annotations from BRAT stand-off format (``.ann``) to the subtree under ``root_key``. The offsets refer to the text as
+    ///exported by ``to_brat()``. Text-bound annotations become span annotations over all words
+    ///they overlap with and relations become dependency relations. Returns the keys of the added elements.
+    pub fn add_brat(&mut self, root_key: ElementKey, ann: &str, parameters: &StandoffParameters) -> Result<Vec<ElementKey>,FoliaError> {
+        let standofftext = self.standoff_text(root_key, parameters)?;
+        self.add_brat_annotations(&standofftext, ann, parameters)
+    }
+
+    ///Builds a new document from BRAT stand-off format, consisting of the text (``.txt``) and the annotations (``.ann``).
+    ///Each non-empty line of the text becomes a sentence, which is tokenised on whitespace and on
+    ///the boundaries of the text-bound annotations.
+    pub fn from_brat(id: &str, text: &str, ann: &str, parameters: &StandoffParameters, properties: DocumentProperties) -> Result<Self, FoliaError> {
+        //tokens are also split at the boundaries of text-bound annotations
+        let mut boundaries: HashSet<usize> = HashSet::new();
+        for line in ann.lines().filter(|line| line.starts_with('T')) {
+            if let Some(offsets) = line.split('\t').nth(1) {
+                for offset in offsets.split([' ', ';']).skip(1) {
+                    if let Ok(offset) = offset.parse::<usize>() {
+                        boundaries.insert(offset);
+                    }
+                }
+            }
+        }
+        let mut sentences: Vec<Vec<(String,usize,usize,bool)>> = Vec::new();
+        let mut offset = 0;
+        for line in text.split('\n') {
+            let mut tokens: Vec<(String,usize,usize,bool)> = Vec::new();
+            let mut token: Option<(String,usize)> = None;
+            for (i, c) in line.chars().enumerate() {
+                if boundaries.contains(&(offset + i)) {
+                    if let Some((text, begin)) = token.take() {
+                        tokens.push((text, offset + begin, offset + i, false));
+                    }
+                }
+                if c.is_whitespace() {
+                    if let Some((text, begin)) = token.take() {
+                        tokens.push((text, offset + begin, offset + i, true));
+                    }
+                    if let Some(previous) = tokens.last_mut() {
+                        previous.3 = true;
+                    }
+                } else if let Some((text, _)) = token.as_mut() {
+                    text.push(c);
+                } else {
+                    token = Some((c.to_string(), i));
+                }
+            }
+            let length = line.chars().count();
+            if let Some((text, begin)) = token.take() {
+                tokens.push((text, offset + begin, offset + length, true));
+            }
+            if !tokens.is_empty() {
+                sentences.push(tokens);
+            }
+            offset += length + 1;
+        }
+        let (mut doc, standofftext) = Self::from_standoff_tokens(id, &sentences, properties)?;
+        doc.add_brat_annotations(&standofftext, ann, parameters)?;
+        Ok(doc)
+    }
+
+    fn add_brat_annotations(&mut self, standofftext: &StandoffText, ann: &str, parameters: &StandoffParameters) -> Result<Vec<ElementKey>,FoliaError> {
+        let mut keys: Vec<ElementKey> = Vec::new();
+        let mut textbound: HashMap<&str,Vec<usize>> = HashMap::new();
+        let mut relations: Vec<(&str,&str,&str,usize)> = Vec::new();
+        for (linenr, line) in ann.lines().enumerate() {
+            let linenr = linenr + 1;
+            let fields: Vec<&str> = line.trim_end_matches('\r').split('\t').collect();
+            if fields.len() < 2 {
+                continue;
+            }
+            let id = fields[0];
+            let mut annotation = fields[1].split(' ');
+            let label = annotation.next().unwrap_or_default();
+            if id.starts_with('T') {
+                let offsets = annotation.collect::<Vec<&str>>().join(" ");
+                let mut tokens: Vec<usize> = Vec::new();
+                for fragment in offsets.split(';') {
+                    let mut fragment = fragment.split(' ').map(|offset| offset.parse::<usize>());
+                    match (fragment.next(), fragment.next()) {
+                        (Some(Ok(begin)), Some(Ok(end))) => tokens.extend(standofftext.tokens_in(begin, end)),
+                        _ => return Err(FoliaError::ParseError(format!("BRAT line {} has invalid offsets", linenr)))
+                    }
+                }
+                tokens.sort_unstable();
+                tokens.dedup();
+                if tokens.is_empty() {
+                    return Err(FoliaError::ParseError(format!("BRAT line {} does not cover any words", linenr)));
+                }
+                if label != ElementType::Headspan.as_str() && label != ElementType::DependencyDependent.as_str() {
+                    if let Some((elementtype, class)) = parameters.parse_label(label) {
+                        keys.push(self.add_standoff_span(standofftext, elementtype, class, &tokens, parameters)?);
+                    }
+                }
+                textbound.insert(id, tokens);
+            } else if id.starts_with('R') {
+                let mut head: Option<&str> = None;
+                let mut dependent: Option<&str> = None;
+                for argument in annotation {
+                    if let Some(target) = argument.strip_prefix("Arg1:") {
+                        head = Some(target);
+                    } else if let Some(target) = argument.strip_prefix("Arg2:") {
+                        dependent = Some(target);
+                    }
+                }
+                match (head, dependent) {
+                    (Some(head), Some(dependent)) => relations.push((label, head, dependent, linenr)),
+                    _ => return Err(FoliaError::ParseError(format!("BRAT line {} is a relation without two arguments", linenr)))
+                }
+            }
+            //other annotations (events, attributes, notes) are not converted
+        }
+        for (label, head, dependent, linenr) in relations {
+            match (textbound.get(head), textbound.get(dependent)) {
+                (Some(head), Some(dependent)) => {
+                    let class = if label == ElementType::Dependency.as_str() { None } else { Some(label.to_string()) };
+                    keys.push(self.add_standoff_relation(standofftext, class, head, dependent, parameters)?);
+                },
+                _ => return Err(FoliaError::ParseError(format!("BRAT line {} refers to an unknown text-bound annotation", linenr)))
+            }
+        }
+        Ok(keys)
+    }
+
+    ///Exports the subtree under ``root_key`` to WebAnno TSV (version 3), with one layer per span
+    ///annotation type and a layer for dependency relations (if any). Span annotations are labelled by their class.
+    ///Dependency relations are reduced to the first word of the head and of the dependent.
+    pub fn to_webanno_tsv(&self, root_key: ElementKey, parameters: &StandoffParameters) -> Result<String,FoliaError> {
+        let standofftext = self.standoff_text(root_key, parameters)?;
+        let (spans, relations) = self.standoff_annotations(root_key, &standofftext, parameters)?;
+        let offsets = standofftext.byte_offsets();
+        let addresses = webanno_addresses(&standofftext);
+
+        //span layers: one column per layer, holding the cell values per token
+        let spantypes: Vec<ElementType> = parameters.spantypes.iter().filter(|spantype| spans.iter().any(|span| span.elementtype == **spantype)).copied().collect();
+        let mut columns: Vec<Vec<Vec<String>>> = vec![vec![Vec::new(); standofftext.tokens.len()]; spantypes.len()];
+        let mut spannr = 0;
+        for span in spans.iter() {
+            let column = spantypes.iter().position(|spantype| *spantype == span.elementtype).expect("span layer");
+            let label = span.class.as_deref().map(webanno_escape).unwrap_or_else(|| NOLABEL.to_string());
+            let value = if span.tokens.len() > 1 {
+                spannr += 1;
+                format!("{}[{}]", label, spannr)
+            } else {
+                label
+            };
+            for token in span.tokens.iter() {
+                columns[column][*token].push(value.clone());
+            }
+        }
+        //relation layer: the relation type and the governor, on the dependent
+        let mut relationcolumns: Vec<Vec<(String,String)>> = vec![Vec::new(); standofftext.tokens.len()];
+        for relation in relations.iter() {
+            relationcolumns[relation.dependent[0]].push((
+                relation.class.as_deref().map(webanno_escape).unwrap_or_else(|| NOLABEL.to_string()),
+                addresses[relation.head[0]].clone(),
+            ));
+        }
+
+        let mut output = String::new();
+        writeln!(output, "#FORMAT={}", WEBANNO_TSV_FORMAT)?;
+        for spantype in spantypes.iter() {
+            let (layer, feature) = webanno_layer(*spantype);
+            writeln!(output, "#T_SP={}|{}", layer, feature)?;
+        }
+        if !relations.is_empty() {
+            writeln!(output, "#T_RL={}|DependencyType|flavor|BT_{}", WEBANNO_DEPENDENCY_LAYER, WEBANNO_DEPENDENCY_BASE)?;
+        }
+        output.push_str("\n\n");
+        let mut begin = 0;
+        while begin < standofftext.tokens.len() {
+            let sentence = standofftext.tokens[begin].sentence;
+            let end = standofftext.tokens[begin..].iter().position(|token| token.sentence != sentence).map(|i| begin + i).unwrap_or_else(|| standofftext.tokens.len());
+            let text = &standofftext.text[offsets[standofftext.tokens[begin].begin]..offsets[standofftext.tokens[end-1].end]];
+            writeln!(output, "#Text={}", text)?;
+            for i in begin..end {
+                let token = &standofftext.tokens[i];
+                write!(output, "{}\t{}-{}\t{}\t", addresses[i], token.begin, token.end, webanno_escape(&token.text))?;
+                for column in columns.iter() {
+                    write!(output, "{}\t", webanno_cell(&column[i]))?;
+                }
+                if !relations.is_empty() {
+                    let types: Vec<String> = relationcolumns[i].iter().map(|(class, _)| class.clone()).collect();
+                    let flavors: Vec<String> = relationcolumns[i].iter().map(|_| "basic".to_string()).collect();
+                    let governors: Vec<String> = relationcolumns[i].iter().map(|(_, governor)| governor.clone()).collect();
+                    write!(output, "{}\t{}\t{}\t", webanno_cell(&types), webanno_cell(&flavors), webanno_cell(&governors))?;
+                }
+                output.push('\n');
+            }
+            output.push('\n');
+            begin = end;
+        }
+        Ok(output)
+    }
+
+    ///Builds a new document from WebAnno TSV (version 3). Sentences and tokens are created as
+    ///given, span layers that map to one of the span types in the parameters are converted to
+    ///span annotations and the dependency layer to dependency relations. Other layers are not converted.
+    pub fn from_webanno_tsv(id: &str, data: &str, parameters: &StandoffParameters, properties: DocumentProperties) -> Result<Self, FoliaError> {
+        //the meaning of each annotation column
+        enum Column {
+            Span(ElementType),
+            RelationType,
+            RelationGovernor,
+            Other,
+        }
+        let mut columns: Vec<Column> = Vec::new();
+        let mut sentences: Vec<Vec<(String,usize,usize,bool)>> = Vec::new();
+        let mut cells: Vec<Vec<&str>> = Vec::new(); //per token
+        let mut addresses: HashMap<&str,usize> = HashMap::new(); //token address to token index
+        let mut tokens: Vec<(String,usize,usize,bool)> = Vec::new();
+        for (linenr, line) in data.lines().chain(std::iter::once("")).enumerate() {
+            let line = line.trim_end_matches('\r');
+            if let Some(layer) = line.strip_prefix("#T_SP=").or_else(|| line.strip_prefix("#T_CH=")) {
+                let mut features = layer.split('|');
+                let elementtype = webanno_elementtype(features.next().unwrap_or_default());
+                let features: Vec<&str> = features.collect();
+                //the label is in the feature we export it to (e.g. NamedEntity|identifier|value), the first feature otherwise
+                let labelcolumn = elementtype.and_then(|elementtype| features.iter().position(|feature| *feature == webanno_layer(elementtype).1)).unwrap_or(0);
+                for i in 0..features.len() {
+                    columns.push(match elementtype {
+                        Some(elementtype) if i == labelcolumn && parameters.spantypes.contains(&elementtype) => Column::Span(elementtype),
+                        _ => Column::Other
+                    });
+                }
+            } else if let Some(layer) = line.strip_prefix("#T_RL=") {
+                let mut features = layer.split('|');
+                let dependency = features.next() == Some(WEBANNO_DEPENDENCY_LAYER);
+                for feature in features {
+                    columns.push(match feature {
+                        "DependencyType" if dependency => Column::RelationType,
+                        feature if dependency && feature.starts_with("BT_") => Column::RelationGovernor,
+                        _ => Column::Other
+                    });
+                }
+            } else if line.starts_with('#') {
+                continue;
+            } else if line.trim().is_empty() {
+                if !tokens.is_empty() {
+                    sentences.push(std::mem::take(&mut tokens));
+                }
+            } else {
+                let fields: Vec<&str> = line.split('\t').collect();
+                if fields.len() < 3 {
+                    return Err(FoliaError::ParseError(format!("WebAnno TSV line {} has too few fields", linenr + 1)));
+                }
+                if fields[0].contains('.') {
+                    //sub-token
+                    continue;
+                }
+                let mut offsets = fields[1].split('-').map(|offset| offset.parse::<usize>());
+                let (begin, end) = match (offsets.next(), offsets.next()) {
+                    (Some(Ok(begin)), Some(Ok(end))) => (begin, end),
+                    _ => return Err(FoliaError::ParseError(format!("WebAnno TSV line {} has invalid offsets", linenr + 1)))
+                };
+                if let Some(previous) = tokens.last_mut() {
+                    previous.3 = previous.2 != begin;
+                }
+                addresses.insert(fields[0], sentences.iter().map(|sentence| sentence.len()).sum::<usize>() + tokens.len());
+                tokens.push((webanno_unescape(fields[2]), begin, end, true));
+                cells.push(fields[3..].to_vec());
+            }
+        }
+
+        let (mut doc, standofftext) = Self::from_standoff_tokens(id, &sentences, properties)?;
+
+        //spans, multi-token spans are identified by their number
+        let mut spans: BTreeMap<(usize,usize),StandoffSpan> = BTreeMap::new();
+        let mut relations: Vec<(Option<String>,usize,usize)> = Vec::new();
+        for (token, cells) in cells.iter().enumerate() {
+            let mut relationtypes: Vec<&str> = Vec::new();
+            let mut governors: Vec<&str> = Vec::new();
+            for (cell, column) in cells.iter().zip(columns.iter()) {
+                match column {
+                    Column::Span(elementtype) if *cell != EMPTY => {
+                        for value in webanno_split(cell) {
+                            let (label, spannr) = match (value.rfind('['), value.ends_with(']')) {
+                                (Some(index), true) if !value[..index].ends_with('\\') => (&value[..index], value[index+1..value.len()-1].parse::<usize>().ok()),
+                                _ => (value, None)
+                            };
+                            let class = if label == NOLABEL || label.is_empty() { None } else { Some(webanno_unescape(label)) };
+                            //single-token spans are identified by the token
+                            let key = match spannr { Some(spannr) => (spannr, 0), None => (0, spans.len() + 1) };
+                            spans.entry(key).or_insert_with(|| StandoffSpan { elementtype: *elementtype, class, tokens: Vec::new() }).tokens.push(token);
+                        }
+                    },
+                    Column::RelationType if *cell != EMPTY => relationtypes = webanno_split(cell),
+                    Column::RelationGovernor if *cell != EMPTY => governors = webanno_split(cell),
+                    _ => {}
+                }
+            }
+            for (i, governor) in governors.iter().enumerate() {
+                let governor = governor.split('[').next().unwrap_or_default();
+                let head = *addresses.get(governor).ok_or_else(|| FoliaError::ParseError(format!("WebAnno TSV refers to an unknown token: {}", governor)))?;
+                let class = relationtypes.get(i).filter(|label| **label != NOLABEL).map(|label| webanno_unescape(label));
+                relations.push((class, head, token));
+            }
+        }
+        let mut spans: Vec<StandoffSpan> = spans.into_values().collect();
+        spans.sort_by(|a, b| a.tokens.cmp(&b.tokens));
+        for span in spans {
+            doc.add_standoff_span(&standofftext, span.elementtype, span.class, &span.tokens, parameters)?;
+        }
+        for (class, head, dependent) in relations {
+            doc.add_standoff_relation(&standofftext, class, &[head], &[dependent], parameters)?;
+        }
+        Ok(doc)
+    }
+}
+
+///Returns the WebAnno layer and feature for a span annotation type
+fn webanno_layer(elementtype: ElementType) -> (String, &'static str) {
+    match elementtype {
+        ElementType::Entity => ("de.tudarmstadt.ukp.dkpro.core.api.ner.type.NamedEntity".to_string(), "value"),
+        ElementType::Chunk => ("de.tudarmstadt.ukp.dkpro.core.api.syntax.type.chunk.Chunk".to_string(), "chunkValue"),
+        elementtype => (format!("webanno.custom.{}", elementtype), "value"),
+    }
+}
+
+///Returns the span annotation type for a WebAnno layer, the reverse of ``webanno_layer()``
+fn webanno_elementtype(layer: &str) -> Option<ElementType> {
+    match layer {
+        "de.tudarmstadt.ukp.dkpro.core.api.ner.type.NamedEntity" => Some(ElementType::Entity),
+        "de.tudarmstadt.ukp.dkpro.core.api.syntax.type.chunk.Chunk" => Some(ElementType::Chunk),
+        layer => layer.strip_prefix("webanno.custom.").and_then(|tag| ElementType::from_str(tag).ok()),
+    }
+}
+
+///Returns the WebAnno addresses of all tokens: the sentence number and token number
+fn webanno_addresses(standofftext: &StandoffText) -> Vec<String> {
+    let mut addresses: Vec<String> = Vec::with_capacity(standofftext.tokens.len());
+    let mut tokennr = 0;
+    for (i, token) in standofftext.tokens.iter().enumerate() {
+        if i == 0 || standofftext.tokens[i-1].sentence != token.sentence {
+            tokennr = 0;
+        }
+        tokennr += 1;
+        addresses.push(format!("{}-{}", token.sentence + 1, tokennr));
+    }
+    addresses
+}
+
+///Formats a cell holding zero or more (stacked) values
+fn webanno_cell(values: &[String]) -> String {
+    if values.is_empty() {
+        EMPTY.to_string()
+    } else {
+        values.join("|")
+    }
+}
+
+///Splits a cell into its (stacked) values, honouring escaped separators
+fn webanno_split(cell: &str) -> Vec<&str> {
+    let mut values: Vec<&str> = Vec::new();
+    let mut begin = 0;
+    let mut escaped = false;
+    for (i, c) in cell.char_indices() {
+        match c {
+            '\\' if !escaped => { escaped = true; continue },
+            '|' if !escaped => {
+                values.push(&cell[begin..i]);
+                begin = i + 1;
+            },
+            _ => {}
+        }
+        escaped = false;
+    }
+    values.push(&cell[begin..]);
+    values
+}
+
+///Escapes the characters that have a special meaning in WebAnno TSV
+fn webanno_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | '[' | ']' | '|' | '_' | '*' | ';' => { escaped.push('\\'); escaped.push(c) },
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+///Unescapes a WebAnno TSV value, the reverse of ``webanno_escape()``
+fn webanno_unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('t') => unescaped.push('\t'),
+                Some('n') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => unescaped.push('\\'),
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
@@ -0,0 +1,611 @@
+use std::collections::{HashMap,HashSet,BTreeSet};
+use std::fmt;
+
+use crate::common::*;
+use crate::types::*;
+use crate::transaction::*;
+use crate::journal::*;
+use crate::error::*;
+use crate::element::*;
+use crate::attrib::*;
+use crate::store::*;
+use crate::elementstore::*;
+use crate::metadata::*;
+use crate::select::*;
+use crate::query::*;
+use crate::document::Document;
+
+#[derive(Default)]
+///Keeps track of the declarations, processors and submetadata used by an extracted subtree
+struct SubtreeUsage {
+    declarations: BTreeSet<DecKey>,
+    processors: HashSet<ProcKey>,
+    submetadata: BTreeSet<String>,
+}
+
+#[derive(Debug,Clone,PartialEq)]
+///A conflict encountered when merging documents. In case of a conflict, the information
+///already in the target document is retained.
+pub enum MergeConflict {
+    ///A declaration for the same annotation type and set has a different alias or format
+    Declaration { annotationtype: AnnotationType, set: Option<String> },
+    ///A processor with the same ID has a different name, type or version
+    Processor { id: String },
+    ///A metadata field has a different value
+    Metadata { key: String },
+    ///The ID is already in use by a different type of element, the merged element was given a new ID
+    Id { id: String, new_id: String },
+    ///The same annotation has a different class. ``element`` is the ID of the annotation, or of the
+    ///nearest element with an ID
+    Class { element: Option<String>, elementtype: ElementType, set: Option<String>, ours: Option<String>, theirs: Option<String> },
+    ///The text content differs
+    Text { element: Option<String>, ours: String, theirs: String },
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergeConflict::Declaration { annotationtype, set } => write!(f, "declaration {} ({}) differs", annotationtype, set.as_deref().unwrap_or("no set")),
+            MergeConflict::Processor { id } => write!(f, "processor {} differs", id),
+            MergeConflict::Metadata { key } => write!(f, "metadata {} differs", key),
+            MergeConflict::Id { id, new_id } => write!(f, "ID {} already in use, renamed to {}", id, new_id),
+            MergeConflict::Class { element, elementtype, set, ours, theirs } => write!(f, "{} ({}) on {} has class {} rather than {}", elementtype, set.as_deref().unwrap_or("no set"), element.as_deref().unwrap_or("?"), ours.as_deref().unwrap_or("(none)"), theirs.as_deref().unwrap_or("(none)")),
+            MergeConflict::Text { element, ours, theirs } => write!(f, "text on {} is \"{}\" rather than \"{}\"", element.as_deref().unwrap_or("?"), ours, theirs),
+        }
+    }
+}
+
+#[derive(Debug,Clone,Default,PartialEq)]
+///Reports the result of merging a document into another
+pub struct MergeReport {
+    ///The number of elements (subtrees) that were added
+    pub added: usize,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl MergeReport {
+    ///Returns true if the merge had no conflicts
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+impl Document {
+    ///Extracts the element with the specified key, and everything under it, as a new standalone
+    ///document. The new document carries only the declarations, processors and submetadata
+    ///that the subtree uses. Span annotations are only retained if all the elements they span
+    ///are within the subtree. If the key refers to a structure element, it is placed directly under a body
+    ///of the same type as in the original document.
+    pub fn extract(&self, key: ElementKey) -> Result<Document, FoliaError> {
+        let element = self.get_elementdata(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+        if element.elementtype == ElementType::Text || element.elementtype == ElementType::Speech {
+            if element.parent_key().is_some() {
+                return Err(FoliaError::TypeError("Unable to extract a nested text or speech body".to_string()));
+            }
+        } else if !ElementGroup::Structure.contains(element.elementtype) {
+            return Err(FoliaError::TypeError(format!("Only structure elements can be extracted, got {}", element.elementtype)));
+        }
+
+        //find the body
+        let mut body_key = key;
+        while let Some(parent_key) = self.get_elementdata(body_key).and_then(|e| e.parent_key()) {
+            body_key = parent_key;
+        }
+
+        //all IDs in the subtree, spans may only refer to these
+        let scope = self.subtree_ids(key);
+
+        let mut usage = SubtreeUsage::default();
+        let tree = self.decoded_tree(key, Some(&scope), &mut usage)?.ok_or_else(|| FoliaError::InternalError("Subtree is empty after extraction".to_string()))?;
+
+        let mut doc = Document {
+            id: self.id.clone(),
+            filename: None,
+            version: self.version.clone(),
+            elementstore: ElementStore::default(),
+            provenancestore: ProvenanceStore::default(),
+            declarationstore: DeclarationStore::default(),
+            metadata: self.metadata.clone(),
+            submetadata: HashMap::default(),
+            autodeclare: self.autodeclare,
+            history: History::default(),
+            journal: None,
+            active_processor: None,
+        };
+        for submetadata_id in usage.submetadata.iter() {
+            if let Some(submetadata) = self.submetadata.get(submetadata_id) {
+                doc.submetadata.insert(submetadata_id.clone(), submetadata.clone());
+            }
+        }
+
+        //copy the used processors, along with their ancestors so the hierarchy is retained
+        let mut needed: HashSet<ProcKey> = HashSet::new();
+        for proc_key in usage.processors.iter() {
+            let mut proc_key = Some(*proc_key);
+            while let Some(k) = proc_key {
+                needed.insert(k);
+                proc_key = self.get_processor(k).and_then(|p| p.parent);
+            }
+        }
+        let mut processor_map: HashMap<ProcKey,ProcKey> = HashMap::new();
+        for proc_key in self.provenancestore.chain.iter() {
+            self.copy_processor(&mut doc, *proc_key, None, &needed, &mut processor_map)?;
+        }
+
+        for dec_key in usage.declarations.iter() {
+            if let Some(declaration) = self.get_declaration(*dec_key) {
+                let mut newdeclaration = declaration.clone();
+                newdeclaration.key = None;
+                newdeclaration.processors = declaration.processors.iter().filter_map(|k| processor_map.get(k).copied()).collect();
+                doc.add_declaration(newdeclaration)?;
+            }
+        }
+
+        let body = self.get_elementdata(body_key).expect("unwrapping body");
+        let bodydata = ElementData::new(body.elementtype).with_attribs(self.decoded_attributes(body_key)?);
+        let new_body_key = doc.add(bodydata, None)?;
+        if key == body_key {
+            for item in tree.data {
+                doc.add_tree_item(new_body_key, item)?;
+            }
+        } else {
+            doc.add_tree_to(new_body_key, tree)?;
+        }
+        Ok(doc)
+    }
+
+    ///Copies a processor (and the needed subprocessors) to another document
+    fn copy_processor(&self, doc: &mut Document, proc_key: ProcKey, parent_key: Option<ProcKey>, needed: &HashSet<ProcKey>, processor_map: &mut HashMap<ProcKey,ProcKey>) -> Result<(), FoliaError> {
+        if !needed.contains(&proc_key) {
+            return Ok(());
+        }
+        if let Some(processor) = self.get_processor(proc_key) {
+            let mut newprocessor = processor.clone();
+            newprocessor.processors = vec!();
+            newprocessor.parent = None;
+            newprocessor.key = None;
+            let new_key = match parent_key {
+                Some(parent_key) => doc.add_subprocessor(parent_key, newprocessor)?,
+                None => doc.add_processor(newprocessor)?,
+            };
+            processor_map.insert(proc_key, new_key);
+            for subprocessor_key in processor.processors.iter() {
+                self.copy_processor(doc, *subprocessor_key, Some(new_key), needed, processor_map)?;
+            }
+        }
+        Ok(())
+    }
+
+    ///Returns a decoded copy of the element and everything under it, with children to be added
+    ///(``DataType::AddElement``). If a scope is provided, span annotations that refer to elements outside the scope are
+    ///left out, as are annotation layers that end up empty. Returns ``None`` if the element itself is left out.
+    fn decoded_tree(&self, key: ElementKey, scope: Option<&HashSet<String>>, usage: &mut SubtreeUsage) -> Result<Option<ElementData>, FoliaError> {
+        let element = self.get_element(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+        let elementtype = element.elementtype();
+        if let Some(scope) = scope {
+            if ElementGroup::Span.contains(elementtype) && !self.span_in_scope(key, scope) {
+                return Ok(None);
+            }
+        }
+        let attribs = self.decoded_attributes(key)?;
+        if let Some(dec_key) = element.declaration_key() {
+            usage.declarations.insert(dec_key);
+        } else if let Some(annotationtype) = elementtype.annotationtype() {
+            //elements without set, class or processor are not encoded, they may use any (default) declaration of their type
+            for declaration in self.declarations().flatten() {
+                if declaration.annotationtype == annotationtype {
+                    usage.declarations.insert(declaration.key.expect("declaration key"));
+                }
+            }
+        }
+        if let Some(proc_key) = element.processor_key() {
+            usage.processors.insert(proc_key);
+        }
+        if let Some(Attribute::Metadata(submetadata_id)) = element.attrib(AttribType::METADATA) {
+            usage.submetadata.insert(submetadata_id.clone());
+        }
+
+        let mut data: Vec<DataType> = Vec::new();
+        let mut has_elements = false;
+        for item in element.elementdata().data.iter() {
+            match item {
+                DataType::Element(child_key) => {
+                    if let Some(child) = self.decoded_tree(*child_key, scope, usage)? {
+                        data.push(DataType::AddElement(child));
+                        has_elements = true;
+                    }
+                },
+                DataType::SpanReference(_) => {},
+                item => data.push(item.clone()),
+            }
+        }
+        if ElementGroup::Layer.contains(elementtype) && !has_elements {
+            return Ok(None);
+        }
+        Ok(Some(ElementData::new(elementtype).with_attribs(attribs).with_children(data)))
+    }
+
+    ///Returns the IDs of the element and everything under it
+    fn subtree_ids(&self, key: ElementKey) -> HashSet<String> {
+        let mut ids: HashSet<String> = HashSet::new();
+        for item in self.select_data_by_key(key, Selector::elements(), Recursion::Always, true, false) {
+            if let DataType::Element(subkey) = item.data {
+                if let Some(id) = self.get_elementdata(*subkey).and_then(|e| e.id()) {
+                    ids.insert(id.to_string());
+                }
+            }
+        }
+        ids
+    }
+
+    ///Tests whether all elements spanned by the specified span element are in scope
+    fn span_in_scope(&self, key: ElementKey, scope: &HashSet<String>) -> bool {
+        for item in self.select_data_by_key(key, Selector::elements().element(Cmp::Is(ElementType::WordReference)), Recursion::Always, false, false) {
+            if let DataType::Element(wref_key) = item.data {
+                if let Some(Attribute::Idref(idref)) = self.get_elementdata(*wref_key).and_then(|e| e.attrib(AttribType::IDREF)) {
+                    if !scope.contains(idref) {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+impl Document {
+    ///Copies the element with the specified key, and everything under it, to another parent
+    ///within this document. The copies get new IDs, references to elements within the copied
+    ///subtree (such as in span annotations) are updated accordingly. Returns the key of the copy.
+    pub fn clone_subtree(&mut self, key: ElementKey, new_parent: ElementKey) -> Result<ElementKey, FoliaError> {
+        let mut usage = SubtreeUsage::default();
+        let tree = self.decoded_tree(key, None, &mut usage)?.ok_or_else(|| FoliaError::InternalError("Nothing to copy".to_string()))?;
+        self.add_imported_tree(None, tree, new_parent)
+    }
+
+    ///Copies the element with the specified key in another document, and everything under it, to
+    ///the specified parent in this document. Sets, classes and processors are encoded against
+    ///this document, missing declarations, processors and submetadata are copied along.
+    ///Elements whose ID is already in use in this document get a new ID. Span annotations are only retained
+    ///if all the elements they span are within the subtree. Returns the key of the copy.
+    pub fn import_subtree(&mut self, other: &Document, key: ElementKey, new_parent: ElementKey) -> Result<ElementKey, FoliaError> {
+        let scope = other.subtree_ids(key);
+        let mut usage = SubtreeUsage::default();
+        let tree = other.decoded_tree(key, Some(&scope), &mut usage)?.ok_or_else(|| FoliaError::InternalError("Nothing to import".to_string()))?;
+
+        let mut processor_map: HashMap<ProcKey,ProcKey> = HashMap::new();
+        for proc_key in usage.processors.iter() {
+            self.import_processor(other, *proc_key, &mut processor_map)?;
+        }
+        for dec_key in usage.declarations.iter() {
+            if let Some(declaration) = other.get_declaration(*dec_key) {
+                let index_id = Declaration::index_id(declaration.annotationtype, &declaration.set.as_deref());
+                if self.get_declaration_key_by_id(&index_id).is_none() {
+                    let mut newdeclaration = Declaration::new(declaration.annotationtype, declaration.set.clone(), declaration.alias.clone(), declaration.format.clone());
+                    newdeclaration.processors = declaration.processors.iter().filter_map(|k| processor_map.get(k).copied()).collect();
+                    self.add_declaration(newdeclaration)?;
+                }
+            }
+        }
+        for submetadata_id in usage.submetadata.iter() {
+            if let (false, Some(submetadata)) = (self.submetadata.contains_key(submetadata_id), other.submetadata.get(submetadata_id)) {
+                self.submetadata.insert(submetadata_id.clone(), submetadata.clone());
+            }
+        }
+        self.add_imported_tree(Some(other), tree, new_parent)
+    }
+
+    ///Adds a decoded tree from another document (or from this document if none is specified), renaming any IDs that are already in use
+    fn add_imported_tree(&mut self, other: Option<&Document>, mut tree: ElementData, new_parent: ElementKey) -> Result<ElementKey, FoliaError> {
+        self.check_element_addable(new_parent, &tree)?;
+        let mut id_map: HashMap<String,String> = HashMap::new();
+        let mut ids: Vec<String> = Vec::new();
+        collect_ids(&tree, &mut ids);
+        for id in ids {
+            if self.get_element_key_by_id(&id).is_some() {
+                let new_id = self.unique_id(&id, other, &id_map);
+                id_map.insert(id, new_id);
+            }
+        }
+        rename_ids(&mut tree, &id_map);
+        self.add_tree_to(new_parent, tree)
+    }
+
+    ///Copies a processor (and its ancestors) from another document, unless a processor with the same ID already exists
+    fn import_processor(&mut self, other: &Document, proc_key: ProcKey, processor_map: &mut HashMap<ProcKey,ProcKey>) -> Result<Option<ProcKey>, FoliaError> {
+        if let Some(new_key) = processor_map.get(&proc_key) {
+            return Ok(Some(*new_key));
+        }
+        if let Some(processor) = other.get_processor(proc_key) {
+            let new_key = if let Some(existing_key) = self.get_processor_key_by_id(&processor.id) {
+                existing_key
+            } else {
+                let parent_key = match processor.parent {
+                    Some(parent_key) => self.import_processor(other, parent_key, processor_map)?,
+                    None => None,
+                };
+                let mut newprocessor = processor.clone();
+                newprocessor.processors = vec!();
+                newprocessor.parent = None;
+                newprocessor.key = None;
+                match parent_key {
+                    Some(parent_key) => self.add_subprocessor(parent_key, newprocessor)?,
+                    None => self.add_processor(newprocessor)?,
+                }
+            };
+            processor_map.insert(proc_key, new_key);
+            Ok(Some(new_key))
+        } else {
+            Ok(None)
+        }
+    }
+
+    ///Returns a variant of the ID that is not in use in this document, nor in the other document (if any), nor as a new ID in the map
+    fn unique_id(&self, id: &str, other: Option<&Document>, id_map: &HashMap<String,String>) -> String {
+        let mut n = 1;
+        let mut new_id = format!("{}.{}", id, n);
+        while self.get_element_key_by_id(&new_id).is_some() || other.and_then(|other| other.get_element_key_by_id(&new_id)).is_some() || id_map.values().any(|v| *v == new_id) {
+            n += 1;
+            new_id = format!("{}.{}", id, n);
+        }
+        new_id
+    }
+}
+
+impl Document {
+    ///Merges another document into this one, for instance to re-insert a subdocument that was
+    ///obtained through ``extract()`` and annotated separately. Elements are matched by ID,
+    ///elements without ID (such as token annotations and annotation layers) by type and set. New
+    ///elements from the other document that can not be matched are added under the element with the specified key. Declarations are
+    ///reconciled by annotation type and set and processors by ID. Conflicts do not abort the merge
+    ///but are reported, the information already in this document is retained in that case.
+    pub fn merge(&mut self, other: &Document, at_key: ElementKey) -> Result<MergeReport, FoliaError> {
+        if self.get_elementdata(at_key).is_none() {
+            return Err(FoliaError::KeyError(format!("No such element key: {}", at_key)));
+        }
+        let mut report = MergeReport::default();
+
+        let mut processor_map: HashMap<ProcKey,ProcKey> = HashMap::new();
+        for proc_key in other.provenancestore.chain.iter() {
+            self.merge_processor(other, *proc_key, None, &mut processor_map, &mut report)?;
+        }
+
+        for declaration in other.declarations().flatten() {
+            let processors: Vec<ProcKey> = declaration.processors.iter().filter_map(|k| processor_map.get(k).copied()).collect();
+            let index_id = Declaration::index_id(declaration.annotationtype, &declaration.set.as_deref());
+            if let Some(existing) = self.get_mut_declaration_by_id(&index_id) {
+                if existing.alias != declaration.alias || existing.format != declaration.format {
+                    report.conflicts.push(MergeConflict::Declaration { annotationtype: declaration.annotationtype, set: declaration.set.clone() });
+                }
+                for proc_key in processors {
+                    if !existing.processors.contains(&proc_key) {
+                        existing.processors.push(proc_key);
+                    }
+                }
+            } else {
+                let mut newdeclaration = Declaration::new(declaration.annotationtype, declaration.set.clone(), declaration.alias.clone(), declaration.format.clone());
+                newdeclaration.processors = processors;
+                self.add_declaration(newdeclaration)?;
+            }
+        }
+
+        for (key, value) in other.metadata.data.iter() {
+            match self.metadata.data.get(key) {
+                Some(existing) if existing != value => report.conflicts.push(MergeConflict::Metadata { key: key.clone() }),
+                Some(_) => {},
+                None => { self.metadata.data.insert(key.clone(), value.clone()); },
+            }
+        }
+        for (submetadata_id, submetadata) in other.submetadata.iter() {
+            if !self.submetadata.contains_key(submetadata_id) {
+                self.submetadata.insert(submetadata_id.clone(), submetadata.clone());
+            }
+        }
+
+        //elements with an ID that is in use by a different type of element get a new ID
+        let mut id_map: HashMap<String,String> = HashMap::new();
+        for item in other.select_data(Selector::elements(), Recursion::Always, false) {
+            if let DataType::Element(key) = item.data {
+                if let Some(element) = other.get_elementdata(*key) {
+                    if let (Some(id), Some(existing)) = (element.id(), element.id().and_then(|id| self.get_elementdata_by_id(id))) {
+                        if existing.elementtype != element.elementtype {
+                            let new_id = self.unique_id(id, Some(other), &id_map);
+                            report.conflicts.push(MergeConflict::Id { id: id.to_string(), new_id: new_id.clone() });
+                            id_map.insert(id.to_string(), new_id);
+                        }
+                    }
+                }
+            }
+        }
+
+        let other_body = other.get_elementdata(0).ok_or_else(|| FoliaError::KeyError("Document to merge has no body".to_string()))?;
+        if other_body.id().is_some() && other_body.id() == self.get_elementdata(at_key).and_then(|e| e.id()) {
+            self.merge_element(other, 0, at_key, &id_map, &mut report)?;
+        } else {
+            self.merge_children(other, 0, at_key, &id_map, &mut report)?;
+        }
+        Ok(report)
+    }
+
+    ///Merges a processor (and its subprocessors) from another document
+    fn merge_processor(&mut self, other: &Document, proc_key: ProcKey, parent_key: Option<ProcKey>, processor_map: &mut HashMap<ProcKey,ProcKey>, report: &mut MergeReport) -> Result<(), FoliaError> {
+        if let Some(processor) = other.get_processor(proc_key) {
+            let new_key = if let Some(existing_key) = self.get_processor_key_by_id(&processor.id) {
+                let existing = self.get_processor(existing_key).expect("unwrapping processor");
+                if existing.name != processor.name || existing.processortype != processor.processortype || existing.version != processor.version {
+                    report.conflicts.push(MergeConflict::Processor { id: processor.id.clone() });
+                }
+                existing_key
+            } else {
+                let mut newprocessor = processor.clone();
+                newprocessor.processors = vec!();
+                newprocessor.parent = None;
+                newprocessor.key = None;
+                match parent_key {
+                    Some(parent_key) => self.add_subprocessor(parent_key, newprocessor)?,
+                    None => self.add_processor(newprocessor)?,
+                }
+            };
+            processor_map.insert(proc_key, new_key);
+            for subprocessor_key in processor.processors.iter() {
+                self.merge_processor(other, *subprocessor_key, Some(new_key), processor_map, report)?;
+            }
+        }
+        Ok(())
+    }
+
+    ///Merges an element from another document with its counterpart in this document
+    fn merge_element(&mut self, other: &Document, other_key: ElementKey, key: ElementKey, id_map: &HashMap<String,String>, report: &mut MergeReport) -> Result<(), FoliaError> {
+        let (elementtype, set, _, _) = element_signature(other, other_key);
+        let ours = self.get_element(key).and_then(|e| e.class().map(|s| s.to_string()));
+        let theirs = other.get_element(other_key).and_then(|e| e.class().map(|s| s.to_string()));
+        if ours != theirs {
+            report.conflicts.push(MergeConflict::Class { element: self.nearest_id(key), elementtype, set, ours, theirs });
+            return Ok(());
+        }
+        let props = self.props(elementtype);
+        if props.textcontainer || props.phoncontainer {
+            let ours = self.raw_text(key);
+            let theirs = other.raw_text(other_key);
+            if ours != theirs {
+                report.conflicts.push(MergeConflict::Text { element: self.nearest_id(key), ours, theirs });
+            }
+            return Ok(());
+        }
+        self.merge_children(other, other_key, key, id_map, report)
+    }
+
+    ///Merges the children of an element from another document into the specified element
+    fn merge_children(&mut self, other: &Document, other_key: ElementKey, key: ElementKey, id_map: &HashMap<String,String>, report: &mut MergeReport) -> Result<(), FoliaError> {
+        let other_element = other.get_elementdata(other_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", other_key)))?;
+        for item in other_element.data.iter() {
+            if let DataType::Element(other_child_key) = item {
+                let other_child = other.get_elementdata(*other_child_key).expect("unwrapping child");
+                let counterpart = match other_child.id() {
+                    Some(id) if !id_map.contains_key(id) => self.get_element_key_by_id(id),
+                    Some(_) => None,
+                    None => self.find_counterpart(key, other, *other_child_key),
+                };
+                if let Some(counterpart) = counterpart {
+                    self.merge_element(other, *other_child_key, counterpart, id_map, report)?;
+                } else {
+                    let mut usage = SubtreeUsage::default();
+                    if let Some(mut tree) = other.decoded_tree(*other_child_key, None, &mut usage)? {
+                        rename_ids(&mut tree, id_map);
+                        self.add_tree_to(key, tree)?;
+                        report.added += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    ///Finds the child of the specified element that corresponds to an element (without ID) in another document, by
+    ///type, set, subset and (for span elements) the spanned elements. Text and phonetic content
+    ///is also matched by class, as there may be multiple for the same set.
+    fn find_counterpart(&self, key: ElementKey, other: &Document, other_key: ElementKey) -> Option<ElementKey> {
+        let signature = element_signature(other, other_key);
+        let span = other.span_ids(other_key);
+        let props = self.props(signature.0);
+        let class = if props.textcontainer || props.phoncontainer {
+            other.get_element(other_key).and_then(|e| e.class().map(|s| s.to_string()))
+        } else {
+            None
+        };
+        for item in self.get_elementdata(key)?.data.iter() {
+            if let DataType::Element(child_key) = item {
+                if element_signature(self, *child_key) == signature && self.get_elementdata(*child_key).and_then(|e| e.id()).is_none() && self.span_ids(*child_key) == span
+                   && (class.is_none() || self.get_element(*child_key).and_then(|e| e.class().map(|s| s.to_string())) == class) {
+                    return Some(*child_key);
+                }
+            }
+        }
+        None
+    }
+
+    ///Returns the IDs of the elements a span element spans (empty for other elements)
+    pub(crate) fn span_ids(&self, key: ElementKey) -> Vec<String> {
+        let mut span: Vec<String> = Vec::new();
+        if self.get_elementdata(key).map(|e| ElementGroup::Span.contains(e.elementtype)) == Some(true) {
+            for item in self.select_data_by_key(key, Selector::elements().element(Cmp::Is(ElementType::WordReference)), Recursion::Always, false, false) {
+                if let DataType::Element(wref_key) = item.data {
+                    if let Some(Attribute::Idref(idref)) = self.get_elementdata(*wref_key).and_then(|e| e.attrib(AttribType::IDREF)) {
+                        span.push(idref.clone());
+                    }
+                }
+            }
+        }
+        span
+    }
+
+    ///Returns all text under the specified element
+    pub(crate) fn raw_text(&self, key: ElementKey) -> String {
+        let mut text = String::new();
+        for item in self.select_data_by_key(key, Selector::all_data(), Recursion::Always, false, false) {
+            if let DataType::Text(s) = item.data {
+                text += s;
+            }
+        }
+        text
+    }
+
+    ///Returns the ID of the element, or of its nearest ancestor with an ID
+    pub(crate) fn nearest_id(&self, key: ElementKey) -> Option<String> {
+        let mut key = Some(key);
+        while let Some(k) = key {
+            let element = self.get_elementdata(k)?;
+            if let Some(id) = element.id() {
+                return Some(id.to_string());
+            }
+            key = element.parent_key();
+        }
+        None
+    }
+}
+
+///The element type, set, subset and referenced ID of an element
+pub(crate) type ElementSignature = (ElementType, Option<String>, Option<String>, Option<String>);
+
+///Returns the signature of an element, elements without ID are matched on these when merging or comparing documents
+pub(crate) fn element_signature(document: &Document, key: ElementKey) -> ElementSignature {
+    if let Some(element) = document.get_element(key) {
+        let idref = if let Some(Attribute::Idref(idref)) = element.attrib(AttribType::IDREF) { Some(idref.clone()) } else { None };
+        (element.elementtype(), element.set().map(|s| s.to_string()), element.subset().map(|s| s.to_string()), idref)
+    } else {
+        (ElementType::Text, None, None, None)
+    }
+}
+
+///Applies the mapping of renamed IDs to an element (and everything under it) that is to be added
+fn rename_ids(element: &mut ElementData, id_map: &HashMap<String,String>) {
+    if !id_map.is_empty() {
+        for attrib in element.attribs.iter_mut() {
+            match attrib {
+                Attribute::Id(id) | Attribute::Idref(id) => {
+                    if let Some(new_id) = id_map.get(id) {
+                        *id = new_id.clone();
+                    }
+                },
+                _ => {}
+            }
+        }
+        for item in element.data.iter_mut() {
+            if let DataType::AddElement(child) = item {
+                rename_ids(child, id_map);
+            }
+        }
+    }
+}
+
+///Collects the IDs of an element (and everything under it) that is to be added
+fn collect_ids(element: &ElementData, ids: &mut Vec<String>) {
+    if let Some(id) = element.id() {
+        ids.push(id.to_string());
+    }
+    for item in element.data.iter() {
+        if let DataType::AddElement(child) = item {
+            collect_ids(child, ids);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Submetadata: metadata blocks (other than the main document metadata) that apply to only part of a document. Elements
+//! refer to a submetadata block through their ``metadata`` attribute, which is inherited by everything under them.
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::store::*;
+use crate::attrib::*;
+use crate::metadata::*;
+use crate::select::*;
+use crate::document::Document;
+
+impl Document {
+    ///Adds a submetadata block with the specified ID, the ID must not be in use already
+    pub fn add_submetadata(&mut self, id: &str, metadata: Metadata) -> Result<(), FoliaError> {
+        if self.submetadata.contains_key(id) || self.get_element_key_by_id(id).is_some() {
+            return Err(FoliaError::ValidationError(format!("ID already exists: {}", id)));
+        }
+        self.submetadata.insert(id.to_string(), metadata);
+        Ok(())
+    }
+
+    ///Returns the submetadata block with the specified ID
+    pub fn get_submetadata(&self, id: &str) -> Option<&Metadata> {
+        self.submetadata.get(id)
+    }
+
+    ///Returns the submetadata block with the specified ID, for editing
+    pub fn get_mut_submetadata(&mut self, id: &str) -> Option<&mut Metadata> {
+        self.submetadata.get_mut(id)
+    }
+
+    ///Removes the submetadata block with the specified ID and returns it. References to it from
+    ///elements are removed as well, so these elements inherit their metadata again.
+    pub fn remove_submetadata(&mut self, id: &str) -> Result<Metadata, FoliaError> {
+        let metadata = self.submetadata.remove(id).ok_or_else(|| FoliaError::KeyError(format!("No such submetadata: {}", id)))?;
+        for key in self.select_keys(Selector::elements(), Recursion::Always) {
+            if self.get_elementdata(key).and_then(|element| element.metadata_id()) == Some(id) {
+                self.get_mut_elementdata(key).expect("unwrapping element").del_attrib(AttribType::METADATA);
+            }
+        }
+        Ok(metadata)
+    }
+
+    ///Makes an element refer to the submetadata block with the specified ID, or removes the reference if ``None`` is passed.
+    ///The submetadata must exist.
+    pub fn set_element_metadata(&mut self, key: ElementKey, id: Option<&str>) -> Result<(), FoliaError> {
+        if let Some(id) = id {
+            if !self.submetadata.contains_key(id) {
+                return Err(FoliaError::KeyError(format!("No such submetadata: {}", id)));
+            }
+        }
+        let element = self.get_mut_elementdata(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+        match id {
+            Some(id) => element.set_attrib(Attribute::Metadata(id.to_string())),
+            None => { element.del_attrib(AttribType::METADATA); },
+        }
+        Ok(())
+    }
+
+    ///Returns the ID of the submetadata that applies to the element: the one it refers to itself or, failing that, the one
+    ///its nearest ancestor refers to. Returns ``None`` if the main document metadata applies.
+    pub fn element_metadata_id(&self, key: ElementKey) -> Option<&str> {
+        let mut key = Some(key);
+        while let Some(element) = key.and_then(|key| self.get_elementdata(key)) {
+            if let Some(id) = element.metadata_id() {
+                return Some(id);
+            }
+            key = element.parent_key();
+        }
+        None
+    }
+
+    ///Returns the metadata that applies to the element, taking inheritance from ancestors into account. This is the
+    ///main document metadata if no submetadata applies. Returns ``None`` only if the element does not exist.
+    pub fn element_metadata(&self, key: ElementKey) -> Option<&Metadata> {
+        self.get_elementdata(key)?;
+        match self.element_metadata_id(key) {
+            Some(id) => self.submetadata.get(id),
+            None => Some(&self.metadata),
+        }
+    }
+
+    ///Returns the value of a metadata field that applies to the element, taking inheritance from ancestors into account
+    pub fn element_meta(&self, key: ElementKey, meta_id: &str) -> Option<&str> {
+        self.element_metadata(key).and_then(|metadata| metadata.get(meta_id))
+    }
+
+    ///Returns the keys of all elements matching the selector for which the applicable metadata
+    ///has the specified value for the field, in document order
+    pub fn select_by_metadata(&self, selector: Selector, meta_id: &str, value: &str) -> Vec<ElementKey> {
+        self.select_keys(selector, Recursion::Always).into_iter().filter(|key| self.element_meta(*key, meta_id) == Some(value)).collect()
+    }
+}
+
+impl ElementData {
+    ///Returns the ID of the submetadata the element itself refers to (not taking inheritance into account)
+    pub fn metadata_id(&self) -> Option<&str> {
+        match self.attrib(AttribType::METADATA) {
+            Some(Attribute::Metadata(id)) => Some(id.as_str()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,559 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::str;
+
+use quick_xml::{Reader,Writer};
+use quick_xml::events::{Event,BytesStart,BytesEnd,BytesText};
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::attrib::*;
+use crate::store::*;
+use crate::query::*;
+use crate::select::*;
+use crate::specification::*;
+use crate::text::*;
+use crate::plaintext::*;
+use crate::conversion::*;
+use crate::document::*;
+
+///The TEI namespace
+pub const NSTEI: &str = "http://www.tei-c.org/ns/1.0";
+
+///The FoLiA structure elements that map onto TEI elements. These share their tag with TEI (as
+///defined in the specification) unless listed in ``TEI_TAGS``.
+pub const TEI_STRUCTURE: &[ElementType] = &[ElementType::Division, ElementType::Head, ElementType::Paragraph, ElementType::Sentence,
+                                            ElementType::Word, ElementType::List, ElementType::ListItem, ElementType::Figure,
+                                            ElementType::Caption, ElementType::Note, ElementType::Quote, ElementType::Linebreak];
+
+///Structure elements for which TEI uses a different tag than FoLiA
+const TEI_TAGS: &[(ElementType,&str)] = &[(ElementType::Caption, "figDesc"), (ElementType::Linebreak, "lb")];
+
+const NL: &[u8] = b"\n";
+
+///Returns the TEI tag for a FoLiA element type, if it can be mapped
+fn tei_tag(specification: &Specification, elementtype: ElementType) -> Option<&'static str> {
+    if !TEI_STRUCTURE.contains(&elementtype) {
+        return None;
+    }
+    match TEI_TAGS.iter().find(|(t, _)| *t == elementtype) {
+        Some((_, tag)) => Some(tag),
+        None => Some(specification.get(elementtype).xmltag),
+    }
+}
+
+///Returns the FoLiA element type for a TEI tag, if it can be mapped. TEI punctuation (``pc``) maps to words.
+fn tei_elementtype(specification: &Specification, tag: &str) -> Option<ElementType> {
+    if tag == "pc" {
+        return Some(ElementType::Word);
+    }
+    TEI_STRUCTURE.iter().find(|elementtype| tei_tag(specification, **elementtype) == Some(tag)).copied()
+}
+
+///Tests whether an annotation is in the specified set (``None`` matches any set)
+fn in_set(element: &Element, set: &Option<String>) -> bool {
+    match set {
+        Some(set) => element.set() == Some(set.as_str()),
+        None => true,
+    }
+}
+
+///Collapses whitespace, as TEI documents are usually indented
+fn normalize_space(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+impl Document {
+    ///Exports the document (or the subtree under ``root_key``) to TEI XML. The structure elements in
+    ///``TEI_STRUCTURE`` are mapped to their TEI counterparts, part-of-speech and lemma annotations (in the
+    ///sets of the parameters) map to ``@pos`` and ``@lemma`` on ``<w>``. All other content can not be
+    ///mapped and is reported in the returned ``ConversionReport``, text in unmapped structure elements is retained.
+    pub fn to_tei(&self, root_key: ElementKey, parameters: &ExportParameters) -> Result<(String,ConversionReport),FoliaError> {
+        let mut report = ConversionReport::new();
+        let mut writer = Writer::new(Cursor::new(Vec::new()));
+        let mut tei_start = BytesStart::borrowed_name(b"TEI");
+        tei_start.push_attribute(("xmlns", NSTEI));
+        writer.write_event(Event::Start(tei_start)).map_err(to_serialisation_error)?;
+        writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+        self.tei_header(&mut writer, &mut report)?;
+
+        let root = self.get_element(root_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", root_key)))?;
+        let mut text_start = BytesStart::borrowed_name(b"text");
+        if let Some(id) = root.id() {
+            if root.elementtype() == ElementType::Text {
+                text_start.push_attribute(("xml:id", id));
+            }
+        }
+        writer.write_event(Event::Start(text_start)).map_err(to_serialisation_error)?;
+        writer.write_event(Event::Start(BytesStart::borrowed_name(b"body"))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+        if root.elementtype() == ElementType::Text {
+            self.tei_elements(&mut writer, root_key, parameters, &mut report)?;
+        } else {
+            self.tei_element(&mut writer, root_key, parameters, &mut report)?;
+        }
+        writer.write_event(Event::End(BytesEnd::borrowed(b"body"))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::End(BytesEnd::borrowed(b"text"))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::End(BytesEnd::borrowed(b"TEI"))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+        let result = String::from_utf8(writer.into_inner().into_inner()).map_err(|err| FoliaError::SerialisationError(format!("{}", err)))?;
+        Ok((result, report))
+    }
+
+    ///Writes the TEI header, the title is taken from the ``title`` metadata field (or the document ID),
+    ///other metadata fields are reported
+    fn tei_header(&self, writer: &mut Writer<Cursor<Vec<u8>>>, report: &mut ConversionReport) -> Result<(),FoliaError> {
+        let title = self.metadata.data.get("title").map(|s| s.as_str()).unwrap_or_else(|| self.id());
+        for key in self.metadata.data.keys() {
+            if key != "title" {
+                report.add(&format!("metadata/{}", key));
+            }
+        }
+        for tag in ["teiHeader", "fileDesc", "titleStmt", "title"].iter() {
+            writer.write_event(Event::Start(BytesStart::borrowed_name(tag.as_bytes()))).map_err(to_serialisation_error)?;
+        }
+        writer.write_event(Event::Text(BytesText::from_plain_str(title))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::End(BytesEnd::borrowed(b"title"))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::End(BytesEnd::borrowed(b"titleStmt"))).map_err(to_serialisation_error)?;
+        for (tag, text) in [("publicationStmt", GENERATOR), ("sourceDesc", self.id())].iter() {
+            writer.write_event(Event::Start(BytesStart::borrowed_name(tag.as_bytes()))).map_err(to_serialisation_error)?;
+            writer.write_event(Event::Start(BytesStart::borrowed_name(b"p"))).map_err(to_serialisation_error)?;
+            writer.write_event(Event::Text(BytesText::from_plain_str(text))).map_err(to_serialisation_error)?;
+            writer.write_event(Event::End(BytesEnd::borrowed(b"p"))).map_err(to_serialisation_error)?;
+            writer.write_event(Event::End(BytesEnd::borrowed(tag.as_bytes()))).map_err(to_serialisation_error)?;
+        }
+        writer.write_event(Event::End(BytesEnd::borrowed(b"fileDesc"))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::End(BytesEnd::borrowed(b"teiHeader"))).map_err(to_serialisation_error)?;
+        writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+        Ok(())
+    }
+
+    ///Writes the children of the specified element to TEI, words are separated by a space unless ``space="no"``
+    fn tei_elements(&self, writer: &mut Writer<Cursor<Vec<u8>>>, key: ElementKey, parameters: &ExportParameters, report: &mut ConversionReport) -> Result<(),FoliaError> {
+        let elementdata = self.get_elementdata(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+        let mut space: Option<bool> = None; //is the previous sibling a word that is followed by a space?
+        for item in elementdata.data.iter() {
+            match item {
+                DataType::Element(child_key) => {
+                    let child = self.get_element(*child_key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", child_key)))?;
+                    if child.elementtype() == ElementType::Word {
+                        if space == Some(true) {
+                            writer.write_event(Event::Text(BytesText::from_plain_str(" "))).map_err(to_serialisation_error)?;
+                        }
+                        space = Some(child.attrib(AttribType::SPACE) != Some(&Attribute::Space(false)));
+                    } else if child.elementtype() != ElementType::TextContent {
+                        space = None;
+                    }
+                    self.tei_element(writer, *child_key, parameters, report)?;
+                },
+                DataType::Comment(comment) => {
+                    writer.write_event(Event::Comment(BytesText::from_plain_str(comment.as_str()))).map_err(to_serialisation_error)?;
+                },
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    ///Writes a single element to TEI, or reports it if it can not be mapped
+    fn tei_element(&self, writer: &mut Writer<Cursor<Vec<u8>>>, key: ElementKey, parameters: &ExportParameters, report: &mut ConversionReport) -> Result<(),FoliaError> {
+        let element = self.get_element(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+        let elementtype = element.elementtype();
+        if elementtype == ElementType::Word {
+            return self.tei_word(writer, &element, parameters, report);
+        }
+        if let Some(tag) = tei_tag(&self.elementstore.specification, elementtype) {
+            let mut start = BytesStart::borrowed_name(tag.as_bytes());
+            if let Some(id) = element.id() {
+                start.push_attribute(("xml:id", id));
+            }
+            if elementtype == ElementType::Linebreak {
+                writer.write_event(Event::Empty(start)).map_err(to_serialisation_error)?;
+                return Ok(());
+            }
+            writer.write_event(Event::Start(start)).map_err(to_serialisation_error)?;
+            self.tei_text(writer, key, parameters)?;
+            self.tei_elements(writer, key, parameters, report)?;
+            writer.write_event(Event::End(BytesEnd::borrowed(tag.as_bytes()))).map_err(to_serialisation_error)?;
+            if elementtype != ElementType::Note && elementtype != ElementType::Quote {
+                writer.write_event(Event::Text(BytesText::from_plain(NL))).map_err(to_serialisation_error)?;
+            }
+        } else if elementtype == ElementType::TextContent {
+            //handled by the parent (tei_text)
+        } else if ElementGroup::Structure.contains(elementtype) {
+            //unmapped structure, the text it contains is retained
+            report.add(elementtype.as_str());
+            self.tei_text(writer, key, parameters)?;
+            self.tei_elements(writer, key, parameters, report)?;
+        } else if ElementGroup::Layer.contains(elementtype) {
+            for annotation in self.get_elementdata(key).map(|data| data.data.iter()).into_iter().flatten() {
+                if let DataType::Element(annotation_key) = annotation {
+                    if let Some(annotation) = self.get_element(*annotation_key) {
+                        report.add(annotation.elementtype().as_str());
+                    }
+                }
+            }
+        } else {
+            report.add(elementtype.as_str());
+        }
+        Ok(())
+    }
+
+    ///Writes the text of an element if it is not represented by its structural children
+    fn tei_text(&self, writer: &mut Writer<Cursor<Vec<u8>>>, key: ElementKey, parameters: &ExportParameters) -> Result<(),FoliaError> {
+        let elementdata = self.get_elementdata(key).ok_or_else(|| FoliaError::KeyError(format!("No such element key: {}", key)))?;
+        let mut has_text = false;
+        for item in elementdata.data.iter() {
+            if let DataType::Element(child_key) = item {
+                if let Some(child) = self.get_elementdata(*child_key) {
+                    if child.elementtype == ElementType::TextContent {
+                        has_text = true;
+                    } else if ElementGroup::Structure.contains(child.elementtype) && child.elementtype != ElementType::Linebreak && child.elementtype != ElementType::Whitespace {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        if has_text {
+            match self.text(key, &parameters.textparameters) {
+                Ok(text) => writer.write_event(Event::Text(BytesText::from_plain_str(text.as_str()))).map_err(to_serialisation_error)?,
+                Err(FoliaError::NoTextError(_)) => {},
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    ///Writes a word to TEI, with its part-of-speech tag and lemma as attributes
+    fn tei_word(&self, writer: &mut Writer<Cursor<Vec<u8>>>, word: &Element, parameters: &ExportParameters, report: &mut ConversionReport) -> Result<(),FoliaError> {
+        let mut pos: Option<String> = None;
+        let mut lemma: Option<String> = None;
+        let word_key = word.key().expect("word key");
+        let children = self.get_elementdata(word_key).map(|data| data.data.iter()).into_iter().flatten();
+        for annotation in children.filter_map(|item| if let DataType::Element(key) = item { self.get_element(*key) } else { None }) {
+            match annotation.elementtype() {
+                ElementType::TextContent => {},
+                ElementType::PosAnnotation if pos.is_none() && in_set(&annotation, &parameters.posset) => pos = annotation.class().map(|s| s.to_string()),
+                ElementType::LemmaAnnotation if lemma.is_none() && in_set(&annotation, &parameters.lemmaset) => lemma = annotation.class().map(|s| s.to_string()),
+                elementtype => {
+                    report.add(elementtype.as_str());
+                    continue;
+                },
+            }
+            //features and other children of the mapped annotations are lost
+            for item in self.get_elementdata(annotation.key().expect("key")).map(|data| data.data.iter()).into_iter().flatten() {
+                if let DataType::Element(key) = item {
+                    if let Some(child) = self.get_elementdata(*key) {
+                        report.add(child.elementtype.as_str());
+                    }
+                }
+            }
+        }
+        let mut start = BytesStart::borrowed_name(b"w");
+        if let Some(id) = word.id() {
+            start.push_attribute(("xml:id", id));
+        }
+        if let Some(pos) = pos {
+            start.push_attribute(("pos", pos.as_str()));
+        }
+        if let Some(lemma) = lemma {
+            start.push_attribute(("lemma", lemma.as_str()));
+        }
+        writer.write_event(Event::Start(start)).map_err(to_serialisation_error)?;
+        match word.text(&parameters.textparameters) {
+            Ok(text) => writer.write_event(Event::Text(BytesText::from_plain_str(text.as_str()))).map_err(to_serialisation_error)?,
+            Err(FoliaError::NoTextError(_)) => {},
+            Err(err) => return Err(err),
+        }
+        writer.write_event(Event::End(BytesEnd::borrowed(b"w"))).map_err(to_serialisation_error)?;
+        Ok(())
+    }
+
+    ///Builds a new document from TEI XML. The TEI elements corresponding to ``TEI_STRUCTURE`` become FoLiA
+    ///structure elements (``pc`` becomes a word), ``@pos`` and ``@lemma`` on words become part-of-speech and
+    ///lemma annotations in the sets of the parameters. Other elements are reported in the returned
+    ///``ConversionReport``, their text is retained in the nearest mapped ancestor. Elements
+    ///without an ``xml:id`` get a generated ID.
+    pub fn from_tei(id: &str, data: &str, parameters: &ExportParameters, properties: DocumentProperties) -> Result<(Self,ConversionReport), FoliaError> {
+        let mut doc = Self::new(id, properties)?;
+        let mut parser = TeiParser {
+            parameters,
+            report: ConversionReport::new(),
+            stack: vec![TeiNode::mapped(0, ElementType::Text)],
+            counters: HashMap::new(),
+            header: false,
+            title: None,
+        };
+        let mut reader = Reader::from_str(data);
+        reader.trim_text(false);
+        let mut buf = Vec::new();
+        loop {
+            match reader.read_event(&mut buf)? {
+                Event::Start(ref e) => {
+                    let tag = reader.decode(e.local_name())?.to_string();
+                    let attribs = tei_attributes(&reader, e)?;
+                    parser.start(&mut doc, &tag, attribs)?;
+                },
+                Event::Empty(ref e) => {
+                    let tag = reader.decode(e.local_name())?.to_string();
+                    let attribs = tei_attributes(&reader, e)?;
+                    parser.start(&mut doc, &tag, attribs)?;
+                    parser.end(&mut doc, &tag)?;
+                },
+                Event::End(ref e) => {
+                    let tag = reader.decode(e.local_name())?.to_string();
+                    parser.end(&mut doc, &tag)?;
+                },
+                Event::Text(ref e) => {
+                    let text = e.unescape_and_decode(&reader)?;
+                    parser.text(&text);
+                },
+                Event::CData(ref e) => {
+                    let text = reader.decode(e)?.to_string();
+                    parser.text(&text);
+                },
+                Event::Eof => break,
+                _ => {},
+            }
+            buf.clear();
+        }
+        if !normalize_space(&parser.stack[0].text).is_empty() {
+            parser.report.add("text in body");
+        }
+        if let Some(title) = parser.title.take() {
+            doc.metadata.data.insert("title".to_string(), title);
+        }
+        Ok((doc, parser.report))
+    }
+}
+
+///Reads the attributes of a TEI element, keyed by their qualified name
+fn tei_attributes(reader: &Reader<&[u8]>, e: &BytesStart) -> Result<HashMap<String,String>,FoliaError> {
+    let mut attribs = HashMap::new();
+    for attrib in e.attributes() {
+        let attrib = attrib?;
+        let value = attrib.unescape_and_decode_value(reader)?;
+        attribs.insert(reader.decode(attrib.key)?.to_string(), value);
+    }
+    Ok(attribs)
+}
+
+///An open TEI element during import
+struct TeiNode {
+    ///The FoLiA element, ``None`` if the TEI element is not mapped (or not created yet, for words)
+    key: Option<ElementKey>,
+    ///The FoLiA element type, ``None`` if the TEI element is not mapped
+    elementtype: Option<ElementType>,
+    ///The attributes, only retained for words as these are only created once their text is known
+    attribs: HashMap<String,String>,
+    ///The text directly under this element (and its unmapped descendants)
+    text: String,
+    ///Does this element have mapped structural children?
+    has_children: bool,
+    ///The last word created in this element, if it was the last mapped child
+    last_word: Option<ElementKey>,
+    ///Is there whitespace since the last word?
+    gap: bool,
+}
+
+impl TeiNode {
+    fn mapped(key: ElementKey, elementtype: ElementType) -> Self {
+        Self { key: Some(key), elementtype: Some(elementtype), attribs: HashMap::new(), text: String::new(), has_children: false, last_word: None, gap: false }
+    }
+
+    fn word(attribs: HashMap<String,String>) -> Self {
+        Self { key: None, elementtype: Some(ElementType::Word), attribs, text: String::new(), has_children: false, last_word: None, gap: false }
+    }
+
+    fn unmapped() -> Self {
+        Self { key: None, elementtype: None, attribs: HashMap::new(), text: String::new(), has_children: false, last_word: None, gap: false }
+    }
+}
+
+///Holds the state while importing TEI
+struct TeiParser<'a> {
+    parameters: &'a ExportParameters,
+    report: ConversionReport,
+    stack: Vec<TeiNode>,
+    ///Counts the children of each type per parent, for ID generation
+    counters: HashMap<(ElementKey,ElementType),usize>,
+    ///Are we in the TEI header?
+    header: bool,
+    title: Option<String>,
+}
+
+impl<'a> TeiParser<'a> {
+    ///Returns the index in the stack of the nearest mapped element that can take children or text
+    fn parent(&self) -> usize {
+        self.stack.iter().rposition(|node| node.elementtype.is_some()).expect("root node")
+    }
+
+    fn start(&mut self, doc: &mut Document, tag: &str, attribs: HashMap<String,String>) -> Result<(),FoliaError> {
+        if self.header {
+            if tag == "title" && self.title.is_none() {
+                self.title = Some(String::new());
+            }
+            return Ok(());
+        }
+        match tag {
+            "teiHeader" => {
+                self.header = true;
+                return Ok(());
+            },
+            "TEI" | "text" | "body" => {
+                if let (Some(id), "text") = (attribs.get("xml:id"), tag) {
+                    if let Some(body) = doc.get_mut_elementdata(0) {
+                        body.set_attrib(Attribute::Id(id.clone()));
+                    }
+                    <Document as Store<ElementData,ElementKey>>::index_mut(doc).insert(id.clone(), 0);
+                }
+                self.stack.push(TeiNode::unmapped());
+                return Ok(());
+            },
+            _ => {},
+        }
+        let parent = self.parent();
+        match tei_elementtype(&doc.elementstore.specification, tag) {
+            Some(ElementType::Word) if self.stack[parent].elementtype != Some(ElementType::Word) => {
+                self.stack.push(TeiNode::word(attribs));
+            },
+            Some(elementtype) if self.stack[parent].elementtype != Some(ElementType::Word) => {
+                let parent_key = self.stack[parent].key.expect("parent key");
+                let element = ElementData::new(elementtype).with_attrib(Attribute::Id(self.id(doc, parent_key, elementtype, &attribs)));
+                if let Some(annotationtype) = elementtype.annotationtype() {
+                    doc.declare(annotationtype, &None, &None, &None)?;
+                }
+                match doc.annotate(parent_key, element) {
+                    Ok(key) => {
+                        let parent = &mut self.stack[parent];
+                        if elementtype != ElementType::Linebreak {
+                            parent.has_children = true;
+                        }
+                        parent.last_word = None;
+                        self.stack.push(TeiNode::mapped(key, elementtype));
+                    },
+                    Err(FoliaError::ValidationError(_)) => {
+                        self.report.add(&format!("{} (in {})", tag, self.stack[parent].elementtype.expect("parent type")));
+                        self.stack.push(TeiNode::unmapped());
+                    },
+                    Err(err) => return Err(err),
+                }
+            },
+            _ => {
+                self.report.add(tag);
+                self.stack.push(TeiNode::unmapped());
+            }
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, doc: &mut Document, tag: &str) -> Result<(),FoliaError> {
+        if self.header {
+            if tag == "teiHeader" {
+                self.header = false;
+            }
+            return Ok(());
+        }
+        let node = self.stack.pop().ok_or_else(|| FoliaError::ParseError(format!("Unexpected end tag: {}", tag)))?;
+        let parent = self.parent();
+        match node.elementtype {
+            Some(ElementType::Word) => {
+                let parent_key = self.stack[parent].key.expect("parent key");
+                let mut word = ElementData::new(ElementType::Word).with_attrib(Attribute::Id(self.id(doc, parent_key, ElementType::Word, &node.attribs)));
+                let text = normalize_space(&node.text);
+                if !text.is_empty() {
+                    word = word.with_text(text);
+                }
+                if let Some(pos) = node.attribs.get("pos") {
+                    doc.declare(AnnotationType::POS, &self.parameters.posset, &None, &None)?;
+                    let mut pos = ElementData::new(ElementType::PosAnnotation).with_attrib(Attribute::Class(pos.clone()));
+                    if let Some(set) = &self.parameters.posset {
+                        pos = pos.with_attrib(Attribute::Set(set.clone()));
+                    }
+                    word = word.add_element(pos);
+                }
+                if let Some(lemma) = node.attribs.get("lemma") {
+                    doc.declare(AnnotationType::LEMMA, &self.parameters.lemmaset, &None, &None)?;
+                    let mut lemma = ElementData::new(ElementType::LemmaAnnotation).with_attrib(Attribute::Class(lemma.clone()));
+                    if let Some(set) = &self.parameters.lemmaset {
+                        lemma = lemma.with_attrib(Attribute::Set(set.clone()));
+                    }
+                    word = word.add_element(lemma);
+                }
+                doc.declare(AnnotationType::TOKEN, &None, &None, &None)?;
+                match doc.annotate(parent_key, word) {
+                    Ok(key) => {
+                        let parent = &mut self.stack[parent];
+                        if let Some(previous_key) = parent.last_word {
+                            if !parent.gap {
+                                if let Some(previous) = doc.get_mut_elementdata(previous_key) {
+                                    previous.set_attrib(Attribute::Space(false));
+                                }
+                            }
+                        }
+                        parent.has_children = true;
+                        parent.last_word = Some(key);
+                        parent.gap = false;
+                    },
+                    Err(FoliaError::ValidationError(_)) => {
+                        self.report.add(&format!("{} (in {})", tag, self.stack[parent].elementtype.expect("parent type")));
+                        self.stack[parent].text += &node.text;
+                    },
+                    Err(err) => return Err(err),
+                }
+            },
+            Some(_) => {
+                let key = node.key.expect("key");
+                let text = normalize_space(&node.text);
+                if !text.is_empty() {
+                    if node.has_children {
+                        self.report.add(&format!("text in {}", tag));
+                    } else {
+                        doc.annotate(key, ElementData::new(ElementType::TextContent).with(DataType::Text(text)))?;
+                    }
+                }
+                self.stack[parent].gap = true;
+            },
+            None => {
+                //text of unmapped elements is passed on to the parent
+                self.stack[parent].text += &node.text;
+            }
+        }
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.header {
+            if let Some(title) = self.title.as_mut() {
+                if title.is_empty() {
+                    *title = normalize_space(text);
+                }
+            }
+            return;
+        }
+        if text.is_empty() {
+            return;
+        }
+        let parent = self.parent();
+        let node = &mut self.stack[parent];
+        if node.elementtype == Some(ElementType::Text) && text.trim().is_empty() {
+            return;
+        }
+        node.gap = true;
+        node.text += text;
+    }
+
+    ///Returns the ID for a new element, either the ``xml:id`` from TEI or one derived from its parent
+    fn id(&mut self, doc: &Document, parent_key: ElementKey, elementtype: ElementType, attribs: &HashMap<String,String>) -> String {
+        if let Some(id) = attribs.get("xml:id") {
+            return id.clone();
+        }
+        let counter = self.counters.entry((parent_key, elementtype)).or_insert(0);
+        *counter += 1;
+        let parent_id = doc.get_element(parent_key).and_then(|parent| parent.id().map(|s| s.to_string())).unwrap_or_else(|| doc.id().to_string());
+        format!("{}.{}.{}", parent_id, doc.props(elementtype).xmltag, counter)
+    }
+}
@@ -0,0 +1,251 @@
+//! Transactions group edits to a document so they can be committed or rolled back atomically,
+//! committed transactions can be undone and redone. Rather than copying the whole document,
+//! a transaction records the prior state of only those elements, declarations and processors
+//! that are changed.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Debug;
+
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::store::*;
+use crate::metadata::*;
+use crate::journal::*;
+use crate::document::Document;
+
+#[derive(Clone)]
+///The state of a store: its length and a copy of the items that were changed since
+struct StoreImage<T> {
+    len: usize,
+    items: HashMap<usize,Option<Box<T>>>,
+}
+
+impl<T: Clone> StoreImage<T> {
+    fn new(len: usize) -> Self {
+        Self { len, items: HashMap::new() }
+    }
+
+    ///Records the state of an item prior to a change, items added after the image was made need
+    ///not be recorded
+    fn record(&mut self, key: usize, items: &[Option<Box<T>>]) {
+        if key < self.len && !self.items.contains_key(&key) {
+            if let Some(item) = items.get(key) {
+                self.items.insert(key, item.clone());
+            }
+        }
+    }
+
+    ///Restores this state in the store and updates the index. Returns the state that was replaced.
+    fn restore<Key>(self, items: &mut Vec<Option<Box<T>>>, index: &mut HashMap<String,Key>) -> Self
+    where T: Storable<Key>, Key: TryFrom<usize> + Copy, <Key as TryFrom<usize>>::Error: Debug {
+        let current_len = items.len();
+        let mut replaced = Self::new(current_len);
+        for key in self.items.keys().copied().filter(|key| *key < current_len).chain(self.len..current_len) {
+            replaced.items.insert(key, items[key].clone());
+        }
+        for item in replaced.items.values().flatten() {
+            if let Some(id) = item.maybe_id() {
+                index.remove(id.as_ref());
+            }
+        }
+        items.resize_with(self.len, || None);
+        for (key, item) in self.items.into_iter() {
+            if key < self.len {
+                if let Some(id) = item.as_ref().and_then(|item| item.maybe_id()) {
+                    index.insert(id.to_string(), Key::try_from(key).expect("conversion from usize"));
+                }
+                items[key] = item;
+            }
+        }
+        replaced
+    }
+}
+
+#[derive(Clone)]
+///The state of a document prior to a transaction (or undo/redo step)
+struct Checkpoint {
+    elements: StoreImage<ElementData>,
+    declarations: StoreImage<Declaration>,
+    processors: StoreImage<Processor>,
+    chain: Vec<ProcKey>,
+    metadata: Metadata,
+    submetadata: HashMap<String,Metadata>,
+    active_processor: Option<ProcKey>,
+}
+
+///A step on the undo or redo stack: the state to return to, and the lengths of the stores it applies to
+struct Step {
+    checkpoint: Checkpoint,
+    lengths: (usize, usize, usize),
+}
+
+#[derive(Default)]
+///Keeps track of the active transaction and the undo and redo stacks of a document
+pub(crate) struct History {
+    transaction: Option<Checkpoint>,
+    ///The length of the journal when the active transaction started
+    journal_len: Option<usize>,
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+}
+
+impl Document {
+    ///Starts a transaction, all subsequent edits can be committed or rolled back as a whole.
+    ///Transactions can not be nested.
+    pub fn begin_transaction(&mut self) -> Result<(), FoliaError> {
+        if self.history.transaction.is_some() {
+            return Err(FoliaError::InternalError("A transaction is already active".to_string()));
+        }
+        self.history.transaction = Some(self.checkpoint());
+        self.history.journal_len = self.journal_len();
+        self.record(Edit::BeginTransaction, vec!());
+        Ok(())
+    }
+
+    ///Commits the active transaction, it can be undone with ``undo()``
+    pub fn commit(&mut self) -> Result<(), FoliaError> {
+        let checkpoint = self.history.transaction.take().ok_or_else(|| FoliaError::InternalError("No active transaction".to_string()))?;
+        let lengths = self.store_lengths();
+        self.history.undo.push(Step { checkpoint, lengths });
+        self.history.redo.clear();
+        self.record(Edit::Commit, vec!());
+        Ok(())
+    }
+
+    ///Rolls back all edits made in the active transaction, they are removed from the journal as well
+    pub fn rollback(&mut self) -> Result<(), FoliaError> {
+        let checkpoint = self.history.transaction.take().ok_or_else(|| FoliaError::InternalError("No active transaction".to_string()))?;
+        self.restore(checkpoint);
+        if let Some(len) = self.history.journal_len.take() {
+            self.truncate_journal(len);
+        }
+        Ok(())
+    }
+
+    ///Returns true if a transaction is active
+    pub fn in_transaction(&self) -> bool {
+        self.history.transaction.is_some()
+    }
+
+    ///Runs the function in a transaction: commits if it succeeds and rolls back if it returns an error
+    pub fn transaction<F, T>(&mut self, f: F) -> Result<T, FoliaError>
+    where F: FnOnce(&mut Document) -> Result<T, FoliaError> {
+        self.begin_transaction()?;
+        match f(self) {
+            Ok(result) => {
+                self.commit()?;
+                Ok(result)
+            },
+            Err(e) => {
+                self.rollback()?;
+                Err(e)
+            }
+        }
+    }
+
+    ///Undoes the last committed transaction. Returns false if there was nothing to undo.
+    pub fn undo(&mut self) -> Result<bool, FoliaError> {
+        self.step(true)
+    }
+
+    ///Redoes the last undone transaction. Returns false if there was nothing to redo.
+    pub fn redo(&mut self) -> Result<bool, FoliaError> {
+        self.step(false)
+    }
+
+    ///Returns true if there is a committed transaction that can be undone
+    pub fn can_undo(&self) -> bool {
+        !self.history.undo.is_empty()
+    }
+
+    ///Returns true if there is an undone transaction that can be redone
+    pub fn can_redo(&self) -> bool {
+        !self.history.redo.is_empty()
+    }
+
+    fn step(&mut self, undo: bool) -> Result<bool, FoliaError> {
+        if self.history.transaction.is_some() {
+            return Err(FoliaError::InternalError("Unable to undo or redo during a transaction".to_string()));
+        }
+        let step = if undo { self.history.undo.pop() } else { self.history.redo.pop() };
+        if let Some(step) = step {
+            if step.lengths != self.store_lengths() {
+                //the document was changed outside of a transaction, the history no longer applies
+                self.history.undo.clear();
+                self.history.redo.clear();
+                return Err(FoliaError::InternalError("The document was changed outside of a transaction, unable to undo or redo".to_string()));
+            }
+            let lengths = (step.checkpoint.elements.len, step.checkpoint.declarations.len, step.checkpoint.processors.len);
+            let checkpoint = self.restore(step.checkpoint);
+            let step = Step { checkpoint, lengths };
+            if undo { self.history.redo.push(step) } else { self.history.undo.push(step) };
+            self.record(if undo { Edit::Undo } else { Edit::Redo }, vec!());
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        let (elements, declarations, processors) = self.store_lengths();
+        Checkpoint {
+            elements: StoreImage::new(elements),
+            declarations: StoreImage::new(declarations),
+            processors: StoreImage::new(processors),
+            chain: self.provenancestore.chain.clone(),
+            metadata: self.metadata.clone(),
+            submetadata: self.submetadata.clone(),
+            active_processor: self.active_processor,
+        }
+    }
+
+    fn store_lengths(&self) -> (usize, usize, usize) {
+        (self.elementstore.items.len(), self.declarationstore.items.len(), self.provenancestore.items.len())
+    }
+
+    ///Restores the checkpoint, returns a checkpoint of the state that was replaced
+    fn restore(&mut self, checkpoint: Checkpoint) -> Checkpoint {
+        Checkpoint {
+            elements: checkpoint.elements.restore(&mut self.elementstore.items, &mut self.elementstore.index),
+            declarations: checkpoint.declarations.restore(&mut self.declarationstore.items, &mut self.declarationstore.index),
+            processors: checkpoint.processors.restore(&mut self.provenancestore.items, &mut self.provenancestore.index),
+            chain: std::mem::replace(&mut self.provenancestore.chain, checkpoint.chain),
+            metadata: std::mem::replace(&mut self.metadata, checkpoint.metadata),
+            submetadata: std::mem::replace(&mut self.submetadata, checkpoint.submetadata),
+            active_processor: std::mem::replace(&mut self.active_processor, checkpoint.active_processor),
+        }
+    }
+
+    ///Records the state of an element before it is changed. Any change outside of a transaction invalidates the undo history.
+    pub(crate) fn record_element(&mut self, key: ElementKey) {
+        match self.history.transaction.as_mut() {
+            Some(transaction) => transaction.elements.record(key as usize, &self.elementstore.items),
+            None => self.history.invalidate(),
+        }
+    }
+
+    ///Records the state of a declaration before it is changed
+    pub(crate) fn record_declaration(&mut self, key: DecKey) {
+        match self.history.transaction.as_mut() {
+            Some(transaction) => transaction.declarations.record(key as usize, &self.declarationstore.items),
+            None => self.history.invalidate(),
+        }
+    }
+
+    ///Records the state of a processor before it is changed
+    pub(crate) fn record_processor(&mut self, key: ProcKey) {
+        match self.history.transaction.as_mut() {
+            Some(transaction) => transaction.processors.record(key as usize, &self.provenancestore.items),
+            None => self.history.invalidate(),
+        }
+    }
+}
+
+impl History {
+    fn invalidate(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
@@ -0,0 +1,128 @@
+//! Upgrading of FoLiA v1 documents to FoLiA v2, this is done automatically when such documents are loaded. Old-style
+//! annotators (``annotator`` and ``annotatortype`` attributes on declarations and elements) are turned into processors
+//! in the provenance chain, elements that were renamed in v2 are mapped to their new names and all annotation types
+//! in use are declared.
+
+use std::str::FromStr;
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::attrib::*;
+use crate::store::*;
+use crate::metadata::*;
+use crate::select::*;
+use crate::document::Document;
+
+///Returns true for FoLiA versions prior to 2.0, documents of such versions are upgraded when loaded
+pub fn is_legacy_version(version: &str) -> bool {
+    match version.split('.').next().map(|major| major.trim().parse::<u32>()) {
+        Some(Ok(major)) => major < 2,
+        _ => false
+    }
+}
+
+///Returns the FoLiA v2 name for an element (or annotation type) that was renamed since FoLiA v1
+pub(crate) fn legacy_tag(tag: &str) -> Option<&'static str> {
+    match tag {
+        "alignment" => Some("relation"),
+        "aref" => Some("xref"),
+        _ => None
+    }
+}
+
+///Returns the element type for an XML tag, taking into account tags of FoLiA v1 that were renamed
+pub(crate) fn parse_elementtype(tag: &str) -> Result<ElementType, FoliaError> {
+    match ElementType::from_str(tag) {
+        Ok(elementtype) => Ok(elementtype),
+        Err(e) => match legacy_tag(tag) {
+            Some(tag) => ElementType::from_str(tag),
+            None => Err(e)
+        }
+    }
+}
+
+///Returns the annotation type for the name used in declarations, taking into account annotation types of FoLiA v1 that were renamed
+pub(crate) fn parse_annotationtype(name: &str) -> Option<AnnotationType> {
+    AnnotationType::from_str(name).or_else(|| legacy_tag(name).and_then(AnnotationType::from_str))
+}
+
+impl Document {
+    ///Returns the ID of the processor for an old-style annotator, the processor is added to the
+    ///provenance chain if it does not exist yet
+    pub(crate) fn legacy_processor(&mut self, name: &str, processortype: Option<ProcessorType>) -> Result<String, FoliaError> {
+        let found = self.find_processors(|processor| processor.name == name && processortype.map(|t| t == processor.processortype) != Some(false));
+        if let Some(processor) = found.first().and_then(|key| self.get_processor(*key)) {
+            return Ok(processor.id.clone());
+        }
+        let base: String = format!("proc.{}", name.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect::<String>());
+        let mut id = base.clone();
+        let mut n = 1;
+        while self.get_processor_key_by_id(&id).is_some() {
+            n += 1;
+            id = format!("{}.{}", base, n);
+        }
+        let processor = Processor::new(name.to_string()).with_id(id.clone()).with_type(processortype.unwrap_or_default());
+        self.add_processor(processor)?;
+        Ok(id)
+    }
+
+    ///Replaces the old-style ``annotator`` and ``annotatortype`` attributes of an element (prior to encoding) by a processor
+    pub(crate) fn upgrade_legacy_attribs(&mut self, mut element: ElementData) -> Result<ElementData, FoliaError> {
+        let mut annotator: Option<String> = None;
+        let mut annotatortype: Option<ProcessorType> = None;
+        element.attribs.retain(|attrib| {
+            match attrib {
+                Attribute::Annotator(name) => { annotator = Some(name.clone()); false },
+                Attribute::AnnotatorType(processortype) => { annotatortype = Some(*processortype); false },
+                _ => true
+            }
+        });
+        if let Some(annotator) = annotator {
+            if !element.attribs.iter().any(|attrib| attrib.attribtype() == AttribType::PROCESSOR) {
+                let processor_id = self.legacy_processor(&annotator, annotatortype)?;
+                element.attribs.push(Attribute::Processor(processor_id));
+            }
+        }
+        Ok(element)
+    }
+
+    ///Completes the upgrade of a document from FoLiA v1 once it is loaded: all processors used by
+    ///annotations are associated with their declarations, missing declarations are added and the version is set to the current FoLiA version
+    pub(crate) fn finish_upgrade(&mut self) -> Result<(), FoliaError> {
+        let mut annotators: Vec<(DecKey,ProcKey)> = Vec::new();
+        let mut undeclared: Vec<AnnotationType> = Vec::new();
+        for item in self.select_data(Selector::elements(), Recursion::Always, false) {
+            if let DataType::Element(key) = item.data {
+                if let Some(element) = self.get_element(*key) {
+                    if let (Some(dec_key), Some(proc_key)) = (element.declaration_key(), element.processor_key()) {
+                        if !annotators.contains(&(dec_key, proc_key)) {
+                            annotators.push((dec_key, proc_key));
+                        }
+                    }
+                    if let Some(annotationtype) = element.elementtype().annotationtype() {
+                        if element.declaration_key().is_none() && !undeclared.contains(&annotationtype) {
+                            undeclared.push(annotationtype);
+                        }
+                    }
+                }
+            }
+        }
+        //FoLiA v2 requires all annotation types to be declared, including structure annotation
+        for annotationtype in undeclared {
+            if self.declarationstore.get_default_key(annotationtype).is_none() {
+                self.declare(annotationtype, &None, &None, &None)?;
+            }
+        }
+        for (dec_key, proc_key) in annotators {
+            if let Some(declaration) = self.get_mut_declaration(dec_key) {
+                if !declaration.processors.contains(&proc_key) {
+                    declaration.processors.push(proc_key);
+                }
+            }
+        }
+        self.version = FOLIAVERSION.to_string();
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::common::*;
+use crate::types::*;
+use crate::error::*;
+use crate::element::*;
+use crate::select::*;
+use crate::document::Document;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+///A FoLiA version
+pub struct FoliaVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl FoliaVersion {
+    pub fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self { major, minor, patch }
+    }
+
+    ///The version implemented by this library
+    pub fn current() -> Self {
+        FoliaVersion::from_str(FOLIAVERSION).expect("valid version")
+    }
+}
+
+impl FromStr for FoliaVersion {
+    type Err = FoliaError;
+
+    ///Parses a version such as ``2.1.0``, missing minor and patch numbers default to zero
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut numbers: Vec<u16> = Vec::with_capacity(3);
+        for part in s.trim().split('.') {
+            numbers.push(part.parse().map_err(|_| FoliaError::ParseError(format!("Invalid FoLiA version: {}", s)))?);
+        }
+        if numbers.is_empty() || numbers.len() > 3 {
+            return Err(FoliaError::ParseError(format!("Invalid FoLiA version: {}", s)));
+        }
+        numbers.resize(3, 0);
+        Ok(Self::new(numbers[0], numbers[1], numbers[2]))
+    }
+}
+
+impl fmt::Display for FoliaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl AnnotationType {
+    ///Returns the minimal FoLiA version in which documents with this annotation type can be written. Documents are always written
+    ///in the FoLiA v2 format (e.g. with provenance rather than old-style annotators), so this is at least 2.0.0.
+    pub fn required_version(&self) -> FoliaVersion {
+        match self {
+            AnnotationType::HSPACE => FoliaVersion::new(2,1,0),
+            _ => FoliaVersion::new(2,0,0),
+        }
+    }
+}
+
+impl Document {
+    ///Computes the minimal FoLiA version required to express the document, based on the annotation types that are declared or actually used
+    pub fn required_version(&self) -> FoliaVersion {
+        self.annotationtypes().iter().map(|annotationtype| annotationtype.required_version()).max().unwrap_or_else(|| FoliaVersion::new(2,0,0))
+    }
+
+    ///Checks whether the document can be written as the specified FoLiA version, returns an error
+    ///naming the annotation types that require a newer version otherwise
+    pub fn check_version(&self, target: FoliaVersion) -> Result<(), FoliaError> {
+        if target.major < 2 {
+            return Err(FoliaError::SerialisationError(format!("Unable to write FoLiA {}, only FoLiA v2 can be written", target)));
+        }
+        if target > FoliaVersion::current() {
+            return Err(FoliaError::SerialisationError(format!("Unable to write FoLiA {}, the latest supported version is {}", target, FOLIAVERSION)));
+        }
+        let incompatible: Vec<String> = self.annotationtypes().into_iter().filter(|annotationtype| annotationtype.required_version() > target)
+                                            .map(|annotationtype| format!("{} (FoLiA {})", annotationtype, annotationtype.required_version())).collect();
+        if !incompatible.is_empty() {
+            return Err(FoliaError::SerialisationError(format!("Unable to write FoLiA {}, the document uses: {}", target, incompatible.join(", "))));
+        }
+        Ok(())
+    }
+
+    ///Returns the annotation types of all declarations (which are all serialised, used or not) and of all elements under the body
+    fn annotationtypes(&self) -> Vec<AnnotationType> {
+        let mut annotationtypes: Vec<AnnotationType> = Vec::new();
+        for declaration in self.declarationstore.items.iter().flatten() {
+            if !annotationtypes.contains(&declaration.annotationtype) {
+                annotationtypes.push(declaration.annotationtype);
+            }
+        }
+        for item in self.select_data(Selector::elements(), Recursion::Always, false) {
+            if let DataType::Element(key) = item.data {
+                if let Some(annotationtype) = self.get_elementdata(*key).and_then(|element| element.elementtype.annotationtype()) {
+                    if !annotationtypes.contains(&annotationtype) {
+                        annotationtypes.push(annotationtype);
+                    }
+                }
+            }
+        }
+        annotationtypes
+    }
+}
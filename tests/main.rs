@@ -365,6 +365,39 @@ fn test002c_annotate_span_fromstructure() {
     }
 }
 
+#[test]
+fn test002d_add_nested_elements() {
+    let mut doc = Document::new("example", DocumentProperties::default()).expect("instantiation");
+    let sentence = doc.annotate(0, ElementData::new(ElementType::Sentence).with_attrib(Attribute::Id("s.1".to_string()))).expect("adding sentence");
+    for (id, text) in &[("word.1", "the"), ("word.2", "man")] {
+        doc.annotate(sentence, ElementData::new(ElementType::Word).with_attrib(Attribute::Id(id.to_string())).with_text(text.to_string())).expect("adding word");
+    }
+    //a dependency with its roles and their word references is added in one go
+    let dependency_key = doc.annotate(sentence, ElementData::new(ElementType::Dependency)
+                                      .with_attrib(Attribute::Set("adhoc".to_string()))
+                                      .with_attrib(Attribute::Class("det".to_string()))
+                                      .add_element(ElementData::new(ElementType::Headspan).with_span(&["word.2"]))
+                                      .add_element(ElementData::new(ElementType::DependencyDependent).with_span(&["word.1"]))
+    ).expect("adding dependency");
+    let dependency = doc.get_element(dependency_key).expect("dependency");
+    let hd = dependency.get_element(ElementType::Headspan, Cmp::Any, Recursion::No).expect("hd");
+    assert_eq!(hd.parent_key(), Some(dependency_key));
+    let wref = hd.get_element(ElementType::WordReference, Cmp::Any, Recursion::No).expect("wref");
+    assert_eq!(wref.parent_key(), hd.key());
+    //the word references were postprocessed with their span in place, so the words refer back to the dependency
+    let word = doc.get_element_by_id("word.1").expect("word");
+    let annotation = word.get_annotation(AnnotationType::DEPENDENCY, Cmp::Any, Recursion::No).expect("dependency of word");
+    assert_eq!(annotation.key(), Some(dependency_key));
+
+    //grandchildren of an orphan get their parent as well
+    let orphan_key = doc.add_element(ElementData::new(ElementType::Dependency)
+                                     .add_element(ElementData::new(ElementType::Headspan).with_span(&["word.2"]))).expect("adding orphan");
+    let orphan = doc.get_element(orphan_key).expect("orphan");
+    let hd = orphan.get_element(ElementType::Headspan, Cmp::Any, Recursion::No).expect("hd");
+    assert_eq!(hd.parent_key(), Some(orphan_key));
+    assert_eq!(hd.get_element(ElementType::WordReference, Cmp::Any, Recursion::No).expect("wref").parent_key(), hd.key());
+}
+
 #[test]
 fn test003_parse() {
     match Document::from_str(str::from_utf8(EXAMPLE).expect("decoding utf-8"), DocumentProperties::default()) {
@@ -379,6 +412,41 @@ fn test003_parse() {
 }
 
 
+#[test]
+fn test003b_parse_empty_declaration() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<FoLiA xmlns="http://ilk.uvt.nl/folia" version="2.0" xml:id="example">
+  <metadata type="native">
+    <annotations>
+      <text-annotation/>
+      <sentence-annotation/>
+      <pos-annotation set="adhoc"/>
+    </annotations>
+  </metadata>
+  <text xml:id="example.text">
+    <s xml:id="example.s.1">
+      <w xml:id="example.s.1.w.1">
+        <t>Hello</t>
+        <pos class="interjection"/>
+      </w>
+    </s>
+  </text>
+</FoLiA>"#;
+    match Document::from_str(xml, DocumentProperties::default()) {
+        Ok(doc) => {
+            //declarations without annotators are empty elements
+            assert!(doc.get_declaration_key_by_id(&Declaration::index_id(AnnotationType::POS, &Some("adhoc"))).is_some());
+            assert!(doc.get_declaration_key_by_id(&Declaration::index_id(AnnotationType::SENTENCE, &None)).is_some());
+            let word = doc.get_element_by_id("example.s.1.w.1").expect("word");
+            let pos = word.get_annotation(AnnotationType::POS, Cmp::Is("adhoc".to_string()), Recursion::No).expect("pos");
+            assert_eq!(pos.class(), Some("interjection"));
+        },
+        Err(err) => {
+            assert!(false, format!("Parsing failed with error: {}",err));
+        }
+    }
+}
+
 #[test]
 fn test004_get_word_from_index() {
     match Document::from_str(str::from_utf8(EXAMPLE).expect("decoding utf-8"), DocumentProperties::default()) {
@@ -565,6 +633,28 @@ fn test008d_selector_elementgroup() {
     }
 }
 
+#[test]
+fn test008e_selector_features() {
+    match Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
+        Ok(doc) => {
+            //the set of a feature is that of its context, so it is resolved through the contexttype
+            let selector = Selector::from_query(&doc,
+                        &Query::select()
+                        .element(Cmp::Is(ElementType::Feature))
+                        .contexttype(Cmp::Is(ElementType::PosAnnotation))
+                        .set(Cmp::Is("adhoc".to_string()))).expect("Compiling query");
+            assert!(selector.matchable());
+            let features: Vec<_> = doc.select(selector, Recursion::Always).collect();
+            assert_eq!(features.len(), 1);
+            assert_eq!(features[0].subset(), Some("number"));
+            assert_eq!(features[0].class(), Some("singular"));
+        }
+        Err(err) => {
+            assert!(false, format!("Instantiation failed with error: {}",err));
+        }
+    }
+}
+
 #[test]
 fn test009a_text() {
     match Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
@@ -812,6 +902,29 @@ const TEST_FEAT_AS_ATTRIB: &[u8] = br#"<?xml version="1.0" encoding="utf-8"?>
 
 }
 
+#[test]
+fn test011c_feature_declaration() {
+    let mut doc = Document::new("example", DocumentProperties::default()).expect("instantiation");
+    let sentence = doc.annotate(0, ElementData::new(ElementType::Sentence).with_attrib(Attribute::Id("s.1".to_string()))).expect("adding sentence");
+    let word = doc.annotate(sentence, ElementData::new(ElementType::Word).with_attrib(Attribute::Id("word.1".to_string())).with_text("man".to_string())).expect("adding word");
+    let pos_key = doc.annotate(word, ElementData::new(ElementType::PosAnnotation)
+                                     .with_attrib(Attribute::Set("adhoc".to_string()))
+                                     .with_attrib(Attribute::Class("noun".to_string()))).expect("adding pos");
+    //with a second set there is no default declaration to fall back to
+    doc.annotate(word, ElementData::new(ElementType::PosAnnotation)
+                       .with_attrib(Attribute::Set("other".to_string()))
+                       .with_attrib(Attribute::Class("N".to_string()))).expect("adding second pos");
+    //a feature without an explicit set inherits the declaration of its context
+    let feature_key = doc.add_element_to(pos_key, ElementData::new(ElementType::Feature)
+                                         .with_attrib(Attribute::Subset("number".to_string()))
+                                         .with_attrib(Attribute::Class("singular".to_string()))).expect("adding feature");
+    let pos_deckey = doc.get_element(pos_key).expect("pos").elementdata().declaration_key().expect("declaration key");
+    assert_eq!(doc.get_element(feature_key).expect("feature").elementdata().declaration_key().expect("declaration key"), pos_deckey);
+    assert!(doc.get_declaration_key_by_id(&Declaration::index_id(AnnotationType::POS, &None)).is_none());
+    let pos = doc.get_element(pos_key).expect("pos");
+    assert_eq!(pos.get_feature(Cmp::Is("number".to_string())).expect("feature").class(), Some("singular"));
+}
+
 #[test]
 fn test012_spanroles() {
     match Document::from_str(str::from_utf8(EXAMPLE_DEP).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
@@ -941,3 +1054,1561 @@ fn test016_add_provenance() {
         }
     }
 }
+
+#[test]
+fn test017a_export_text() {
+    match Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
+        Ok(doc) => {
+            match doc.to_text(0, &ExportParameters::default()) {
+                Ok(text) => assert_eq!(text, "Hello world! This is an example & a test.\n"),
+                Err(err) => assert!(false, format!("Export failed with error: {}",err))
+            }
+        },
+        Err(err) => {
+            assert!(false, format!("Instantiation failed with error: {}",err));
+        }
+    }
+}
+
+#[test]
+fn test017b_export_tokenised() {
+    match Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
+        Ok(doc) => {
+            match doc.to_tokenised_text(0, &ExportParameters::default()) {
+                Ok(text) => assert_eq!(text, "Hello world !\nThis is an example & a test .\n"),
+                Err(err) => assert!(false, format!("Export failed with error: {}",err))
+            }
+        },
+        Err(err) => {
+            assert!(false, format!("Instantiation failed with error: {}",err));
+        }
+    }
+}
+
+#[test]
+fn test017c_export_vertical() {
+    match Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
+        Ok(doc) => {
+            let sentence = doc.get_element_key_by_id("example.p.1.s.2").expect("sentence");
+            match doc.to_vertical(sentence, &ExportParameters::default().with_posset("adhoc")) {
+                Ok(text) => {
+                    let lines: Vec<&str> = text.lines().collect();
+                    assert_eq!(lines.len(), 9);
+                    assert_eq!(lines[0], "This\t_\t_");
+                    assert_eq!(lines[3], "example\tnoun\t_");
+                    assert_eq!(lines[8], "");
+                },
+                Err(err) => assert!(false, format!("Export failed with error: {}",err))
+            }
+        },
+        Err(err) => {
+            assert!(false, format!("Instantiation failed with error: {}",err));
+        }
+    }
+}
+
+const EXAMPLE_CONLLU: &str = "# newpar id = conllu.p.1
+# sent_id = conllu.s.1
+# text = The man walked.
+1\tThe\tthe\tDET\t_\tDefinite=Def|PronType=Art\t2\tdet\t_\t_
+2\tman\tman\tNOUN\t_\tNumber=Sing\t3\tnsubj\t_\t_
+3\twalked\twalk\tVERB\t_\tMood=Ind|Tense=Past\t0\troot\t_\tSpaceAfter=No
+4\t.\t.\tPUNCT\t_\t_\t3\tpunct\t_\t_
+
+";
+
+#[test]
+fn test018a_conllu_import() {
+    match Document::from_conllu("conllu", EXAMPLE_CONLLU, &ConlluParameters::default(), DocumentProperties::default()) {
+        Ok((doc, _)) => {
+            let word = doc.get_element_by_id("conllu.s.1.w.2").expect("word");
+            assert_eq!(word.text(&TextParameters::default()).expect("text"), "man");
+            let pos = word.get_annotation(AnnotationType::POS, Cmp::Is(UD_POS_SET.to_string()), Recursion::No).expect("pos");
+            assert_eq!(pos.class(), Some("NOUN"));
+            assert_eq!(pos.get_feature(Cmp::Is("Number".to_string())).expect("feature").class(), Some("Sing"));
+            let dependency = word.get_annotation(AnnotationType::DEPENDENCY, Cmp::Any, Recursion::No).expect("dependency");
+            assert_eq!(dependency.class(), Some("det"));
+            assert_eq!(doc.get_element_by_id("conllu.s.1.w.3").expect("word").attrib(AttribType::SPACE), Some(&Attribute::Space(false)));
+        },
+        Err(err) => {
+            assert!(false, format!("Import failed with error: {}",err));
+        }
+    }
+}
+
+#[test]
+fn test018b_conllu_roundtrip() {
+    match Document::from_conllu("conllu", EXAMPLE_CONLLU, &ConlluParameters::default(), DocumentProperties::default()) {
+        Ok((doc, _)) => {
+            match doc.to_conllu(0, &ConlluParameters::default()) {
+                Ok(conllu) => assert_eq!(conllu.as_str(), EXAMPLE_CONLLU),
+                Err(err) => assert!(false, format!("Export failed with error: {}",err))
+            }
+            //the conversion must also survive serialisation to and parsing from FoLiA XML
+            let xml = doc.xml(0,4).expect("serialisation");
+            let doc = Document::from_str(str::from_utf8(&xml).expect("utf-8"), DocumentProperties::default()).expect("parsing");
+            assert_eq!(doc.to_conllu(0, &ConlluParameters::default()).expect("export").as_str(), EXAMPLE_CONLLU);
+        },
+        Err(err) => {
+            assert!(false, format!("Import failed with error: {}",err));
+        }
+    }
+}
+
+#[test]
+fn test018c_conllu_export_without_syntax() {
+    match Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
+        Ok(doc) => {
+            match doc.to_conllu(0, &ConlluParameters::default().with_uposset(Some("adhoc".to_string()))) {
+                Ok(conllu) => {
+                    let lines: Vec<&str> = conllu.lines().collect();
+                    assert_eq!(lines[0], "# newpar id = example.p.1");
+                    assert_eq!(lines[1], "# sent_id = example.p.1.s.1");
+                    assert_eq!(lines[2], "# text = Hello world!");
+                    assert_eq!(lines[4], "2\tworld\t_\t_\t_\t_\t_\t_\t_\tSpaceAfter=No");
+                    assert!(lines.contains(&"4\texample\t_\tnoun\t_\tnumber=singular\t_\t_\t_\t_"));
+                },
+                Err(err) => assert!(false, format!("Export failed with error: {}",err))
+            }
+        },
+        Err(err) => {
+            assert!(false, format!("Instantiation failed with error: {}",err));
+        }
+    }
+}
+
+const EXAMPLE_CONLLU_IDS: &str = "# newpar id = par-a
+# sent_id = sent-a
+# text = Run!
+1\tRun\trun\tVERB\t_\tMood=Imp\t0\troot:imp\t_\tSpaceAfter=No
+2\t!\t!\tPUNCT\t_\t_\t1\tpunct\t_\t_
+
+";
+
+#[test]
+fn test018d_conllu_ids_and_root() {
+    match Document::from_conllu("conllu", EXAMPLE_CONLLU_IDS, &ConlluParameters::default(), DocumentProperties::default()) {
+        Ok((doc, _)) => {
+            assert!(doc.get_element_by_id("par-a").is_some());
+            assert!(doc.get_element_by_id("conllu.p.1").is_none());
+            let word = doc.get_element_by_id("sent-a.w.1").expect("word");
+            //the root relation is kept as a dependency without head
+            let dependency = word.get_annotation(AnnotationType::DEPENDENCY, Cmp::Any, Recursion::No).expect("dependency");
+            assert_eq!(dependency.class(), Some("root:imp"));
+            assert!(dependency.get_element(ElementType::Headspan, Cmp::Any, Recursion::No).is_none());
+            assert_eq!(doc.to_conllu(0, &ConlluParameters::default()).expect("export").as_str(), EXAMPLE_CONLLU_IDS);
+        },
+        Err(err) => {
+            assert!(false, format!("Import failed with error: {}",err));
+        }
+    }
+    //IDs that are not valid XML IDs or that are already in use are replaced by generated ones
+    let conllu = EXAMPLE_CONLLU_IDS.replace("par-a", "1");
+    let (doc, _) = Document::from_conllu("conllu", &conllu, &ConlluParameters::default(), DocumentProperties::default()).expect("import");
+    assert!(doc.get_element_by_id("conllu.p.1").is_some());
+    assert!(doc.get_element_by_id("sent-a.w.1").is_some());
+    let conllu = EXAMPLE_CONLLU_IDS.replace("sent-a", "par-a");
+    let (doc, _) = Document::from_conllu("conllu", &conllu, &ConlluParameters::default(), DocumentProperties::default()).expect("import");
+    assert!(doc.get_element_by_id("par-a").is_some());
+    assert!(doc.get_element_by_id("conllu.s.1.w.1").is_some());
+}
+
+#[test]
+fn test018e_conllu_partial_dependencies() {
+    //multi-word tokens and empty nodes are reported
+    let conllu = "# text = Del tren.\n1-2\tDel\t_\t_\t_\t_\t_\t_\t_\t_\n1\tDe\tde\tADP\t_\t_\t_\t_\t_\t_\n2\tel\tel\tDET\t_\t_\t_\t_\t_\t_\n3\ttren\ttren\tNOUN\t_\t_\t_\t_\t_\tSpaceAfter=No\n3.1\tx\t_\t_\t_\t_\t_\t_\t_\t_\n4\t.\t.\tPUNCT\t_\t_\t_\t_\t_\t_\n\n";
+    let (doc, report) = Document::from_conllu("conllu", conllu, &ConlluParameters::default(), DocumentProperties::default()).expect("import");
+    assert_eq!(report.count("multi-word token"), 1);
+    assert_eq!(report.count("empty node"), 1);
+    assert!(doc.get_element_by_id("conllu.s.1.w.4").is_some());
+
+    //only the word that heads the others without a head of its own is the root, other words are left unattached
+    let conllu = "# text = The man walked.\n1\tThe\tthe\tDET\t_\t_\t2\tdet\t_\t_\n2\tman\tman\tNOUN\t_\t_\t_\t_\t_\t_\n3\twalked\twalk\tVERB\t_\t_\t_\t_\t_\tSpaceAfter=No\n4\t.\t.\tPUNCT\t_\t_\t_\t_\t_\t_\n\n";
+    let (doc, _) = Document::from_conllu("conllu", conllu, &ConlluParameters::default(), DocumentProperties::default()).expect("import");
+    let export = doc.to_conllu(0, &ConlluParameters::default()).expect("export");
+    let heads: Vec<(&str,&str)> = export.lines().filter(|line| !line.is_empty() && !line.starts_with('#'))
+                                        .map(|line| { let fields: Vec<&str> = line.split('\t').collect(); (fields[6], fields[7]) }).collect();
+    assert_eq!(heads, vec!(("2","det"), ("0","root"), ("_","_"), ("_","_")));
+
+    //with two unattached heads, there is no root
+    let conllu = conllu.replace("4\t.\t.\tPUNCT\t_\t_\t_\t_", "4\t.\t.\tPUNCT\t_\t_\t3\tpunct");
+    let (doc, _) = Document::from_conllu("conllu", &conllu, &ConlluParameters::default(), DocumentProperties::default()).expect("import");
+    let export = doc.to_conllu(0, &ConlluParameters::default()).expect("export");
+    assert!(!export.contains("\troot\t"));
+    assert!(export.contains("2\tman\tman\tNOUN\t_\t_\t_\t_\t_\t_\n"));
+
+    //a head outside of the sentence leaves both columns empty
+    let conllu = format!("{}1\tYes\tyes\tINTJ\t_\t_\t_\t_\t_\t_\n\n", conllu);
+    let (mut doc, _) = Document::from_conllu("conllu", &conllu, &ConlluParameters::default(), DocumentProperties::default()).expect("import");
+    let sentence = doc.get_element_key_by_id("conllu.s.2").expect("sentence");
+    doc.annotate(sentence, ElementData::new(ElementType::Dependency)
+                           .with_attrib(Attribute::Set(UD_DEPENDENCY_SET.to_string()))
+                           .with_attrib(Attribute::Class("discourse".to_string()))
+                           .add_element(ElementData::new(ElementType::Headspan).with_span(&["conllu.s.1.w.3"]))
+                           .add_element(ElementData::new(ElementType::DependencyDependent).with_span(&["conllu.s.2.w.1"]))).expect("annotating");
+    let export = doc.to_conllu(0, &ConlluParameters::default()).expect("export");
+    assert!(export.contains("1\tYes\tyes\tINTJ\t_\t_\t_\t_\t_\t_\n"));
+}
+
+#[test]
+fn test019a_json_roundtrip() {
+    match Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
+        Ok(doc) => {
+            let json = doc.json(false).expect("serialisation to json");
+            match Document::from_json(&json, DocumentProperties::default()) {
+                Ok(doc2) => {
+                    assert_eq!(doc2.id(), "example");
+                    assert_eq!(doc2.metadata.data.get("language").map(|s| s.as_str()), Some("eng"));
+                    assert_eq!(doc2.get_processor_by_id("p2").expect("processor").name, "proycon");
+                    assert_eq!(doc.xml(0,4).expect("serialisation"), doc2.xml(0,4).expect("serialisation"));
+                },
+                Err(err) => assert!(false, format!("Deserialisation failed with error: {}",err))
+            }
+        },
+        Err(err) => {
+            assert!(false, format!("Instantiation failed with error: {}",err));
+        }
+    }
+}
+
+#[test]
+fn test019b_json_subtree() {
+    match Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
+        Ok(mut doc) => {
+            let word_key = doc.get_element_key_by_id("example.p.1.s.2.w.4").expect("word");
+            let json = doc.json_element(word_key, false).expect("serialisation to json");
+            assert!(json.starts_with("{\"type\":\"w\",\"id\":\"example.p.1.s.2.w.4\""));
+            assert!(json.contains("{\"type\":\"pos\",\"set\":\"adhoc\",\"class\":\"noun\",\"processor\":\"p2\",\"children\":[{\"type\":\"feat\",\"subset\":\"number\",\"class\":\"singular\"}]}"));
+
+            //add it back as a new word with a different ID
+            let json = json.replace("example.p.1.s.2.w.4", "example.p.1.s.2.w.9");
+            let sentence_key = doc.get_element_key_by_id("example.p.1.s.2").expect("sentence");
+            match doc.add_json_to(sentence_key, &json) {
+                Ok(key) => {
+                    let word = doc.get_element(key).expect("word");
+                    assert_eq!(word.id(), Some("example.p.1.s.2.w.9"));
+                    assert_eq!(word.text(&TextParameters::default()).expect("text"), "example");
+                    let pos = word.get_annotation(AnnotationType::POS, Cmp::Any, Recursion::No).expect("pos");
+                    assert_eq!(pos.get_feature(Cmp::Is("number".to_string())).expect("feature").class(), Some("singular"));
+                },
+                Err(err) => assert!(false, format!("Adding from json failed with error: {}",err))
+            }
+        },
+        Err(err) => {
+            assert!(false, format!("Instantiation failed with error: {}",err));
+        }
+    }
+}
+
+#[test]
+fn test019c_json_reftype() {
+    //the type attribute of an xref must not clash with the element type
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<FoLiA xmlns="http://ilk.uvt.nl/folia" xmlns:xlink="http://www.w3.org/1999/xlink" xml:id="example" version="2.0">
+  <metadata type="native">
+    <annotations>
+      <text-annotation/>
+      <sentence-annotation/>
+      <token-annotation/>
+      <relation-annotation set="alignments"/>
+    </annotations>
+  </metadata>
+  <text xml:id="example.text">
+    <s xml:id="example.s.1">
+      <w xml:id="example.s.1.w.1"><t>wereld</t>
+        <relation class="translation" xlink:href="en.xml" xlink:type="simple"><xref id="en.w.2" type="w" t="world"/></relation>
+      </w>
+    </s>
+  </text>
+</FoLiA>"#;
+    let doc = Document::from_str(xml, DocumentProperties::default()).expect("parsing");
+    let xref = doc.get_element_by_id("example.s.1.w.1")
+                  .and_then(|word| word.get_element(ElementType::LinkReference, Cmp::Any, Recursion::Always).map(|xref| xref.key().expect("key"))).expect("xref");
+    let json = doc.json_element(xref, false).expect("serialisation to json");
+    assert!(json.starts_with("{\"type\":\"xref\""));
+    assert!(json.contains("\"reftype\":\"w\""));
+    let json = doc.json(false).expect("serialisation to json");
+    let doc2 = Document::from_json(&json, DocumentProperties::default()).expect("deserialisation");
+    assert_eq!(doc.xml(0,0).expect("serialisation"), doc2.xml(0,0).expect("serialisation"));
+    assert!(str::from_utf8(&doc2.xml(0,0).expect("serialisation")).expect("utf-8").contains("<xref id=\"en.w.2\" type=\"w\""));
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+fn test020a_snapshot_roundtrip() {
+    match Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
+        Ok(doc) => {
+            let snapshot = doc.to_snapshot(EXAMPLE).expect("snapshot");
+            match Document::from_snapshot(&snapshot, EXAMPLE) {
+                Ok(doc2) => {
+                    assert_eq!(doc2.id(), "example");
+                    assert_eq!(doc2.get_element_key_by_id("example.p.1.s.2.w.4"), doc.get_element_key_by_id("example.p.1.s.2.w.4"));
+                    assert_eq!(doc.xml(0,4).expect("serialisation"), doc2.xml(0,4).expect("serialisation"));
+                },
+                Err(err) => assert!(false, format!("Loading snapshot failed with error: {}",err))
+            }
+            //the snapshot is rejected once the source changes
+            let mut changed = EXAMPLE.to_vec();
+            changed.push(b'\n');
+            match Document::from_snapshot(&snapshot, &changed) {
+                Err(FoliaError::SnapshotError(_)) => {},
+                _ => assert!(false, "Outdated snapshot was not rejected")
+            }
+            match Document::from_snapshot(&snapshot[..snapshot.len() - 1], EXAMPLE) {
+                Err(FoliaError::SnapshotError(_)) => {},
+                _ => assert!(false, "Truncated snapshot was not rejected")
+            }
+        },
+        Err(err) => {
+            assert!(false, format!("Instantiation failed with error: {}",err));
+        }
+    }
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+fn test020b_snapshot_file() {
+    let filename = std::env::temp_dir().join("folia-rust-test020b.folia.xml").to_str().expect("filename").to_string();
+    let snapshotfilename = Document::snapshot_filename(&filename);
+    let _ = std::fs::remove_file(&snapshotfilename);
+    std::fs::write(&filename, EXAMPLE).expect("writing example");
+    let doc = Document::from_file_with_snapshot(&filename, DocumentProperties::default()).expect("loading from xml");
+    assert!(std::path::Path::new(&snapshotfilename).exists());
+    let doc2 = Document::from_file_with_snapshot(&filename, DocumentProperties::default()).expect("loading from snapshot");
+    assert_eq!(doc2.filename(), Some(filename.as_str()));
+    assert_eq!(doc.xml(0,4).expect("serialisation"), doc2.xml(0,4).expect("serialisation"));
+    let _ = std::fs::remove_file(&snapshotfilename);
+    let _ = std::fs::remove_file(&filename);
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+fn test020c_snapshot_corrupt() {
+    let doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let snapshot = doc.to_snapshot(EXAMPLE).expect("snapshot");
+    //corrupt snapshots are rejected with an error, never with a panic or a huge allocation
+    for i in 0..snapshot.len() {
+        assert!(Document::from_snapshot(&snapshot[..i], EXAMPLE).is_err());
+        //overwrite with a huge integer, as a length that would be read here
+        let mut corrupt = snapshot.clone();
+        let end = std::cmp::min(i + 9, corrupt.len() - 1);
+        for byte in corrupt[i..end].iter_mut() {
+            *byte = 0xff;
+        }
+        corrupt[end] = 0x7f;
+        let _ = Document::from_snapshot(&corrupt, EXAMPLE);
+    }
+    //a corrupt snapshot file is ignored and the XML is parsed instead
+    let filename = std::env::temp_dir().join("folia-rust-test020c.folia.xml").to_str().expect("filename").to_string();
+    let snapshotfilename = Document::snapshot_filename(&filename);
+    std::fs::write(&filename, EXAMPLE).expect("writing example");
+    let mut corrupt = snapshot[..snapshot.len() / 2].to_vec();
+    corrupt.extend_from_slice(&[0xff; 16]);
+    std::fs::write(&snapshotfilename, &corrupt).expect("writing snapshot");
+    let doc2 = Document::from_file_with_snapshot(&filename, DocumentProperties::default()).expect("loading with corrupt snapshot");
+    assert_eq!(doc.xml(0,4).expect("serialisation"), doc2.xml(0,4).expect("serialisation"));
+    let _ = std::fs::remove_file(&snapshotfilename);
+    let _ = std::fs::remove_file(&filename);
+}
+
+#[test]
+fn test021a_brat() {
+    match Document::from_str(str::from_utf8(EXAMPLE_DEP).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
+        Ok(mut doc) => {
+            let parameters = StandoffParameters::default().with_set(Some("adhoc".to_string())).with_dependencyset(Some("alpino-dependencies".to_string()));
+            let (text, ann) = doc.to_brat(0, &parameters).expect("brat export");
+            assert_eq!(text, "De man begroette hem.\n");
+            assert_eq!(ann, "T1\thd 3 6\tman\nT2\tdep 0 2\tDe\nR1\tdet Arg1:T1 Arg2:T2\nT3\thd 7 16\tbegroette\nT4\tdep 3 6\tman\nR2\tsu Arg1:T3 Arg2:T4\nT5\tdep 17 20\them\nR3\tobj1 Arg1:T3 Arg2:T5\n");
+
+            //import as a new document
+            let doc2 = Document::from_brat("brat", &text, &ann, &parameters, DocumentProperties::default()).expect("brat import");
+            let (_, ann2) = doc2.to_brat(0, &parameters).expect("brat export");
+            assert_eq!(ann, ann2);
+
+            //add an entity to the existing document
+            let keys = doc.add_brat(0, "T1\tentity-person 0 6\tDe man\n", &parameters).expect("brat import");
+            assert_eq!(keys.len(), 1);
+            let entity = doc.get_element(keys[0]).expect("entity");
+            assert_eq!(entity.elementtype(), ElementType::Entity);
+            assert_eq!(entity.class(), Some("person"));
+            assert_eq!(entity.text(&TextParameters::default()).expect("text"), "De man");
+        },
+        Err(err) => {
+            assert!(false, format!("Instantiation failed with error: {}",err));
+        }
+    }
+}
+
+#[test]
+fn test021b_webanno_tsv() {
+    match Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
+        Ok(doc) => {
+            let parameters = StandoffParameters::default().with_set(Some("shallowsyntaxset".to_string()));
+            let tsv = doc.to_webanno_tsv(0, &parameters).expect("tsv export");
+            assert!(tsv.starts_with("#FORMAT=WebAnno TSV 3.3\n#T_SP=de.tudarmstadt.ukp.dkpro.core.api.syntax.type.chunk.Chunk|chunkValue\n\n\n#Text=Hello world!\n1-1\t0-5\tHello\t_\t\n"));
+            assert!(tsv.contains("2-3\t21-23\tan\tnp[1]\t\n2-4\t24-31\texample\tnp[1]\t\n"));
+            match Document::from_webanno_tsv("tsv", &tsv, &parameters, DocumentProperties::default()) {
+                Ok(doc2) => {
+                    let word = doc2.get_element_by_id("tsv.s.2.w.3").expect("word");
+                    let chunk = word.get_annotation(AnnotationType::CHUNKING, Cmp::Any, Recursion::No).expect("chunk");
+                    assert_eq!(chunk.class(), Some("np"));
+                    assert_eq!(chunk.text(&TextParameters::default()).expect("text"), "an example");
+                    assert_eq!(doc2.to_webanno_tsv(0, &parameters).expect("tsv export"), tsv);
+                },
+                Err(err) => assert!(false, format!("Import failed with error: {}",err))
+            }
+        },
+        Err(err) => {
+            assert!(false, format!("Instantiation failed with error: {}",err));
+        }
+    }
+}
+
+#[test]
+fn test021c_webanno_tsv_dependencies() {
+    match Document::from_str(str::from_utf8(EXAMPLE_DEP).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
+        Ok(doc) => {
+            let parameters = StandoffParameters::default().with_dependencyset(Some("alpino-dependencies".to_string()));
+            let tsv = doc.to_webanno_tsv(0, &parameters).expect("tsv export");
+            assert!(tsv.contains("1-1\t0-2\tDe\tdet\tbasic\t1-2\t\n"));
+            let doc2 = Document::from_webanno_tsv("tsv", &tsv, &parameters, DocumentProperties::default()).expect("tsv import");
+            let word = doc2.get_element_by_id("tsv.s.1.w.4").expect("word");
+            let dependency = word.get_annotation(AnnotationType::DEPENDENCY, Cmp::Any, Recursion::No).expect("dependency");
+            assert_eq!(dependency.class(), Some("obj1"));
+            assert_eq!(doc2.to_webanno_tsv(0, &parameters).expect("tsv export"), tsv);
+        },
+        Err(err) => {
+            assert!(false, format!("Instantiation failed with error: {}",err));
+        }
+    }
+}
+
+#[test]
+fn test021d_webanno_tsv_inception() {
+    //INCEpTION exports named entities with an identifier feature before the value
+    let tsv = "#FORMAT=WebAnno TSV 3.3\n#T_SP=de.tudarmstadt.ukp.dkpro.core.api.ner.type.NamedEntity|identifier|value\n\n\n#Text=Zoë lives in Nijmegen\n1-1\t0-3\tZoë\thttp://www.wikidata.org/entity/Q1\tPER\t\n1-2\t4-9\tlives\t_\t_\t\n1-3\t10-12\tin\t_\t_\t\n1-4\t13-21\tNijmegen\t*\tLOC\t\n\n";
+    let parameters = StandoffParameters::default().with_set(Some("nerset".to_string()));
+    let doc = Document::from_webanno_tsv("tsv", tsv, &parameters, DocumentProperties::default()).expect("tsv import");
+    let word = doc.get_element_by_id("tsv.s.1.w.1").expect("word");
+    let entity = word.get_annotation(AnnotationType::ENTITY, Cmp::Any, Recursion::No).expect("entity");
+    assert_eq!(entity.class(), Some("PER"));
+    let word = doc.get_element_by_id("tsv.s.1.w.4").expect("word");
+    let entity = word.get_annotation(AnnotationType::ENTITY, Cmp::Any, Recursion::No).expect("entity");
+    assert_eq!(entity.class(), Some("LOC"));
+    let tsv2 = doc.to_webanno_tsv(0, &parameters).expect("tsv export");
+    assert!(tsv2.contains("#Text=Zoë lives in Nijmegen\n1-1\t0-3\tZoë\tPER\t\n"));
+    let (text, ann) = doc.to_brat(0, &parameters).expect("brat export");
+    assert_eq!(text, "Zoë lives in Nijmegen\n");
+    assert_eq!(ann, "T1\tentity-PER 0 3\tZoë\nT2\tentity-LOC 13 21\tNijmegen\n");
+}
+
+#[test]
+fn test022a_tei_roundtrip() {
+    match Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
+        Ok(doc) => {
+            let (tei, report) = doc.to_tei(0, &ExportParameters::default()).expect("tei export");
+            assert!(tei.contains("<text xml:id=\"example.text\"><body>"));
+            assert!(tei.contains("<s xml:id=\"example.p.1.s.1\"><w xml:id=\"example.p.1.s.1.w.1\">Hello</w> <w xml:id=\"example.p.1.s.1.w.2\">world</w><w xml:id=\"example.p.1.s.1.w.3\">!</w></s>"));
+            assert!(tei.contains("<w xml:id=\"example.p.1.s.2.w.4\" pos=\"noun\">example</w>"));
+            assert!(tei.contains("<w xml:id=\"example.p.1.s.2.w.5\">&amp;</w>"));
+            assert_eq!(report.count("chunk"), 1);
+            assert_eq!(report.count("feat"), 1);
+            assert_eq!(report.count("metadata/language"), 1);
+
+            let (doc2, report2) = Document::from_tei("example", &tei, &ExportParameters::default().with_posset("adhoc"), DocumentProperties::default()).expect("tei import");
+            assert!(report2.is_empty(), "{}", report2);
+            let word = doc2.get_element_by_id("example.p.1.s.2.w.4").expect("word");
+            assert_eq!(word.get_annotation(AnnotationType::POS, Cmp::Any, Recursion::No).and_then(|pos| pos.class().map(|s| s.to_string())), Some("noun".to_string()));
+            assert_eq!(doc2.to_text(0, &ExportParameters::default()).expect("text"), doc.to_text(0, &ExportParameters::default()).expect("text"));
+            let (tei2, _) = doc2.to_tei(0, &ExportParameters::default()).expect("tei export");
+            assert_eq!(tei, tei2);
+        },
+        Err(err) => {
+            assert!(false, format!("Instantiation failed with error: {}",err));
+        }
+    }
+}
+
+#[test]
+fn test022b_tei_import() {
+    let tei = r#"<?xml version="1.0" encoding="utf-8"?>
+<TEI xmlns="http://www.tei-c.org/ns/1.0">
+  <teiHeader>
+    <fileDesc>
+      <titleStmt><title>A  test</title></titleStmt>
+    </fileDesc>
+  </teiHeader>
+  <text>
+    <body>
+      <div>
+        <head>Introduction</head>
+        <p>This is <hi rend="italic">emphasised</hi> text.<lb/></p>
+        <p>
+          <s><w lemma="hello">Hello</w> <w>world</w><pc>!</pc></s>
+        </p>
+        <list>
+          <item>one</item>
+          <item>two</item>
+        </list>
+        <table><row><cell>x</cell></row></table>
+      </div>
+    </body>
+  </text>
+</TEI>"#;
+    let (doc, report) = Document::from_tei("tei", tei, &ExportParameters::default(), DocumentProperties::default()).expect("tei import");
+    assert_eq!(doc.metadata.data.get("title").map(|s| s.as_str()), Some("A test"));
+    assert_eq!(report.count("hi"), 1);
+    assert_eq!(report.count("table"), 1);
+    assert_eq!(report.count("row"), 1);
+    assert_eq!(report.count("cell"), 1);
+    let paragraph = doc.get_element_by_id("tei.div.1.p.1").expect("paragraph");
+    assert_eq!(paragraph.text(&TextParameters::default()).expect("text"), "This is emphasised text.");
+    assert!(paragraph.get_element(ElementType::Linebreak, Cmp::Any, Recursion::No).is_some());
+    let word = doc.get_element_by_id("tei.div.1.p.2.s.1.w.1").expect("word");
+    assert_eq!(word.get_annotation(AnnotationType::LEMMA, Cmp::Any, Recursion::No).and_then(|lemma| lemma.class().map(|s| s.to_string())), Some("hello".to_string()));
+    let sentence = doc.get_element_by_id("tei.div.1.p.2.s.1").expect("sentence");
+    assert_eq!(sentence.text(&TextParameters::default()).expect("text"), "Hello world!");
+    assert_eq!(doc.get_element_by_id("tei.div.1.list.1.item.2").expect("item").text(&TextParameters::default()).expect("text"), "two");
+    assert_eq!(report.count("text in div"), 1);
+}
+
+#[test]
+fn test022c_naf_roundtrip() {
+    match Document::from_str(str::from_utf8(EXAMPLE_DEP).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
+        Ok(doc) => {
+            let parameters = NafParameters::default().with_dependencyset(Some("alpino-dependencies".to_string()));
+            let (naf, report) = doc.to_naf(0, &parameters).expect("naf export");
+            assert!(naf.contains("<raw>De man begroette hem.</raw>"));
+            assert!(naf.contains("<wf id=\"w4\" sent=\"1\" para=\"1\" offset=\"17\" length=\"3\">hem</wf>"));
+            assert!(naf.contains("<term id=\"t1\"><span><target id=\"w1\"/></span></term>"));
+            assert!(naf.contains("<dep from=\"t3\" to=\"t2\" rfunc=\"su\"/>"));
+            assert_eq!(report.count("su"), 8);
+            assert_eq!(report.count("syntax"), 1);
+
+            let (doc2, report2) = Document::from_naf("example", &naf, &parameters, DocumentProperties::default()).expect("naf import");
+            assert!(report2.is_empty(), "{}", report2);
+            assert_eq!(doc2.to_text(0, &ExportParameters::default()).expect("text"), "De man begroette hem.\n");
+            let (naf2, _) = doc2.to_naf(0, &parameters).expect("naf export");
+            assert_eq!(naf, naf2);
+        },
+        Err(err) => {
+            assert!(false, format!("Instantiation failed with error: {}",err));
+        }
+    }
+}
+
+#[test]
+fn test022d_naf_import() {
+    let naf = r#"<?xml version="1.0" encoding="UTF-8"?>
+<NAF xml:lang="en" version="v3">
+  <nafHeader><fileDesc title="Test"/></nafHeader>
+  <text>
+    <wf id="w1" sent="1" offset="0" length="4">John</wf>
+    <wf id="w2" sent="1" offset="5" length="5">Smith</wf>
+    <wf id="w3" sent="1" offset="11" length="6">sleeps</wf>
+    <wf id="w4" sent="1" offset="17" length="1">.</wf>
+  </text>
+  <terms>
+    <term id="t1" lemma="John" pos="PROPN"><span><target id="w1"/></span></term>
+    <term id="t2" lemma="Smith" pos="PROPN"><span><target id="w2"/></span></term>
+    <term id="t3" lemma="sleep" pos="VERB"><span><target id="w3"/></span><externalReferences/></term>
+    <term id="t4" lemma="." pos="PUNCT"><span><target id="w4"/></span></term>
+  </terms>
+  <entities>
+    <entity id="e1" type="PER"><references><span><target id="t1"/><target id="t2"/></span></references></entity>
+  </entities>
+  <coreferences>
+    <coref id="co1"><span><target id="t1"/></span></coref>
+  </coreferences>
+</NAF>"#;
+    let parameters = NafParameters::default().with_posset(Some("upos".to_string())).with_entityset(Some("ner".to_string()));
+    let (doc, report) = Document::from_naf("naf", naf, &parameters, DocumentProperties::default()).expect("naf import");
+    assert_eq!(report.count("coreferences"), 1);
+    assert_eq!(report.count("terms/externalReferences"), 1);
+    assert_eq!(doc.metadata.data.get("language").map(|s| s.as_str()), Some("en"));
+    assert_eq!(doc.to_text(0, &ExportParameters::default()).expect("text"), "John Smith sleeps.\n");
+    let root = doc.get_element(0).expect("root");
+    let entity = root.get_element(ElementType::Entity, Cmp::Is("ner".to_string()), Recursion::Always).expect("entity");
+    assert_eq!(entity.class(), Some("PER"));
+    assert_eq!(entity.text(&TextParameters::default()).expect("text"), "John Smith");
+}
+
+#[test]
+fn test022e_naf_unresolved_targets() {
+    let naf = r#"<?xml version="1.0" encoding="UTF-8"?>
+<NAF xml:lang="en" version="v3">
+  <text>
+    <wf id="w1" sent="1" offset="0" length="4">John</wf>
+    <wf id="w2" sent="1" offset="5" length="6">sleeps</wf>
+  </text>
+  <terms>
+    <term id="t1" lemma="John" pos="PROPN"><span><target id="w1"/></span></term>
+    <term id="t2" lemma="sleep" pos="VERB"><span><target id="w2"/></span></term>
+  </terms>
+  <entities>
+    <entity id="e1" type="PER"><references><span><target id="t1"/></span></references></entity>
+    <entity id="e2" type="LOC"><references><span><target id="t9"/></span></references></entity>
+  </entities>
+  <chunks>
+    <chunk id="c1" phrase="NP"><span><target id="t8"/></span></chunk>
+  </chunks>
+  <deps>
+    <dep from="t2" to="t1" rfunc="nsubj"/>
+    <dep from="t2" to="t9" rfunc="obj"/>
+  </deps>
+</NAF>"#;
+    let parameters = NafParameters::default().with_entityset(Some("ner".to_string())).with_dependencyset(Some("ud".to_string()));
+    let (doc, report) = Document::from_naf("naf", naf, &parameters, DocumentProperties::default()).expect("naf import");
+    //annotations whose targets do not exist are not converted, but they are reported
+    assert_eq!(report.count("entities/entity (unresolved targets)"), 1);
+    assert_eq!(report.count("chunks/chunk (unresolved targets)"), 1);
+    assert_eq!(report.count("deps/dep (unresolved targets)"), 1);
+    let root = doc.get_element(0).expect("root");
+    assert_eq!(root.get_element(ElementType::Entity, Cmp::Any, Recursion::Always).expect("entity").class(), Some("PER"));
+    assert!(root.get_element(ElementType::Chunk, Cmp::Any, Recursion::Always).is_none());
+    assert_eq!(root.get_element(ElementType::Dependency, Cmp::Any, Recursion::Always).expect("dependency").class(), Some("nsubj"));
+}
+
+#[test]
+fn test023a_html() {
+    match Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()) {
+        Ok(doc) => {
+            let html = doc.to_html(0, &ExportParameters::default()).expect("html export");
+            assert!(html.starts_with("<!DOCTYPE html>"));
+            assert!(html.contains("<tr><th>language</th><td>eng</td></tr>"));
+            assert!(html.contains("<li>pos <span class=\"folia-set\">(adhoc)</span></li>"));
+            assert!(html.contains("<li id=\"p1\"><strong>proycon</strong> (manual)</li>"));
+            assert!(html.contains("<span class=\"folia-w\" id=\"example.p.1.s.1.w.2\">world</span><span class=\"folia-w\" id=\"example.p.1.s.1.w.3\">!</span>"));
+            assert!(html.contains("<span class=\"folia-span folia-chunk\" id=\"example.p.1.s.2.chunk.1\" title=\"chunk: np\"><span class=\"folia-w\" id=\"example.p.1.s.2.w.3\">an</span> <span class=\"folia-w\" id=\"example.p.1.s.2.w.4\" title=\"part-of-speech: noun\n  number: singular\">example</span></span> "));
+            assert!(html.contains("<span class=\"folia-w\" id=\"example.p.1.s.2.w.5\">&amp;</span>"));
+        },
+        Err(err) => {
+            assert!(false, format!("Instantiation failed with error: {}",err));
+        }
+    }
+}
+
+#[test]
+fn test023b_html_correction() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<FoLiA xmlns="http://ilk.uvt.nl/folia" version="2.0" xml:id="corr">
+  <metadata>
+      <annotations>
+          <token-annotation/>
+          <sentence-annotation/>
+          <correction-annotation set="corrections"/>
+      </annotations>
+  </metadata>
+  <text xml:id="corr.text">
+      <s xml:id="corr.s.1">
+         <w xml:id="corr.s.1.w.1"><t>I</t></w>
+         <w xml:id="corr.s.1.w.2"><t>see</t></w>
+         <correction xml:id="corr.s.1.c.1" class="spelling">
+            <new><w xml:id="corr.s.1.w.3"><t>the</t></w></new>
+            <original><w xml:id="corr.s.1.w.3a"><t>teh</t></w></original>
+         </correction>
+         <w xml:id="corr.s.1.w.4"><t>house</t></w>
+      </s>
+  </text>
+</FoLiA>"#;
+    let doc = Document::from_str(xml, DocumentProperties::default()).expect("parsing");
+    let html = doc.to_html(0, &ExportParameters::default()).expect("html export");
+    assert!(html.contains("<span class=\"folia-w\" id=\"corr.s.1.w.2\">see</span> <span class=\"folia-correction\" id=\"corr.s.1.c.1\" title=\"correction: spelling\"><del class=\"folia-original\"><span class=\"folia-w\" id=\"corr.s.1.w.3a\">teh</span></del><ins class=\"folia-new\"><span class=\"folia-w\" id=\"corr.s.1.w.3\">the</span></ins></span> <span class=\"folia-w\" id=\"corr.s.1.w.4\">house</span>"));
+}
+
+#[test]
+fn test024a_canonical_xml() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<FoLiA xmlns="http://ilk.uvt.nl/folia" version="2.0" xml:id="canon">
+  <metadata type="native">
+      <annotations>
+          <token-annotation/>
+          <sentence-annotation/>
+          <pos-annotation set="pos"/>
+          <lemma-annotation set="lemma"/>
+      </annotations>
+      <meta id="title">Canonical</meta>
+      <meta id="author">Someone</meta>
+      <meta id="language">en</meta>
+      <meta id="genre">test</meta>
+  </metadata>
+  <text xml:id="canon.text">
+      <s xml:id="canon.s.1">
+         <w xml:id="canon.s.1.w.1" class="WORD"><t>Hello</t><pos class="INTJ"/><lemma class="hello"/></w>
+         <w xml:id="canon.s.1.w.2" space="no"><t>big   
+ world</t></w>
+      </s>
+  </text>
+</FoLiA>"#;
+    let doc = Document::from_str(xml, DocumentProperties::default()).expect("parsing");
+    let canonical = doc.xml_canonical(0).expect("serialising");
+    assert_eq!(canonical, doc.xml_canonical(0).expect("serialising"));
+    let canonical = String::from_utf8(canonical).expect("utf-8");
+    let author = canonical.find("<meta id=\"author\">").expect("author");
+    let genre = canonical.find("<meta id=\"genre\">").expect("genre");
+    let language = canonical.find("<meta id=\"language\">").expect("language");
+    let title = canonical.find("<meta id=\"title\">").expect("title");
+    assert!(author < genre && genre < language && language < title);
+    assert!(canonical.find("<lemma-annotation").unwrap() < canonical.find("<pos-annotation").unwrap());
+    assert!(canonical.contains("<w xml:id=\"canon.s.1.w.1\" class=\"WORD\">"));
+    assert!(canonical.contains("<w xml:id=\"canon.s.1.w.2\" space=\"no\">"));
+    assert!(canonical.contains("<t>big world</t>"));
+    //a reparsed document yields the very same bytes
+    let reparsed = Document::from_str(&canonical, DocumentProperties::default()).expect("reparsing");
+    assert_eq!(String::from_utf8(reparsed.xml_canonical(0).expect("serialising")).expect("utf-8"), canonical);
+}
+
+#[test]
+fn test025a_extract() {
+    let doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let key = doc.get_element_key_by_id("example.p.1.s.1").expect("sentence");
+    let subdoc = doc.extract(key).expect("extraction");
+    assert_eq!(subdoc.id(), "example");
+    assert!(subdoc.get_element_by_id("example.p.1.s.1.w.3").is_some());
+    assert!(subdoc.get_element_by_id("example.p.1.s.2").is_none());
+    assert!(subdoc.get_element_by_id("example.p.1").is_none());
+    let annotationtypes: Vec<AnnotationType> = subdoc.declarations().filter_map(|d| d.as_ref().map(|d| d.annotationtype)).collect();
+    assert!(annotationtypes.contains(&AnnotationType::SENTENCE));
+    assert!(!annotationtypes.contains(&AnnotationType::POS));
+    assert!(!annotationtypes.contains(&AnnotationType::PARAGRAPH));
+    assert!(subdoc.get_processor_by_id("p1").is_some());
+    assert!(subdoc.get_processor_by_id("p2").is_none());
+    assert_eq!(subdoc.metadata.data.get("language").map(|s| s.as_str()), Some("eng"));
+    let body = subdoc.get_element(0).expect("body");
+    assert_eq!(body.id(), Some("example.text"));
+    assert_eq!(body.text(&TextParameters::default()).expect("text"), "Hello world!");
+}
+
+#[test]
+fn test025b_extract_spans() {
+    let doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let key = doc.get_element_key_by_id("example.p.1.s.2").expect("sentence");
+    let subdoc = doc.extract(key).expect("extraction");
+    let chunk = subdoc.get_element_by_id("example.p.1.s.2.chunk.1").expect("chunk");
+    assert_eq!(chunk.set(), Some("shallowsyntaxset"));
+    assert_eq!(chunk.class(), Some("np"));
+    let pos = subdoc.get_element_by_id("example.p.1.s.2.w.4").expect("word").get_annotation(AnnotationType::POS, Cmp::Any, Recursion::No).map(|pos| pos.class().map(|c| c.to_string())).expect("pos");
+    assert_eq!(pos.as_deref(), Some("noun"));
+    assert!(subdoc.get_processor_by_id("p2").is_some());
+    //the extracted document serialises and parses again
+    let xml = subdoc.xml(0, 4).expect("serialisation");
+    let reparsed = Document::from_str(str::from_utf8(&xml).expect("utf-8"), DocumentProperties::default()).expect("reparsing");
+    assert!(reparsed.get_element_by_id("example.p.1.s.2.chunk.1").is_some());
+}
+
+#[test]
+fn test026a_merge() {
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let key = doc.get_element_key_by_id("example.p.1.s.1").expect("sentence");
+    let mut subdoc = doc.extract(key).expect("extraction");
+    //annotate the extracted sentence
+    let word = subdoc.get_element_key_by_id("example.p.1.s.1.w.1").expect("word");
+    subdoc.annotate(word, ElementData::new(ElementType::PosAnnotation).with_attrib(Attribute::Set("adhoc".to_string())).with_attrib(Attribute::Class("interjection".to_string()))).expect("pos");
+    subdoc.annotate_span(ElementData::new(ElementType::Chunk).with_attrib(Attribute::Set("shallowsyntaxset".to_string())).with_attrib(Attribute::Class("np".to_string())).with_span(&["example.p.1.s.1.w.1", "example.p.1.s.1.w.2"])).expect("chunk");
+    let report = doc.merge(&subdoc, 0).expect("merge");
+    assert!(report.is_clean());
+    assert_eq!(report.added, 2);
+    let pos = doc.get_element_by_id("example.p.1.s.1.w.1").expect("word").get_annotation(AnnotationType::POS, Cmp::Is("adhoc".to_string()), Recursion::No).map(|pos| pos.class().map(|c| c.to_string())).expect("pos");
+    assert_eq!(pos.as_deref(), Some("interjection"));
+    let chunks: Vec<String> = doc.get_element_by_id("example.p.1.s.1").expect("sentence").get_annotations(AnnotationType::CHUNKING, Cmp::Any, Recursion::Always).filter_map(|chunk| chunk.class().map(|c| c.to_string())).collect();
+    assert_eq!(chunks, vec!["np".to_string()]);
+    //merging once more adds nothing
+    let report = doc.merge(&subdoc, 0).expect("merge");
+    assert!(report.is_clean());
+    assert_eq!(report.added, 0);
+}
+
+#[test]
+fn test026b_merge_conflicts() {
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let other = Document::from_str(r#"<?xml version="1.0" encoding="utf-8"?>
+<FoLiA xmlns="http://ilk.uvt.nl/folia" version="2.0" xml:id="other">
+  <metadata type="native">
+      <annotations>
+          <token-annotation set="https://raw.githubusercontent.com/LanguageMachines/uctodata/master/setdefinitions/tokconfig-eng.foliaset.ttl"/>
+          <text-annotation/>
+          <sentence-annotation/>
+          <paragraph-annotation/>
+          <pos-annotation set="adhoc">
+             <annotator processor="p1" />
+          </pos-annotation>
+      </annotations>
+      <provenance>
+         <processor xml:id="p1" name="someone else" type="auto" />
+      </provenance>
+      <meta id="language">nld</meta>
+  </metadata>
+  <text xml:id="example.text">
+    <s xml:id="example.p.1.s.2">
+       <w xml:id="example.p.1.s.2.w.4" class="WORD"><t>examples</t><pos class="verb"/></w>
+    </s>
+    <p xml:id="example.p.1.s.1">
+       <s xml:id="other.s.1"><w xml:id="other.w.1"><t>new</t></w></s>
+    </p>
+  </text>
+</FoLiA>"#, DocumentProperties::default()).expect("parsing");
+    let report = doc.merge(&other, 0).expect("merge");
+    assert_eq!(report.added, 1);
+    assert!(report.conflicts.contains(&MergeConflict::Processor { id: "p1".to_string() }));
+    assert!(report.conflicts.contains(&MergeConflict::Metadata { key: "language".to_string() }));
+    assert!(report.conflicts.contains(&MergeConflict::Id { id: "example.p.1.s.1".to_string(), new_id: "example.p.1.s.1.1".to_string() }));
+    assert!(report.conflicts.contains(&MergeConflict::Text { element: Some("example.p.1.s.2.w.4".to_string()), ours: "example".to_string(), theirs: "examples".to_string() }));
+    assert!(report.conflicts.contains(&MergeConflict::Class { element: Some("example.p.1.s.2.w.4".to_string()), elementtype: ElementType::PosAnnotation, set: Some("adhoc".to_string()), ours: Some("noun".to_string()), theirs: Some("verb".to_string()) }));
+    assert_eq!(report.conflicts.len(), 5);
+    //our information is retained
+    assert_eq!(doc.get_element_by_id("example.p.1.s.2.w.4").expect("word").text(&TextParameters::default()).expect("text"), "example");
+    assert_eq!(doc.get_element_by_id("example.p.1.s.1").expect("sentence").elementtype(), ElementType::Sentence);
+    assert_eq!(doc.get_element_by_id("example.p.1.s.1.1").expect("paragraph").elementtype(), ElementType::Paragraph);
+    assert!(doc.get_element_by_id("other.w.1").is_some());
+}
+
+#[test]
+fn test027a_diff() {
+    let example = str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example");
+    let old = Document::from_str(example, DocumentProperties::default()).expect("parsing");
+    assert!(old.diff(&old).expect("diff").is_empty());
+    let modified = example.replace("<t>Hello</t>", "<t>Hi</t>")
+                          .replace("<pos class=\"noun\">", "<pos class=\"verb\">")
+                          .replace("<meta id=\"language\">eng</meta>", "<meta id=\"language\">nld</meta>")
+                          .replace("<w xml:id=\"example.p.1.s.2.w.2\" class=\"WORD\">", "<w xml:id=\"example.p.1.s.2.w.2\" class=\"PUNCTUATION\">")
+                          .replace("<chunk xml:id=\"example.p.1.s.2.chunk.1\" class=\"np\">", "<chunk xml:id=\"example.p.1.s.2.chunk.1\" class=\"np\"><wref id=\"example.p.1.s.2.w.2\" />")
+                          .replace("<w xml:id=\"example.p.1.s.1.w.3\" class=\"PUNCTUATION\">\n            <t>!</t>\n         </w>", "<w xml:id=\"example.p.1.s.1.w.4\" class=\"PUNCTUATION\">\n            <t>!</t>\n         </w>");
+    let new = Document::from_str(&modified, DocumentProperties::default()).expect("parsing");
+    let diff = old.diff(&new).expect("diff");
+    assert!(diff.changes.contains(&Change::MetadataChanged { key: "language".to_string(), old: Some("eng".to_string()), new: Some("nld".to_string()) }));
+    assert!(diff.changes.contains(&Change::TextChanged { id: "example.p.1.s.1.w.1".to_string(), textclass: None, old: "Hello".to_string(), new: "Hi".to_string() }));
+    assert!(diff.changes.contains(&Change::ClassChanged { id: "example.p.1.s.2.w.4".to_string(), elementtype: ElementType::PosAnnotation, set: Some("adhoc".to_string()), old: Some("noun".to_string()), new: Some("verb".to_string()) }));
+    assert!(diff.changes.contains(&Change::ClassChanged { id: "example.p.1.s.2.w.2".to_string(), elementtype: ElementType::Word, set: Some("https://raw.githubusercontent.com/LanguageMachines/uctodata/master/setdefinitions/tokconfig-eng.foliaset.ttl".to_string()), old: Some("WORD".to_string()), new: Some("PUNCTUATION".to_string()) }));
+    assert!(diff.changes.contains(&Change::SpanChanged { id: "example.p.1.s.2.chunk.1".to_string(), elementtype: ElementType::Chunk, old: vec!["example.p.1.s.2.w.3".to_string(), "example.p.1.s.2.w.4".to_string()], new: vec!["example.p.1.s.2.w.2".to_string(), "example.p.1.s.2.w.3".to_string(), "example.p.1.s.2.w.4".to_string()] }));
+    assert!(diff.changes.contains(&Change::Removed { id: "example.p.1.s.1.w.3".to_string(), elementtype: ElementType::Word }));
+    assert!(diff.changes.contains(&Change::Added { id: "example.p.1.s.1.w.4".to_string(), elementtype: ElementType::Word }));
+    assert_eq!(diff.changes.len(), 7, "{}", diff);
+    assert!(format!("{}", diff).contains("~ example.p.1.s.1.w.1: text \"Hello\" -> \"Hi\"\n"));
+}
+
+const EXAMPLE_AGREEMENT: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<FoLiA xmlns="http://ilk.uvt.nl/folia" version="2.0" xml:id="agreement">
+  <metadata>
+      <annotations>
+          <token-annotation/>
+          <sentence-annotation/>
+          <pos-annotation set="pos"/>
+          <entity-annotation set="entities">
+             <annotator processor="p1" />
+             <annotator processor="p2" />
+          </entity-annotation>
+      </annotations>
+      <provenance>
+         <processor xml:id="p1" name="annotator1" type="manual" />
+         <processor xml:id="p2" name="annotator2" type="manual" />
+      </provenance>
+  </metadata>
+  <text xml:id="agreement.text">
+    <s xml:id="agreement.s.1">
+       <w xml:id="agreement.s.1.w.1"><t>John</t><pos class="N"/></w>
+       <w xml:id="agreement.s.1.w.2"><t>Smith</t><pos class="V"/></w>
+       <w xml:id="agreement.s.1.w.3"><t>New</t><pos class="N"/></w>
+       <w xml:id="agreement.s.1.w.4"><t>York</t><pos class="A"/></w>
+       <entities>
+          <entity class="per" processor="p1"><wref id="agreement.s.1.w.1"/><wref id="agreement.s.1.w.2"/></entity>
+          <entity class="per" processor="p2"><wref id="agreement.s.1.w.1"/><wref id="agreement.s.1.w.2"/></entity>
+          <entity class="loc" processor="p1"><wref id="agreement.s.1.w.4"/></entity>
+          <entity class="loc" processor="p2"><wref id="agreement.s.1.w.3"/><wref id="agreement.s.1.w.4"/></entity>
+       </entities>
+    </s>
+  </text>
+</FoLiA>"#;
+
+#[test]
+fn test028a_agreement_documents() {
+    let doc1 = Document::from_str(EXAMPLE_AGREEMENT, DocumentProperties::default()).expect("parsing");
+    let doc2 = Document::from_str(&EXAMPLE_AGREEMENT.replace("<t>New</t><pos class=\"N\"/>", "<t>New</t><pos class=\"V\"/>"), DocumentProperties::default()).expect("parsing");
+    let report = doc1.agreement(&doc2);
+    let pos = report.token(AnnotationType::POS, Some("pos")).expect("pos agreement");
+    assert_eq!(pos.items, 4);
+    assert!((pos.observed - 0.75).abs() < 1e-9);
+    assert!((pos.kappa - 0.4375 / 0.6875).abs() < 1e-9);
+    assert!((pos.alpha - 2.0 / 3.0).abs() < 1e-9);
+    assert!((pos.f1 - 0.75).abs() < 1e-9);
+    let identical = doc1.agreement(&doc1);
+    let pos = identical.token(AnnotationType::POS, Some("pos")).expect("pos agreement");
+    assert_eq!(pos.kappa, 1.0);
+    assert_eq!(pos.alpha, 1.0);
+    let entities = identical.span(AnnotationType::ENTITY, Some("entities")).expect("entity agreement");
+    assert_eq!(entities.exact, 4);
+    assert_eq!(entities.exact_f1, 1.0);
+}
+
+#[test]
+fn test028b_agreement_processors() {
+    let doc = Document::from_str(EXAMPLE_AGREEMENT, DocumentProperties::default()).expect("parsing");
+    let report = doc.processor_agreement("p1", "p2").expect("agreement");
+    assert!(report.tokens.is_empty());
+    let entities = report.span(AnnotationType::ENTITY, Some("entities")).expect("entity agreement");
+    assert_eq!((entities.count1, entities.count2), (2, 2));
+    assert_eq!(entities.exact, 1);
+    assert_eq!(entities.partial, 2);
+    assert!((entities.exact_f1 - 0.5).abs() < 1e-9);
+    assert!((entities.partial_f1 - 1.0).abs() < 1e-9);
+    assert!(format!("{}", report).contains("entities): spans=2/2 exact=1 partial=2 exact_f1=0.500 partial_f1=1.000"));
+}
+
+#[test]
+fn test028c_agreement_duplicates() {
+    //a second annotation of the same type and set on an element is reported rather than silently replacing the first
+    let doc1 = Document::from_str(EXAMPLE_AGREEMENT, DocumentProperties::default()).expect("parsing");
+    let doc2 = Document::from_str(&EXAMPLE_AGREEMENT.replace("<t>New</t><pos class=\"N\"/>", "<t>New</t><pos class=\"N\"/><pos class=\"V\"/>"), DocumentProperties::default()).expect("parsing");
+    let report = doc1.agreement(&doc2);
+    let pos = report.token(AnnotationType::POS, Some("pos")).expect("pos agreement");
+    assert_eq!(pos.items, 4);
+    assert_eq!(pos.duplicates, 1);
+    assert_eq!(pos.observed, 1.0);
+    assert!(format!("{}", report).contains("f1=1.000 duplicates=1\n"));
+    assert_eq!(doc1.agreement(&doc1).token(AnnotationType::POS, Some("pos")).expect("pos agreement").duplicates, 0);
+}
+
+#[test]
+fn test029a_evaluate() {
+    let gold = Document::from_str(EXAMPLE_AGREEMENT, DocumentProperties::default()).expect("parsing");
+    let system = Document::from_str(&EXAMPLE_AGREEMENT.replace("<t>New</t><pos class=\"N\"/>", "<t>New</t><pos class=\"V\"/>")
+                                                      .replace("<entity class=\"loc\" processor=\"p2\"><wref id=\"agreement.s.1.w.3\"/><wref id=\"agreement.s.1.w.4\"/></entity>", ""), DocumentProperties::default()).expect("parsing");
+    let report = system.evaluate(&gold).expect("evaluation");
+    let pos = report.get(AnnotationType::POS, Some("pos")).expect("pos evaluation");
+    assert!(!pos.span);
+    assert_eq!((pos.scores.truepositives, pos.scores.falsepositives, pos.scores.falsenegatives), (3, 1, 1));
+    assert!((pos.scores.f1 - 0.75).abs() < 1e-9);
+    let noun = pos.classes.get("N").expect("class N");
+    assert_eq!((noun.precision, noun.recall), (1.0, 0.5));
+    let verb = pos.classes.get("V").expect("class V");
+    assert_eq!((verb.precision, verb.recall), (0.5, 1.0));
+    assert_eq!(pos.confusion.get("N").and_then(|row| row.get("V")), Some(&1));
+    assert_eq!(pos.confusion.get("N").and_then(|row| row.get("N")), Some(&1));
+    let entities = report.get(AnnotationType::ENTITY, Some("entities")).expect("entity evaluation");
+    assert!(entities.span);
+    assert_eq!((entities.scores.precision, entities.scores.recall), (1.0, 0.75));
+    assert_eq!(entities.classes.get("loc").map(|scores| scores.falsenegatives), Some(1));
+    let json = report.json(false).expect("json");
+    assert!(json.contains("\"confusion\":{\"A\":{\"A\":1},\"N\":{\"N\":1,\"V\":1},\"V\":{\"V\":1}}"));
+}
+
+#[test]
+fn test029b_evaluate_tokenisation() {
+    let gold = Document::from_str(EXAMPLE_AGREEMENT, DocumentProperties::default()).expect("parsing");
+    let system = Document::from_str(&EXAMPLE_AGREEMENT.replace("<w xml:id=\"agreement.s.1.w.4\"><t>York</t><pos class=\"A\"/></w>", "").replace("<wref id=\"agreement.s.1.w.4\"/>", ""), DocumentProperties::default()).expect("parsing");
+    assert!(matches!(system.evaluate(&gold), Err(FoliaError::ValidationError(_))));
+}
+
+#[test]
+fn test030a_generate_ids() {
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let sentence = doc.get_element_key_by_id("example.p.1.s.1").expect("sentence");
+    let word = doc.annotate(sentence, ElementData::new(ElementType::Word).with_text("again".to_string())).expect("adding word");
+    assert_eq!(doc.get_element(word).expect("word").id(), Some("example.p.1.s.1.w.4"));
+    assert_eq!(doc.get_element_key_by_id("example.p.1.s.1.w.4"), Some(word));
+    //inline annotations do not get an ID
+    let pos = doc.annotate(word, ElementData::new(ElementType::PosAnnotation).with_attrib(Attribute::Set("adhoc".to_string())).with_attrib(Attribute::Class("adverb".to_string()))).expect("adding pos");
+    assert_eq!(doc.get_element(pos).expect("pos").id(), None);
+    //children that are added along with an element get IDs too
+    let paragraph = doc.get_element_key_by_id("example.p.1").expect("paragraph");
+    let sentence = doc.annotate(paragraph, ElementData::new(ElementType::Sentence)
+                                .with_children(vec![
+                                    DataType::AddElement(ElementData::new(ElementType::Word).with_text("Bye".to_string())),
+                                    DataType::AddElement(ElementData::new(ElementType::Word).with_text("!".to_string())),
+                                ])).expect("adding sentence");
+    assert_eq!(doc.get_element(sentence).expect("sentence").id(), Some("example.p.1.s.3"));
+    assert!(doc.get_element_key_by_id("example.p.1.s.3.w.2").is_some());
+}
+
+#[test]
+fn test030b_renumber_ids() {
+    let mut doc = Document::new("example", DocumentProperties::default()).expect("instantiation");
+    let sentence = doc.annotate(0, ElementData::new(ElementType::Sentence).with_attrib(Attribute::Id("s".to_string()))).expect("adding sentence");
+    doc.annotate(sentence, ElementData::new(ElementType::Word).with_attrib(Attribute::Id("hello".to_string())).with_text("hello".to_string())).expect("adding word 1");
+    doc.annotate(sentence, ElementData::new(ElementType::Word).with_attrib(Attribute::Id("world".to_string())).with_text("world".to_string())).expect("adding word 2");
+    doc.annotate(sentence, ElementData::new(ElementType::Entity).with_attrib(Attribute::Set("adhoc".to_string())).with_attrib(Attribute::Class("greeting".to_string())).with_span(&[ "hello", "world" ])).expect("adding entity");
+    let idmap = doc.renumber_ids().expect("renumbering");
+    assert_eq!(idmap.get("world").map(|s| s.as_str()), Some("example.s.1.w.2"));
+    assert!(doc.get_element_key_by_id("hello").is_none());
+    let word = doc.get_element_key_by_id("example.s.1.w.1").expect("renumbered word");
+    assert_eq!(doc.get_element(word).expect("word").text(&TextParameters::default()).expect("text"), "hello");
+    let xml = str::from_utf8(&doc.xml(0,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(xml.contains("<wref id=\"example.s.1.w.1\""));
+    assert!(!xml.contains("\"hello\""));
+}
+
+#[test]
+fn test031a_clone_subtree() {
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let sentence = doc.get_element_key_by_id("example.p.1.s.2").expect("sentence");
+    let paragraph = doc.get_element_key_by_id("example.p.1").expect("paragraph");
+    let copy = doc.clone_subtree(sentence, paragraph).expect("cloning");
+    assert_ne!(copy, sentence);
+    let element = doc.get_element(copy).expect("copy");
+    assert_eq!(element.id(), Some("example.p.1.s.2.1"));
+    assert_eq!(element.get_parent().and_then(|p| p.id().map(|s| s.to_string())), Some("example.p.1".to_string()));
+    assert_eq!(element.text(&TextParameters::default()).expect("text"), doc.get_element(sentence).expect("sentence").text(&TextParameters::default()).expect("text"));
+    //the chunk in the copy refers to the copied words
+    let chunk = doc.get_element_key_by_id("example.p.1.s.2.chunk.1.1").expect("copied chunk");
+    let xml = str::from_utf8(&doc.xml(chunk,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(xml.contains("<wref id=\"example.p.1.s.2.w.3.1\""));
+    assert!(xml.contains("<wref id=\"example.p.1.s.2.w.4.1\""));
+    //the original is untouched
+    let chunk = doc.get_element_key_by_id("example.p.1.s.2.chunk.1").expect("chunk");
+    let xml = str::from_utf8(&doc.xml(chunk,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(xml.contains("<wref id=\"example.p.1.s.2.w.3\""));
+}
+
+#[test]
+fn test031b_import_subtree() {
+    let source = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let mut doc = Document::new("corpus", DocumentProperties::default()).expect("instantiation");
+    let sentence = source.get_element_key_by_id("example.p.1.s.2").expect("sentence");
+    let key = doc.import_subtree(&source, sentence, 0).expect("importing");
+    assert_eq!(doc.get_element(key).expect("sentence").id(), Some("example.p.1.s.2"));
+    assert!(doc.get_processor_by_id("p2").is_some());
+    let annotationtypes: Vec<AnnotationType> = doc.declarations().filter_map(|d| d.as_ref().map(|d| d.annotationtype)).collect();
+    assert!(annotationtypes.contains(&AnnotationType::POS));
+    assert!(annotationtypes.contains(&AnnotationType::CHUNKING));
+    let word = doc.get_element_by_id("example.p.1.s.2.w.4").expect("word");
+    let pos = word.get_annotation(AnnotationType::POS, Cmp::Any, Recursion::No).expect("pos");
+    assert_eq!(pos.class(), Some("noun"));
+    assert_eq!(pos.set(), Some("adhoc"));
+    //importing again yields new IDs
+    let key2 = doc.import_subtree(&source, sentence, 0).expect("importing again");
+    assert_eq!(doc.get_element(key2).expect("sentence").id(), Some("example.p.1.s.2.1"));
+    assert_eq!(doc.get_element(key2).expect("sentence").text(&TextParameters::default()).expect("text"), "This is an example & a test.");
+}
+
+#[test]
+fn test032a_edit_annotations() {
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let word = doc.get_element_key_by_id("example.p.1.s.2.w.4").expect("word");
+    let pos = doc.get_element(word).expect("word").get_annotation(AnnotationType::POS, Cmp::Any, Recursion::No).expect("pos").key().expect("key");
+    doc.set_class(pos, Some("verb")).expect("setting class");
+    assert_eq!(doc.get_element(pos).expect("pos").class(), Some("verb"));
+    doc.set_set(pos, Some("otherset")).expect("setting set");
+    assert_eq!(doc.get_element(pos).expect("pos").set(), Some("otherset"));
+    assert_eq!(doc.get_element(pos).expect("pos").class(), Some("verb"));
+    assert!(doc.get_declaration_by_id(&Declaration::index_id(AnnotationType::POS, &Some("otherset"))).is_some());
+    doc.set_processor(pos, "p1").expect("setting processor");
+    assert_eq!(doc.get_element(pos).expect("pos").processor(), Some("p1"));
+    assert!(doc.set_processor(pos, "nonexistant").is_err());
+    doc.set_confidence(pos, Some(0.5)).expect("setting confidence");
+    assert_eq!(doc.get_element(pos).expect("pos").attrib(AttribType::CONFIDENCE), Some(&Attribute::Confidence(0.5)));
+    assert!(doc.set_confidence(pos, Some(2.0)).is_err());
+
+    doc.set_text(word, "sample", None).expect("setting text");
+    assert_eq!(doc.get_element(word).expect("word").text(&TextParameters::default()).expect("text"), "sample");
+    let textcontent = doc.set_text(word, "example", Some("original")).expect("setting original text");
+    assert_eq!(doc.get_element(textcontent).expect("text content").class(), Some("original"));
+    let xml = str::from_utf8(&doc.xml(word,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(xml.contains("<t>sample</t>"));
+    assert!(xml.contains("<t class=\"original\">example</t>"));
+
+    let newpos = doc.replace_annotation(pos, ElementData::new(ElementType::PosAnnotation).with_attrib(Attribute::Set("adhoc".to_string())).with_attrib(Attribute::Class("adjective".to_string()))).expect("replacing");
+    assert!(doc.get_element(pos).is_none());
+    let element = doc.get_element(word).expect("word");
+    assert_eq!(element.get_annotation(AnnotationType::POS, Cmp::Any, Recursion::No).and_then(|p| p.key()), Some(newpos));
+    assert_eq!(doc.get_element(newpos).expect("pos").class(), Some("adjective"));
+}
+
+#[test]
+fn test032b_edit_autodeclare() {
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default().with_autodeclare(false)).expect("parsing");
+    let word = doc.get_element_key_by_id("example.p.1.s.2.w.4").expect("word");
+    let pos = doc.get_element(word).expect("word").get_annotation(AnnotationType::POS, Cmp::Any, Recursion::No).expect("pos").key().expect("key");
+    assert!(matches!(doc.set_set(pos, Some("undeclared")), Err(FoliaError::EncodeError(_))));
+    assert_eq!(doc.get_element(pos).expect("pos").set(), Some("adhoc"));
+    doc.set_class(pos, Some("verb")).expect("setting class");
+    assert_eq!(doc.get_element(pos).expect("pos").class(), Some("verb"));
+}
+
+#[test]
+fn test032c_remove_spanned_element() {
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let sentence = doc.get_element_key_by_id("example.p.1.s.1").expect("sentence");
+    let entity = doc.annotate(sentence, ElementData::new(ElementType::Entity).with_attrib(Attribute::Set("adhoc".to_string())).with_attrib(Attribute::Class("greeting".to_string()))
+                 .with_span(&[ "example.p.1.s.1.w.1", "example.p.1.s.1.w.2" ])).expect("adding entity");
+    //removing a spanned word removes the word reference to it
+    let word = doc.get_element_key_by_id("example.p.1.s.1.w.1").expect("word");
+    doc.remove_element(word).expect("removing word");
+    let xml = str::from_utf8(&doc.xml(0,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(!xml.contains("<wref id=\"example.p.1.s.1.w.1\""));
+    assert!(xml.contains("<wref id=\"example.p.1.s.1.w.2\""));
+    let reparsed = Document::from_str(&xml, DocumentProperties::default()).expect("reparsing");
+    assert_eq!(reparsed.xml(0,0).expect("serialisation"), doc.xml(0,0).expect("serialisation"));
+    //removing the last spanned word removes the span, and the layer it leaves empty
+    let word = doc.get_element_key_by_id("example.p.1.s.1.w.2").expect("word");
+    doc.remove_element(word).expect("removing word");
+    assert!(doc.get_element(entity).is_none());
+    let xml = str::from_utf8(&doc.xml(0,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(!xml.contains("<entities"));
+    Document::from_str(&xml, DocumentProperties::default()).expect("reparsing");
+    //removing a span removes the span references from the words it covered
+    let chunk = doc.get_element_key_by_id("example.p.1.s.2.chunk.1").expect("chunk");
+    doc.remove_element(chunk).expect("removing chunk");
+    let word = doc.get_element_by_id("example.p.1.s.2.w.3").expect("word");
+    let word = word.elementdata();
+    assert!(!(0..word.len()).filter_map(|index| word.get_data_at(index)).any(|item| matches!(item, DataType::SpanReference(_))));
+}
+
+#[test]
+fn test033a_for_each_mut() {
+    let mut doc = Document::from_str(EXAMPLE_AGREEMENT, DocumentProperties::default()).expect("parsing");
+    let query = Query::select().element(Cmp::Is(ElementType::PosAnnotation)).set(Cmp::Is("pos".to_string())).class(Cmp::Is("N".to_string()));
+    let selector = Selector::from_query(&doc, &query).expect("selector");
+    assert_eq!(doc.select_keys(selector.clone(), Recursion::Always).len(), 2);
+    let count = doc.for_each_mut(selector, Recursion::Always, |doc, key| doc.set_class(key, Some("NOUN"))).expect("editing");
+    assert_eq!(count, 2);
+    let classes: Vec<String> = doc.select(Selector::from_query(&doc, &Query::select().element(Cmp::Is(ElementType::PosAnnotation))).expect("selector"), Recursion::Always)
+                                  .map(|e| e.class().unwrap_or("").to_string()).collect();
+    assert_eq!(classes, vec!("NOUN", "V", "NOUN", "A"));
+}
+
+#[test]
+fn test034a_transaction_rollback() {
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let original = doc.xml(0,0).expect("serialisation");
+    let sentence = doc.get_element_key_by_id("example.p.1.s.1").expect("sentence");
+    doc.begin_transaction().expect("transaction");
+    assert!(doc.in_transaction());
+    doc.annotate(sentence, ElementData::new(ElementType::Entity).with_attrib(Attribute::Set("adhoc".to_string())).with_attrib(Attribute::Class("greeting".to_string()))
+                 .with_span(&[ "example.p.1.s.1.w.1", "example.p.1.s.1.w.2" ])).expect("adding entity");
+    let word = doc.get_element_key_by_id("example.p.1.s.1.w.3").expect("word");
+    doc.remove_element(word).expect("removing word");
+    doc.rollback().expect("rollback");
+    assert!(!doc.in_transaction());
+    assert_eq!(doc.xml(0,0).expect("serialisation"), original);
+    assert_eq!(doc.get_element_key_by_id("example.p.1.s.1.w.3"), Some(word));
+    assert!(doc.get_declaration_by_id(&Declaration::index_id(AnnotationType::ENTITY, &Some("adhoc"))).is_none());
+
+    //a failing transaction leaves the document untouched
+    let result = doc.transaction(|doc| {
+        doc.annotate(sentence, ElementData::new(ElementType::Word).with_attrib(Attribute::Id("new.word".to_string())).with_text("new".to_string()))?;
+        doc.annotate(sentence, ElementData::new(ElementType::Speech))
+    });
+    assert!(result.is_err());
+    assert!(doc.get_element_key_by_id("new.word").is_none());
+    assert_eq!(doc.xml(0,0).expect("serialisation"), original);
+}
+
+#[test]
+fn test034b_undo_redo() {
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let original = doc.xml(0,0).expect("serialisation");
+    let word = doc.get_element_key_by_id("example.p.1.s.2.w.4").expect("word");
+    doc.transaction(|doc| {
+        doc.annotate(word, ElementData::new(ElementType::LemmaAnnotation).with_attrib(Attribute::Set("lemmas".to_string())).with_attrib(Attribute::Class("example".to_string())))?;
+        doc.set_text(word, "sample", None)
+    }).expect("transaction");
+    let edited = doc.xml(0,0).expect("serialisation");
+    assert_ne!(edited, original);
+    assert!(doc.can_undo());
+    assert!(doc.undo().expect("undo"));
+    assert_eq!(doc.xml(0,0).expect("serialisation"), original);
+    assert!(!doc.can_undo());
+    assert!(doc.redo().expect("redo"));
+    assert_eq!(doc.xml(0,0).expect("serialisation"), edited);
+    assert!(!doc.redo().expect("redo"));
+    //edits outside of a transaction clear the history
+    doc.set_confidence(word, Some(0.5)).expect("confidence");
+    assert!(!doc.can_undo());
+}
+
+#[test]
+fn test035a_journal() {
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    doc.enable_journal();
+    let processor_key = doc.add_processor(Processor::new("annotator".to_string()).with_id("annotator.1".to_string())).expect("adding processor");
+    doc.activate_processor(processor_key);
+    let word = doc.get_element_key_by_id("example.p.1.s.2.w.3").expect("word");
+    doc.set_text(word, "a", None).expect("setting text");
+    let sentence = doc.get_element_key_by_id("example.p.1.s.1").expect("sentence");
+    doc.annotate(sentence, ElementData::new(ElementType::Word).with_text("!".to_string())).expect("adding word");
+    let journal = doc.journal().expect("journal");
+    assert_eq!(journal.entries.len(), 3);
+    assert_eq!(journal.entries[0].active_processor, None);
+    assert_eq!(journal.entries[1].edit, Edit::SetText {
+        element: ElementLocator { id: Some("example.p.1.s.2.w.3".to_string()), path: vec!() },
+        textclass: None,
+        before: Some("an".to_string()),
+        after: "a".to_string(),
+    });
+    assert_eq!(journal.entries[1].active_processor, Some("annotator.1".to_string()));
+    assert_eq!(journal.entries[2].ids, vec!("example.p.1.s.1".to_string(), "example.p.1.s.1.w.4".to_string()));
+    //nested edits (such as the declaration for the word) are not recorded separately
+    assert!(journal.entries.iter().all(|entry| !matches!(entry.edit, Edit::Declare { .. })));
+
+    //rolled back edits are removed from the journal
+    doc.begin_transaction().expect("transaction");
+    doc.remove_element(word).expect("removing word");
+    doc.rollback().expect("rollback");
+    assert_eq!(doc.journal().expect("journal").entries.len(), 3);
+}
+
+#[test]
+fn test035b_journal_replay() {
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let mut copy = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    doc.enable_journal();
+    let processor_key = doc.add_processor(Processor::new("annotator".to_string()).with_id("annotator.1".to_string())).expect("adding processor");
+    doc.activate_processor(processor_key);
+    let word = doc.get_element_key_by_id("example.p.1.s.2.w.4").expect("word");
+    doc.transaction(|doc| {
+        doc.annotate(word, ElementData::new(ElementType::LemmaAnnotation).with_attrib(Attribute::Set("lemmas".to_string())).with_attrib(Attribute::Class("example".to_string())))?;
+        doc.set_text(word, "sample", None)?;
+        Ok(())
+    }).expect("transaction");
+    doc.undo().expect("undo");
+    doc.redo().expect("redo");
+    let pos = doc.select_keys(Selector::elements().element(Cmp::Is(ElementType::PosAnnotation)), Recursion::Always);
+    doc.set_class(pos[0], Some("NOUN")).expect("setting class");
+    let word = doc.get_element_key_by_id("example.p.1.s.2.w.5").expect("word");
+    doc.remove_element(word).expect("removing word");
+
+    let json = doc.journal().expect("journal").json(false).expect("serialising journal");
+    let journal = Journal::from_json(&json).expect("parsing journal");
+    assert_eq!(&journal, doc.journal().expect("journal"));
+    copy.replay(&journal.entries).expect("replay");
+    assert_eq!(copy.xml(0,0).expect("serialisation"), doc.xml(0,0).expect("serialisation"));
+}
+
+#[test]
+fn test035c_journal_replay_active_processor() {
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let mut copy = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    doc.enable_journal();
+    let lemma = |class: &str| ElementData::new(ElementType::LemmaAnnotation).with_attrib(Attribute::Set("lemmas".to_string())).with_attrib(Attribute::Class(class.to_string()));
+    doc.annotate(doc.get_element_key_by_id("example.p.1.s.2.w.4").expect("word"), lemma("example")).expect("annotating");
+    let processor_key = doc.add_processor(Processor::new("annotator".to_string()).with_id("annotator.1".to_string())).expect("adding processor");
+    doc.activate_processor(processor_key);
+    doc.set_text(doc.get_element_key_by_id("example.p.1.s.2.w.3").expect("word"), "a", None).expect("setting text");
+    doc.active_processor = None;
+    doc.annotate(doc.get_element_key_by_id("example.p.1.s.2.w.2").expect("word"), lemma("be")).expect("annotating");
+
+    //edits made without an active processor are replayed without one, regardless of what is active in the copy
+    let p1 = copy.get_processor_key_by_id("p1").expect("processor");
+    copy.activate_processor(p1);
+    copy.replay(&doc.journal().expect("journal").entries).expect("replay");
+    assert_eq!(str::from_utf8(&copy.xml(0,0).expect("serialisation")).expect("utf-8"), str::from_utf8(&doc.xml(0,0).expect("serialisation")).expect("utf-8"));
+    assert_eq!(copy.active_processor, Some(p1));
+}
+
+#[test]
+fn test036a_provenance_queries() {
+    let mut doc = Document::from_str(EXAMPLE_AGREEMENT, DocumentProperties::default()).expect("parsing");
+    let p1 = doc.get_processor_key_by_id("p1").expect("processor");
+    let p2 = doc.get_processor_key_by_id("p2").expect("processor");
+    let sub = doc.add_subprocessor(p1, Processor::new("tokeniser".to_string()).with_id("p1.1".to_string()).with_version("0.1".to_string())).expect("subprocessor");
+    assert_eq!(doc.processor_tree(), vec!(
+        ProcessorTree { key: p1, children: vec!(ProcessorTree { key: sub, children: vec!() }) },
+        ProcessorTree { key: p2, children: vec!() },
+    ));
+    assert_eq!(doc.find_processors_by_name("annotator2"), vec!(p2));
+    assert_eq!(doc.find_processors_by_version("0.1"), vec!(sub));
+    assert_eq!(doc.find_processors_by_type(ProcessorType::Manual), vec!(p1, p2));
+    let contributions = doc.processor_contributions();
+    assert_eq!(contributions.len(), 3);
+    let entities = doc.get_declaration_key_by_id(&Declaration::index_id(AnnotationType::ENTITY, &Some("entities"))).expect("declaration");
+    assert_eq!(contributions[0], ProcessorContribution { key: p1, declarations: vec!(entities), annotations: 2 });
+    assert_eq!(contributions[1], ProcessorContribution { key: sub, declarations: vec!(), annotations: 0 });
+}
+
+#[test]
+fn test036b_remove_annotations_by_processor() {
+    let mut doc = Document::from_str(EXAMPLE_AGREEMENT, DocumentProperties::default()).expect("parsing");
+    let p1 = doc.get_processor_key_by_id("p1").expect("processor");
+    let p2 = doc.get_processor_key_by_id("p2").expect("processor");
+    assert_eq!(doc.remove_annotations_by_processor(p1, true).expect("removal"), 2);
+    let xml = str::from_utf8(&doc.xml(0,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(!xml.contains("<entity class=\"per\" processor=\"p1\""));
+    assert!(xml.contains("<entity class=\"per\" processor=\"p2\""));
+    assert_eq!(doc.remove_annotations_by_processor(p2, false).expect("removal"), 2);
+    //the layer is left empty and is removed
+    let xml = str::from_utf8(&doc.xml(0,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(!xml.contains("<entities"));
+    assert!(doc.get_processor_by_id("p2").is_some());
+}
+
+#[test]
+fn test036c_remove_tokens_by_processor() {
+    //the tokeniser produced the words, a separate processor the structure and the chunk over the words
+    let xml = str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example")
+        .replacen("<annotator processor=\"p1\" />", "<annotator processor=\"p3\" />", 1)
+        .replace("<processor xml:id=\"p2\" name=\"proycon\" type=\"manual\" />", "<processor xml:id=\"p2\" name=\"proycon\" type=\"manual\" />\n         <processor xml:id=\"p3\" name=\"ucto\" type=\"auto\" />");
+    let mut doc = Document::from_str(&xml, DocumentProperties::default()).expect("parsing");
+    let tokeniser = doc.get_processor_key_by_id("p3").expect("processor");
+    assert_eq!(doc.remove_annotations_by_processor(tokeniser, false).expect("removal"), 11);
+    let xml = str::from_utf8(&doc.xml(0,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(!xml.contains("<w "));
+    assert!(!xml.contains("<wref"));
+    assert!(!xml.contains("<chunking>"));
+    assert!(xml.contains("<s xml:id=\"example.p.1.s.2\""));
+    let reparsed = Document::from_str(&xml, DocumentProperties::default()).expect("reparsing");
+    assert_eq!(reparsed.xml(0,0).expect("serialisation"), doc.xml(0,0).expect("serialisation"));
+}
+
+#[test]
+fn test037a_processor_guard() {
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let word = doc.get_element_key_by_id("example.p.1.s.2.w.4").expect("word");
+    let (tool, component) = {
+        let mut tool = doc.begin_processor(Processor::new("tagger".to_string()).with_id("tagger.1".to_string())).expect("processor");
+        tool.set_document_version("2");
+        tool.annotate(word, ElementData::new(ElementType::LemmaAnnotation).with_attrib(Attribute::Set("lemmas".to_string())).with_attrib(Attribute::Class("example".to_string()))).expect("lemma");
+        let component = {
+            let component = tool.begin_subprocessor(Processor::new("lemmatiser".to_string()).with_id("tagger.1.1".to_string())).expect("subprocessor");
+            assert_eq!(component.active_processor, Some(component.key()));
+            component.key()
+        };
+        assert_eq!(tool.active_processor, Some(tool.key()));
+        (tool.key(), component)
+    };
+    assert_eq!(doc.active_processor, None);
+    let processor = doc.get_processor(tool).expect("processor");
+    assert!(processor.begindatetime.is_some());
+    assert!(processor.enddatetime >= processor.begindatetime);
+    assert_eq!(processor.document_version, "2");
+    assert_eq!(processor.processors, vec!(component));
+    assert!(doc.get_processor(component).expect("subprocessor").enddatetime.is_some());
+    let xml = str::from_utf8(&doc.xml(word,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(xml.contains("processor=\"tagger.1\""));
+}
+
+#[test]
+fn test037b_processor_guard_undo() {
+    //recording the times of the processor must not discard the undo history
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    let word = doc.get_element_key_by_id("example.p.1.s.2.w.4").expect("word");
+    let tool = {
+        let mut tool = doc.begin_processor(Processor::new("tagger".to_string()).with_id("tagger.1".to_string())).expect("processor");
+        tool.transaction(|doc| {
+            doc.annotate(word, ElementData::new(ElementType::LemmaAnnotation).with_attrib(Attribute::Set("lemmas".to_string())).with_attrib(Attribute::Class("example".to_string())))
+        }).expect("transaction");
+        tool.key()
+    };
+    assert!(doc.get_processor(tool).expect("processor").enddatetime.is_some());
+    assert!(doc.can_undo());
+    assert!(doc.undo().expect("undo"));
+    assert!(doc.get_element(word).expect("word").get_annotation(AnnotationType::LEMMA, Cmp::Any, Recursion::No).is_none());
+    assert!(doc.redo().expect("redo"));
+    assert!(doc.get_element(word).expect("word").get_annotation(AnnotationType::LEMMA, Cmp::Any, Recursion::No).is_some());
+}
+
+const EXAMPLE_V1: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<FoLiA xmlns="http://ilk.uvt.nl/folia" xmlns:xlink="http://www.w3.org/1999/xlink" xml:id="v1" version="1.5.0">
+  <metadata type="native">
+    <annotations>
+      <token-annotation annotator="ucto" annotatortype="auto" set="tokconfig-nld"/>
+      <pos-annotation set="cgn" annotator="frog" annotatortype="auto"/>
+      <lemma-annotation set="lemmas"/>
+      <alignment-annotation set="alignments"/>
+    </annotations>
+  </metadata>
+  <text xml:id="v1.text">
+    <s xml:id="v1.s.1">
+      <w xml:id="v1.s.1.w.1"><t>Hallo</t><pos class="TSW"/><lemma class="hallo" annotator="proycon" annotatortype="manual"/></w>
+      <w xml:id="v1.s.1.w.2"><t>wereld</t><pos class="N" annotator="proycon" annotatortype="manual"/>
+        <alignment class="translation" xlink:href="en.xml" xlink:type="simple"><aref id="en.w.2" type="w" t="world"/></alignment>
+      </w>
+    </s>
+  </text>
+</FoLiA>"#;
+
+#[test]
+fn test038a_upgrade_v1() {
+    let doc = Document::from_str(EXAMPLE_V1, DocumentProperties::default()).expect("parsing");
+    assert_eq!(doc.version, FOLIAVERSION);
+    //old-style annotators become processors
+    let frog = doc.get_processor_by_id("proc.frog").expect("processor");
+    assert_eq!(frog.name, "frog");
+    assert_eq!(frog.processortype, ProcessorType::Auto);
+    let proycon = doc.get_processor_key_by_id("proc.proycon").expect("processor");
+    assert_eq!(doc.get_processor(proycon).expect("processor").processortype, ProcessorType::Manual);
+    let pos = doc.get_declaration_by_id(&Declaration::index_id(AnnotationType::POS, &Some("cgn"))).expect("declaration");
+    assert_eq!(pos.processors, vec!(frog.key.expect("key"), proycon));
+    let word = doc.get_element_by_id("v1.s.1.w.1").expect("word");
+    let pos = word.get_annotation(AnnotationType::POS, Cmp::Any, Recursion::No).expect("pos");
+    assert_eq!(pos.processor(), Some("proc.frog"));
+    let lemma = word.get_annotation(AnnotationType::LEMMA, Cmp::Any, Recursion::No).expect("lemma");
+    assert_eq!(lemma.processor(), Some("proc.proycon"));
+    //renamed elements and declarations
+    assert!(doc.get_declaration_by_id(&Declaration::index_id(AnnotationType::RELATION, &Some("alignments"))).is_some());
+    let xml = str::from_utf8(&doc.xml(0,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(xml.contains("<relation "));
+    assert!(xml.contains("<xref id=\"en.w.2\""));
+    assert!(!xml.contains("annotator=\""));
+    //structure annotation is declared
+    assert!(xml.contains("<sentence-annotation/>"));
+    let reparsed = Document::from_str(&xml, DocumentProperties::default()).expect("reparsing");
+    assert_eq!(reparsed.xml(0,0).expect("serialisation"), doc.xml(0,0).expect("serialisation"));
+}
+
+#[test]
+fn test038b_legacy_version() {
+    assert!(is_legacy_version("1.5.0"));
+    assert!(is_legacy_version("0.9"));
+    assert!(!is_legacy_version("2.0"));
+    assert!(!is_legacy_version(FOLIAVERSION));
+}
+
+#[test]
+fn test039a_required_version() {
+    let doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    assert_eq!(doc.required_version(), FoliaVersion::new(2,0,0));
+    let xml = str::from_utf8(&doc.xml(0,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(xml.contains("version=\"2.0.0\""));
+    assert!(doc.xml_for_version(0, 0, "1.5".parse().expect("version")).is_err());
+    assert!(doc.xml_for_version(0, 0, "9.0".parse().expect("version")).is_err());
+}
+
+#[test]
+fn test039b_target_version() {
+    let xml = str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example").replace("<t>Hello</t>", "<t>He<t-hspace class=\"tab\"/>llo</t>");
+    let doc = Document::from_str(&xml, DocumentProperties::default()).expect("parsing");
+    assert_eq!(doc.required_version(), FoliaVersion::new(2,1,0));
+    assert_eq!(doc.required_version().to_string(), FOLIAVERSION);
+    match doc.xml_for_version(0, 0, FoliaVersion::new(2,0,0)) {
+        Err(FoliaError::SerialisationError(msg)) => assert!(msg.contains("hspace")),
+        _ => panic!("expected a serialisation error"),
+    }
+    let xml = str::from_utf8(&doc.xml_for_version(0, 0, FoliaVersion::new(2,1,0)).expect("serialisation")).expect("utf-8").to_string();
+    assert!(xml.contains("version=\"2.1.0\""));
+}
+
+#[test]
+fn test039c_declared_version() {
+    //an unused declaration is still serialised, so it counts towards the required version
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    doc.declare(AnnotationType::HSPACE, &None, &None, &None).expect("declaration");
+    assert_eq!(doc.required_version(), FoliaVersion::new(2,1,0));
+    let xml = str::from_utf8(&doc.xml(0,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(xml.contains("<hspace-annotation"));
+    assert!(xml.contains("version=\"2.1.0\""));
+    match doc.xml_for_version(0, 0, FoliaVersion::new(2,0,0)) {
+        Err(FoliaError::SerialisationError(msg)) => assert!(msg.contains("hspace")),
+        _ => panic!("expected a serialisation error"),
+    }
+}
+
+#[test]
+fn test040a_submetadata_roundtrip() {
+    let xml = str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example")
+        .replace("<meta id=\"language\">eng</meta>", "<meta id=\"language\">eng</meta>\n      <submetadata xml:id=\"sub.1\" type=\"native\"><meta id=\"speaker\">alice</meta></submetadata>\n      <submetadata xml:id=\"sub.2\" type=\"cmdi\" src=\"sub2.cmdi\"/>")
+        .replace("<s xml:id=\"example.p.1.s.2\">", "<s xml:id=\"example.p.1.s.2\" metadata=\"sub.1\">");
+    let doc = Document::from_str(&xml, DocumentProperties::default()).expect("parsing");
+    let sub1 = doc.get_submetadata("sub.1").expect("submetadata");
+    assert_eq!(sub1.get("speaker"), Some("alice"));
+    assert_eq!(sub1.metadatatype.as_deref(), Some("native"));
+    assert_eq!(sub1.src, None);
+    let sub2 = doc.get_submetadata("sub.2").expect("submetadata");
+    assert_eq!(sub2.src.as_deref(), Some("sub2.cmdi"));
+    assert_eq!(sub2.metadatatype.as_deref(), Some("cmdi"));
+    let xml = str::from_utf8(&doc.xml(0,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(xml.contains("<submetadata xml:id=\"sub.1\" type=\"native\">"));
+    assert!(xml.contains("<meta id=\"speaker\">alice</meta>"));
+    assert!(xml.contains("<submetadata xml:id=\"sub.2\" type=\"cmdi\" src=\"sub2.cmdi\">"));
+    let reparsed = Document::from_str(&xml, DocumentProperties::default()).expect("reparsing");
+    assert_eq!(reparsed.xml_canonical(0).expect("serialisation"), doc.xml_canonical(0).expect("serialisation"));
+}
+
+#[test]
+fn test040b_submetadata_inheritance() {
+    let mut doc = Document::from_str(str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example"), DocumentProperties::default()).expect("parsing");
+    doc.add_submetadata("sub.1", Metadata::new().with_type("native").with_meta("speaker", "alice")).expect("adding submetadata");
+    assert!(doc.add_submetadata("sub.1", Metadata::new()).is_err());
+    assert!(doc.add_submetadata("example.p.1", Metadata::new()).is_err());
+    let sentence = doc.get_element_key_by_id("example.p.1.s.2").expect("sentence");
+    let word = doc.get_element_key_by_id("example.p.1.s.2.w.4").expect("word");
+    assert!(doc.set_element_metadata(sentence, Some("nonexistent")).is_err());
+    doc.set_element_metadata(sentence, Some("sub.1")).expect("setting metadata");
+    //the word inherits the submetadata from the sentence
+    assert_eq!(doc.element_metadata_id(word), Some("sub.1"));
+    assert_eq!(doc.element_meta(word, "speaker"), Some("alice"));
+    //elements outside the sentence fall back to the main metadata
+    let other = doc.get_element_key_by_id("example.p.1.s.1.w.1").expect("word");
+    assert_eq!(doc.element_metadata_id(other), None);
+    assert_eq!(doc.element_meta(other, "language"), Some("eng"));
+    let words = doc.select_by_metadata(Selector::elements().element(Cmp::Is(ElementType::Word)), "speaker", "alice");
+    assert_eq!(words.len(), 8);
+    assert_eq!(words[3], word);
+    let xml = str::from_utf8(&doc.xml(0,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(xml.contains("metadata=\"sub.1\""));
+    //removing the submetadata removes the references to it
+    doc.remove_submetadata("sub.1").expect("removing submetadata");
+    assert_eq!(doc.element_metadata_id(word), None);
+    assert!(doc.get_submetadata("sub.1").is_none());
+}
+
+const FOREIGN_CMDI: &str = r#"<CMD xmlns="http://www.clarin.eu/cmd/" xmlns:dc="http://purl.org/dc/elements/1.1/" CMDVersion="1.1">
+          <Header><MdCreator>archive</MdCreator></Header>
+          <Components><DC><dc:title>An example</dc:title><dc:creator>proycon</dc:creator><dc:language>eng</dc:language></DC></Components>
+        </CMD>"#;
+
+#[test]
+fn test041a_foreign_metadata() {
+    let xml = str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example")
+        .replace("<FoLiA xmlns=\"http://ilk.uvt.nl/folia\"", "<FoLiA xmlns=\"http://ilk.uvt.nl/folia\" xmlns:oai_dc=\"http://www.openarchives.org/OAI/2.0/oai_dc/\"")
+        .replace("<meta id=\"language\">eng</meta>", &format!("<foreign-data>\n        {}\n      </foreign-data>\n      <submetadata xml:id=\"sub.1\" type=\"foreign\"><foreign-data><oai_dc:dc xmlns:dc=\"http://purl.org/dc/elements/1.1/\"><dc:date>2020-01-01</dc:date></oai_dc:dc></foreign-data></submetadata>", FOREIGN_CMDI));
+    let doc = Document::from_str(&xml, DocumentProperties::default()).expect("parsing");
+    assert_eq!(doc.metadata.foreign.len(), 1);
+    assert_eq!(doc.metadata.foreign[0].format(), ForeignFormat::CMDI);
+    assert!(doc.metadata.foreign[0].xml.contains(FOREIGN_CMDI));
+    let dublincore = doc.metadata.dublin_core().expect("dublin core").expect("dublin core fields");
+    assert_eq!(dublincore.title(), Some("An example"));
+    assert_eq!(dublincore.creator(), Some("proycon"));
+    assert_eq!(dublincore.language(), Some("eng"));
+    //the namespace declared outside of the foreign data is added to its root element
+    let sub1 = doc.get_submetadata("sub.1").expect("submetadata");
+    assert_eq!(sub1.foreign[0].format(), ForeignFormat::DublinCore);
+    assert!(sub1.foreign[0].xml.starts_with("<oai_dc:dc xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:oai_dc=\"http://www.openarchives.org/OAI/2.0/oai_dc/\">"));
+    assert_eq!(sub1.dublin_core().expect("dublin core").expect("dublin core fields").date(), Some("2020-01-01"));
+    //foreign metadata survives a round-trip through XML, JSON and snapshots
+    let xml = doc.xml(0,0).expect("serialisation");
+    assert!(str::from_utf8(&xml).expect("utf-8").contains(FOREIGN_CMDI));
+    let reparsed = Document::from_str(str::from_utf8(&xml).expect("utf-8"), DocumentProperties::default()).expect("reparsing");
+    assert_eq!(reparsed.xml(0,0).expect("serialisation"), xml);
+    let json = doc.json(false).expect("json");
+    let fromjson = Document::from_json(&json, DocumentProperties::default()).expect("parsing json");
+    assert_eq!(fromjson.metadata.foreign, doc.metadata.foreign);
+    assert_eq!(fromjson.get_submetadata("sub.1").expect("submetadata").foreign, sub1.foreign);
+}
+
+#[test]
+fn test041c_foreign_metadata_namespaces() {
+    //prefixes used anywhere in the foreign data but declared on the FoLiA root are declared on the foreign root element
+    let xml = str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example")
+        .replace("<FoLiA xmlns=\"http://ilk.uvt.nl/folia\"", "<FoLiA xmlns=\"http://ilk.uvt.nl/folia\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\"")
+        .replace("<meta id=\"language\">eng</meta>", "<foreign-data><CMD xmlns=\"http://www.clarin.eu/cmd/\" xsi:schemaLocation=\"http://www.clarin.eu/cmd/ cmd.xsd\"><Components><dc:title>An example</dc:title></Components></CMD></foreign-data>");
+    let doc = Document::from_str(&xml, DocumentProperties::default()).expect("parsing");
+    assert_eq!(doc.metadata.foreign[0].xml, "<CMD xmlns=\"http://www.clarin.eu/cmd/\" xsi:schemaLocation=\"http://www.clarin.eu/cmd/ cmd.xsd\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\"><Components><dc:title>An example</dc:title></Components></CMD>");
+    assert_eq!(doc.metadata.dublin_core().expect("dublin core").expect("dublin core fields").title(), Some("An example"));
+    let xml = doc.xml(0,0).expect("serialisation");
+    let reparsed = Document::from_str(str::from_utf8(&xml).expect("utf-8"), DocumentProperties::default()).expect("reparsing");
+    assert_eq!(reparsed.metadata.foreign, doc.metadata.foreign);
+}
+
+#[test]
+fn test041b_external_metadata() {
+    let dir = std::env::temp_dir();
+    let filename = dir.join("folia-rust-test041b.folia.xml").to_str().expect("filename").to_string();
+    let metadatafilename = dir.join("folia-rust-test041b.cmdi.xml");
+    std::fs::write(&metadatafilename, format!("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n{}\n", FOREIGN_CMDI)).expect("writing metadata");
+    let xml = str::from_utf8(EXAMPLE).expect("conversion from utf-8 of example")
+        .replace("<metadata type=\"native\">", "<metadata type=\"cmdi\" src=\"folia-rust-test041b.cmdi.xml\">");
+    std::fs::write(&filename, &xml).expect("writing example");
+    //external metadata is not loaded by default
+    let doc = Document::from_file(&filename, DocumentProperties::default()).expect("parsing");
+    assert!(doc.metadata.external.is_none());
+    assert_eq!(doc.metadata.dublin_core().expect("dublin core"), None);
+    let doc = Document::from_file(&filename, DocumentProperties::default().with_load_external_metadata(true)).expect("parsing");
+    assert_eq!(doc.metadata.external.as_ref().expect("external metadata").xml, FOREIGN_CMDI);
+    assert_eq!(doc.metadata.foreign_by_format(ForeignFormat::CMDI), doc.metadata.external.as_ref());
+    assert_eq!(doc.metadata.dublin_core().expect("dublin core").expect("dublin core fields").title(), Some("An example"));
+    //the external metadata is still only referenced
+    let xml = str::from_utf8(&doc.xml(0,0).expect("serialisation")).expect("utf-8").to_string();
+    assert!(xml.contains("src=\"folia-rust-test041b.cmdi.xml\""));
+    assert!(!xml.contains("foreign-data"));
+    let _ = std::fs::remove_file(&metadatafilename);
+    let _ = std::fs::remove_file(&filename);
+}